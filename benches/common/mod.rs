@@ -0,0 +1,73 @@
+// what `benches/lexer.rs` and `benches/tokenize.rs` share: the input, so
+// that their numbers stay comparable, and the allocation counting
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+// tracks the high-water mark of heap usage and the number of allocations
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Relaxed);
+        let current = CURRENT.fetch_add(layout.size(), Relaxed) + layout.size();
+        PEAK.fetch_max(current, Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// the heap usage since it was started
+pub struct Usage {
+    base: usize,
+    allocs: usize,
+}
+
+impl Usage {
+    pub fn start() -> Self {
+        let base = CURRENT.load(Relaxed);
+        PEAK.store(base, Relaxed);
+        Usage {
+            base,
+            allocs: ALLOCS.load(Relaxed),
+        }
+    }
+
+    // the extra memory used at peak
+    pub fn peak_mib(&self) -> f64 {
+        (PEAK.load(Relaxed) - self.base) as f64 / (1 << 20) as f64
+    }
+
+    pub fn allocs(&self) -> usize {
+        ALLOCS.load(Relaxed) - self.allocs
+    }
+}
+
+const FUNCTIONS: usize = 20_000;
+
+pub fn generated_source() -> String {
+    let mut src = String::new();
+    for i in 0..FUNCTIONS {
+        src += &format!(
+            "/* 第 {i} 个函数 */\n\
+             static int f{i}(int a, const char *s) {{\n\
+             \tint x = a * {i} + 0x{i:x};\n\
+             \tdouble d = 1.5e3 / (a + 1);\n\
+             \tif (x >= 10 && s[0] != '\\n') {{ x -= (int)d; }}\n\
+             \treturn x % 7; // done\n\
+             }}\n",
+            i = i
+        );
+    }
+    src
+}
@@ -0,0 +1,23 @@
+#!/bin/sh
+# runs `benches/tokenize.rs` on a baseline revision and on the working tree,
+# for a before/after comparison on the same input:
+#
+#     benches/compare.sh d8b9ed3^
+set -e
+
+base=${1:?usage: benches/compare.sh <baseline revision>}
+root=$(git rev-parse --show-toplevel)
+tree=$(mktemp -d)
+trap 'git -C "$root" worktree remove --force "$tree"' EXIT
+
+git -C "$root" worktree add --detach --force -q "$tree" "$base"
+mkdir -p "$tree/benches"
+cp -r "$root/benches/tokenize.rs" "$root/benches/common" "$tree/benches/"
+if ! grep -q '^name = "tokenize"' "$tree/Cargo.toml"; then
+    printf '\n[[bench]]\nname = "tokenize"\nharness = false\n' >>"$tree/Cargo.toml"
+fi
+
+echo "before ($base):"
+(cd "$tree" && cargo bench -q --bench tokenize)
+echo "after (working tree):"
+(cd "$root" && cargo bench -q --bench tokenize)
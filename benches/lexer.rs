@@ -0,0 +1,101 @@
+// `cargo bench --bench lexer`: times loading, lexing, looking up names and locating
+// diagnostics in a large file
+use toy_compiler::{session, tokenize, Diagnostic, Renderer, SourceFile, Symbol, Token};
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[path = "common/mod.rs"]
+mod common;
+
+use common::{generated_source, Usage};
+
+// the best of a few runs, to keep noise out, the extra memory used at peak
+// and the allocations of a run
+fn time<T>(name: &str, bytes: usize, mut f: impl FnMut() -> T) {
+    const RUNS: usize = 5;
+    let mut best = Duration::MAX;
+    let usage = Usage::start();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+    let throughput = bytes as f64 / best.as_secs_f64() / (1 << 20) as f64;
+    println!(
+        "{:<14} {:>10.2?} {:>10.1} MiB/s {:>10.1} MiB peak {:>10} allocs",
+        name,
+        best,
+        throughput,
+        usage.peak_mib(),
+        usage.allocs() / RUNS
+    );
+}
+
+fn main() {
+    let src = generated_source();
+    let bytes = src.len();
+    println!("{} bytes, {} lines", bytes, src.lines().count());
+
+    // each in a session of its own, which frees the file after
+    time("load", bytes, || {
+        session::enter(|| SourceFile::new(&src, "bench.c").line_count())
+    });
+
+    let file = SourceFile::new(&src, "bench.c");
+    time("tokenize", bytes, || tokenize(&file));
+
+    // what later passes do with names: count the uses of each, keyed by
+    // string as before interning, when each token held its own copy, and by
+    // symbol
+    let (tokens, _) = tokenize(&file);
+    let names: Vec<Symbol> = tokens
+        .iter()
+        .filter_map(|t| match t {
+            Token::Identifier(ident) => Some(ident.value),
+            _ => None,
+        })
+        .collect();
+    println!("{} identifiers, {} distinct", names.len(), {
+        let mut distinct = names.clone();
+        distinct.sort();
+        distinct.dedup();
+        distinct.len()
+    });
+    let strings: Vec<String> = names.iter().map(Symbol::to_string).collect();
+    time("names/string", bytes, || {
+        let mut uses: HashMap<String, usize> = HashMap::new();
+        for name in &strings {
+            match uses.get_mut(name) {
+                Some(n) => *n += 1,
+                None => {
+                    uses.insert(name.clone(), 1);
+                }
+            }
+        }
+        uses.len()
+    });
+    time("names/symbol", bytes, || {
+        let mut uses: HashMap<Symbol, usize> = HashMap::new();
+        for &name in &names {
+            *uses.entry(name).or_default() += 1;
+        }
+        uses.len()
+    });
+
+    // a thousand diagnostics spread over the file
+    let spans: Vec<_> = tokens
+        .iter()
+        .step_by(tokens.len() / 1000)
+        .map(|t| t.span())
+        .collect();
+    time("render", bytes, || {
+        let renderer = Renderer::new();
+        let rendered = spans
+            .iter()
+            .map(|&span| renderer.render(&Diagnostic::error(span, "here")).len())
+            .sum::<usize>();
+        rendered
+    });
+}
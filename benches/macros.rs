@@ -0,0 +1,57 @@
+// `cargo bench --bench macros`: times preprocessing chains of macros, each
+// expanding to the next, at doubling depths; the time should grow at most
+// about four times per row
+use toy_compiler::{session, Preprocessor, SourceFile};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const DEPTHS: [usize; 4] = [1000, 2000, 4000, 8000];
+
+// `A<n>` expands through `A<n-1>` down to `A0`
+fn object_chain(depth: usize) -> String {
+    let mut src = String::from("#define A0 0\n");
+    for i in 1..=depth {
+        src += &format!("#define A{} A{}\n", i, i - 1);
+    }
+    src + &format!("int x = A{};\n", depth)
+}
+
+// the same through function-like macros, whose hidesets are intersected
+// with that of the closing parenthesis
+fn function_chain(depth: usize) -> String {
+    let mut src = String::from("#define F0(x) (x)\n");
+    for i in 1..=depth {
+        src += &format!("#define F{}(x) F{}(x)\n", i, i - 1);
+    }
+    src + &format!("int x = F{}(0);\n", depth)
+}
+
+fn time(src: &str) -> Duration {
+    session::enter(|| {
+        let file = SourceFile::new(src, "bench.c");
+        let start = Instant::now();
+        let (tokens, errors) = black_box(Preprocessor::new().resolve(file));
+        let elapsed = start.elapsed();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(tokens.len() > 3);
+        elapsed
+    })
+}
+
+fn main() {
+    for (name, generate) in [
+        ("object-like", object_chain as fn(usize) -> String),
+        ("function-like", function_chain),
+    ] {
+        let mut prev: Option<Duration> = None;
+        for depth in DEPTHS {
+            let elapsed = time(&generate(depth));
+            let growth = prev.map_or(String::new(), |prev| {
+                format!("x{:.1}", elapsed.as_secs_f64() / prev.as_secs_f64())
+            });
+            println!("{:<14} {:>6} {:>12.2?} {:>6}", name, depth, elapsed, growth);
+            prev = Some(elapsed);
+        }
+    }
+}
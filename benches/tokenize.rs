@@ -0,0 +1,35 @@
+// `cargo bench --bench tokenize`: times lexing a large file into tokens; it
+// only uses `SourceFile::new` and `tokenize`, so that `benches/compare.sh`
+// can run it on older revisions too
+use toy_compiler::{tokenize, SourceFile};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[path = "common/mod.rs"]
+mod common;
+
+use common::{generated_source, Usage};
+
+fn main() {
+    const RUNS: usize = 5;
+    let src = generated_source();
+    let file = SourceFile::new(&src, "bench.c");
+
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    let usage = Usage::start();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let (output, _) = black_box(tokenize(&file));
+        best = best.min(start.elapsed());
+        tokens = output.len();
+    }
+    println!(
+        "tokenize {:>10.2?} {:>10.1} MiB peak {:>10} allocs {:>10} tokens",
+        best,
+        usage.peak_mib(),
+        usage.allocs() / RUNS,
+        tokens
+    );
+}
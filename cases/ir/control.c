@@ -0,0 +1,31 @@
+double scale(float f, unsigned long n) {
+    double d = f;
+    do {
+        d = d * 2 + n;
+    } while (d < 100.0);
+    return d;
+}
+
+int classify(int c) {
+    int kind;
+    switch (c) {
+    case 'a':
+    case 'e':
+        kind = 1;
+        break;
+    case 0:
+        goto done;
+    default:
+        kind = 2;
+    }
+    return kind;
+done:
+    return -1;
+}
+
+int main(void) {
+    char c = 'e';
+    _Bool ok = classify(c) == 1;
+    long l = (long)scale(1.5f, 3);
+    return ok ? (int)l : !ok;
+}
@@ -0,0 +1,25 @@
+int printf(const char *, ...);
+
+int g = 3;
+
+int sum(int n) {
+    int s = 0;
+    for (int i = 0; i < n; i++) {
+        if (i % 2)
+            s += i;
+        else
+            s -= 1;
+    }
+    return s && g ? s : -1;
+}
+
+int main(void) {
+    int x = 10;
+    while (x > 0) {
+        x--;
+        if (x == 5)
+            break;
+    }
+    printf("%d %d\n", sum(x), x);
+    return 0;
+}
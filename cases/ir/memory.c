@@ -0,0 +1,25 @@
+struct point { int x, y; };
+
+int table[4] = { 1, 2 };
+int *second = &table[1];
+const char *name = "ir";
+
+struct point flip(struct point p) {
+    struct point q = { p.y, p.x };
+    return q;
+}
+
+void swap(int *a, int *b) {
+    int t = *a;
+    *a = *b;
+    *b = t;
+}
+
+int main(void) {
+    int a = 1, b = 2;
+    char buf[4] = "hi";
+    struct point p = { 3, 4 };
+    swap(&a, &b);
+    p = flip(p);
+    return a + buf[1] + p.x + table[0] + *second;
+}
@@ -0,0 +1,11 @@
+int next(void) {
+    static int count = 0;
+    static const char *label = "next";
+    return count++ + label[0];
+}
+
+int main(void) {
+    static int calls;
+    calls = next();
+    return next() + calls;
+}
@@ -0,0 +1,13 @@
+#ifndef _STDIO_H
+#define _STDIO_H
+
+/* the subset of <stdio.h> the interpreter provides as builtins */
+
+#define NULL ((void *)0)
+#define EOF (-1)
+
+int printf(const char *format, ...);
+int puts(const char *s);
+int putchar(int c);
+
+#endif
@@ -0,0 +1,381 @@
+use crate::span::Span;
+use crate::tokens::{Constant, Identifier, StringLiteral};
+
+#[derive(Debug, Clone)]
+pub struct TranslationUnit {
+    pub items: Vec<ExternalDecl>,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum ExternalDecl {
+    Function(FunctionDef),
+    Declaration(Declaration),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDef {
+    pub specifiers: DeclSpecifiers,
+    pub declarator: Declarator,
+    pub body: CompoundStmt,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub specifiers: DeclSpecifiers,
+    pub declarators: Vec<InitDeclarator>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeclSpecifiers {
+    pub storage: Vec<StorageClass>,
+    pub qualifiers: Vec<TypeQualifier>,
+    pub function: Vec<FunctionSpecifier>,
+    pub types: Vec<TypeSpecifier>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Typedef,
+    Extern,
+    Static,
+    ThreadLocal,
+    Auto,
+    Register,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeQualifier {
+    Const,
+    Restrict,
+    Volatile,
+    Atomic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionSpecifier {
+    Inline,
+    Noreturn,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeSpecifier {
+    Void,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Signed,
+    Unsigned,
+    Bool,
+    Complex,
+    Struct(StructSpecifier),
+    Enum(EnumSpecifier),
+    TypedefName(Identifier),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructKind {
+    Struct,
+    Union,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructSpecifier {
+    pub kind: StructKind,
+    pub tag: Option<Identifier>,
+    pub fields: Option<Vec<StructField>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructField {
+    pub specifiers: DeclSpecifiers,
+    pub declarators: Vec<StructDeclarator>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDeclarator {
+    pub declarator: Option<Declarator>,
+    pub bit_width: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumSpecifier {
+    pub tag: Option<Identifier>,
+    pub enumerators: Option<Vec<Enumerator>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Enumerator {
+    pub name: Identifier,
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct InitDeclarator {
+    pub declarator: Declarator,
+    pub initializer: Option<Initializer>,
+    pub span: Span,
+}
+
+// `derived` is ordered from the name outwards:
+// `*a[3]` is `[Array(3), Pointer]`, i.e. "array of 3 pointers to ..."
+#[derive(Debug, Clone)]
+pub struct Declarator {
+    pub name: Option<Identifier>,
+    pub derived: Vec<DerivedDeclarator>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum DerivedDeclarator {
+    Pointer(Vec<TypeQualifier>),
+    Array(Option<Box<Expr>>),
+    Function(FunctionDeclarator),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDeclarator {
+    pub params: Vec<ParamDecl>,
+    pub variadic: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamDecl {
+    pub specifiers: DeclSpecifiers,
+    pub declarator: Declarator,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeName {
+    pub specifiers: DeclSpecifiers,
+    pub declarator: Declarator,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Initializer {
+    Expr(Expr),
+    List(Vec<InitItem>, Span),
+}
+
+#[derive(Debug, Clone)]
+pub struct InitItem {
+    pub designators: Vec<Designator>,
+    pub init: Initializer,
+}
+
+#[derive(Debug, Clone)]
+pub enum Designator {
+    Index(Expr),
+    Member(Identifier),
+}
+
+#[derive(Debug, Clone)]
+pub struct CompoundStmt {
+    pub items: Vec<BlockItem>,
+    pub span: Span,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum BlockItem {
+    Declaration(Declaration),
+    Stmt(Stmt),
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Compound(CompoundStmt),
+    Expr(Option<Expr>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    For(ForInit, Option<Expr>, Option<Expr>, Box<Stmt>),
+    Switch(Expr, Box<Stmt>),
+    Case(Expr, Box<Stmt>),
+    Default(Box<Stmt>),
+    Labeled(Identifier, Box<Stmt>),
+    Goto(Identifier),
+    Continue,
+    Break,
+    Return(Option<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub enum ForInit {
+    Empty,
+    Expr(Expr),
+    Declaration(Declaration),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Identifier(Identifier),
+    Constant(Constant),
+    StringLiteral(StringLiteral),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Assign(AssignOp, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Vec<Expr>),
+    Cast(Box<TypeName>, Box<Expr>),
+    SizeofExpr(Box<Expr>),
+    SizeofType(Box<TypeName>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, Identifier),
+    Arrow(Box<Expr>, Identifier),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    PreInc,
+    PreDec,
+    PostInc,
+    PostDec,
+    Address,
+    Deref,
+    Plus,
+    Minus,
+    BitNot,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignOp {
+    Assign,
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    BitAnd,
+    BitXor,
+    BitOr,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl Stmt {
+    // the `case` and `default` labels of a switch body, not those of nested switches
+    pub fn switch_cases(&self) -> Vec<&Stmt> {
+        let mut cases = Vec::new();
+        collect_cases(self, &mut cases);
+        cases
+    }
+
+    // the error for a `break`, `continue`, `case` or `default` outside the
+    // statement it belongs to
+    pub fn misplaced_msg(&self) -> &'static str {
+        match self.kind {
+            StmtKind::Break => "'break' statement not in loop or switch statement",
+            StmtKind::Continue => "'continue' statement not in loop statement",
+            StmtKind::Case(..) => "'case' statement not in switch statement",
+            _ => "'default' statement not in switch statement",
+        }
+    }
+}
+
+fn collect_cases<'s>(stmt: &'s Stmt, out: &mut Vec<&'s Stmt>) {
+    match &stmt.kind {
+        StmtKind::Case(_, body) | StmtKind::Default(body) => {
+            out.push(stmt);
+            collect_cases(body, out);
+        }
+        StmtKind::Compound(block) => {
+            for item in &block.items {
+                if let BlockItem::Stmt(stmt) = item {
+                    collect_cases(stmt, out);
+                }
+            }
+        }
+        StmtKind::If(_, then, otherwise) => {
+            collect_cases(then, out);
+            if let Some(otherwise) = otherwise {
+                collect_cases(otherwise, out);
+            }
+        }
+        StmtKind::While(_, body)
+        | StmtKind::DoWhile(body, _)
+        | StmtKind::For(_, _, _, body)
+        | StmtKind::Labeled(_, body) => collect_cases(body, out),
+        _ => {}
+    }
+}
+
+impl AssignOp {
+    pub fn binary_op(self) -> Option<BinaryOp> {
+        match self {
+            AssignOp::Assign => None,
+            AssignOp::Mul => Some(BinaryOp::Mul),
+            AssignOp::Div => Some(BinaryOp::Div),
+            AssignOp::Mod => Some(BinaryOp::Mod),
+            AssignOp::Add => Some(BinaryOp::Add),
+            AssignOp::Sub => Some(BinaryOp::Sub),
+            AssignOp::Shl => Some(BinaryOp::Shl),
+            AssignOp::Shr => Some(BinaryOp::Shr),
+            AssignOp::BitAnd => Some(BinaryOp::BitAnd),
+            AssignOp::BitXor => Some(BinaryOp::BitXor),
+            AssignOp::BitOr => Some(BinaryOp::BitOr),
+        }
+    }
+}
+
+impl DeclSpecifiers {
+    pub fn is_typedef(&self) -> bool {
+        self.storage.contains(&StorageClass::Typedef)
+    }
+}
@@ -1,75 +1,183 @@
-use crate::{source_file::SourceFile, span::Pos};
+use crate::source_file::SourceFile;
+use crate::span::Span;
+use crate::tokens::TRIGRAPH_TABLE;
 
+use std::ops::Range;
 use std::rc::Rc;
 
+// reads the chars of the source after translation phases 1 and 2: with
+// trigraphs replaced, if enabled, and backslash-newlines removed; offsets
+// are those of the source as written
 pub struct CharStream {
-    content: Rc<[char]>,
-    file_path: Rc<str>,
+    content: Rc<str>,
+    // the source map offset of `content`
+    start: u32,
 
+    // byte offset of the next char in `content`, past any splices
     idx: usize,
-    byte_pos: usize,
-    lineno: usize,
-    column: usize,
+    cur: Option<char>,
+    // where `cur` is written in `content`, 3 bytes for a trigraph
+    cur_range: Range<usize>,
+    // whether `??=` and the like are read as the char they stand for
+    trigraphs: bool,
 }
 
 impl CharStream {
     pub fn new(src: SourceFile) -> Self {
-        Self {
-            content: src.content,
-            file_path: src.file_path,
+        Self::at(src.content, src.start)
+    }
 
+    // `text` as found at offset `start` of the source map
+    pub fn at(text: impl Into<Rc<str>>, start: u32) -> Self {
+        let mut chars = Self {
+            content: text.into(),
+            start,
             idx: 0,
-            byte_pos: 0,
-            lineno: 1,
-            column: 0,
-        }
+            cur: None,
+            cur_range: 0..0,
+            trigraphs: false,
+        };
+        chars.idx = chars.skip_splices(0);
+        chars
     }
 
-    pub fn cur(&self) -> Option<char> {
-        if self.idx == 0 {
-            return None;
-        }
-        Some(self.content[self.idx - 1])
+    // translation phase 1, off by default as in GCC
+    pub fn set_trigraphs(&mut self, enabled: bool) {
+        self.trigraphs = enabled;
+        self.idx = self.skip_splices(self.idx);
+    }
+
+    pub fn trigraphs(&self) -> bool {
+        self.trigraphs
+    }
+
+    // the whole text, and its source map offset
+    pub fn content(&self) -> (&Rc<str>, u32) {
+        (&self.content, self.start)
     }
 
     pub fn peek(&self) -> Option<char> {
-        self.content.get(self.idx).copied()
+        self.char_at(self.idx).map(|(ch, _)| ch)
     }
 
     pub fn peek2(&self) -> Option<char> {
-        self.content.get(self.idx + 1).copied()
+        self.ahead().nth(1)
+    }
+
+    // the chars not yet consumed, as read by `next`
+    pub fn ahead(&self) -> impl Iterator<Item = char> + '_ {
+        let mut idx = self.idx;
+        std::iter::from_fn(move || {
+            let (ch, len) = self.char_at(idx)?;
+            idx = self.skip_splices(idx + len);
+            Some(ch)
+        })
+    }
+
+    // the char at byte `idx` of `content`, and its length there
+    fn char_at(&self, idx: usize) -> Option<(char, usize)> {
+        // most of a C source is ASCII, which needs no decoding
+        match *self.content.as_bytes().get(idx)? {
+            b'?' if self.trigraphs => {}
+            b if b.is_ascii() => return Some((b as char, 1)),
+            _ => {}
+        }
+        let rest = &self.content[idx..];
+        if self.trigraphs && rest.starts_with("??") {
+            let third = rest[2..].chars().next();
+            if let Some(&(_, ch)) = TRIGRAPH_TABLE.iter().find(|&&(c, _)| Some(c) == third) {
+                return Some((ch, 3));
+            }
+        }
+        let ch = rest.chars().next()?;
+        Some((ch, ch.len_utf8()))
+    }
+
+    // past the backslash-newlines at byte `idx` of `content`
+    fn skip_splices(&self, mut idx: usize) -> usize {
+        loop {
+            let len = match self.content.as_bytes().get(idx) {
+                Some(b'\\') => 1,
+                Some(b'?') if self.trigraphs && self.content[idx..].starts_with("??/") => 3,
+                _ => break,
+            };
+            let rest = &self.content[idx..];
+            let rest = &rest[len..];
+            if rest.starts_with('\n') {
+                idx += len + 1;
+            } else if rest.starts_with("\r\n") {
+                idx += len + 2;
+            } else {
+                break;
+            }
+        }
+        idx
+    }
+
+    // the text consumed from offset `pos` of the source map on, as written
+    pub fn since(&self, pos: u32) -> &str {
+        let from = (pos - self.start) as usize;
+        &self.content[from..self.cur_range.end.max(from)]
+    }
+
+    // the span of bytes `range` of the text read from offset `pos` on
+    pub fn span_of(&self, pos: u32, range: Range<usize>) -> Span {
+        let mut idx = (pos - self.start) as usize;
+        let mut read = 0;
+        let mut lo = None;
+        let mut hi = idx;
+        loop {
+            if read >= range.start && lo.is_none() {
+                lo = Some(idx);
+            }
+            if read >= range.end {
+                break;
+            }
+            match self.char_at(idx) {
+                None => break,
+                Some((ch, len)) => {
+                    hi = idx + len;
+                    idx = self.skip_splices(hi);
+                    read += ch.len_utf8();
+                }
+            }
+        }
+        let lo = lo.unwrap_or(hi);
+        Span::new(self.start + lo as u32, self.start + hi.max(lo) as u32)
     }
 
     pub fn next_char(&mut self) -> Option<char> {
-        if self.idx > 0 {
-            let cur = self.content.get(self.idx - 1).copied()?;
-            let char_len = cur.len_utf8();
-            self.byte_pos += char_len;
-            if cur == '\n' {
-                self.lineno += 1;
-                self.column = 0;
+        match self.char_at(self.idx) {
+            Some((ch, len)) => {
+                self.cur = Some(ch);
+                self.cur_range = self.idx..self.idx + len;
+                self.idx = self.skip_splices(self.idx + len);
+            }
+            None => {
+                self.cur = None;
+                self.cur_range = self.idx..self.idx;
             }
         }
-        self.column += 1;
-        let ch = self.content.get(self.idx).copied()?;
-        self.idx += 1;
-        Some(ch)
+        self.cur
     }
 
     pub fn consume1(&mut self) -> char {
         self.next().unwrap()
     }
 
-    pub fn pos(&self) -> Pos {
-        Pos {
-            byte_pos: self.byte_pos,
-            lineno: self.lineno,
-            column: self.column,
-        }
+    // the offset of the current char, or of the first one before any
+    pub fn pos(&self) -> u32 {
+        self.start + self.cur_range.start as u32
+    }
+
+    // the offset just past the current char
+    pub fn end_pos(&self) -> u32 {
+        self.start + self.cur_range.end as u32
     }
 
-    pub fn file_path(&self) -> &Rc<str> {
-        &self.file_path
+    // the offset of the next char
+    pub fn next_pos(&self) -> u32 {
+        self.start + self.idx as u32
     }
 }
 
@@ -84,72 +192,86 @@ impl Iterator for CharStream {
 mod tests {
     use super::*;
 
-    fn dummy_char_stream(content: &str) -> CharStream {
-        CharStream::new(SourceFile::new(content, "<dummy file>"))
-    }
-
     #[test]
     fn char_stream() {
         {
-            let mut chars = dummy_char_stream("");
-            assert_eq!(chars.cur(), None);
+            let mut chars = CharStream::at("", 7);
             assert_eq!(chars.next(), None);
+            assert_eq!(chars.pos(), 7);
         }
         {
-            let mut chars = dummy_char_stream("12");
-            assert_eq!(chars.cur(), None);
+            let mut chars = CharStream::at("1\n2", 0);
 
             assert_eq!(chars.peek(), Some('1'));
             assert_eq!(chars.next(), Some('1'));
-            assert_eq!(chars.cur(), Some('1'));
-            assert_eq!(chars.pos(), Pos::new(0, 1, 1));
-
-            assert_eq!(chars.peek(), Some('2'));
-            assert_eq!(chars.next(), Some('2'));
-            assert_eq!(chars.cur(), Some('2'));
-            assert_eq!(chars.pos(), Pos::new(1, 1, 2));
-
-            assert_eq!(chars.next(), None);
-            assert_eq!(chars.pos(), Pos::new(2, 1, 3));
-        }
-        {
-            let mut chars = dummy_char_stream("1\n2");
-            assert_eq!(chars.cur(), None);
-
-            assert_eq!(chars.peek(), Some('1'));
-            assert_eq!(chars.next(), Some('1'));
-            assert_eq!(chars.cur(), Some('1'));
-            assert_eq!(chars.pos(), Pos::new(0, 1, 1));
+            assert_eq!((chars.pos(), chars.next_pos()), (0, 1));
 
             assert_eq!(chars.peek(), Some('\n'));
             assert_eq!(chars.next(), Some('\n'));
-            assert_eq!(chars.cur(), Some('\n'));
-            assert_eq!(chars.pos(), Pos::new(1, 1, 2));
+            assert_eq!(chars.pos(), 1);
 
-            assert_eq!(chars.peek(), Some('2'));
             assert_eq!(chars.next(), Some('2'));
-            assert_eq!(chars.cur(), Some('2'));
-            assert_eq!(chars.pos(), Pos::new(2, 2, 1));
+            assert_eq!(chars.pos(), 2);
 
             assert_eq!(chars.next(), None);
-            assert_eq!(chars.pos(), Pos::new(3, 2, 2));
+            assert_eq!(chars.pos(), 3);
         }
         {
-            let mut chars = dummy_char_stream("好，很有精神");
-            assert_eq!(chars.cur(), None);
+            let mut chars = CharStream::at("好，很有精神", 10);
 
             assert_eq!(chars.peek(), Some('好'));
             assert_eq!(chars.next(), Some('好'));
-            assert_eq!(chars.cur(), Some('好'));
-            assert_eq!(chars.pos(), Pos::new(0, 1, 1));
+            assert_eq!((chars.pos(), chars.next_pos()), (10, 13));
 
             assert_eq!(chars.peek(), Some('，'));
+            assert_eq!(chars.peek2(), Some('很'));
             assert_eq!(chars.next(), Some('，'));
-            assert_eq!(chars.cur(), Some('，'));
-            assert_eq!(chars.pos(), Pos::new(3, 1, 2));
+            assert_eq!(chars.pos(), 13);
 
             assert_eq!(chars.next(), Some('很'));
-            assert_eq!(chars.pos(), Pos::new(6, 1, 3));
+            assert_eq!(chars.pos(), 16);
+            assert_eq!(chars.ahead().collect::<String>(), "有精神");
+        }
+        {
+            let chars = CharStream::new(SourceFile::new("x", "<dummy file>"));
+            assert_eq!(chars.peek(), Some('x'));
         }
     }
+
+    #[test]
+    fn trigraphs() {
+        let mut chars = CharStream::at("??=a??/??!??", 0);
+        assert_eq!(chars.ahead().collect::<String>(), "??=a??/??!??");
+
+        chars.set_trigraphs(true);
+        assert_eq!(chars.ahead().collect::<String>(), "#a\\|??");
+        assert_eq!(chars.next(), Some('#'));
+        assert_eq!((chars.pos(), chars.next_pos()), (0, 3));
+        assert_eq!(chars.peek2(), Some('\\'));
+        chars.next();
+        assert_eq!(chars.next(), Some('\\'));
+        assert_eq!((chars.pos(), chars.next_pos()), (4, 7));
+        assert_eq!(chars.since(0), "??=a??/");
+        assert_eq!(chars.ahead().collect::<String>(), "|??");
+    }
+
+    #[test]
+    fn splices() {
+        let mut chars = CharStream::at("\\\nab\\\r\n\\\nc\\", 0);
+        assert_eq!(chars.ahead().collect::<String>(), "abc\\");
+        assert_eq!(chars.next_pos(), 2);
+        chars.next();
+        assert_eq!(chars.next(), Some('b'));
+        // the splices go with neither char
+        assert_eq!((chars.pos(), chars.end_pos(), chars.next_pos()), (3, 4, 9));
+        assert_eq!(chars.next(), Some('c'));
+        assert_eq!(chars.since(2), "ab\\\r\n\\\nc");
+        assert_eq!(chars.span_of(2, 1..3), Span::new(3, 10));
+        assert_eq!(chars.span_of(2, 2..2), Span::new(9, 9));
+
+        // a trigraph backslash splices too
+        let mut chars = CharStream::at("a??/\nb", 0);
+        chars.set_trigraphs(true);
+        assert_eq!(chars.ahead().collect::<String>(), "ab");
+    }
 }
@@ -0,0 +1,2507 @@
+use crate::{
+    ast::*,
+    diagnostics::{Code, Diagnostic},
+    initializer::{self, Target},
+    interner::Symbol,
+    span::Span,
+    symbols::{Namespace, ScopeKind, SymbolTable},
+    tokens::{Constant, Identifier},
+    types::*,
+};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// what a name stands for, scope resolution has already been checked by the resolver
+#[derive(Debug, Clone)]
+enum Binding {
+    Object(Type),
+    // an object with automatic storage duration, whose address is not constant
+    Automatic(Type),
+    Typedef(Type),
+    EnumConstant(i64),
+    Tag(Type),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+}
+
+// `ty` is the type before lvalue conversion, so arrays and functions have not decayed yet
+#[derive(Debug, Clone)]
+struct ExprInfo {
+    ty: Type,
+    is_lvalue: bool,
+    value: Option<ConstValue>,
+}
+
+// the labels of a switch statement checked so far
+#[derive(Debug, Default)]
+struct SwitchLabels {
+    // the promoted type of the controlling expression, which case values
+    // are converted to
+    ty: Option<Type>,
+    cases: HashMap<i64, Span>,
+    default: Option<Span>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Conversion {
+    Assign,
+    Initialize,
+    Pass,
+    Return,
+}
+
+// the types the checker computed, for later passes that walk the same AST;
+// nodes are identified by address, so the unit must not be mutated in between
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    exprs: HashMap<usize, (Type, Option<ConstValue>)>,
+    declarators: HashMap<usize, Type>,
+    type_names: HashMap<usize, Type>,
+}
+
+pub struct Checked {
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
+    pub types: TypeTable,
+}
+
+pub struct Checker {
+    symbols: SymbolTable<Binding>,
+    function: Option<(Symbol, Type)>,
+    // how many loops enclose the statement being checked
+    loops: usize,
+    // the enclosing switch statements, innermost last
+    switches: Vec<SwitchLabels>,
+    // where each file-scope name was last declared
+    declared: HashMap<Symbol, Span>,
+    types: TypeTable,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+}
+
+fn node_key<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+impl TypeTable {
+    // the type of `expr` before lvalue conversion
+    pub fn expr(&self, expr: &Expr) -> &Type {
+        &self.exprs[&node_key(expr)].0
+    }
+
+    // the value of `expr` if it is a constant expression
+    pub fn value(&self, expr: &Expr) -> Option<ConstValue> {
+        self.exprs
+            .get(&node_key(expr))
+            .and_then(|(_, value)| *value)
+    }
+
+    pub fn declarator(&self, declarator: &Declarator) -> &Type {
+        &self.declarators[&node_key(declarator)]
+    }
+
+    pub fn type_name(&self, type_name: &TypeName) -> &Type {
+        &self.type_names[&node_key(type_name)]
+    }
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            symbols: SymbolTable::new(),
+            function: None,
+            loops: 0,
+            switches: Vec::new(),
+            declared: HashMap::new(),
+            types: TypeTable::default(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn check(mut self, unit: &TranslationUnit) -> Checked {
+        for item in &unit.items {
+            match item {
+                ExternalDecl::Function(f) => self.check_function(f),
+                ExternalDecl::Declaration(decl) => self.check_declaration(decl),
+            }
+        }
+        Checked {
+            errors: self.errors,
+            warnings: self.warnings,
+            types: self.types,
+        }
+    }
+
+    fn error(&mut self, code: Code, span: &Span, msg: impl Into<String>) {
+        self.errors
+            .push(Diagnostic::error(*span, msg).with_code(code));
+    }
+
+    fn warning(&mut self, span: &Span, msg: impl Into<String>) {
+        self.warnings.push(Diagnostic::warning(*span, msg));
+    }
+}
+
+fn int() -> Type {
+    Type::int(IntKind::Int)
+}
+
+fn rvalue(ty: Type) -> ExprInfo {
+    ExprInfo {
+        ty,
+        is_lvalue: false,
+        value: None,
+    }
+}
+
+fn lvalue(ty: Type) -> ExprInfo {
+    ExprInfo {
+        ty,
+        is_lvalue: true,
+        value: None,
+    }
+}
+
+fn constant(ty: Type, value: ConstValue) -> ExprInfo {
+    ExprInfo {
+        ty,
+        is_lvalue: false,
+        value: Some(value),
+    }
+}
+
+fn qualifiers(list: &[TypeQualifier]) -> Qualifiers {
+    let mut qualifiers = Qualifiers::default();
+    for qualifier in list {
+        match qualifier {
+            TypeQualifier::Const => qualifiers.is_const = true,
+            TypeQualifier::Volatile => qualifiers.is_volatile = true,
+            TypeQualifier::Restrict => qualifiers.is_restrict = true,
+            TypeQualifier::Atomic => qualifiers.is_atomic = true,
+        }
+    }
+    qualifiers
+}
+
+// declarations
+impl Checker {
+    fn check_function(&mut self, f: &FunctionDef) {
+        let base = self.base_type(&f.specifiers);
+
+        self.symbols.push_scope(ScopeKind::Function);
+        let mut derived = f.declarator.derived.iter();
+        let (params, variadic) = match derived.next() {
+            Some(DerivedDeclarator::Function(func)) => (self.params(func), func.variadic),
+            _ => (Vec::new(), false),
+        };
+        let mut ret = base;
+        for d in derived.rev() {
+            ret = self.apply_derived(ret, d);
+        }
+        if ret.is_array() || ret.is_function() {
+            self.error(
+                Code::InvalidDeclarator,
+                &f.declarator.span,
+                format!("function cannot return '{}'", ret),
+            );
+        }
+
+        let ty = Type::new(TypeKind::Function(FunctionType {
+            ret: Box::new(ret.clone()),
+            params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+            variadic,
+        }));
+        self.types
+            .declarators
+            .insert(node_key(&f.declarator), ty.clone());
+        let name = f
+            .declarator
+            .name
+            .as_ref()
+            .map_or_else(|| Symbol::intern(""), |n| n.value);
+        let binding = match &f.declarator.name {
+            Some(ident) => self.redeclare(ident, Binding::Object(ty)),
+            None => Binding::Object(ty),
+        };
+        self.symbols
+            .insert_global(Namespace::Ordinary, name, binding);
+
+        for (param, ty) in params {
+            if let Some(param) = param {
+                if !ty.is_complete() {
+                    self.error(
+                        Code::IncompleteType,
+                        &param.span,
+                        format!("variable has incomplete type '{}'", ty),
+                    );
+                }
+                self.symbols
+                    .insert(Namespace::Ordinary, param.value, Binding::Automatic(ty));
+            }
+        }
+        let func_name = Type::new(TypeKind::Array(
+            Box::new(Type::int(IntKind::Char).with_qualifiers(Qualifiers {
+                is_const: true,
+                ..Default::default()
+            })),
+            Some(name.len() as u64 + 1),
+        ));
+        self.symbols.insert(
+            Namespace::Ordinary,
+            Symbol::intern("__func__"),
+            Binding::Object(func_name),
+        );
+
+        self.function = Some((name, ret));
+        for item in &f.body.items {
+            self.check_block_item(item);
+        }
+        self.function = None;
+        self.symbols.pop_scope();
+    }
+
+    fn check_declaration(&mut self, decl: &Declaration) {
+        let base = self.base_type(&decl.specifiers);
+        let is_file_scope = self.symbols.current_scope().kind == ScopeKind::File;
+        let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+        let is_static =
+            is_file_scope || is_extern || decl.specifiers.storage.contains(&StorageClass::Static);
+
+        for init in &decl.declarators {
+            let ty = self.declarator_type(base.clone(), &init.declarator);
+            self.types
+                .declarators
+                .insert(node_key(&init.declarator), ty.clone());
+            let name = match &init.declarator.name {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if decl.specifiers.is_typedef() || ty.is_function() {
+                if init.initializer.is_some() {
+                    self.error(
+                        Code::InvalidInitializer,
+                        &name.span,
+                        "illegal initializer (only variables can be initialized)",
+                    );
+                }
+                let mut binding = if decl.specifiers.is_typedef() {
+                    Binding::Typedef(ty)
+                } else {
+                    Binding::Object(ty)
+                };
+                if is_file_scope {
+                    binding = self.redeclare(name, binding);
+                }
+                self.symbols
+                    .insert(Namespace::Ordinary, name.value, binding);
+                continue;
+            }
+
+            let object = |ty| match is_static {
+                true => Binding::Object(ty),
+                false => Binding::Automatic(ty),
+            };
+            let mut binding = object(ty.clone());
+            if is_file_scope {
+                binding = self.redeclare(name, binding);
+            }
+            self.symbols
+                .insert(Namespace::Ordinary, name.value, binding);
+
+            match &init.initializer {
+                Some(initializer) => {
+                    let errors = self.errors.len();
+                    let ty = self.check_initializer(&ty, initializer);
+                    // the constants of an erroneous initializer are unknown
+                    if is_static && self.errors.len() == errors {
+                        self.check_static_initializer(&ty, initializer);
+                    }
+                    // arrays are either completed by their initializer or already reported
+                    if !ty.is_complete() && !ty.is_array() {
+                        self.error(Code::IncompleteType,
+                            &name.span,
+                            format!("variable has incomplete type '{}'", ty),
+                        );
+                    }
+                    self.types
+                        .declarators
+                        .insert(node_key(&init.declarator), ty.clone());
+                    self.symbols
+                        .insert(Namespace::Ordinary, name.value, object(ty));
+                }
+                None if is_extern || ty.is_complete() => {}
+                // a tentative definition at file scope may be completed later
+                None if is_file_scope && ty.is_array() => {}
+                None if ty.is_array() && !ty.is_void() => self.error(Code::IncompleteType,
+                    &name.span,
+                    "definition of variable with array type needs an explicit size or an initializer",
+                ),
+                None => self.error(Code::IncompleteType,
+                    &name.span,
+                    format!("variable has incomplete type '{}'", ty),
+                ),
+            }
+        }
+    }
+
+    // a file-scope name may be declared again only with a compatible type,
+    // C11 6.7p4; returns the binding to record, which keeps what the previous
+    // declaration knew, such as an array size
+    fn redeclare(&mut self, name: &Identifier, binding: Binding) -> Binding {
+        let prev_span = self.declared.insert(name.value, name.span);
+        // a function definition looks from its own scope, where no parameter is yet
+        let prev = match self.symbols.lookup(Namespace::Ordinary, name.value) {
+            Some(prev) => prev.clone(),
+            None => return binding,
+        };
+        let (prev_ty, ty) = match (&prev, &binding) {
+            (Binding::Typedef(a), Binding::Typedef(b)) if a.is_compatible(b) => return binding,
+            (Binding::Typedef(a), Binding::Typedef(b)) => (a, b),
+            // a different kind of symbol is the resolver's error
+            (Binding::Object(a), Binding::Object(b)) if a.is_function() == b.is_function() => {
+                if is_compatible_redeclaration(a, b) {
+                    return Binding::Object(composite(a, b));
+                }
+                (a, b)
+            }
+            _ => return binding,
+        };
+
+        let mut error =
+            Diagnostic::error(name.span, format!("conflicting types for '{}'", name.value))
+                .with_code(Code::ConflictingTypes);
+        if let Some(span) = prev_span {
+            error = error.with_secondary(
+                span,
+                format!(
+                    "previous declaration as '{}' is here, now '{}'",
+                    prev_ty, ty
+                ),
+            );
+        }
+        self.errors.push(error);
+        binding
+    }
+
+    fn base_type(&mut self, specifiers: &DeclSpecifiers) -> Type {
+        let mut words = Vec::new();
+        let mut named = None;
+        for ty in &specifiers.types {
+            let word = match ty {
+                TypeSpecifier::Void => "void",
+                TypeSpecifier::Char => "char",
+                TypeSpecifier::Short => "short",
+                TypeSpecifier::Int => "int",
+                TypeSpecifier::Long => "long",
+                TypeSpecifier::Float => "float",
+                TypeSpecifier::Double => "double",
+                TypeSpecifier::Signed => "signed",
+                TypeSpecifier::Unsigned => "unsigned",
+                TypeSpecifier::Bool => "_Bool",
+                TypeSpecifier::Complex => "_Complex",
+                TypeSpecifier::Struct(s) => {
+                    named = Some(self.record_type(s));
+                    continue;
+                }
+                TypeSpecifier::Enum(e) => {
+                    named = Some(self.enum_type(e));
+                    continue;
+                }
+                TypeSpecifier::TypedefName(ident) => {
+                    named = Some(
+                        match self.symbols.lookup(Namespace::Ordinary, ident.value) {
+                            Some(Binding::Typedef(ty)) => ty.clone(),
+                            _ => int(),
+                        },
+                    );
+                    continue;
+                }
+            };
+            words.push(word);
+        }
+        words.sort_unstable();
+
+        let kind = match (named, words.join(" ").as_str()) {
+            (Some(ty), "") => return ty.with_qualifiers(qualifiers(&specifiers.qualifiers)),
+            (Some(_), _) => None,
+            (None, "") => {
+                self.warning(
+                    &specifiers.span,
+                    "type specifier missing, defaults to 'int'",
+                );
+                Some(TypeKind::Int(IntKind::Int))
+            }
+            (None, key) => specifier_kind(key),
+        };
+        let kind = kind.unwrap_or_else(|| {
+            let msg = if words.contains(&"_Complex") {
+                "_Complex types are not supported"
+            } else {
+                "invalid combination of type specifiers"
+            };
+            self.error(Code::IncompleteType, &specifiers.span, msg);
+            TypeKind::Int(IntKind::Int)
+        });
+        Type::new(kind).with_qualifiers(qualifiers(&specifiers.qualifiers))
+    }
+
+    fn record_type(&mut self, s: &StructSpecifier) -> Type {
+        let existing = s.tag.as_ref().and_then(|tag| {
+            // a definition always introduces a new type in the current scope
+            let binding = if s.fields.is_some() {
+                self.symbols.lookup_current(Namespace::Tag, tag.value)
+            } else {
+                self.symbols.lookup(Namespace::Tag, tag.value)
+            };
+            match binding {
+                Some(Binding::Tag(Type {
+                    kind: TypeKind::Record(record),
+                    ..
+                })) if record.borrow().kind == s.kind
+                    && (s.fields.is_none() || record.borrow().fields.is_none()) =>
+                {
+                    Some(record.clone())
+                }
+                _ => None,
+            }
+        });
+
+        let record = existing.unwrap_or_else(|| {
+            let record = Rc::new(RefCell::new(Record {
+                kind: s.kind,
+                tag: s.tag.as_ref().map(|tag| tag.value),
+                fields: None,
+            }));
+            if let Some(tag) = &s.tag {
+                let ty = Type::new(TypeKind::Record(record.clone()));
+                self.symbols
+                    .insert(Namespace::Tag, tag.value, Binding::Tag(ty));
+            }
+            record
+        });
+
+        if let Some(fields) = &s.fields {
+            let fields = self.fields(fields);
+            record.borrow_mut().fields = Some(fields);
+        }
+        Type::new(TypeKind::Record(record))
+    }
+
+    fn fields(&mut self, fields: &[StructField]) -> Vec<Field> {
+        let mut result = Vec::new();
+        for field in fields {
+            let base = self.base_type(&field.specifiers);
+            if field.declarators.is_empty() && base.is_record() {
+                result.push(Field {
+                    name: None,
+                    ty: base.clone(),
+                    bit_width: None,
+                });
+            }
+            for d in &field.declarators {
+                let ty = match &d.declarator {
+                    Some(declarator) => self.declarator_type(base.clone(), declarator),
+                    None => base.clone(),
+                };
+                let name = d.declarator.as_ref().and_then(|d| d.name.as_ref());
+                if !ty.is_complete() {
+                    self.error(
+                        Code::IncompleteType,
+                        &d.span,
+                        format!("field has incomplete type '{}'", ty),
+                    );
+                    continue;
+                }
+                let bit_width = match &d.bit_width {
+                    Some(width) => {
+                        if !ty.is_integer() {
+                            self.error(
+                                Code::InvalidBitField,
+                                &d.span,
+                                format!("bit-field has non-integral type '{}'", ty),
+                            );
+                        }
+                        let bits = self.const_expr(width).unwrap_or(0);
+                        if bits < 0 || bits as u64 > ty.size().unwrap_or(0) * 8 {
+                            self.error(
+                                Code::InvalidBitField,
+                                &width.span,
+                                format!("invalid bit-field width {} for type '{}'", bits, ty),
+                            );
+                        }
+                        Some(bits as u64)
+                    }
+                    None => None,
+                };
+                result.push(Field {
+                    name: name.map(|n| n.value),
+                    ty,
+                    bit_width,
+                });
+            }
+        }
+        result
+    }
+
+    fn enum_type(&mut self, e: &EnumSpecifier) -> Type {
+        let existing =
+            e.tag
+                .as_ref()
+                .and_then(|tag| match self.symbols.lookup(Namespace::Tag, tag.value) {
+                    Some(Binding::Tag(
+                        ty @ Type {
+                            kind: TypeKind::Enum(_),
+                            ..
+                        },
+                    )) if e.enumerators.is_none() => Some(ty.clone()),
+                    _ => None,
+                });
+        if let Some(ty) = existing {
+            return ty;
+        }
+
+        let ty = Type::new(TypeKind::Enum(Rc::new(RefCell::new(EnumType {
+            tag: e.tag.as_ref().map(|tag| tag.value),
+            is_complete: e.enumerators.is_some(),
+        }))));
+        if let Some(tag) = &e.tag {
+            self.symbols
+                .insert(Namespace::Tag, tag.value, Binding::Tag(ty.clone()));
+        }
+
+        let mut next = 0;
+        for enumerator in e.enumerators.iter().flatten() {
+            if let Some(value) = &enumerator.value {
+                next = self.const_expr(value).unwrap_or(next);
+            }
+            if next < IntKind::Int.min() as i64 || next > IntKind::Int.max() as i64 {
+                self.error(
+                    Code::EnumeratorOverflow,
+                    &enumerator.span,
+                    "enumerator value is not representable in 'int'",
+                );
+            }
+            self.symbols.insert(
+                Namespace::Ordinary,
+                enumerator.name.value,
+                Binding::EnumConstant(next),
+            );
+            next = next.wrapping_add(1);
+        }
+        ty
+    }
+
+    fn declarator_type(&mut self, base: Type, declarator: &Declarator) -> Type {
+        let mut ty = base;
+        for d in declarator.derived.iter().rev() {
+            ty = self.apply_derived(ty, d);
+        }
+        ty
+    }
+
+    fn apply_derived(&mut self, ty: Type, derived: &DerivedDeclarator) -> Type {
+        match derived {
+            DerivedDeclarator::Pointer(quals) => {
+                Type::pointer_to(ty).with_qualifiers(qualifiers(quals))
+            }
+            DerivedDeclarator::Array(size) => {
+                if !ty.is_complete() {
+                    // the error is reported against the size or, lacking one, left to the user
+                    let span = size.as_ref().map(|s| s.span);
+                    if let Some(span) = span {
+                        self.error(
+                            Code::IncompleteType,
+                            &span,
+                            format!("array has incomplete element type '{}'", ty),
+                        );
+                    }
+                }
+                let size = size.as_ref().and_then(|size| {
+                    let n = self.const_expr(size)?;
+                    if n < 0 {
+                        self.error(
+                            Code::InvalidDeclarator,
+                            &size.span,
+                            "array size is negative",
+                        );
+                        return None;
+                    }
+                    Some(n as u64)
+                });
+                Type::new(TypeKind::Array(Box::new(ty), size))
+            }
+            DerivedDeclarator::Function(func) => {
+                self.symbols.push_scope(ScopeKind::Prototype);
+                let params = self.params(func).into_iter().map(|(_, ty)| ty).collect();
+                self.symbols.pop_scope();
+                Type::new(TypeKind::Function(FunctionType {
+                    ret: Box::new(ty),
+                    params,
+                    variadic: func.variadic,
+                }))
+            }
+        }
+    }
+
+    // parameter types after the array and function to pointer adjustment
+    fn params(&mut self, func: &FunctionDeclarator) -> Vec<(Option<Identifier>, Type)> {
+        let mut params = Vec::new();
+        for param in &func.params {
+            let base = self.base_type(&param.specifiers);
+            let ty = self.declarator_type(base, &param.declarator);
+            let ty = match &ty.kind {
+                TypeKind::Array(elem, _) => {
+                    Type::pointer_to((**elem).clone()).with_qualifiers(ty.qualifiers)
+                }
+                TypeKind::Function(_) => Type::pointer_to(ty),
+                _ => ty,
+            };
+            if ty.is_void() {
+                self.error(
+                    Code::InvalidDeclarator,
+                    &param.span,
+                    "'void' must be the first and only parameter",
+                );
+            }
+            self.types
+                .declarators
+                .insert(node_key(&param.declarator), ty.clone());
+            params.push((param.declarator.name.clone(), ty));
+        }
+        params
+    }
+
+    fn type_name(&mut self, type_name: &TypeName) -> Type {
+        let base = self.base_type(&type_name.specifiers);
+        let ty = self.declarator_type(base, &type_name.declarator);
+        self.types
+            .type_names
+            .insert(node_key(type_name), ty.clone());
+        ty
+    }
+}
+
+fn specifier_kind(key: &str) -> Option<TypeKind> {
+    let kind = match key {
+        "void" => return Some(TypeKind::Void),
+        "float" => return Some(TypeKind::Float(FloatKind::Float)),
+        "double" => return Some(TypeKind::Float(FloatKind::Double)),
+        "double long" => return Some(TypeKind::Float(FloatKind::LongDouble)),
+        "_Bool" => IntKind::Bool,
+        "char" => IntKind::Char,
+        "char signed" => IntKind::SChar,
+        "char unsigned" => IntKind::UChar,
+        "short" | "short signed" | "int short" | "int short signed" => IntKind::Short,
+        "short unsigned" | "int short unsigned" => IntKind::UShort,
+        "int" | "signed" | "int signed" => IntKind::Int,
+        "unsigned" | "int unsigned" => IntKind::UInt,
+        "long" | "long signed" | "int long" | "int long signed" => IntKind::Long,
+        "long unsigned" | "int long unsigned" => IntKind::ULong,
+        "long long" | "long long signed" | "int long long" | "int long long signed" => {
+            IntKind::LongLong
+        }
+        "long long unsigned" | "int long long unsigned" => IntKind::ULongLong,
+        _ => return None,
+    };
+    Some(TypeKind::Int(kind))
+}
+
+// initializers
+impl Checker {
+    // returns the declared type, completed if it was an array of unknown size
+    fn check_initializer(&mut self, ty: &Type, init: &Initializer) -> Type {
+        match init {
+            Initializer::Expr(e) => {
+                if let TypeKind::Array(elem, size) = &ty.kind {
+                    return match &e.kind {
+                        ExprKind::StringLiteral(s)
+                            if elem.int_kind().is_some_and(|k| s.encoding.initializes(k)) =>
+                        {
+                            let len = s.value.len() as u64;
+                            if size.is_some_and(|size| size < len) {
+                                self.warning(
+                                    &e.span,
+                                    "initializer-string for char array is too long",
+                                );
+                            }
+                            Type::new(TypeKind::Array(elem.clone(), Some(size.unwrap_or(len + 1))))
+                                .with_qualifiers(ty.qualifiers)
+                        }
+                        _ => {
+                            self.error(
+                                Code::InvalidInitializer,
+                                &e.span,
+                                "array initializer must be an initializer list",
+                            );
+                            ty.clone()
+                        }
+                    };
+                }
+                if let Some(info) = self.check_expr(e) {
+                    self.check_conversion(ty, &info, &e.span, Conversion::Initialize);
+                }
+                ty.clone()
+            }
+            Initializer::List(items, span) => {
+                if ty.is_aggregate() {
+                    let mut pos = 0;
+                    let count = self.check_aggregate(ty, items, &mut pos, true);
+                    return match &ty.kind {
+                        TypeKind::Array(elem, None) => {
+                            Type::new(TypeKind::Array(elem.clone(), Some(count)))
+                                .with_qualifiers(ty.qualifiers)
+                        }
+                        _ => ty.clone(),
+                    };
+                }
+                match items.first() {
+                    None => self.error(
+                        Code::InvalidInitializer,
+                        span,
+                        "scalar initializer cannot be empty",
+                    ),
+                    Some(item) if !item.designators.is_empty() => self.error(
+                        Code::InvalidInitializer,
+                        span,
+                        format!("designator in initializer for scalar type '{}'", ty),
+                    ),
+                    Some(item) => {
+                        self.check_initializer(ty, &item.init);
+                    }
+                }
+                if let Some(item) = items.get(1) {
+                    self.warning(
+                        initializer_span(&item.init),
+                        "excess elements in scalar initializer",
+                    );
+                }
+                ty.clone()
+            }
+        }
+    }
+
+    // the initializer of an object with static storage duration must be made
+    // of arithmetic and address constants, C11 6.7.9p4
+    fn check_static_initializer(&mut self, ty: &Type, init: &Initializer) {
+        let constants = initializer::constants(&self.types, ty, init);
+        let not_constant = constants.and_then(|targets| {
+            let automatic = targets.into_iter().find(|(target, _)| match target {
+                Target::Symbol(name) => matches!(
+                    self.symbols.lookup(Namespace::Ordinary, *name),
+                    Some(Binding::Automatic(_))
+                ),
+                Target::String(_) => false,
+            });
+            match automatic {
+                Some((_, e)) => Err(e),
+                None => Ok(()),
+            }
+        });
+        if let Err(e) = not_constant {
+            self.error(
+                Code::NotConstant,
+                &e.span,
+                "initializer element is not a compile-time constant",
+            );
+        }
+    }
+
+    // checks the items of a braced (or, with brace elision, implicit) list
+    // starting at `pos` and returns how many array elements were initialized
+    fn check_aggregate(
+        &mut self,
+        ty: &Type,
+        items: &[InitItem],
+        pos: &mut usize,
+        braced: bool,
+    ) -> u64 {
+        let (elem, size, fields) = match &ty.kind {
+            TypeKind::Array(elem, size) => ((**elem).clone(), *size, Vec::new()),
+            TypeKind::Record(record) => {
+                let record = record.borrow();
+                let mut fields = record.fields.clone().unwrap_or_default();
+                if record.kind == StructKind::Union {
+                    fields.truncate(1);
+                }
+                (ty.clone(), None, fields)
+            }
+            _ => unreachable!(),
+        };
+        let is_array = ty.is_array();
+
+        let mut idx = 0;
+        let mut count = 0;
+        while let Some(item) = items.get(*pos) {
+            if let Some(designator) = item.designators.first() {
+                if !braced {
+                    break;
+                }
+                *pos += 1;
+                let target = match self.designated(ty, designator) {
+                    Some((i, target)) => {
+                        idx = i;
+                        target
+                    }
+                    None => continue,
+                };
+                self.check_designated(&target, &item.designators[1..], &item.init);
+            } else {
+                let target = if is_array {
+                    if size.is_some_and(|size| idx >= size) {
+                        None
+                    } else {
+                        Some(elem.clone())
+                    }
+                } else {
+                    fields.get(idx as usize).map(|f| f.ty.clone())
+                };
+                let target = match target {
+                    Some(target) => target,
+                    None if braced => {
+                        let kind = if is_array { "array" } else { "struct" };
+                        self.warning(
+                            initializer_span(&item.init),
+                            format!("excess elements in {} initializer", kind),
+                        );
+                        *pos = items.len();
+                        break;
+                    }
+                    None => break,
+                };
+                self.check_element(&target, items, pos);
+            }
+            idx += 1;
+            count = count.max(idx);
+        }
+        count
+    }
+
+    fn check_element(&mut self, ty: &Type, items: &[InitItem], pos: &mut usize) {
+        let item = &items[*pos];
+        let e = match &item.init {
+            Initializer::Expr(e) if ty.is_aggregate() => e,
+            init => {
+                *pos += 1;
+                self.check_initializer(ty, init);
+                return;
+            }
+        };
+
+        let is_direct = match &e.kind {
+            ExprKind::StringLiteral(_) => ty.is_array(),
+            _ if ty.is_record() => {
+                let info = match self.check_expr(e) {
+                    Some(info) => info,
+                    None => {
+                        *pos += 1;
+                        return;
+                    }
+                };
+                info.ty.is_compatible_unqualified(ty)
+            }
+            _ => false,
+        };
+        if is_direct {
+            *pos += 1;
+            self.check_initializer(ty, &item.init);
+        } else {
+            self.check_aggregate(ty, items, pos, false);
+        }
+    }
+
+    fn designated(&mut self, ty: &Type, designator: &Designator) -> Option<(u64, Type)> {
+        match (&ty.kind, designator) {
+            (TypeKind::Array(elem, size), Designator::Index(e)) => {
+                let idx = self.const_expr(e)?;
+                if idx < 0 || size.is_some_and(|size| idx as u64 >= size) {
+                    self.error(
+                        Code::InvalidInitializer,
+                        &e.span,
+                        format!("array designator index ({}) exceeds array bounds", idx),
+                    );
+                    return None;
+                }
+                Some((idx as u64, (**elem).clone()))
+            }
+            (TypeKind::Record(record), Designator::Member(name)) => {
+                let record = record.borrow();
+                let fields = record.fields.iter().flatten();
+                let found = fields.enumerate().find(|(_, f)| f.name == Some(name.value));
+                match found {
+                    Some((i, field)) => Some((i as u64, field.ty.clone())),
+                    None => {
+                        drop(record);
+                        self.error(
+                            Code::InvalidInitializer,
+                            &name.span,
+                            format!(
+                                "field designator '{}' does not refer to any field in type '{}'",
+                                name.value, ty
+                            ),
+                        );
+                        None
+                    }
+                }
+            }
+            (_, Designator::Index(e)) => {
+                self.error(
+                    Code::InvalidInitializer,
+                    &e.span,
+                    format!("array designator cannot initialize non-array type '{}'", ty),
+                );
+                None
+            }
+            (_, Designator::Member(name)) => {
+                self.error(
+                    Code::InvalidInitializer,
+                    &name.span,
+                    format!(
+                        "field designator cannot initialize a non-struct, non-union type '{}'",
+                        ty
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_designated(&mut self, ty: &Type, designators: &[Designator], init: &Initializer) {
+        match designators.split_first() {
+            None => {
+                self.check_initializer(ty, init);
+            }
+            Some((designator, rest)) => {
+                if let Some((_, target)) = self.designated(ty, designator) {
+                    self.check_designated(&target, rest, init);
+                }
+            }
+        }
+    }
+}
+
+// `()` and `(void)` parse alike, so an empty parameter list is taken to be
+// one without a prototype, which is compatible with any
+fn is_compatible_redeclaration(prev: &Type, new: &Type) -> bool {
+    let is_unprototyped = |f: &FunctionType| f.params.is_empty() && !f.variadic;
+    match (&prev.kind, &new.kind) {
+        (TypeKind::Function(a), TypeKind::Function(b))
+            if is_unprototyped(a) || is_unprototyped(b) =>
+        {
+            a.ret.is_compatible(&b.ret)
+        }
+        _ => prev.is_compatible(new),
+    }
+}
+
+// of two compatible declarations, C11 6.2.7p3, as far as calls and sizes go
+fn composite(prev: &Type, new: &Type) -> Type {
+    match (&prev.kind, &new.kind) {
+        (TypeKind::Array(_, Some(_)), TypeKind::Array(_, None)) => prev.clone(),
+        (TypeKind::Function(_), TypeKind::Function(f)) if f.params.is_empty() && !f.variadic => {
+            prev.clone()
+        }
+        _ => new.clone(),
+    }
+}
+
+fn initializer_span(init: &Initializer) -> &Span {
+    match init {
+        Initializer::Expr(e) => &e.span,
+        Initializer::List(_, span) => span,
+    }
+}
+
+// statements
+impl Checker {
+    fn check_block_item(&mut self, item: &BlockItem) {
+        match item {
+            BlockItem::Declaration(decl) => self.check_declaration(decl),
+            BlockItem::Stmt(stmt) => self.check_stmt(stmt),
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Compound(block) => {
+                self.symbols.push_scope(ScopeKind::Block);
+                for item in &block.items {
+                    self.check_block_item(item);
+                }
+                self.symbols.pop_scope();
+            }
+            StmtKind::Expr(e) => {
+                if let Some(e) = e {
+                    self.check_expr(e);
+                }
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                self.check_condition(cond);
+                self.check_stmt(then);
+                if let Some(otherwise) = otherwise {
+                    self.check_stmt(otherwise);
+                }
+            }
+            StmtKind::While(cond, body) => {
+                self.check_condition(cond);
+                self.check_loop_body(body);
+            }
+            StmtKind::DoWhile(body, cond) => {
+                self.check_loop_body(body);
+                self.check_condition(cond);
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.symbols.push_scope(ScopeKind::Block);
+                match init {
+                    ForInit::Empty => {}
+                    ForInit::Expr(e) => {
+                        self.check_expr(e);
+                    }
+                    ForInit::Declaration(decl) => self.check_declaration(decl),
+                }
+                if let Some(cond) = cond {
+                    self.check_condition(cond);
+                }
+                if let Some(step) = step {
+                    self.check_expr(step);
+                }
+                self.check_loop_body(body);
+                self.symbols.pop_scope();
+            }
+            StmtKind::Switch(cond, body) => {
+                let mut labels = SwitchLabels::default();
+                if let Some(info) = self.check_expr(cond) {
+                    if info.ty.is_integer() {
+                        labels.ty = Some(promote(&info.ty));
+                    } else {
+                        self.error(
+                            Code::InvalidOperands,
+                            &cond.span,
+                            format!(
+                                "statement requires expression of integer type ('{}' invalid)",
+                                info.ty
+                            ),
+                        );
+                    }
+                }
+                self.switches.push(labels);
+                self.check_stmt(body);
+                self.switches.pop();
+            }
+            StmtKind::Case(value, body) => {
+                let v = self.const_expr(value);
+                match (self.switches.last(), v) {
+                    (None, _) => self.misplaced(stmt),
+                    (Some(labels), Some(v)) => {
+                        let v = match &labels.ty {
+                            Some(ty) => {
+                                match convert_value(ConstValue::Int(v), self.types.expr(value), ty)
+                                {
+                                    Some(ConstValue::Int(v)) => v,
+                                    _ => v,
+                                }
+                            }
+                            None => v,
+                        };
+                        let labels = self.switches.last_mut().unwrap();
+                        if let Some(prev) = labels.cases.insert(v, value.span) {
+                            self.errors.push(
+                                Diagnostic::error(
+                                    value.span,
+                                    format!("duplicate case value '{}'", v),
+                                )
+                                .with_code(Code::DuplicateCase)
+                                .with_secondary(prev, "previous case defined here"),
+                            );
+                        }
+                    }
+                    (Some(_), None) => {}
+                }
+                self.check_stmt(body);
+            }
+            StmtKind::Default(body) => {
+                match self.switches.last_mut() {
+                    None => self.misplaced(stmt),
+                    Some(labels) => {
+                        let span = stmt.span;
+                        if let Some(prev) = labels.default.replace(span) {
+                            self.errors.push(
+                                Diagnostic::error(span, "multiple default labels in one switch")
+                                    .with_code(Code::DuplicateCase)
+                                    .with_secondary(prev, "previous case defined here"),
+                            );
+                        }
+                    }
+                }
+                self.check_stmt(body);
+            }
+            StmtKind::Labeled(_, body) => self.check_stmt(body),
+            StmtKind::Goto(_) => {}
+            StmtKind::Continue if self.loops == 0 => self.misplaced(stmt),
+            StmtKind::Break if self.loops == 0 && self.switches.is_empty() => self.misplaced(stmt),
+            StmtKind::Continue | StmtKind::Break => {}
+            StmtKind::Return(e) => self.check_return(e.as_ref(), &stmt.span),
+        }
+    }
+
+    fn check_loop_body(&mut self, body: &Stmt) {
+        self.loops += 1;
+        self.check_stmt(body);
+        self.loops -= 1;
+    }
+
+    // a `break`, `continue`, `case` or `default` outside what it belongs to
+    fn misplaced(&mut self, stmt: &Stmt) {
+        self.error(Code::OutsideLoopOrSwitch, &stmt.span, stmt.misplaced_msg());
+    }
+
+    fn check_condition(&mut self, cond: &Expr) {
+        if let Some(info) = self.check_expr(cond) {
+            let ty = info.ty.decay();
+            if !ty.is_scalar() {
+                self.error(
+                    Code::InvalidOperands,
+                    &cond.span,
+                    format!(
+                        "statement requires expression of scalar type ('{}' invalid)",
+                        info.ty
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_return(&mut self, e: Option<&Expr>, span: &Span) {
+        let (name, ret) = match &self.function {
+            Some(function) => function.clone(),
+            None => return,
+        };
+        match e {
+            Some(e) => {
+                let info = match self.check_expr(e) {
+                    Some(info) => info,
+                    None => return,
+                };
+                if ret.is_void() {
+                    if !info.ty.is_void() {
+                        self.error(
+                            Code::IncompatibleTypes,
+                            &e.span,
+                            format!("void function '{}' should not return a value", name),
+                        );
+                    }
+                } else {
+                    self.check_conversion(&ret, &info, &e.span, Conversion::Return);
+                }
+            }
+            None if !ret.is_void() => self.warning(
+                span,
+                format!("non-void function '{}' should return a value", name),
+            ),
+            None => {}
+        }
+    }
+}
+
+// conversions
+impl Checker {
+    // checks that `info` can be converted to `target` as if by assignment (C11 6.5.16.1)
+    fn check_conversion(
+        &mut self,
+        target: &Type,
+        info: &ExprInfo,
+        span: &Span,
+        conversion: Conversion,
+    ) {
+        let source = info.ty.decay();
+        let target = target.unqualified();
+
+        if target.is_arithmetic() && source.is_arithmetic() {
+            return self.check_arithmetic_conversion(&target, &source, info.value, span);
+        }
+        if target.int_kind() == Some(IntKind::Bool) && source.is_pointer() {
+            return;
+        }
+        if target.is_record() && source.is_compatible_unqualified(&target) {
+            return;
+        }
+
+        match (target.pointee(), source.pointee()) {
+            (Some(_), _) if is_null_pointer_constant(info) => {}
+            (Some(to), Some(from)) => {
+                let describe = describe_conversion(conversion, &target, &source);
+                if !to.qualifiers.contains(from.qualifiers) {
+                    self.warning(span, format!("{} discards qualifiers", describe));
+                } else if !(to.is_void() && !from.is_function()
+                    || from.is_void() && !to.is_function()
+                    || to.is_compatible_unqualified(from))
+                {
+                    self.warning(span, format!("incompatible pointer types {}", describe));
+                }
+            }
+            (Some(_), None) if source.is_integer() => {
+                let describe = describe_conversion(conversion, &target, &source);
+                self.warning(
+                    span,
+                    format!("incompatible integer to pointer conversion {}", describe),
+                );
+            }
+            (None, Some(_)) if target.is_integer() => {
+                let describe = describe_conversion(conversion, &target, &source);
+                self.warning(
+                    span,
+                    format!("incompatible pointer to integer conversion {}", describe),
+                );
+            }
+            _ => {
+                let msg = match conversion {
+                    Conversion::Assign => {
+                        format!(
+                            "assigning to '{}' from incompatible type '{}'",
+                            target, source
+                        )
+                    }
+                    Conversion::Initialize => format!(
+                        "initializing '{}' with an expression of incompatible type '{}'",
+                        target, source
+                    ),
+                    Conversion::Pass => format!(
+                        "passing '{}' to parameter of incompatible type '{}'",
+                        source, target
+                    ),
+                    Conversion::Return => format!(
+                        "returning '{}' from a function with incompatible result type '{}'",
+                        source, target
+                    ),
+                };
+                self.error(Code::IncompatibleTypes, span, msg);
+            }
+        }
+    }
+
+    fn check_arithmetic_conversion(
+        &mut self,
+        target: &Type,
+        source: &Type,
+        value: Option<ConstValue>,
+        span: &Span,
+    ) {
+        let kind = match target.int_kind() {
+            Some(kind) if kind != IntKind::Bool => kind,
+            _ => return,
+        };
+        match value {
+            Some(ConstValue::Float(v)) if source.is_floating() => {
+                // through `u64` for unsigned targets, as `v as i64` saturates above `i64::MAX`
+                let converted = match kind.is_signed() {
+                    true => kind.wrap(v as i64) as i128,
+                    false => kind.wrap(v as u64 as i64) as u64 as i128,
+                };
+                if v.trunc() != v || converted as f64 != v.trunc() {
+                    self.warning(
+                        span,
+                        format!(
+                            "implicit conversion from '{}' to '{}' changes value from {} to {}",
+                            source, target, v, converted
+                        ),
+                    );
+                }
+            }
+            _ if source.is_floating() => self.warning(
+                span,
+                format!(
+                    "implicit conversion turns floating-point number into integer: '{}' to '{}'",
+                    source, target
+                ),
+            ),
+            Some(ConstValue::Int(v)) => {
+                let v = match source.int_kind() {
+                    Some(k) if !k.is_signed() => v as u64 as i128,
+                    _ => v as i128,
+                };
+                // only values that do not fit in the width at all, `unsigned x = -1;` is fine
+                let bits = kind.size() * 8;
+                if v < -(1 << (bits - 1)) || v > (1 << bits) - 1 {
+                    self.warning(
+                        span,
+                        format!(
+                            "implicit conversion from '{}' to '{}' changes value from {} to {}",
+                            source,
+                            target,
+                            v,
+                            kind.wrap(v as i64)
+                        ),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_null_pointer_constant(info: &ExprInfo) -> bool {
+    let is_void_pointer = info.ty.pointee().is_some_and(|ty| ty.is_void());
+    (info.ty.is_integer() || is_void_pointer) && info.value == Some(ConstValue::Int(0))
+}
+
+fn describe_conversion(conversion: Conversion, target: &Type, source: &Type) -> String {
+    match conversion {
+        Conversion::Assign => format!("assigning to '{}' from '{}'", target, source),
+        Conversion::Initialize => format!(
+            "initializing '{}' with an expression of type '{}'",
+            target, source
+        ),
+        Conversion::Pass => format!("passing '{}' to parameter of type '{}'", source, target),
+        Conversion::Return => format!(
+            "returning '{}' from a function with result type '{}'",
+            source, target
+        ),
+    }
+}
+
+pub fn convert_value(value: ConstValue, from: &Type, to: &Type) -> Option<ConstValue> {
+    match (value, to.int_kind(), &to.kind) {
+        (ConstValue::Int(v), Some(kind), _) => Some(ConstValue::Int(kind.wrap(v))),
+        (ConstValue::Float(v), Some(IntKind::Bool), _) => Some(ConstValue::Int((v != 0.0) as i64)),
+        (ConstValue::Float(v), Some(kind), _) if !kind.is_signed() => {
+            Some(ConstValue::Int(kind.wrap(v as u64 as i64)))
+        }
+        (ConstValue::Float(v), Some(kind), _) => Some(ConstValue::Int(kind.wrap(v as i64))),
+        (ConstValue::Int(v), None, TypeKind::Float(kind)) => {
+            let v = match from.int_kind() {
+                Some(k) if !k.is_signed() => v as u64 as f64,
+                _ => v as f64,
+            };
+            Some(ConstValue::Float(round_float(v, *kind)))
+        }
+        (ConstValue::Float(v), None, TypeKind::Float(kind)) => {
+            Some(ConstValue::Float(round_float(v, *kind)))
+        }
+        (ConstValue::Int(v), None, TypeKind::Pointer(_)) => Some(ConstValue::Int(v)),
+        _ => None,
+    }
+}
+
+fn round_float(v: f64, kind: FloatKind) -> f64 {
+    match kind {
+        FloatKind::Float => v as f32 as f64,
+        _ => v,
+    }
+}
+
+// expressions
+impl Checker {
+    fn const_expr(&mut self, expr: &Expr) -> Option<i64> {
+        let info = self.check_expr(expr)?;
+        match info.value {
+            Some(ConstValue::Int(v)) if info.ty.is_integer() => Some(v),
+            _ => {
+                self.error(
+                    Code::NotConstant,
+                    &expr.span,
+                    "expression is not an integer constant expression",
+                );
+                None
+            }
+        }
+    }
+
+    // `None` means an error has been reported already
+    fn check_expr(&mut self, expr: &Expr) -> Option<ExprInfo> {
+        let info = self.check_expr_kind(expr)?;
+        let entry = (info.ty.clone(), info.value);
+        self.types.exprs.insert(node_key(expr), entry);
+        Some(info)
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Option<ExprInfo> {
+        let span = &expr.span;
+        match &expr.kind {
+            ExprKind::Identifier(ident) => {
+                match self.symbols.lookup(Namespace::Ordinary, ident.value)? {
+                    Binding::Object(ty) if ty.is_function() => Some(rvalue(ty.clone())),
+                    Binding::Object(ty) | Binding::Automatic(ty) => Some(lvalue(ty.clone())),
+                    Binding::EnumConstant(v) => Some(constant(int(), ConstValue::Int(*v))),
+                    Binding::Typedef(_) | Binding::Tag(_) => {
+                        self.error(
+                            Code::UnexpectedTypeName,
+                            span,
+                            format!("unexpected type name '{}'", ident.value),
+                        );
+                        None
+                    }
+                }
+            }
+            ExprKind::Constant(c) => self.check_constant(c),
+            ExprKind::StringLiteral(s) => {
+                let len = s.value.len() as u64 + 1;
+                let ty = Type::new(TypeKind::Array(
+                    Box::new(Type::int(s.encoding.unit_kind())),
+                    Some(len),
+                ));
+                Some(lvalue(ty))
+            }
+            ExprKind::Unary(op, operand) => self.check_unary(*op, operand, span),
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.check_expr(lhs)?;
+                let rhs = self.check_expr(rhs)?;
+                self.check_binary(*op, lhs, rhs, span)
+            }
+            ExprKind::Assign(op, lhs, rhs) => {
+                let target = self.check_expr(lhs)?;
+                let value = self.check_expr(rhs)?;
+                self.check_assignable(&target, &lhs.span)?;
+                match op.binary_op() {
+                    None => {
+                        self.check_conversion(&target.ty, &value, &rhs.span, Conversion::Assign)
+                    }
+                    Some(op) => {
+                        let result = self.check_binary(op, target.clone(), value, span)?;
+                        if !result.ty.is_pointer() || !target.ty.is_pointer() {
+                            self.check_conversion(
+                                &target.ty,
+                                &result,
+                                &rhs.span,
+                                Conversion::Assign,
+                            );
+                        }
+                    }
+                }
+                Some(rvalue(target.ty.unqualified()))
+            }
+            ExprKind::Conditional(cond, then, otherwise) => {
+                let cond_info = self.check_expr(cond)?;
+                let then = self.check_expr(then)?;
+                let otherwise = self.check_expr(otherwise)?;
+                if !cond_info.ty.decay().is_scalar() {
+                    self.error(
+                        Code::InvalidOperands,
+                        &cond.span,
+                        format!(
+                            "used type '{}' where arithmetic or pointer type is required",
+                            cond_info.ty
+                        ),
+                    );
+                    return None;
+                }
+                self.check_conditional(cond_info.value, then, otherwise, span)
+            }
+            ExprKind::Comma(exprs) => {
+                let mut last = None;
+                for e in exprs {
+                    last = self.check_expr(e);
+                }
+                let last = last?;
+                Some(rvalue(last.ty.decay()))
+            }
+            ExprKind::Cast(type_name, operand) => {
+                let target = self.type_name(type_name);
+                let info = self.check_expr(operand)?;
+                self.check_cast(&target, info, span)
+            }
+            ExprKind::SizeofExpr(operand) => {
+                let info = self.check_expr(operand)?;
+                self.check_sizeof(&info.ty, span)
+            }
+            ExprKind::SizeofType(type_name) => {
+                let ty = self.type_name(type_name);
+                self.check_sizeof(&ty, span)
+            }
+            ExprKind::Call(callee, args) => self.check_call(callee, args, span),
+            ExprKind::Index(lhs, rhs) => {
+                let lhs = self.check_expr(lhs)?.ty.decay();
+                let rhs = self.check_expr(rhs)?.ty.decay();
+                let (pointer, index) = if lhs.is_pointer() {
+                    (lhs, rhs)
+                } else {
+                    (rhs, lhs)
+                };
+                let elem = match pointer.pointee() {
+                    Some(elem) => elem.clone(),
+                    None => {
+                        self.error(
+                            Code::InvalidOperands,
+                            span,
+                            "subscripted value is not an array or pointer",
+                        );
+                        return None;
+                    }
+                };
+                if !index.is_integer() {
+                    self.error(
+                        Code::InvalidOperands,
+                        span,
+                        "array subscript is not an integer",
+                    );
+                    return None;
+                }
+                if !elem.is_complete() {
+                    self.error(
+                        Code::IncompleteType,
+                        span,
+                        format!("subscript of pointer to incomplete type '{}'", elem),
+                    );
+                    return None;
+                }
+                Some(lvalue(elem))
+            }
+            ExprKind::Member(base, member) => {
+                let info = self.check_expr(base)?;
+                let ty = self.member_type(&info.ty, member)?;
+                let ty = ty.with_qualifiers(info.ty.qualifiers);
+                Some(ExprInfo {
+                    ty,
+                    is_lvalue: info.is_lvalue,
+                    value: None,
+                })
+            }
+            ExprKind::Arrow(base, member) => {
+                let info = self.check_expr(base)?;
+                let pointer = info.ty.decay();
+                let record = match pointer.pointee() {
+                    Some(record) => record.clone(),
+                    None => {
+                        self.error(
+                            Code::InvalidMember,
+                            &base.span,
+                            format!("member reference type '{}' is not a pointer", info.ty),
+                        );
+                        return None;
+                    }
+                };
+                let ty = self.member_type(&record, member)?;
+                Some(lvalue(ty.with_qualifiers(record.qualifiers)))
+            }
+        }
+    }
+
+    fn check_constant(&mut self, c: &Constant) -> Option<ExprInfo> {
+        match c {
+            Constant::Int(int) => Some(constant(
+                Type::int(int.ty),
+                ConstValue::Int(int.value as i64),
+            )),
+            Constant::Float(float) => Some(constant(
+                Type::new(TypeKind::Float(float.ty())),
+                ConstValue::Float(float.value),
+            )),
+            Constant::Char(ch) => Some(constant(
+                Type::int(ch.encoding.char_kind()),
+                ConstValue::Int(ch.value),
+            )),
+        }
+    }
+
+    fn check_assignable(&mut self, info: &ExprInfo, span: &Span) -> Option<()> {
+        let msg = if !info.is_lvalue {
+            "expression is not assignable".to_owned()
+        } else if info.ty.is_array() {
+            format!("array type '{}' is not assignable", info.ty)
+        } else if info.ty.qualifiers.is_const {
+            format!(
+                "cannot assign to expression with const-qualified type '{}'",
+                info.ty
+            )
+        } else if !info.ty.is_complete() {
+            format!("incomplete type '{}' is not assignable", info.ty)
+        } else {
+            return Some(());
+        };
+        self.error(Code::NotAssignable, span, msg);
+        None
+    }
+
+    fn check_unary(&mut self, op: UnaryOp, operand: &Expr, span: &Span) -> Option<ExprInfo> {
+        let info = self.check_expr(operand)?;
+        let ty = info.ty.decay();
+        let invalid = |checker: &mut Self| {
+            checker.error(
+                Code::InvalidOperands,
+                span,
+                format!("invalid argument type '{}' to unary expression", info.ty),
+            );
+            None
+        };
+
+        match op {
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                self.check_assignable(&info, &operand.span)?;
+                if !ty.is_scalar() {
+                    let verb = match op {
+                        UnaryOp::PreInc | UnaryOp::PostInc => "increment",
+                        _ => "decrement",
+                    };
+                    self.error(
+                        Code::InvalidOperands,
+                        span,
+                        format!("cannot {} value of type '{}'", verb, info.ty),
+                    );
+                    return None;
+                }
+                Some(rvalue(ty))
+            }
+            UnaryOp::Address => {
+                if info.ty.is_function() || info.is_lvalue {
+                    Some(rvalue(Type::pointer_to(info.ty)))
+                } else {
+                    self.error(
+                        Code::InvalidOperands,
+                        span,
+                        format!("cannot take the address of an rvalue of type '{}'", info.ty),
+                    );
+                    None
+                }
+            }
+            UnaryOp::Deref => match ty.pointee() {
+                Some(pointee) => Some(ExprInfo {
+                    ty: pointee.clone(),
+                    is_lvalue: !pointee.is_function(),
+                    value: None,
+                }),
+                None => {
+                    self.error(
+                        Code::InvalidOperands,
+                        span,
+                        format!(
+                            "indirection requires pointer operand ('{}' invalid)",
+                            info.ty
+                        ),
+                    );
+                    None
+                }
+            },
+            UnaryOp::Plus | UnaryOp::Minus => {
+                if !ty.is_arithmetic() {
+                    return invalid(self);
+                }
+                let result = promote(&ty);
+                let value = info.value.and_then(|v| convert_value(v, &ty, &result));
+                let value = match (op, value) {
+                    (UnaryOp::Minus, Some(ConstValue::Int(v))) => {
+                        Some(ConstValue::Int(result.int_kind()?.wrap(v.wrapping_neg())))
+                    }
+                    (UnaryOp::Minus, Some(ConstValue::Float(v))) => Some(ConstValue::Float(-v)),
+                    (_, value) => value,
+                };
+                Some(ExprInfo {
+                    ty: result,
+                    is_lvalue: false,
+                    value,
+                })
+            }
+            UnaryOp::BitNot => {
+                if !ty.is_integer() {
+                    return invalid(self);
+                }
+                let result = promote(&ty);
+                let value = match info.value.and_then(|v| convert_value(v, &ty, &result)) {
+                    Some(ConstValue::Int(v)) => Some(ConstValue::Int(result.int_kind()?.wrap(!v))),
+                    _ => None,
+                };
+                Some(ExprInfo {
+                    ty: result,
+                    is_lvalue: false,
+                    value,
+                })
+            }
+            UnaryOp::Not => {
+                if !ty.is_scalar() {
+                    return invalid(self);
+                }
+                let value = match info.value {
+                    Some(ConstValue::Int(v)) => Some(ConstValue::Int((v == 0) as i64)),
+                    Some(ConstValue::Float(v)) => Some(ConstValue::Int((v == 0.0) as i64)),
+                    None => None,
+                };
+                Some(ExprInfo {
+                    ty: int(),
+                    is_lvalue: false,
+                    value,
+                })
+            }
+        }
+    }
+
+    fn check_binary(
+        &mut self,
+        op: BinaryOp,
+        lhs: ExprInfo,
+        rhs: ExprInfo,
+        span: &Span,
+    ) -> Option<ExprInfo> {
+        let lt = lhs.ty.decay();
+        let rt = rhs.ty.decay();
+        let is_complete_object = |ty: &Type| ty.pointee().is_some_and(|p| p.is_complete());
+
+        let ty = match op {
+            BinaryOp::Mul | BinaryOp::Div if lt.is_arithmetic() && rt.is_arithmetic() => {
+                usual_arithmetic_conversions(&lt, &rt)
+            }
+            BinaryOp::Mod | BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr
+                if lt.is_integer() && rt.is_integer() =>
+            {
+                usual_arithmetic_conversions(&lt, &rt)
+            }
+            BinaryOp::Add | BinaryOp::Sub if lt.is_arithmetic() && rt.is_arithmetic() => {
+                usual_arithmetic_conversions(&lt, &rt)
+            }
+            BinaryOp::Add if is_complete_object(&lt) && rt.is_integer() => return Some(rvalue(lt)),
+            BinaryOp::Add if lt.is_integer() && is_complete_object(&rt) => return Some(rvalue(rt)),
+            BinaryOp::Sub if is_complete_object(&lt) && rt.is_integer() => return Some(rvalue(lt)),
+            BinaryOp::Sub
+                if is_complete_object(&lt)
+                    && is_complete_object(&rt)
+                    && lt.pointee()?.is_compatible_unqualified(rt.pointee()?) =>
+            {
+                return Some(rvalue(Type::int(IntKind::Long)));
+            }
+            BinaryOp::Shl | BinaryOp::Shr if lt.is_integer() && rt.is_integer() => {
+                let ty = promote(&lt);
+                let value = match (lhs.value, rhs.value) {
+                    (Some(ConstValue::Int(a)), Some(ConstValue::Int(b))) => {
+                        let kind = ty.int_kind()?;
+                        let a = kind.wrap(a);
+                        if b < 0 || b as u64 >= kind.size() * 8 {
+                            None
+                        } else if op == BinaryOp::Shl {
+                            Some(ConstValue::Int(kind.wrap(a << b)))
+                        } else if kind.is_signed() {
+                            Some(ConstValue::Int(a >> b))
+                        } else {
+                            Some(ConstValue::Int(kind.wrap((a as u64 >> b) as i64)))
+                        }
+                    }
+                    _ => None,
+                };
+                return Some(ExprInfo {
+                    ty,
+                    is_lvalue: false,
+                    value,
+                });
+            }
+            BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Le
+            | BinaryOp::Ge
+            | BinaryOp::Eq
+            | BinaryOp::Ne
+                if lt.is_arithmetic() && rt.is_arithmetic() =>
+            {
+                usual_arithmetic_conversions(&lt, &rt)
+            }
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge
+                if lt.is_pointer()
+                    && rt.is_pointer()
+                    && lt.pointee()?.is_compatible_unqualified(rt.pointee()?) =>
+            {
+                return Some(rvalue(int()));
+            }
+            BinaryOp::Eq | BinaryOp::Ne if lt.is_pointer() && rt.is_pointer() => {
+                let (a, b) = (lt.pointee()?, rt.pointee()?);
+                if !(a.is_void() || b.is_void() || a.is_compatible_unqualified(b)) {
+                    self.warning(
+                        span,
+                        format!(
+                            "comparison of distinct pointer types ('{}' and '{}')",
+                            lt, rt
+                        ),
+                    );
+                }
+                return Some(rvalue(int()));
+            }
+            BinaryOp::Eq | BinaryOp::Ne
+                if lt.is_pointer() && is_null_pointer_constant(&rhs)
+                    || rt.is_pointer() && is_null_pointer_constant(&lhs) =>
+            {
+                return Some(rvalue(int()));
+            }
+            BinaryOp::And | BinaryOp::Or if lt.is_scalar() && rt.is_scalar() => {
+                let truth = |v: Option<ConstValue>| match v {
+                    Some(ConstValue::Int(v)) => Some(v != 0),
+                    Some(ConstValue::Float(v)) => Some(v != 0.0),
+                    None => None,
+                };
+                let value = match (op, truth(lhs.value), truth(rhs.value)) {
+                    (BinaryOp::And, Some(false), _) | (BinaryOp::Or, Some(true), _) => {
+                        Some(ConstValue::Int((op == BinaryOp::Or) as i64))
+                    }
+                    (_, Some(_), Some(b)) => Some(ConstValue::Int(b as i64)),
+                    _ => None,
+                };
+                return Some(ExprInfo {
+                    ty: int(),
+                    is_lvalue: false,
+                    value,
+                });
+            }
+            _ => {
+                self.error(
+                    Code::InvalidOperands,
+                    span,
+                    format!(
+                        "invalid operands to binary expression ('{}' and '{}')",
+                        lhs.ty, rhs.ty
+                    ),
+                );
+                return None;
+            }
+        };
+
+        let value = match (lhs.value, rhs.value) {
+            (Some(a), Some(b)) => {
+                let a = convert_value(a, &lt, &ty)?;
+                let b = convert_value(b, &rt, &ty)?;
+                fold_binary(op, a, b, &ty)
+            }
+            _ => None,
+        };
+        let is_comparison = matches!(
+            op,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne
+        );
+        Some(ExprInfo {
+            ty: if is_comparison { int() } else { ty },
+            is_lvalue: false,
+            value,
+        })
+    }
+
+    fn check_conditional(
+        &mut self,
+        cond: Option<ConstValue>,
+        then: ExprInfo,
+        otherwise: ExprInfo,
+        span: &Span,
+    ) -> Option<ExprInfo> {
+        let a = then.ty.decay();
+        let b = otherwise.ty.decay();
+        let ty = if a.is_arithmetic() && b.is_arithmetic() {
+            usual_arithmetic_conversions(&a, &b)
+        } else if a.is_void() && b.is_void()
+            || a.is_record() && a.is_compatible_unqualified(&b)
+            || a.is_pointer() && is_null_pointer_constant(&otherwise)
+        {
+            a.clone()
+        } else if b.is_pointer() && is_null_pointer_constant(&then) {
+            b.clone()
+        } else if let (Some(x), Some(y)) = (a.pointee(), b.pointee()) {
+            let qualifiers = x.qualifiers.union(y.qualifiers);
+            if x.is_void() || y.is_void() {
+                Type::pointer_to(Type::new(TypeKind::Void).with_qualifiers(qualifiers))
+            } else if x.is_compatible_unqualified(y) {
+                Type::pointer_to(x.unqualified().with_qualifiers(qualifiers))
+            } else {
+                self.warning(span, format!("pointer type mismatch ('{}' and '{}')", a, b));
+                Type::pointer_to(Type::new(TypeKind::Void))
+            }
+        } else {
+            self.error(
+                Code::InvalidOperands,
+                span,
+                format!(
+                    "incompatible operand types ('{}' and '{}')",
+                    then.ty, otherwise.ty
+                ),
+            );
+            return None;
+        };
+
+        let value = match cond {
+            Some(ConstValue::Int(0)) => otherwise.value.and_then(|v| convert_value(v, &b, &ty)),
+            Some(_) => then.value.and_then(|v| convert_value(v, &a, &ty)),
+            None => None,
+        };
+        Some(ExprInfo {
+            ty,
+            is_lvalue: false,
+            value,
+        })
+    }
+
+    fn check_cast(&mut self, target: &Type, info: ExprInfo, span: &Span) -> Option<ExprInfo> {
+        if target.is_void() {
+            return Some(rvalue(target.clone()));
+        }
+        let source = info.ty.decay();
+        if !target.is_scalar() {
+            self.error(
+                Code::InvalidCast,
+                span,
+                format!(
+                    "used type '{}' where arithmetic or pointer type is required",
+                    target
+                ),
+            );
+            return None;
+        }
+        if !source.is_scalar() {
+            self.error(
+                Code::InvalidCast,
+                span,
+                format!(
+                    "operand of type '{}' where arithmetic or pointer type is required",
+                    info.ty
+                ),
+            );
+            return None;
+        }
+        if target.is_pointer() && source.is_floating() {
+            self.error(
+                Code::InvalidCast,
+                span,
+                format!(
+                    "operand of type '{}' cannot be cast to a pointer type",
+                    source
+                ),
+            );
+            return None;
+        }
+        if target.is_floating() && source.is_pointer() {
+            self.error(
+                Code::InvalidCast,
+                span,
+                format!("pointer cannot be cast to type '{}'", target),
+            );
+            return None;
+        }
+        let target = target.unqualified();
+        let value = info.value.and_then(|v| convert_value(v, &source, &target));
+        Some(ExprInfo {
+            ty: target,
+            is_lvalue: false,
+            value,
+        })
+    }
+
+    fn check_sizeof(&mut self, ty: &Type, span: &Span) -> Option<ExprInfo> {
+        if ty.is_function() {
+            self.error(
+                Code::InvalidOperands,
+                span,
+                "invalid application of 'sizeof' to a function type",
+            );
+            return None;
+        }
+        match ty.size() {
+            Some(size) => Some(constant(
+                Type::int(IntKind::ULong),
+                ConstValue::Int(size as i64),
+            )),
+            None => {
+                self.error(
+                    Code::IncompleteType,
+                    span,
+                    format!(
+                        "invalid application of 'sizeof' to an incomplete type '{}'",
+                        ty
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_call(&mut self, callee: &Expr, args: &[Expr], span: &Span) -> Option<ExprInfo> {
+        let info = self.check_expr(callee)?;
+        let arg_infos = args
+            .iter()
+            .map(|arg| self.check_expr(arg))
+            .collect::<Vec<_>>();
+
+        let func = match info.ty.decay().pointee().map(|ty| &ty.kind) {
+            Some(TypeKind::Function(func)) => func.clone(),
+            _ => {
+                self.error(
+                    Code::InvalidCall,
+                    &callee.span,
+                    format!(
+                        "called object type '{}' is not a function or function pointer",
+                        info.ty
+                    ),
+                );
+                return None;
+            }
+        };
+
+        let expected = func.params.len();
+        if args.len() < expected || args.len() > expected && !func.variadic {
+            let (adjective, at_least) = match (args.len() < expected, func.variadic) {
+                (true, true) => ("few", "at least "),
+                (true, false) => ("few", ""),
+                _ => ("many", ""),
+            };
+            self.error(
+                Code::InvalidCall,
+                span,
+                format!(
+                    "too {} arguments to function call, expected {}{}, have {}",
+                    adjective,
+                    at_least,
+                    expected,
+                    args.len()
+                ),
+            );
+        }
+
+        for ((param, arg), arg_info) in func.params.iter().zip(args).zip(&arg_infos) {
+            if let Some(arg_info) = arg_info {
+                self.check_conversion(param, arg_info, &arg.span, Conversion::Pass);
+            }
+        }
+
+        let ret = *func.ret;
+        if !ret.is_void() && !ret.is_complete() {
+            self.error(
+                Code::IncompleteType,
+                span,
+                format!("calling function with incomplete return type '{}'", ret),
+            );
+            return None;
+        }
+        Some(rvalue(ret))
+    }
+
+    fn member_type(&mut self, ty: &Type, member: &Identifier) -> Option<Type> {
+        let record = match &ty.kind {
+            TypeKind::Record(record) => record.clone(),
+            _ => {
+                self.error(
+                    Code::InvalidMember,
+                    &member.span,
+                    format!(
+                        "member reference base type '{}' is not a structure or union",
+                        ty
+                    ),
+                );
+                return None;
+            }
+        };
+        let record = record.borrow();
+        if record.fields.is_none() {
+            drop(record);
+            self.error(
+                Code::IncompleteType,
+                &member.span,
+                format!("incomplete definition of type '{}'", ty),
+            );
+            return None;
+        }
+        let field = record.member(member.value).map(|(_, ty)| ty);
+        drop(record);
+        if field.is_none() {
+            self.error(
+                Code::InvalidMember,
+                &member.span,
+                format!(
+                    "no member named '{}' in '{}'",
+                    member.value,
+                    ty.unqualified()
+                ),
+            );
+        }
+        field
+    }
+}
+
+pub fn fold_binary(op: BinaryOp, a: ConstValue, b: ConstValue, ty: &Type) -> Option<ConstValue> {
+    match (a, b) {
+        (ConstValue::Int(a), ConstValue::Int(b)) => {
+            let kind = ty.int_kind()?;
+            let signed = kind.is_signed();
+            let (ua, ub) = (a as u64, b as u64);
+            let value = match op {
+                BinaryOp::Mul => a.wrapping_mul(b),
+                BinaryOp::Div | BinaryOp::Mod if b == 0 => return None,
+                BinaryOp::Div if signed => a.wrapping_div(b),
+                BinaryOp::Div => (ua / ub) as i64,
+                BinaryOp::Mod if signed => a.wrapping_rem(b),
+                BinaryOp::Mod => (ua % ub) as i64,
+                BinaryOp::Add => a.wrapping_add(b),
+                BinaryOp::Sub => a.wrapping_sub(b),
+                BinaryOp::BitAnd => a & b,
+                BinaryOp::BitXor => a ^ b,
+                BinaryOp::BitOr => a | b,
+                BinaryOp::Lt => {
+                    return Some(ConstValue::Int(if signed { a < b } else { ua < ub } as i64))
+                }
+                BinaryOp::Gt => {
+                    return Some(ConstValue::Int(if signed { a > b } else { ua > ub } as i64))
+                }
+                BinaryOp::Le => {
+                    return Some(ConstValue::Int(
+                        if signed { a <= b } else { ua <= ub } as i64
+                    ))
+                }
+                BinaryOp::Ge => {
+                    return Some(ConstValue::Int(
+                        if signed { a >= b } else { ua >= ub } as i64
+                    ))
+                }
+                BinaryOp::Eq => return Some(ConstValue::Int((a == b) as i64)),
+                BinaryOp::Ne => return Some(ConstValue::Int((a != b) as i64)),
+                _ => return None,
+            };
+            Some(ConstValue::Int(kind.wrap(value)))
+        }
+        (ConstValue::Float(a), ConstValue::Float(b)) => {
+            let value = match op {
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Lt => return Some(ConstValue::Int((a < b) as i64)),
+                BinaryOp::Gt => return Some(ConstValue::Int((a > b) as i64)),
+                BinaryOp::Le => return Some(ConstValue::Int((a <= b) as i64)),
+                BinaryOp::Ge => return Some(ConstValue::Int((a >= b) as i64)),
+                BinaryOp::Eq => return Some(ConstValue::Int((a == b) as i64)),
+                BinaryOp::Ne => return Some(ConstValue::Int((a != b) as i64)),
+                _ => return None,
+            };
+            match ty.kind {
+                TypeKind::Float(kind) => Some(ConstValue::Float(round_float(value, kind))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source_file::SourceFile;
+
+    fn check(src: &str) -> (Vec<String>, Vec<String>) {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let checked = Checker::new().check(&unit);
+        for error in &checked.errors {
+            assert!(error.code.is_some(), "{}", error.msg);
+        }
+        let messages = |list: Vec<Diagnostic>| list.into_iter().map(|e| e.msg).collect();
+        (messages(checked.errors), messages(checked.warnings))
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        check(src).0
+    }
+
+    fn warnings(src: &str) -> Vec<String> {
+        let (errors, warnings) = check(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        warnings
+    }
+
+    #[test]
+    fn implicit_conversion_warning() {
+        let src = "int main(void)\n{\n\tint floatnum = 123.456;\n\treturn floatnum;\n}";
+        let (tokens, _) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        let (unit, _) = Parser::from_tokens(tokens).parse();
+        let Checked {
+            errors,
+            warnings: found,
+            ..
+        } = Checker::new().check(&unit);
+        assert!(errors.is_empty());
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].msg,
+            "implicit conversion from 'double' to 'int' changes value from 123.456 to 123"
+        );
+        let span = &found[0].span;
+        assert_eq!(span.lc_range().start.line, 3);
+        assert_eq!(
+            (span.lc_range().start.column, span.lc_range().end.column),
+            (17, 24)
+        );
+
+        assert!(warnings("int x = 3.0; char c = 'a'; unsigned u = -1; float f = 1;").is_empty());
+        assert!(warnings("unsigned long u = 1e19; unsigned char c = 255.0;").is_empty());
+        assert_eq!(
+            warnings("unsigned u = 1e10;"),
+            ["implicit conversion from 'double' to 'unsigned int' changes value from 10000000000 to 1410065408"]
+        );
+        assert_eq!(
+            warnings("char c = 300;"),
+            ["implicit conversion from 'int' to 'char' changes value from 300 to 44"]
+        );
+        assert_eq!(
+            warnings("double d; int f(void) { return d; }"),
+            ["implicit conversion turns floating-point number into integer: 'double' to 'int'"]
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert!(errors(
+            "long f(char c, unsigned u, double d) { return c + u * 2 % 3 - (long)d << 1; }"
+        )
+        .is_empty());
+        assert_eq!(
+            errors("int f(double d) { return d % 2; }"),
+            ["invalid operands to binary expression ('double' and 'int')"]
+        );
+        assert_eq!(
+            errors("struct s { int a; } s; int f(void) { return -s; }"),
+            ["invalid argument type 'struct s' to unary expression"]
+        );
+        assert_eq!(
+            errors("int a[1 + 2 * 3 == 7 ? 4 : -1]; int b[(int)(sizeof(a) / sizeof a[0]) - 5];"),
+            ["array size is negative"]
+        );
+        assert_eq!(
+            errors("int n; int a[n];"),
+            ["expression is not an integer constant expression"]
+        );
+    }
+
+    #[test]
+    fn pointers() {
+        assert!(errors(
+            "int f(int *p, int a[], const char *s) { int *q = p + 1; long d = q - p; \
+             void *v = p; p = v; p = 0; return *p + a[0] + s[d] + (p == v) + (q != 0); }"
+        )
+        .is_empty());
+        assert_eq!(
+            warnings("int *p; void f(void) { char *c = p; }"),
+            ["incompatible pointer types initializing 'char *' with an expression of type 'int *'"]
+        );
+        assert_eq!(
+            warnings("const char *s; char *t; void f(void) { t = s; }"),
+            ["assigning to 'char *' from 'const char *' discards qualifiers"]
+        );
+        assert_eq!(
+            warnings("int *p = 1;"),
+            ["incompatible integer to pointer conversion initializing 'int *' with an expression of type 'int'"]
+        );
+        assert_eq!(
+            errors("int f(int x) { return *x; }"),
+            ["indirection requires pointer operand ('int' invalid)"]
+        );
+        assert_eq!(
+            errors("int f(int x) { return x[0]; }"),
+            ["subscripted value is not an array or pointer"]
+        );
+    }
+
+    #[test]
+    fn lvalues() {
+        assert_eq!(
+            errors("void f(int x) { x + 1 = 2; }"),
+            ["expression is not assignable"]
+        );
+        assert_eq!(
+            errors("void f(void) { const int c = 1; c = 2; }"),
+            ["cannot assign to expression with const-qualified type 'const int'"]
+        );
+        assert_eq!(
+            errors("void f(int a[2], int b[2]) { int c[2]; c = a; }"),
+            ["array type 'int [2]' is not assignable"]
+        );
+        assert_eq!(
+            errors("void f(int x) { int *p = &(x + 1); }"),
+            ["cannot take the address of an rvalue of type 'int'"]
+        );
+        assert_eq!(
+            errors("void f(int x) { x++; ++x; 1++; }"),
+            ["expression is not assignable"]
+        );
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        let decls = "int puts(const char *s); int add(int a, int b);";
+        assert!(errors(&format!(
+            "{} int main(void) {{ puts(\"\") + add(1, 2); return 0; }}",
+            decls
+        ))
+        .is_empty());
+        assert_eq!(
+            errors(&format!("{} void f(void) {{ add(1); }}", decls)),
+            ["too few arguments to function call, expected 2, have 1"]
+        );
+        assert_eq!(
+            errors(&format!("{} void f(void) {{ puts(); }}", decls)),
+            ["too few arguments to function call, expected 1, have 0"]
+        );
+        assert_eq!(
+            errors("struct s { int a; } s; int f(int a) { return f(s); }"),
+            ["passing 'struct s' to parameter of incompatible type 'int'"]
+        );
+        assert_eq!(
+            errors("int x; void f(void) { x(); }"),
+            ["called object type 'int' is not a function or function pointer"]
+        );
+        assert_eq!(
+            errors("void f(void) { return 1; }"),
+            ["void function 'f' should not return a value"]
+        );
+        assert_eq!(
+            warnings("int f(void) { return; }"),
+            ["non-void function 'f' should return a value"]
+        );
+    }
+
+    #[test]
+    fn records() {
+        assert!(errors(
+            "struct list { int value; struct list *next; union { int i; float f; }; }; \
+             int f(struct list *l) { return l->next->value + (*l).i + sizeof(struct list); }"
+        )
+        .is_empty());
+        assert_eq!(
+            errors("struct s { int a; }; int f(struct s *p) { return p->b; }"),
+            ["no member named 'b' in 'struct s'"]
+        );
+        assert_eq!(
+            errors("struct s; int f(struct s *p) { return sizeof(*p); }"),
+            ["invalid application of 'sizeof' to an incomplete type 'struct s'"]
+        );
+        assert_eq!(
+            errors("struct s { struct s inner; };"),
+            ["field has incomplete type 'struct s'"]
+        );
+        assert_eq!(
+            errors("int f(int x) { return x.a; }"),
+            ["member reference base type 'int' is not a structure or union"]
+        );
+        assert_eq!(
+            errors("enum e { A, B = 5, C }; int a[C == 6 ? 1 : -1];"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn redeclarations() {
+        assert!(errors(
+            "int f(); int f(int x) { return x; } int g(int); int g(int); \
+             int a[]; int a[3]; extern int a[]; int n = sizeof(a); \
+             typedef int T; typedef int T;"
+        )
+        .is_empty());
+        assert_eq!(errors("double d; int d;"), ["conflicting types for 'd'"]);
+        assert_eq!(
+            errors("int f(int); int f(double x) { return 0; }"),
+            ["conflicting types for 'f'"]
+        );
+        assert_eq!(
+            errors("typedef int T; typedef long T; const int c; int c;"),
+            ["conflicting types for 'T'", "conflicting types for 'c'"]
+        );
+
+        let src = "double d;\nint d;";
+        let (tokens, _) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        let (unit, _) = Parser::from_tokens(tokens).parse();
+        let error = &Checker::new().check(&unit).errors[0];
+        assert_eq!(error.code, Some(Code::ConflictingTypes));
+        assert_eq!(error.span.lc_range().start.line, 2);
+        assert_eq!(error.labels[0].span.lc_range().start.line, 1);
+        assert_eq!(
+            error.labels[0].msg,
+            "previous declaration as 'double' is here, now 'int'"
+        );
+    }
+
+    #[test]
+    fn initializers() {
+        assert!(warnings(
+            "struct p { int x, y; }; struct p ps[] = { 1, 2, { 3, 4 }, [3].y = 5 }; \
+             int m[2][2] = { 1, 2, 3, 4 }; char s[] = \"abc\"; int n[sizeof ps / sizeof ps[0] == 4 ? 1 : -1]; \
+             int t[sizeof s == 4 ? 1 : -1];"
+        )
+        .is_empty());
+        assert_eq!(
+            warnings("int a[2] = { 1, 2, 3 };"),
+            ["excess elements in array initializer"]
+        );
+        assert_eq!(
+            warnings("int x = { 1, 2 };"),
+            ["excess elements in scalar initializer"]
+        );
+        assert_eq!(
+            errors("struct p { int x; } v = { .y = 1 };"),
+            ["field designator 'y' does not refer to any field in type 'struct p'"]
+        );
+        assert_eq!(
+            errors("int a[] = 1;"),
+            ["array initializer must be an initializer list"]
+        );
+        assert_eq!(
+            errors("void f(void) { int a[]; }"),
+            ["definition of variable with array type needs an explicit size or an initializer"]
+        );
+
+        assert!(errors(
+            "int g; int *p = &g + 1; char *s = \"s\"; int f(void); int (*pf)(void) = f; \
+             struct p { int x, y; } ps = { 1 }; int *q = &ps.y; long l = (long)&g; \
+             void h(void) { static int c; static int *pc = &c; int *a = &c; }"
+        )
+        .is_empty());
+        let not_constant = ["initializer element is not a compile-time constant"];
+        assert_eq!(errors("int f(void); int g = f();"), not_constant);
+        assert_eq!(errors("int g; int h = g;"), not_constant);
+        assert_eq!(
+            errors("void f(void) { int local; static int *p = &local; }"),
+            not_constant
+        );
+        assert_eq!(
+            errors("void f(int x) { static int a[2] = { 1, x }; }"),
+            not_constant
+        );
+    }
+
+    #[test]
+    fn jumps_and_labels() {
+        assert!(errors(
+            "int f(int x) { while (x) { switch (x) { case 1: continue; default: break; \
+             for (;;) if (x) { case 2: break; } } } do { continue; } while (0); \
+             switch (x) { case 'a': case 97L + 1: case -1: switch (x) { case 1: default: ; } } \
+             return 0; }"
+        )
+        .is_empty());
+        assert_eq!(
+            errors("void f(void) { break; }"),
+            ["'break' statement not in loop or switch statement"]
+        );
+        assert_eq!(
+            errors("void f(int x) { switch (x) { continue; } }"),
+            ["'continue' statement not in loop statement"]
+        );
+        assert_eq!(
+            errors("void f(void) { while (1) { case 1: ; default: ; } }"),
+            [
+                "'case' statement not in switch statement",
+                "'default' statement not in switch statement"
+            ]
+        );
+        assert_eq!(
+            errors("void f(int x) { switch (x) { case 1: case 2: case 1: ; } }"),
+            ["duplicate case value '1'"]
+        );
+        assert_eq!(
+            errors("void f(unsigned x) { switch (x) { case -1: case 4294967295: ; } }"),
+            ["duplicate case value '4294967295'"]
+        );
+        assert_eq!(
+            errors("void f(int x) { switch (x) { default: default: ; } }"),
+            ["multiple default labels in one switch"]
+        );
+    }
+}
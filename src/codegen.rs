@@ -0,0 +1,1905 @@
+use crate::{
+    ast::*,
+    checker::{convert_value, ConstValue, TypeTable},
+    diagnostics::Diagnostic,
+    initializer::{self, Target},
+    interner::Symbol,
+    span::Span,
+    tokens::StringLiteral,
+    types::*,
+};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+// x86-64 System V code generation, emitting GNU as (AT&T) syntax.
+// Expressions are compiled as a stack machine: a value ends up in %rax
+// (integers, pointers and the addresses of aggregates) or %xmm0 (floating),
+// and intermediate values are pushed on the stack.
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+const GP_ARGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+const FP_ARGS: usize = 8;
+const GP_RETURNS: [&str; 2] = ["%rax", "%rdx"];
+
+macro_rules! emit {
+    ($gen:expr, $($arg:tt)*) => {
+        writeln!($gen.text, "\t{}", format_args!($($arg)*)).unwrap()
+    };
+}
+
+// the class of an eightbyte, psABI 3.2.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Integer,
+    Sse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Passing {
+    // in registers, one class per eightbyte
+    Registers(Vec<Class>),
+    Memory,
+}
+
+#[derive(Debug, Clone)]
+enum Local {
+    // relative to %rbp
+    Stack(i64),
+    Static(String),
+}
+
+struct Global<'u> {
+    name: String,
+    ty: Type,
+    init: Option<&'u Initializer>,
+    is_static: bool,
+}
+
+#[derive(Default)]
+struct Frame {
+    id: usize,
+    name: String,
+    scopes: Vec<HashMap<Symbol, Local>>,
+    size: i64,
+    // 8-byte slots pushed on top of the frame
+    depth: usize,
+    ret: Option<Type>,
+    // the slot holding the caller's buffer for a returned aggregate
+    ret_buffer: Option<i64>,
+    breaks: Vec<String>,
+    continues: Vec<String>,
+    cases: HashMap<*const Stmt, String>,
+}
+
+pub struct Codegen<'a> {
+    types: &'a TypeTable,
+    text: String,
+    data: String,
+    bss: String,
+    rodata: String,
+    labels: usize,
+    // symbols defined in this unit; others are reached through the GOT
+    defined: HashSet<String>,
+    strings: HashMap<*const StringLiteral, String>,
+    func_names: HashMap<usize, String>,
+    frame: Frame,
+}
+
+// compiles a checked translation unit to an assembly file
+pub fn generate(unit: &TranslationUnit, types: &TypeTable) -> Result<String> {
+    let mut gen = Codegen {
+        types,
+        text: String::new(),
+        data: String::new(),
+        bss: String::new(),
+        rodata: String::new(),
+        labels: 0,
+        defined: HashSet::new(),
+        strings: HashMap::new(),
+        func_names: HashMap::new(),
+        frame: Frame::default(),
+    };
+
+    let globals = gen.collect_globals(unit);
+    for item in &unit.items {
+        if let ExternalDecl::Function(f) = item {
+            gen.function(f)?;
+        }
+    }
+    for global in &globals {
+        gen.static_data(&global.name, &global.ty, global.init, !global.is_static)?;
+    }
+
+    let mut asm = String::new();
+    for (section, body) in [
+        (".text", &gen.text),
+        (".data", &gen.data),
+        (".bss", &gen.bss),
+        (".section .rodata", &gen.rodata),
+    ] {
+        if !body.is_empty() {
+            writeln!(asm, "\t{}", section).unwrap();
+            asm.push_str(body);
+        }
+    }
+    asm.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    Ok(asm)
+}
+
+fn error(span: &Span, msg: impl Into<String>) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(*span, msg))
+}
+
+// the assembler symbol of a declarator
+fn declarator_name(declarator: &Declarator) -> String {
+    declarator
+        .name
+        .as_ref()
+        .map_or_else(String::new, |name| name.value.to_string())
+}
+
+fn is_long_double(ty: &Type) -> bool {
+    matches!(ty.kind, TypeKind::Float(FloatKind::LongDouble))
+}
+
+// the floating-point instruction suffix
+fn fp(ty: &Type) -> &'static str {
+    match ty.kind {
+        TypeKind::Float(FloatKind::Float) => "ss",
+        _ => "sd",
+    }
+}
+
+fn member_offset(ty: &Type, name: Symbol) -> i64 {
+    match &ty.kind {
+        TypeKind::Record(record) => record
+            .borrow()
+            .member(name)
+            .map_or(0, |(offset, _)| offset as i64),
+        _ => 0,
+    }
+}
+
+// the scalars making up an object, by offset and whether they are floating
+fn scalars(ty: &Type, offset: u64, out: &mut Vec<(u64, bool)>) {
+    match &ty.kind {
+        TypeKind::Record(record) => {
+            let record = record.borrow();
+            let offsets = record.layout().map(|layout| layout.offsets);
+            for (field, off) in record
+                .fields
+                .iter()
+                .flatten()
+                .zip(offsets.into_iter().flatten())
+            {
+                scalars(&field.ty, offset + off, out);
+            }
+        }
+        TypeKind::Array(elem, size) => {
+            let elem_size = elem.size().unwrap_or(0);
+            for i in 0..size.unwrap_or(0) {
+                scalars(elem, offset + i * elem_size, out);
+            }
+        }
+        _ => out.push((offset, ty.is_floating())),
+    }
+}
+
+fn classify(ty: &Type) -> Passing {
+    if ty.is_floating() {
+        return Passing::Registers(vec![Class::Sse]);
+    }
+    if !ty.is_record() {
+        return Passing::Registers(vec![Class::Integer]);
+    }
+    let size = ty.size().unwrap_or(0);
+    if size > 16 {
+        return Passing::Memory;
+    }
+    let mut list = Vec::new();
+    scalars(ty, 0, &mut list);
+    let classes = (0..size.div_ceil(8))
+        .map(|i| {
+            let is_sse = list
+                .iter()
+                .filter(|(offset, _)| offset / 8 == i)
+                .all(|(_, is_float)| *is_float);
+            if is_sse {
+                Class::Sse
+            } else {
+                Class::Integer
+            }
+        })
+        .collect();
+    Passing::Registers(classes)
+}
+
+// the size of the `i`th eightbyte of an object of `size` bytes
+fn eightbyte_size(size: u64, i: usize) -> u64 {
+    (size - i as u64 * 8).min(8)
+}
+
+// the name of the low `size` bytes of a general purpose register
+fn sub_register(reg: &str, size: u64) -> String {
+    let name = &reg[1..];
+    let numbered = name.starts_with('r') && name[1..].starts_with(|c: char| c.is_ascii_digit());
+    if numbered {
+        let suffix = match size {
+            1 => "b",
+            2 => "w",
+            4 => "d",
+            _ => "",
+        };
+        return format!("%{}{}", name, suffix);
+    }
+    let base = &name[1..];
+    match size {
+        1 if base.ends_with('i') => format!("%{}l", base),
+        1 => format!("%{}l", &base[..1]),
+        2 => format!("%{}", base),
+        4 => format!("%e{}", base),
+        _ => reg.to_owned(),
+    }
+}
+
+// declarations and data
+impl<'a> Codegen<'a> {
+    fn new_label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!(".L.{}.{}", kind, self.labels)
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.text, "{}:", label).unwrap();
+    }
+
+    fn collect_globals<'u>(&mut self, unit: &'u TranslationUnit) -> Vec<Global<'u>> {
+        let mut globals: Vec<Global<'u>> = Vec::new();
+        for item in &unit.items {
+            let decl = match item {
+                ExternalDecl::Function(f) => {
+                    self.defined.insert(declarator_name(&f.declarator));
+                    continue;
+                }
+                ExternalDecl::Declaration(decl) => decl,
+            };
+            if decl.specifiers.is_typedef() {
+                continue;
+            }
+            let is_static = decl.specifiers.storage.contains(&StorageClass::Static);
+            let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+            for init in &decl.declarators {
+                let ty = self.types.declarator(&init.declarator);
+                let name = declarator_name(&init.declarator);
+                if ty.is_function() || is_extern && init.initializer.is_none() {
+                    continue;
+                }
+                self.defined.insert(name.to_owned());
+                let idx = match globals.iter().position(|g| g.name == name) {
+                    Some(idx) => idx,
+                    None => {
+                        globals.push(Global {
+                            name: name.to_owned(),
+                            ty: ty.clone(),
+                            init: None,
+                            is_static,
+                        });
+                        globals.len() - 1
+                    }
+                };
+                let global = &mut globals[idx];
+                if init.initializer.is_some() || ty.is_complete() {
+                    global.ty = ty.clone();
+                }
+                if init.initializer.is_some() {
+                    global.init = init.initializer.as_ref();
+                }
+                global.is_static |= is_static;
+            }
+        }
+        globals
+    }
+
+    fn static_data(
+        &mut self,
+        label: &str,
+        ty: &Type,
+        init: Option<&Initializer>,
+        is_global: bool,
+    ) -> Result<()> {
+        let size = initializer::object_size(ty);
+        let align = ty.align().unwrap_or(1);
+        let mut lines = String::new();
+        if is_global {
+            writeln!(lines, "\t.globl {}", label).unwrap();
+        }
+        writeln!(lines, "\t.align {}", align).unwrap();
+        writeln!(lines, "{}:", label).unwrap();
+
+        let init = match init {
+            Some(init) => init,
+            None => {
+                writeln!(lines, "\t.zero {}", size.max(1)).unwrap();
+                self.bss.push_str(&lines);
+                return Ok(());
+            }
+        };
+
+        let image = initializer::static_image(self.types, ty, size, init)?;
+        let mut pos = 0;
+        for relocation in image.relocations {
+            let symbol = match relocation.target {
+                Target::String(s) => self.string_label(s),
+                Target::Symbol(name) => match self.lookup(name) {
+                    Some(Local::Static(label)) => label,
+                    Some(Local::Stack(_)) => {
+                        return Err(error(
+                            &relocation.expr.span,
+                            "initializer element is not a compile-time constant",
+                        ))
+                    }
+                    None => name.to_string(),
+                },
+            };
+            let offset = relocation.offset as usize;
+            emit_bytes(&mut lines, &image.bytes[pos..offset]);
+            writeln!(lines, "\t.quad {}{:+}", symbol, relocation.addend).unwrap();
+            pos = offset + 8;
+        }
+        emit_bytes(&mut lines, &image.bytes[pos..]);
+        if size == 0 {
+            writeln!(lines, "\t.zero 1").unwrap();
+        }
+        self.data.push_str(&lines);
+        Ok(())
+    }
+
+    fn string_label(&mut self, s: &StringLiteral) -> String {
+        let key = s as *const StringLiteral;
+        if let Some(label) = self.strings.get(&key) {
+            return label.clone();
+        }
+        let label = self.new_label("str");
+        self.rodata_string(&label, &s.bytes());
+        self.strings.insert(key, label.clone());
+        label
+    }
+
+    fn rodata_string(&mut self, label: &str, bytes: &[u8]) {
+        writeln!(self.rodata, "{}:", label).unwrap();
+        emit_bytes(&mut self.rodata, bytes);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Local> {
+        self.frame
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))
+            .cloned()
+    }
+
+    fn bind(&mut self, name: Symbol, local: Local) {
+        let scope = self.frame.scopes.last_mut().unwrap();
+        scope.insert(name, local);
+    }
+
+    fn alloc_local(&mut self, ty: &Type) -> i64 {
+        let size = ty.size().unwrap_or(0) as i64;
+        let align = ty.align().unwrap_or(1) as i64;
+        self.frame.size = align_to((self.frame.size + size) as u64, align as u64) as i64;
+        -self.frame.size
+    }
+
+    fn declare(&mut self, decl: &Declaration) -> Result<()> {
+        let types = self.types;
+        let is_static = decl.specifiers.storage.contains(&StorageClass::Static);
+        let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+        for init in &decl.declarators {
+            let name = match &init.declarator.name {
+                Some(name) => name.value,
+                None => continue,
+            };
+            let ty = types.declarator(&init.declarator);
+            if decl.specifiers.is_typedef() || ty.is_function() {
+                continue;
+            }
+
+            if is_extern {
+                self.bind(name, Local::Static(name.to_string()));
+            } else if is_static {
+                let label = format!("{}.{}", name, self.labels);
+                self.labels += 1;
+                self.defined.insert(label.clone());
+                self.bind(name, Local::Static(label.clone()));
+                self.static_data(&label, ty, init.initializer.as_ref(), false)?;
+            } else {
+                let offset = self.alloc_local(ty);
+                self.bind(name, Local::Stack(offset));
+                if let Some(initializer) = &init.initializer {
+                    self.initialize(offset, ty, initializer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn initialize(&mut self, offset: i64, ty: &Type, init: &Initializer) -> Result<()> {
+        let types = self.types;
+        if initializer::zeroes_first(ty, init) {
+            emit!(self, "lea {}(%rbp), %rdi", offset);
+            emit!(self, "mov ${}, %rcx", ty.size().unwrap_or(0));
+            emit!(self, "xor %eax, %eax");
+            emit!(self, "rep stosb");
+        }
+        for entry in initializer::flatten(types, ty, init) {
+            let offset = offset + entry.offset as i64;
+            let e = entry.expr;
+            if let Some((s, size)) = entry.string() {
+                let label = self.string_label(s);
+                emit!(self, "lea {}(%rip), %rsi", label);
+                emit!(self, "lea {}(%rbp), %rdi", offset);
+                emit!(self, "mov ${}, %rcx", size);
+                emit!(self, "rep movsb");
+                continue;
+            }
+            emit!(self, "lea {}(%rbp), %rax", offset);
+            self.push();
+            self.expr(e)?;
+            self.cast(&types.expr(e).decay(), &entry.ty);
+            self.pop("%rdi");
+            self.store(&entry.ty);
+        }
+        Ok(())
+    }
+}
+
+fn emit_bytes(out: &mut String, bytes: &[u8]) {
+    for chunk in bytes.chunks(16) {
+        let list = chunk
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(out, "\t.byte {}", list).unwrap();
+    }
+}
+
+// functions and statements
+impl<'a> Codegen<'a> {
+    fn function(&mut self, f: &FunctionDef) -> Result<()> {
+        let types = self.types;
+        let name = declarator_name(&f.declarator);
+        let func = match &types.declarator(&f.declarator).kind {
+            TypeKind::Function(func) => func.clone(),
+            _ => unreachable!("function definitions have function type"),
+        };
+        self.labels += 1;
+        self.frame = Frame {
+            id: self.labels,
+            name: name.to_owned(),
+            scopes: vec![HashMap::new()],
+            ret: Some((*func.ret).clone()),
+            ..Frame::default()
+        };
+        let prologue_text = std::mem::take(&mut self.text);
+
+        let mut gp = 0;
+        let mut fp = 0;
+        if func.ret.is_record() && classify(&func.ret) == Passing::Memory {
+            let offset = self.alloc_local(&Type::pointer_to(Type::new(TypeKind::Void)));
+            emit!(self, "mov %rdi, {}(%rbp)", offset);
+            self.frame.ret_buffer = Some(offset);
+            gp = 1;
+        }
+
+        // arguments passed on the stack start above the return address
+        let mut stack = 16;
+        let params = match f.declarator.derived.first() {
+            Some(DerivedDeclarator::Function(func)) => func.params.as_slice(),
+            _ => &[],
+        };
+        for param in params {
+            let ty = types.declarator(&param.declarator);
+            if is_long_double(ty) {
+                return Err(error(
+                    &param.span,
+                    "'long double' is not supported by the code generator",
+                ));
+            }
+            let size = ty.size().unwrap_or(0);
+            let classes = match classify(ty) {
+                Passing::Registers(classes) => classes,
+                Passing::Memory => Vec::new(),
+            };
+            let needs_gp = classes.iter().filter(|&&c| c == Class::Integer).count();
+            let needs_fp = classes.len() - needs_gp;
+            let offset = if !classes.is_empty() && gp + needs_gp <= 6 && fp + needs_fp <= FP_ARGS {
+                let offset = self.alloc_local(ty);
+                for (i, class) in classes.iter().enumerate() {
+                    let reg = match class {
+                        Class::Integer => {
+                            gp += 1;
+                            GP_ARGS[gp - 1].to_owned()
+                        }
+                        Class::Sse => {
+                            fp += 1;
+                            format!("%xmm{}", fp - 1)
+                        }
+                    };
+                    let at = format!("{}(%rbp)", offset + i as i64 * 8);
+                    self.store_eightbyte(&reg, *class, &at, eightbyte_size(size, i));
+                }
+                offset
+            } else {
+                let offset = stack;
+                stack += align_to(size, 8) as i64;
+                offset
+            };
+            if let Some(name) = &param.declarator.name {
+                self.bind(name.value, Local::Stack(offset));
+            }
+        }
+
+        for item in &f.body.items {
+            self.block_item(item)?;
+        }
+        // reaching the end of `main` returns 0
+        emit!(self, "xor %eax, %eax");
+        let body = std::mem::replace(&mut self.text, prologue_text);
+
+        let is_static = f.specifiers.storage.contains(&StorageClass::Static);
+        if !is_static {
+            emit!(self, ".globl {}", name);
+        }
+        emit!(self, ".type {}, @function", name);
+        self.label(&name);
+        emit!(self, "push %rbp");
+        emit!(self, "mov %rsp, %rbp");
+        emit!(self, "sub ${}, %rsp", align_to(self.frame.size as u64, 16));
+        self.text.push_str(&body);
+        let return_label = format!(".L.return.{}", self.frame.id);
+        self.label(&return_label);
+        emit!(self, "mov %rbp, %rsp");
+        emit!(self, "pop %rbp");
+        emit!(self, "ret");
+        Ok(())
+    }
+
+    // stores the first `size` bytes of a register
+    fn store_eightbyte(&mut self, reg: &str, class: Class, at: &str, size: u64) {
+        match (class, size) {
+            (Class::Sse, 4) => emit!(self, "movss {}, {}", reg, at),
+            (Class::Sse, _) => emit!(self, "movsd {}, {}", reg, at),
+            (Class::Integer, 1 | 2 | 4 | 8) => {
+                emit!(self, "mov {}, {}", sub_register(reg, size), at)
+            }
+            (Class::Integer, _) => {
+                let (offset, base) = at.split_at(at.find('(').unwrap());
+                let offset: i64 = offset.parse().unwrap_or(0);
+                for i in 0..size as i64 {
+                    emit!(self, "mov {}, {}{}", sub_register(reg, 1), offset + i, base);
+                    emit!(self, "shr $8, {}", reg);
+                }
+            }
+        }
+    }
+
+    // loads `size` bytes into a register, using %rcx as scratch
+    fn load_eightbyte(&mut self, reg: &str, class: Class, base: &str, offset: i64, size: u64) {
+        match (class, size) {
+            (Class::Sse, 4) => emit!(self, "movss {}({}), {}", offset, base, reg),
+            (Class::Sse, _) => emit!(self, "movsd {}({}), {}", offset, base, reg),
+            (Class::Integer, 8) => emit!(self, "mov {}({}), {}", offset, base, reg),
+            (Class::Integer, _) => {
+                emit!(self, "xor {0}, {0}", reg);
+                for i in (0..size as i64).rev() {
+                    emit!(self, "shl $8, {}", reg);
+                    emit!(self, "movzbl {}({}), %ecx", offset + i, base);
+                    emit!(self, "or %rcx, {}", reg);
+                }
+            }
+        }
+    }
+
+    fn block_item(&mut self, item: &BlockItem) -> Result<()> {
+        match item {
+            BlockItem::Declaration(decl) => self.declare(decl),
+            BlockItem::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        let types = self.types;
+        match &stmt.kind {
+            StmtKind::Compound(block) => {
+                self.frame.scopes.push(HashMap::new());
+                for item in &block.items {
+                    self.block_item(item)?;
+                }
+                self.frame.scopes.pop();
+            }
+            StmtKind::Expr(e) => {
+                if let Some(e) = e {
+                    self.expr(e)?;
+                }
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let else_label = self.new_label("else");
+                let end = self.new_label("end");
+                self.condition(cond, &else_label)?;
+                self.stmt(then)?;
+                emit!(self, "jmp {}", end);
+                self.label(&else_label);
+                if let Some(otherwise) = otherwise {
+                    self.stmt(otherwise)?;
+                }
+                self.label(&end);
+            }
+            StmtKind::While(cond, body) => {
+                let start = self.new_label("while");
+                let end = self.new_label("end");
+                self.label(&start);
+                self.condition(cond, &end)?;
+                self.looped(body, &start, &end)?;
+                emit!(self, "jmp {}", start);
+                self.label(&end);
+            }
+            StmtKind::DoWhile(body, cond) => {
+                let start = self.new_label("do");
+                let next = self.new_label("next");
+                let end = self.new_label("end");
+                self.label(&start);
+                self.looped(body, &next, &end)?;
+                self.label(&next);
+                self.expr(cond)?;
+                self.truth(&types.expr(cond).decay());
+                emit!(self, "cmp $0, %rax");
+                emit!(self, "jne {}", start);
+                self.label(&end);
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.frame.scopes.push(HashMap::new());
+                match init {
+                    ForInit::Empty => {}
+                    ForInit::Expr(e) => self.expr(e)?,
+                    ForInit::Declaration(decl) => self.declare(decl)?,
+                }
+                let start = self.new_label("for");
+                let next = self.new_label("next");
+                let end = self.new_label("end");
+                self.label(&start);
+                if let Some(cond) = cond {
+                    self.condition(cond, &end)?;
+                }
+                self.looped(body, &next, &end)?;
+                self.label(&next);
+                if let Some(step) = step {
+                    self.expr(step)?;
+                }
+                emit!(self, "jmp {}", start);
+                self.label(&end);
+                self.frame.scopes.pop();
+            }
+            StmtKind::Switch(cond, body) => self.switch(cond, body)?,
+            StmtKind::Case(_, body) | StmtKind::Default(body) => {
+                let label = match self.frame.cases.get(&(stmt as *const Stmt)) {
+                    Some(label) => label.clone(),
+                    None => return Err(error(&stmt.span, stmt.misplaced_msg())),
+                };
+                self.label(&label);
+                self.stmt(body)?;
+            }
+            StmtKind::Labeled(name, body) => {
+                let label = format!(".L.label.{}.{}", self.frame.id, name.value);
+                self.label(&label);
+                self.stmt(body)?;
+            }
+            StmtKind::Goto(name) => {
+                emit!(self, "jmp .L.label.{}.{}", self.frame.id, name.value)
+            }
+            StmtKind::Continue => {
+                let label = self.frame.continues.last().cloned();
+                let label = label.ok_or_else(|| error(&stmt.span, stmt.misplaced_msg()))?;
+                emit!(self, "jmp {}", label);
+            }
+            StmtKind::Break => {
+                let label = self.frame.breaks.last().cloned();
+                let label = label.ok_or_else(|| error(&stmt.span, stmt.misplaced_msg()))?;
+                emit!(self, "jmp {}", label);
+            }
+            StmtKind::Return(e) => {
+                if let Some(e) = e {
+                    self.ret(e)?;
+                }
+                emit!(self, "jmp .L.return.{}", self.frame.id);
+            }
+        }
+        Ok(())
+    }
+
+    fn looped(&mut self, body: &Stmt, next: &str, end: &str) -> Result<()> {
+        self.frame.continues.push(next.to_owned());
+        self.frame.breaks.push(end.to_owned());
+        let result = self.stmt(body);
+        self.frame.continues.pop();
+        self.frame.breaks.pop();
+        result
+    }
+
+    // jumps to `otherwise` if `cond` is false
+    fn condition(&mut self, cond: &Expr, otherwise: &str) -> Result<()> {
+        self.expr(cond)?;
+        self.truth(&self.types.expr(cond).decay());
+        emit!(self, "cmp $0, %rax");
+        emit!(self, "je {}", otherwise);
+        Ok(())
+    }
+
+    fn switch(&mut self, cond: &Expr, body: &Stmt) -> Result<()> {
+        let types = self.types;
+        let cases = body.switch_cases();
+
+        let ty = types.expr(cond).decay();
+        let promoted = promote(&ty);
+        self.expr(cond)?;
+        self.cast(&ty, &promoted);
+        let end = self.new_label("end");
+        let mut default = end.clone();
+        for stmt in cases {
+            let label = self.new_label("case");
+            match &stmt.kind {
+                StmtKind::Case(e, _) => {
+                    let value = types
+                        .value(e)
+                        .and_then(|v| convert_value(v, types.expr(e), &promoted));
+                    if let Some(ConstValue::Int(value)) = value {
+                        emit!(self, "mov ${}, %rdi", value);
+                        emit!(self, "cmp %rdi, %rax");
+                        emit!(self, "je {}", label);
+                    }
+                }
+                _ => default = label.clone(),
+            }
+            self.frame.cases.insert(stmt as *const Stmt, label);
+        }
+        emit!(self, "jmp {}", default);
+
+        self.frame.breaks.push(end.clone());
+        let result = self.stmt(body);
+        self.frame.breaks.pop();
+        self.label(&end);
+        result
+    }
+
+    fn ret(&mut self, e: &Expr) -> Result<()> {
+        let types = self.types;
+        let ret = self.frame.ret.clone().unwrap();
+        self.expr(e)?;
+        self.cast(&types.expr(e).decay(), &ret);
+        if !ret.is_record() {
+            return Ok(());
+        }
+        let size = ret.size().unwrap_or(0);
+        match classify(&ret) {
+            Passing::Memory => {
+                emit!(self, "mov {}(%rbp), %rdi", self.frame.ret_buffer.unwrap());
+                self.store(&ret);
+            }
+            Passing::Registers(classes) => {
+                emit!(self, "mov %rax, %rsi");
+                let (mut gp, mut fp) = (0, 0);
+                for (i, class) in classes.iter().enumerate() {
+                    let reg = match class {
+                        Class::Integer => {
+                            gp += 1;
+                            GP_RETURNS[gp - 1].to_owned()
+                        }
+                        Class::Sse => {
+                            fp += 1;
+                            format!("%xmm{}", fp - 1)
+                        }
+                    };
+                    self.load_eightbyte(
+                        &reg,
+                        *class,
+                        "%rsi",
+                        i as i64 * 8,
+                        eightbyte_size(size, i),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// expressions
+impl<'a> Codegen<'a> {
+    fn push(&mut self) {
+        emit!(self, "push %rax");
+        self.frame.depth += 1;
+    }
+
+    fn pop(&mut self, reg: &str) {
+        emit!(self, "pop {}", reg);
+        self.frame.depth -= 1;
+    }
+
+    fn push_value(&mut self, ty: &Type) {
+        if ty.is_floating() {
+            emit!(self, "sub $8, %rsp");
+            emit!(self, "movsd %xmm0, (%rsp)");
+            self.frame.depth += 1;
+        } else {
+            self.push();
+        }
+    }
+
+    // pops into %rdi or %xmm1
+    fn pop_value(&mut self, ty: &Type) {
+        if ty.is_floating() {
+            emit!(self, "movsd (%rsp), %xmm1");
+            emit!(self, "add $8, %rsp");
+            self.frame.depth -= 1;
+        } else {
+            self.pop("%rdi");
+        }
+    }
+
+    fn constant(&mut self, value: ConstValue, ty: &Type) {
+        match (value, &ty.kind) {
+            (ConstValue::Float(v), TypeKind::Float(FloatKind::Float)) => {
+                emit!(self, "mov ${}, %eax", (v as f32).to_bits());
+                emit!(self, "movd %eax, %xmm0");
+            }
+            (ConstValue::Float(v), _) => {
+                emit!(self, "movabs ${}, %rax", v.to_bits() as i64);
+                emit!(self, "movq %rax, %xmm0");
+            }
+            (ConstValue::Int(v), _) if v == v as i32 as i64 => emit!(self, "mov ${}, %rax", v),
+            (ConstValue::Int(v), _) => emit!(self, "movabs ${}, %rax", v),
+        }
+    }
+
+    fn load(&mut self, ty: &Type) {
+        match &ty.kind {
+            TypeKind::Array(..) | TypeKind::Function(_) | TypeKind::Record(_) => {}
+            TypeKind::Float(_) => emit!(self, "mov{} (%rax), %xmm0", fp(ty)),
+            _ => {
+                let is_signed = ty.int_kind().is_some_and(|k| k.is_signed());
+                match (ty.size().unwrap_or(8), is_signed) {
+                    (1, true) => emit!(self, "movsbq (%rax), %rax"),
+                    (1, false) => emit!(self, "movzbq (%rax), %rax"),
+                    (2, true) => emit!(self, "movswq (%rax), %rax"),
+                    (2, false) => emit!(self, "movzwq (%rax), %rax"),
+                    (4, true) => emit!(self, "movslq (%rax), %rax"),
+                    (4, false) => emit!(self, "movl (%rax), %eax"),
+                    _ => emit!(self, "mov (%rax), %rax"),
+                }
+            }
+        }
+    }
+
+    // stores the value in %rax or %xmm0 to the address in %rdi
+    fn store(&mut self, ty: &Type) {
+        match &ty.kind {
+            TypeKind::Record(_) => {
+                emit!(self, "mov %rdi, %rdx");
+                emit!(self, "mov %rax, %rsi");
+                emit!(self, "mov ${}, %rcx", ty.size().unwrap_or(0));
+                emit!(self, "rep movsb");
+                emit!(self, "mov %rdx, %rax");
+            }
+            TypeKind::Float(_) => emit!(self, "mov{} %xmm0, (%rdi)", fp(ty)),
+            _ => {
+                let size = ty.size().unwrap_or(8);
+                emit!(self, "mov {}, (%rdi)", sub_register("%rax", size));
+            }
+        }
+    }
+
+    // sign or zero extends the low bits of %rax to the full register
+    fn normalize(&mut self, kind: IntKind) {
+        match kind {
+            IntKind::Bool | IntKind::UChar => emit!(self, "movzbq %al, %rax"),
+            IntKind::Char | IntKind::SChar => emit!(self, "movsbq %al, %rax"),
+            IntKind::Short => emit!(self, "movswq %ax, %rax"),
+            IntKind::UShort => emit!(self, "movzwq %ax, %rax"),
+            IntKind::Int => emit!(self, "movslq %eax, %rax"),
+            IntKind::UInt => emit!(self, "mov %eax, %eax"),
+            _ => {}
+        }
+    }
+
+    // replaces the scalar value of type `ty` by 0 or 1 in %rax
+    fn truth(&mut self, ty: &Type) {
+        if ty.is_floating() {
+            let s = fp(ty);
+            emit!(self, "xorp{} %xmm1, %xmm1", &s[1..]);
+            emit!(self, "ucomi{} %xmm1, %xmm0", s);
+            emit!(self, "setne %al");
+            emit!(self, "setp %dl");
+            emit!(self, "or %dl, %al");
+        } else {
+            emit!(self, "cmp $0, %rax");
+            emit!(self, "setne %al");
+        }
+        emit!(self, "movzbq %al, %rax");
+    }
+
+    fn cast(&mut self, from: &Type, to: &Type) {
+        if to.is_void() || !to.is_scalar() || !from.is_scalar() {
+            return;
+        }
+        if to.int_kind() == Some(IntKind::Bool) {
+            return self.truth(from);
+        }
+        match (&from.kind, &to.kind) {
+            (TypeKind::Float(a), TypeKind::Float(b)) => match (a, b) {
+                (FloatKind::Float, FloatKind::Double) => emit!(self, "cvtss2sd %xmm0, %xmm0"),
+                (FloatKind::Double, FloatKind::Float) => emit!(self, "cvtsd2ss %xmm0, %xmm0"),
+                _ => {}
+            },
+            (TypeKind::Float(_), _) => {
+                let s = fp(from);
+                if matches!(to.int_kind(), Some(IntKind::ULong | IntKind::ULongLong)) {
+                    // values from 2^63 on do not fit the signed conversion
+                    let big = self.new_label("big");
+                    let end = self.new_label("end");
+                    match s {
+                        "ss" => {
+                            emit!(self, "mov ${}, %eax", (2f32.powi(63)).to_bits());
+                            emit!(self, "movd %eax, %xmm1");
+                        }
+                        _ => {
+                            emit!(self, "movabs ${}, %rax", 2f64.powi(63).to_bits() as i64);
+                            emit!(self, "movq %rax, %xmm1");
+                        }
+                    }
+                    emit!(self, "ucomi{} %xmm1, %xmm0", s);
+                    emit!(self, "jae {}", big);
+                    emit!(self, "cvtt{}2si %xmm0, %rax", s);
+                    emit!(self, "jmp {}", end);
+                    self.label(&big);
+                    emit!(self, "sub{} %xmm1, %xmm0", s);
+                    emit!(self, "cvtt{}2si %xmm0, %rax", s);
+                    emit!(self, "btc $63, %rax");
+                    self.label(&end);
+                } else {
+                    emit!(self, "cvtt{}2si %xmm0, %rax", s);
+                }
+                if let Some(kind) = to.int_kind() {
+                    self.normalize(kind);
+                }
+            }
+            (_, TypeKind::Float(_)) => {
+                let s = fp(to);
+                if matches!(from.int_kind(), Some(IntKind::ULong | IntKind::ULongLong)) {
+                    // halve values from 2^63 on, keeping the lowest bit for rounding
+                    let big = self.new_label("big");
+                    let end = self.new_label("end");
+                    emit!(self, "test %rax, %rax");
+                    emit!(self, "js {}", big);
+                    emit!(self, "cvtsi2{}q %rax, %xmm0", s);
+                    emit!(self, "jmp {}", end);
+                    self.label(&big);
+                    emit!(self, "mov %rax, %rdi");
+                    emit!(self, "and $1, %edi");
+                    emit!(self, "shr %rax");
+                    emit!(self, "or %rdi, %rax");
+                    emit!(self, "cvtsi2{}q %rax, %xmm0", s);
+                    emit!(self, "add{} %xmm0, %xmm0", s);
+                    self.label(&end);
+                } else {
+                    emit!(self, "cvtsi2{}q %rax, %xmm0", s);
+                }
+            }
+            _ => {
+                if let Some(kind) = to.int_kind() {
+                    self.normalize(kind);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) -> Result<()> {
+        let types = self.types;
+        let ty = types.expr(e);
+        if is_long_double(ty) {
+            return Err(error(
+                &e.span,
+                "'long double' is not supported by the code generator",
+            ));
+        }
+        if let Some(value) = types.value(e) {
+            self.constant(value, ty);
+            return Ok(());
+        }
+
+        match &e.kind {
+            ExprKind::Identifier(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Unary(UnaryOp::Deref, _)
+            | ExprKind::Index(..)
+            | ExprKind::Member(..)
+            | ExprKind::Arrow(..) => {
+                self.addr(e)?;
+                self.load(ty);
+            }
+            ExprKind::Constant(_) | ExprKind::SizeofExpr(_) | ExprKind::SizeofType(_) => {
+                unreachable!("constants are folded by the checker")
+            }
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, ty)?,
+            ExprKind::Binary(op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => {
+                let short = self.new_label("short");
+                let end = self.new_label("end");
+                let jump = if *op == BinaryOp::And { "je" } else { "jne" };
+                for operand in [lhs, rhs] {
+                    self.expr(operand)?;
+                    self.truth(&types.expr(operand).decay());
+                    emit!(self, "cmp $0, %rax");
+                    emit!(self, "{} {}", jump, short);
+                }
+                emit!(self, "mov ${}, %rax", (*op == BinaryOp::And) as i32);
+                emit!(self, "jmp {}", end);
+                self.label(&short);
+                emit!(self, "mov ${}, %rax", (*op == BinaryOp::Or) as i32);
+                self.label(&end);
+            }
+            ExprKind::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs)?,
+            ExprKind::Assign(op, lhs, rhs) => self.assign(*op, lhs, rhs)?,
+            ExprKind::Conditional(cond, then, otherwise) => {
+                let else_label = self.new_label("else");
+                let end = self.new_label("end");
+                self.condition(cond, &else_label)?;
+                self.expr(then)?;
+                self.cast(&types.expr(then).decay(), ty);
+                emit!(self, "jmp {}", end);
+                self.label(&else_label);
+                self.expr(otherwise)?;
+                self.cast(&types.expr(otherwise).decay(), ty);
+                self.label(&end);
+            }
+            ExprKind::Comma(exprs) => {
+                for e in exprs {
+                    self.expr(e)?;
+                }
+            }
+            ExprKind::Cast(_, operand) => {
+                self.expr(operand)?;
+                self.cast(&types.expr(operand).decay(), ty);
+            }
+            ExprKind::Call(callee, args) => self.call(callee, args, ty)?,
+        }
+        Ok(())
+    }
+
+    // the address of an lvalue in %rax; aggregates that are rvalues already
+    // evaluate to an address
+    fn addr(&mut self, e: &Expr) -> Result<()> {
+        let types = self.types;
+        match &e.kind {
+            ExprKind::Identifier(ident) => match self.lookup(ident.value) {
+                Some(Local::Stack(offset)) => emit!(self, "lea {}(%rbp), %rax", offset),
+                Some(Local::Static(symbol)) => self.symbol(&symbol),
+                None if ident.value == "__func__" => {
+                    let id = self.frame.id;
+                    let label = match self.func_names.get(&id) {
+                        Some(label) => label.clone(),
+                        None => {
+                            let label = self.new_label("func");
+                            let mut bytes = self.frame.name.as_bytes().to_vec();
+                            bytes.push(0);
+                            self.rodata_string(&label, &bytes);
+                            self.func_names.insert(id, label.clone());
+                            label
+                        }
+                    };
+                    emit!(self, "lea {}(%rip), %rax", label);
+                }
+                None => self.symbol(&ident.value.as_str()),
+            },
+            ExprKind::StringLiteral(s) => {
+                let label = self.string_label(s);
+                emit!(self, "lea {}(%rip), %rax", label);
+            }
+            ExprKind::Unary(UnaryOp::Deref, operand) => self.expr(operand)?,
+            ExprKind::Index(lhs, rhs) => {
+                let (array, index) = if types.expr(lhs).decay().is_pointer() {
+                    (lhs, rhs)
+                } else {
+                    (rhs, lhs)
+                };
+                self.expr(index)?;
+                emit!(self, "imul ${}, %rax", types.expr(e).size().unwrap_or(1));
+                self.push();
+                self.expr(array)?;
+                self.pop("%rdi");
+                emit!(self, "add %rdi, %rax");
+            }
+            ExprKind::Member(base, member) | ExprKind::Arrow(base, member) => {
+                let record = match &e.kind {
+                    ExprKind::Arrow(..) => types.expr(base).decay().pointee().unwrap().clone(),
+                    _ => types.expr(base).clone(),
+                };
+                self.expr(base)?;
+                let offset = member_offset(&record, member.value);
+                if offset != 0 {
+                    emit!(self, "add ${}, %rax", offset);
+                }
+            }
+            _ => self.expr(e)?,
+        }
+        Ok(())
+    }
+
+    fn symbol(&mut self, name: &str) {
+        if self.defined.contains(name) {
+            emit!(self, "lea {}(%rip), %rax", name);
+        } else {
+            emit!(self, "mov {}@GOTPCREL(%rip), %rax", name);
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &Expr, ty: &Type) -> Result<()> {
+        let types = self.types;
+        let source = types.expr(operand).decay();
+        match op {
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                let target = types.expr(operand).unqualified();
+                let is_post = matches!(op, UnaryOp::PostInc | UnaryOp::PostDec);
+                let is_inc = matches!(op, UnaryOp::PreInc | UnaryOp::PostInc);
+                self.addr(operand)?;
+                self.push();
+                self.load(&target);
+                if is_post {
+                    self.push_value(&target);
+                }
+                if target.is_floating() {
+                    let one = ConstValue::Float(1.0);
+                    emit!(self, "movsd %xmm0, %xmm1");
+                    self.constant(one, &target);
+                    let s = fp(&target);
+                    if is_inc {
+                        emit!(self, "add{} %xmm1, %xmm0", s);
+                    } else {
+                        // %xmm0 = %xmm1 - 1
+                        emit!(self, "sub{} %xmm0, %xmm1", s);
+                        emit!(self, "movsd %xmm1, %xmm0");
+                    }
+                } else {
+                    let step = match target.pointee() {
+                        Some(pointee) => pointee.size().unwrap_or(1),
+                        None => 1,
+                    };
+                    let inst = if is_inc { "add" } else { "sub" };
+                    emit!(self, "{} ${}, %rax", inst, step);
+                    if let Some(kind) = target.int_kind() {
+                        if kind == IntKind::Bool {
+                            self.truth(&Type::int(IntKind::Long));
+                        } else {
+                            self.normalize(kind);
+                        }
+                    }
+                }
+                if is_post {
+                    emit!(self, "mov 8(%rsp), %rdi");
+                    self.store(&target);
+                    self.pop_value(&target);
+                    if target.is_floating() {
+                        emit!(self, "movsd %xmm1, %xmm0");
+                    } else {
+                        emit!(self, "mov %rdi, %rax");
+                    }
+                    emit!(self, "add $8, %rsp");
+                    self.frame.depth -= 1;
+                } else {
+                    self.pop("%rdi");
+                    self.store(&target);
+                }
+            }
+            UnaryOp::Address => self.addr(operand)?,
+            UnaryOp::Deref => unreachable!("dereferences are lvalues"),
+            UnaryOp::Not => {
+                self.expr(operand)?;
+                self.truth(&source);
+                emit!(self, "xor $1, %rax");
+            }
+            UnaryOp::Plus | UnaryOp::Minus | UnaryOp::BitNot => {
+                self.expr(operand)?;
+                self.cast(&source, ty);
+                match (op, &ty.kind) {
+                    (UnaryOp::Minus, TypeKind::Float(FloatKind::Float)) => {
+                        emit!(self, "movd %xmm0, %eax");
+                        emit!(self, "xor $0x80000000, %eax");
+                        emit!(self, "movd %eax, %xmm0");
+                    }
+                    (UnaryOp::Minus, TypeKind::Float(_)) => {
+                        emit!(self, "movq %xmm0, %rax");
+                        emit!(self, "btc $63, %rax");
+                        emit!(self, "movq %rax, %xmm0");
+                    }
+                    (UnaryOp::Minus, _) => emit!(self, "neg %rax"),
+                    (UnaryOp::BitNot, _) => emit!(self, "not %rax"),
+                    _ => {}
+                }
+                if let (UnaryOp::Minus | UnaryOp::BitNot, Some(kind)) = (op, ty.int_kind()) {
+                    self.normalize(kind);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> Result<()> {
+        let types = self.types;
+        let lt = types.expr(lhs).decay();
+        let rt = types.expr(rhs).decay();
+
+        if lt.is_pointer() || rt.is_pointer() {
+            let step = |ty: &Type| ty.pointee().and_then(Type::size).unwrap_or(1);
+            match op {
+                BinaryOp::Add | BinaryOp::Sub if !(lt.is_pointer() && rt.is_pointer()) => {
+                    let (pointer, index, size) = if lt.is_pointer() {
+                        (lhs, rhs, step(&lt))
+                    } else {
+                        (rhs, lhs, step(&rt))
+                    };
+                    self.expr(index)?;
+                    emit!(self, "imul ${}, %rax", size);
+                    self.push();
+                    self.expr(pointer)?;
+                    self.pop("%rdi");
+                    let inst = if op == BinaryOp::Add { "add" } else { "sub" };
+                    emit!(self, "{} %rdi, %rax", inst);
+                }
+                _ => {
+                    self.expr(rhs)?;
+                    self.push();
+                    self.expr(lhs)?;
+                    self.pop("%rdi");
+                    if op == BinaryOp::Sub {
+                        emit!(self, "sub %rdi, %rax");
+                        emit!(self, "mov ${}, %rdi", step(&lt));
+                        emit!(self, "cqo");
+                        emit!(self, "idiv %rdi");
+                    } else {
+                        self.compare(op, false);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let ty = operand_type(op, &lt, &rt);
+        let rhs_ty = match op {
+            BinaryOp::Shl | BinaryOp::Shr => promote(&rt),
+            _ => ty.clone(),
+        };
+        self.expr(rhs)?;
+        self.cast(&rt, &rhs_ty);
+        self.push_value(&rhs_ty);
+        self.expr(lhs)?;
+        self.cast(&lt, &ty);
+        self.pop_value(&rhs_ty);
+        self.operation(op, &ty);
+        Ok(())
+    }
+
+    // %rax = %rax op %rdi, or %xmm0 = %xmm0 op %xmm1, with operands of type `ty`
+    fn operation(&mut self, op: BinaryOp, ty: &Type) {
+        if ty.is_floating() {
+            let s = fp(ty);
+            match op {
+                BinaryOp::Add => emit!(self, "add{} %xmm1, %xmm0", s),
+                BinaryOp::Sub => emit!(self, "sub{} %xmm1, %xmm0", s),
+                BinaryOp::Mul => emit!(self, "mul{} %xmm1, %xmm0", s),
+                BinaryOp::Div => emit!(self, "div{} %xmm1, %xmm0", s),
+                // unordered comparisons set CF, ZF and PF, so NaNs compare false
+                BinaryOp::Lt | BinaryOp::Le => {
+                    emit!(self, "ucomi{} %xmm0, %xmm1", s);
+                    let set = if op == BinaryOp::Lt { "seta" } else { "setae" };
+                    emit!(self, "{} %al", set);
+                    emit!(self, "movzbq %al, %rax");
+                }
+                BinaryOp::Gt | BinaryOp::Ge => {
+                    emit!(self, "ucomi{} %xmm1, %xmm0", s);
+                    let set = if op == BinaryOp::Gt { "seta" } else { "setae" };
+                    emit!(self, "{} %al", set);
+                    emit!(self, "movzbq %al, %rax");
+                }
+                BinaryOp::Eq => {
+                    emit!(self, "ucomi{} %xmm1, %xmm0", s);
+                    emit!(self, "sete %al");
+                    emit!(self, "setnp %dl");
+                    emit!(self, "and %dl, %al");
+                    emit!(self, "movzbq %al, %rax");
+                }
+                BinaryOp::Ne => {
+                    emit!(self, "ucomi{} %xmm1, %xmm0", s);
+                    emit!(self, "setne %al");
+                    emit!(self, "setp %dl");
+                    emit!(self, "or %dl, %al");
+                    emit!(self, "movzbq %al, %rax");
+                }
+                _ => unreachable!("operand types are checked"),
+            }
+            return;
+        }
+
+        let kind = ty.int_kind().unwrap_or(IntKind::ULong);
+        let is_signed = kind.is_signed();
+        match op {
+            BinaryOp::Add => emit!(self, "add %rdi, %rax"),
+            BinaryOp::Sub => emit!(self, "sub %rdi, %rax"),
+            BinaryOp::Mul => emit!(self, "imul %rdi, %rax"),
+            BinaryOp::Div | BinaryOp::Mod => {
+                if is_signed {
+                    emit!(self, "cqo");
+                    emit!(self, "idiv %rdi");
+                } else {
+                    emit!(self, "xor %edx, %edx");
+                    emit!(self, "div %rdi");
+                }
+                if op == BinaryOp::Mod {
+                    emit!(self, "mov %rdx, %rax");
+                }
+            }
+            BinaryOp::BitAnd => emit!(self, "and %rdi, %rax"),
+            BinaryOp::BitXor => emit!(self, "xor %rdi, %rax"),
+            BinaryOp::BitOr => emit!(self, "or %rdi, %rax"),
+            BinaryOp::Shl | BinaryOp::Shr => {
+                emit!(self, "mov %rdi, %rcx");
+                let inst = match op {
+                    BinaryOp::Shl => "shl",
+                    _ if is_signed => "sar",
+                    _ => "shr",
+                };
+                emit!(self, "{} %cl, %rax", inst);
+            }
+            _ => return self.compare(op, is_signed),
+        }
+        self.normalize(kind);
+    }
+
+    fn compare(&mut self, op: BinaryOp, is_signed: bool) {
+        let set = match (op, is_signed) {
+            (BinaryOp::Eq, _) => "sete",
+            (BinaryOp::Ne, _) => "setne",
+            (BinaryOp::Lt, true) => "setl",
+            (BinaryOp::Le, true) => "setle",
+            (BinaryOp::Gt, true) => "setg",
+            (BinaryOp::Ge, true) => "setge",
+            (BinaryOp::Lt, false) => "setb",
+            (BinaryOp::Le, false) => "setbe",
+            (BinaryOp::Gt, false) => "seta",
+            (BinaryOp::Ge, false) => "setae",
+            _ => unreachable!("operand types are checked"),
+        };
+        emit!(self, "cmp %rdi, %rax");
+        emit!(self, "{} %al", set);
+        emit!(self, "movzbq %al, %rax");
+    }
+
+    fn assign(&mut self, op: AssignOp, lhs: &Expr, rhs: &Expr) -> Result<()> {
+        let types = self.types;
+        let target = types.expr(lhs).unqualified();
+        let rt = types.expr(rhs).decay();
+        let op = match op.binary_op() {
+            None => {
+                self.addr(lhs)?;
+                self.push();
+                self.expr(rhs)?;
+                self.cast(&rt, &target);
+                self.pop("%rdi");
+                self.store(&target);
+                return Ok(());
+            }
+            Some(op) => op,
+        };
+
+        // the right operand stays below the address of the target
+        let ty = if target.is_pointer() {
+            self.expr(rhs)?;
+            let size = target.pointee().and_then(Type::size).unwrap_or(1);
+            emit!(self, "imul ${}, %rax", size);
+            self.push();
+            Type::int(IntKind::Long)
+        } else {
+            let ty = operand_type(op, &target, &rt);
+            let rhs_ty = match op {
+                BinaryOp::Shl | BinaryOp::Shr => promote(&rt),
+                _ => ty.clone(),
+            };
+            self.expr(rhs)?;
+            self.cast(&rt, &rhs_ty);
+            self.push_value(&rhs_ty);
+            ty
+        };
+        self.addr(lhs)?;
+        self.push();
+        self.load(&target);
+        if target.is_pointer() {
+            emit!(self, "mov 8(%rsp), %rdi");
+            let inst = if op == BinaryOp::Add { "add" } else { "sub" };
+            emit!(self, "{} %rdi, %rax", inst);
+        } else {
+            self.cast(&target, &ty);
+            let rhs_ty = match op {
+                BinaryOp::Shl | BinaryOp::Shr => promote(&rt),
+                _ => ty.clone(),
+            };
+            if rhs_ty.is_floating() {
+                emit!(self, "movsd 8(%rsp), %xmm1");
+            } else {
+                emit!(self, "mov 8(%rsp), %rdi");
+            }
+            self.operation(op, &ty);
+            self.cast(&ty, &target);
+        }
+        self.pop("%rdi");
+        emit!(self, "add $8, %rsp");
+        self.frame.depth -= 1;
+        self.store(&target);
+        Ok(())
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], ret: &Type) -> Result<()> {
+        let types = self.types;
+        let func = match types.expr(callee).decay().pointee().map(|ty| &ty.kind) {
+            Some(TypeKind::Function(func)) => func.clone(),
+            _ => unreachable!("callees are checked"),
+        };
+
+        // the argument types after conversion to the parameter types
+        // or the default argument promotions
+        let arg_types = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| match func.params.get(i) {
+                Some(param) => param.unqualified(),
+                None => match types.expr(arg).decay() {
+                    ty if matches!(ty.kind, TypeKind::Float(FloatKind::Float)) => {
+                        Type::new(TypeKind::Float(FloatKind::Double))
+                    }
+                    ty => promote(&ty),
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let ret_buffer = if ret.is_record() {
+            Some(self.alloc_local(ret))
+        } else {
+            None
+        };
+        let returns_in_memory = ret.is_record() && classify(ret) == Passing::Memory;
+
+        let mut gp = returns_in_memory as usize;
+        let mut fp = 0;
+        let mut in_registers = Vec::new();
+        let mut stack_size = 0;
+        for ty in &arg_types {
+            if is_long_double(ty) {
+                return Err(error(
+                    &callee.span,
+                    "'long double' is not supported by the code generator",
+                ));
+            }
+            let classes = match classify(ty) {
+                Passing::Registers(classes) => classes,
+                Passing::Memory => Vec::new(),
+            };
+            let needs_gp = classes.iter().filter(|&&c| c == Class::Integer).count();
+            let needs_fp = classes.len() - needs_gp;
+            if !classes.is_empty() && gp + needs_gp <= 6 && fp + needs_fp <= FP_ARGS {
+                gp += needs_gp;
+                fp += needs_fp;
+                in_registers.push(Some(classes));
+            } else {
+                stack_size += align_to(ty.size().unwrap_or(8), 8) as usize;
+                in_registers.push(None);
+            }
+        }
+
+        let padding = (self.frame.depth * 8 + stack_size) % 16;
+        if padding != 0 {
+            emit!(self, "sub $8, %rsp");
+            self.frame.depth += 1;
+        }
+        // stack arguments, the last one first
+        for (i, arg) in args.iter().enumerate().rev() {
+            if in_registers[i].is_some() {
+                continue;
+            }
+            let ty = &arg_types[i];
+            self.expr(arg)?;
+            self.cast(&types.expr(arg).decay(), ty);
+            if ty.is_record() {
+                let size = align_to(ty.size().unwrap_or(0), 8);
+                emit!(self, "sub ${}, %rsp", size);
+                emit!(self, "mov %rsp, %rdi");
+                emit!(self, "mov %rax, %rsi");
+                emit!(self, "mov ${}, %rcx", ty.size().unwrap_or(0));
+                emit!(self, "rep movsb");
+                self.frame.depth += size as usize / 8;
+            } else {
+                self.push_value(ty);
+            }
+        }
+        // register arguments, popped into place in order once all are evaluated
+        for (i, arg) in args.iter().enumerate().rev() {
+            let classes = match &in_registers[i] {
+                Some(classes) => classes,
+                None => continue,
+            };
+            let ty = &arg_types[i];
+            self.expr(arg)?;
+            self.cast(&types.expr(arg).decay(), ty);
+            if ty.is_record() {
+                let size = ty.size().unwrap_or(0);
+                for i in (0..classes.len()).rev() {
+                    self.load_eightbyte(
+                        "%rdx",
+                        Class::Integer,
+                        "%rax",
+                        i as i64 * 8,
+                        eightbyte_size(size, i),
+                    );
+                    emit!(self, "push %rdx");
+                    self.frame.depth += 1;
+                }
+            } else {
+                self.push_value(ty);
+            }
+        }
+
+        let is_direct =
+            matches!(callee.kind, ExprKind::Identifier(_)) && types.expr(callee).is_function();
+        if !is_direct {
+            self.expr(callee)?;
+            emit!(self, "mov %rax, %r10");
+        }
+        let (mut gp, mut fp) = (returns_in_memory as usize, 0);
+        for classes in in_registers.iter().flatten() {
+            for class in classes {
+                match class {
+                    Class::Integer => {
+                        self.pop(GP_ARGS[gp]);
+                        gp += 1;
+                    }
+                    Class::Sse => {
+                        emit!(self, "movsd (%rsp), %xmm{}", fp);
+                        emit!(self, "add $8, %rsp");
+                        self.frame.depth -= 1;
+                        fp += 1;
+                    }
+                }
+            }
+        }
+        if let (true, Some(buffer)) = (returns_in_memory, ret_buffer) {
+            emit!(self, "lea {}(%rbp), %rdi", buffer);
+        }
+        // the number of vector registers used, for variadic callees
+        emit!(self, "mov ${}, %eax", fp);
+        match &callee.kind {
+            ExprKind::Identifier(ident) if is_direct => {
+                if self.defined.contains(&*ident.value.as_str()) {
+                    emit!(self, "call {}", ident.value);
+                } else {
+                    emit!(self, "call {}@PLT", ident.value);
+                }
+            }
+            _ => emit!(self, "call *%r10"),
+        }
+        let pushed = stack_size / 8 + (padding != 0) as usize;
+        if pushed != 0 {
+            emit!(self, "add ${}, %rsp", pushed * 8);
+            self.frame.depth -= pushed;
+        }
+
+        match (ret_buffer, classify(ret)) {
+            (Some(buffer), Passing::Registers(classes)) => {
+                let size = ret.size().unwrap_or(0);
+                let (mut gp, mut fp) = (0, 0);
+                for (i, class) in classes.iter().enumerate() {
+                    let reg = match class {
+                        Class::Integer => {
+                            gp += 1;
+                            GP_RETURNS[gp - 1].to_owned()
+                        }
+                        Class::Sse => {
+                            fp += 1;
+                            format!("%xmm{}", fp - 1)
+                        }
+                    };
+                    let at = format!("{}(%rbp)", buffer + i as i64 * 8);
+                    self.store_eightbyte(&reg, *class, &at, eightbyte_size(size, i));
+                }
+                emit!(self, "lea {}(%rbp), %rax", buffer);
+            }
+            (Some(buffer), Passing::Memory) => emit!(self, "lea {}(%rbp), %rax", buffer),
+            (None, _) => {
+                // the callee leaves the upper bits of small integers unspecified
+                if let Some(kind) = ret.int_kind() {
+                    self.normalize(kind);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::interp;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source_file::SourceFile;
+
+    use std::fs;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    // compiles with `cc` and runs the program, or returns `None` if there is no `cc`
+    fn compile_and_run(src: &str) -> Option<(i32, String)> {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let checked = Checker::new().check(&unit);
+        assert!(checked.errors.is_empty(), "{:?}", checked.errors);
+        let asm = generate(&unit, &checked.types).unwrap();
+
+        let dir = std::env::temp_dir().join(format!(
+            "toy-compiler-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let asm_path = dir.join("main.s");
+        let exe_path = dir.join("main");
+        fs::write(&asm_path, &asm).unwrap();
+        let cc = Command::new("cc")
+            .arg("-o")
+            .arg(&exe_path)
+            .arg(&asm_path)
+            .output();
+        let cc = match cc {
+            Ok(cc) => cc,
+            Err(_) => {
+                eprintln!("skipped: `cc` is not available");
+                return None;
+            }
+        };
+        assert!(
+            cc.status.success(),
+            "{}\n{}",
+            String::from_utf8_lossy(&cc.stderr),
+            asm
+        );
+        let run = Command::new(&exe_path).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let status = run.status.code().unwrap();
+        Some((status, String::from_utf8(run.stdout).unwrap()))
+    }
+
+    // the compiled program must behave as the interpreter does
+    fn assert_same(src: &str) -> String {
+        let (tokens, _) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        let (unit, _) = Parser::from_tokens(tokens).parse();
+        let checked = Checker::new().check(&unit);
+        let mut out = Vec::new();
+        let main = interp::find_main(&unit).unwrap();
+        let status = interp::run(&unit, &checked.types, main, &mut out).unwrap();
+        let expected = (status & 0xff, String::from_utf8(out).unwrap());
+
+        if let Some(actual) = compile_and_run(src) {
+            assert_eq!(actual, expected);
+        }
+        expected.1
+    }
+
+    const PRELUDE: &str =
+        "int printf(const char *, ...); int puts(const char *); int putchar(int);\n";
+
+    #[test]
+    fn classification() {
+        let record = |fields: Vec<Type>| {
+            let fields = fields
+                .into_iter()
+                .map(|ty| Field {
+                    name: None,
+                    ty,
+                    bit_width: None,
+                })
+                .collect();
+            Type::new(TypeKind::Record(std::rc::Rc::new(std::cell::RefCell::new(
+                Record {
+                    kind: StructKind::Struct,
+                    tag: None,
+                    fields: Some(fields),
+                },
+            ))))
+        };
+        let int = Type::int(IntKind::Int);
+        let float = Type::new(TypeKind::Float(FloatKind::Float));
+        let double = Type::new(TypeKind::Float(FloatKind::Double));
+        assert_eq!(
+            classify(&record(vec![int.clone(), float.clone()])),
+            Passing::Registers(vec![Class::Integer])
+        );
+        assert_eq!(
+            classify(&record(vec![double.clone(), int.clone()])),
+            Passing::Registers(vec![Class::Sse, Class::Integer])
+        );
+        assert_eq!(
+            classify(&record(vec![float.clone(), float, double.clone()])),
+            Passing::Registers(vec![Class::Sse, Class::Sse])
+        );
+        assert_eq!(
+            classify(&record(vec![double.clone(), double.clone(), double])),
+            Passing::Memory
+        );
+        assert_eq!(sub_register("%rdi", 1), "%dil");
+        assert_eq!(sub_register("%rdx", 4), "%edx");
+        assert_eq!(sub_register("%r8", 2), "%r8w");
+    }
+
+    #[test]
+    fn misplaced_jumps() {
+        let error = |src: &str| {
+            let (tokens, _) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+            let (unit, _) = Parser::from_tokens(tokens).parse();
+            let checked = Checker::new().check(&unit);
+            generate(&unit, &checked.types).unwrap_err().msg
+        };
+        assert_eq!(
+            error("int main(void) { break; return 0; }"),
+            "'break' statement not in loop or switch statement"
+        );
+        assert_eq!(
+            error("int main(void) { switch (1) { continue; } return 0; }"),
+            "'continue' statement not in loop statement"
+        );
+        assert_eq!(
+            error("int main(void) { if (1) { case 1: return 1; } return 0; }"),
+            "'case' statement not in switch statement"
+        );
+        assert_eq!(
+            error("int main(void) { default: return 0; }"),
+            "'default' statement not in switch statement"
+        );
+    }
+
+    #[test]
+    fn exit_status() {
+        let src = "int main(void) { int a = 6, b = 7; return a * b; }";
+        assert_eq!(
+            compile_and_run(src).map(|(status, _)| status).unwrap_or(42),
+            42
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            int main(void) {
+                int a = 7, b = -2;
+                unsigned u = 1;
+                unsigned long big = 18446744073709551615UL;
+                double d = 1.5;
+                float f = 0.25f;
+                char c = 200;
+                short s = -3;
+                printf("%d %d %d %d\n", a / b, a % b, a << 3, -a >> 1);
+                printf("%d %u %d %d %lu\n", u > -1, u - 2, c, s * s, big / 3);
+                printf("%.2f %d %f %g %d\n", d * a, (int)(d * 3), 1 / 3.0f, f * 2 - d, (int)-d);
+                printf("%f %lu %d\n", (double)big, (unsigned long)1.0e19, d > f && !(d != d));
+                a += 5; a *= 2; b -= a; a >>= 1; u <<= 4; d /= 4; f -= 1;
+                printf("%d %d %u %g %g ", a, b, u, d, f);
+                printf("%d\n", (a++, ++a));
+                int max = 2147483647;
+                printf("%d %lu %d\n", max + 1, sizeof max, (_Bool)0.5 + (c < s));
+                return a;
+            }
+            "#
+        );
+        assert_same(&src);
+    }
+
+    #[test]
+    fn control_flow() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            int fib(int n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+            int main(void) {
+                int i, sum = 0;
+                for (i = 0; i < 10; i++) {
+                    if (i == 2) continue;
+                    if (i == 8) break;
+                    sum += i;
+                }
+                while (sum > 20) sum -= 7;
+                do { sum++; } while (sum < 18);
+                printf("%d %d\n", sum, fib(15));
+                for (i = 0; i < 5; i++) {
+                    switch (i) {
+                    case 0: putchar('a');
+                    case 1: putchar('b'); break;
+                    case 3: { putchar('c'); continue; }
+                    default: putchar('d');
+                    }
+                }
+                i = 0;
+            again:
+                if (++i < 3) goto again;
+                printf("\n%d %s\n", i, __func__);
+                return sum;
+            }
+            "#
+        );
+        assert_same(&src);
+    }
+
+    #[test]
+    fn memory() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            struct point { int x, y; };
+            struct pair { double a; long b; };
+            struct big { long v[4]; };
+            struct point make(int x, int y) { struct point p = { x, y }; return p; }
+            struct pair swap_pair(struct pair p) { struct pair q = { p.b, p.a }; return q; }
+            struct big fill(long n) { struct big b; for (int i = 0; i < 4; i++) b.v[i] = n * i; return b; }
+            long sum_big(struct big b) { return b.v[0] + b.v[1] + b.v[2] + b.v[3]; }
+            void swap(int *a, int *b) { int t = *a; *a = *b; *b = t; }
+            int counter(void) { static int n = 10; return n++; }
+            long many(long a, long b, long c, long d, long e, long f, long g, double h, long i) {
+                return a + b + c + d + e + f + g * 10 + (long)h + i * 100;
+            }
+            int squares[5] = { [2] = 4, 9 };
+            char greeting[] = "hi";
+            const char *names[] = { "zero", "one" };
+            int *second = &squares[1] + 1;
+            struct point origin = { .y = 3 };
+            int main(void) {
+                int a[4] = { 1, 2 }, *p = a + 1;
+                struct point pts[2] = { 1, 2, { .y = 5 } }, *q = &pts[1];
+                swap(&a[0], p);
+                printf("%d %d %d %d %ld\n", a[0], a[1], a[2], p[-1], &a[4] - p);
+                printf("%d %d %d %d\n", pts[0].y, q->x, q->y, make(3, 4).y);
+                struct pair zero = { 0 }, s = swap_pair(zero);
+                struct pair t = { 1.5, 7 };
+                t = swap_pair(t);
+                printf("%g %ld %g %ld\n", s.a, s.b, t.a, t.b);
+                printf("%ld %ld\n", fill(3).v[3], sum_big(fill(2)));
+                printf("%ld\n", many(1, 2, 3, 4, 5, 6, 7, 8.5, 9));
+                counter();
+                printf("%d %d %d %d %d\n", counter(), squares[2], squares[3], *second, origin.y);
+                const char *str = "hello";
+                puts(str + 1);
+                char buf[8] = "abc";
+                buf[1] = 'X';
+                printf("%s %c %zu %s %s %d\n", greeting, str[4], sizeof greeting, names[1], buf, buf[7]);
+                int (*fp)(void) = counter;
+                return fp() + origin.x;
+            }
+            "#
+        );
+        assert_same(&src);
+    }
+}
@@ -0,0 +1,480 @@
+use crate::source_map::Location;
+use crate::span::{LineColumn, Span};
+use crate::utils::number_width;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        })
+    }
+}
+
+// numbers are part of the interface: never renumber or reuse one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    // lexer
+    UnclosedComment = 1,
+    UnclosedString = 2,
+    UnclosedChar = 3,
+    EmptyChar = 4,
+    InvalidEscape = 5,
+    UnexpectedChar = 6,
+    InvalidNumber = 7,
+    MultiCharConstant = 8,
+    NonAscii = 9,
+
+    // preprocessor
+    ErrorDirective = 100,
+    InvalidDirective = 101,
+    UnbalancedConditional = 102,
+    FileNotFound = 103,
+    InvalidInclude = 104,
+    InvalidMacroDefinition = 105,
+    MacroArguments = 106,
+    InvalidPaste = 107,
+    InvalidPreprocessorExpr = 108,
+    InvalidLineDirective = 109,
+
+    // parser
+    ExpectedToken = 200,
+    UnbalancedBrace = 201,
+    InvalidTypeName = 202,
+    UnsupportedConcatenation = 203,
+
+    // resolver
+    UndeclaredIdentifier = 300,
+    UndeclaredLabel = 301,
+    Redefinition = 302,
+    TagMismatch = 303,
+    DuplicateMember = 304,
+
+    // checker
+    IncompleteType = 400,
+    NotConstant = 401,
+    ConflictingTypes = 402,
+    OutsideLoopOrSwitch = 403,
+    DuplicateCase = 404,
+    InvalidDeclarator = 405,
+    InvalidBitField = 406,
+    EnumeratorOverflow = 407,
+    InvalidInitializer = 408,
+    IncompatibleTypes = 409,
+    InvalidOperands = 410,
+    NotAssignable = 411,
+    InvalidCast = 412,
+    InvalidCall = 413,
+    InvalidMember = 414,
+    UnexpectedTypeName = 415,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "E{:04}", *self as u16)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub msg: String,
+}
+
+// replacing an empty span inserts, an empty replacement deletes
+#[derive(Debug, Clone)]
+pub struct FixIt {
+    pub span: Span,
+    pub replacement: String,
+    pub msg: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<Code>,
+    pub msg: String,
+    pub span: Span,
+    pub label: Option<String>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    pub fixits: Vec<FixIt>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, span: Span, msg: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            msg: msg.into(),
+            span,
+            label: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            fixits: Vec::new(),
+        }
+    }
+
+    pub fn error(span: Span, msg: impl Into<String>) -> Self {
+        Self::new(Severity::Error, span, msg)
+    }
+
+    pub fn warning(span: Span, msg: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, span, msg)
+    }
+
+    pub fn with_code(mut self, code: Code) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    // text shown under the primary span
+    pub fn with_label(mut self, msg: impl Into<String>) -> Self {
+        self.label = Some(msg.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, msg: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            msg: msg.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, msg: impl Into<String>) -> Self {
+        self.notes.push(msg.into());
+        self
+    }
+
+    pub fn with_help(mut self, msg: impl Into<String>) -> Self {
+        self.help.push(msg.into());
+        self
+    }
+
+    pub fn with_fixit(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        msg: impl Into<String>,
+    ) -> Self {
+        self.fixits.push(FixIt {
+            span,
+            replacement: replacement.into(),
+            msg: msg.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+// ANSI SGR parameters
+const BOLD: &str = "1";
+const LOCATION: &str = "1;34";
+const ADDITION: &str = "1;32";
+
+impl Severity {
+    fn style(self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+            Severity::Note => "1;32",
+            Severity::Help => "1;36",
+        }
+    }
+}
+
+// snippets are read from the files in this thread's source map
+#[derive(Default)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let style = diagnostic.severity.style();
+        let mut out = match diagnostic.code {
+            Some(code) => self.paint(style, &format!("{}[{}]", diagnostic.severity, code)),
+            None => self.paint(style, &diagnostic.severity.to_string()),
+        };
+        out += &self.paint(BOLD, &format!(": {}", diagnostic.msg));
+        out.push('\n');
+        let label = diagnostic.label.as_deref().unwrap_or("");
+        out += &self.annotate(diagnostic.span, '^', label, style);
+        for label in &diagnostic.labels {
+            out += &self.annotate(label.span, '-', &label.msg, LOCATION);
+        }
+        let mut expansion = diagnostic.span.expansion();
+        while let Some(site) = expansion {
+            out += &self.annotate(site, '-', "in expansion of macro", LOCATION);
+            expansion = site.expansion();
+        }
+        let mut include = diagnostic.span.included_from();
+        while let Some(site) = include {
+            out += &self.annotate(site, '-', "included from here", LOCATION);
+            include = site.included_from();
+        }
+        for (severity, msgs) in [
+            (Severity::Note, &diagnostic.notes),
+            (Severity::Help, &diagnostic.help),
+        ] {
+            for msg in msgs {
+                let prefix = self.paint(BOLD, &format!("{}:", severity));
+                out += &format!(" {} {} {}\n", self.paint(LOCATION, "="), prefix, msg);
+            }
+        }
+        for fixit in &diagnostic.fixits {
+            let help = Severity::Help;
+            out += &format!(
+                "{}: {}\n",
+                self.paint(help.style(), &help.to_string()),
+                fixit.msg
+            );
+            out += &self.fixit(fixit);
+        }
+        out
+    }
+
+    // the source lines covered by `span`, with `marker` under the spanned columns
+    pub fn snippet(&self, span: Span, marker: char, label: &str) -> String {
+        self.annotate(span, marker, label, BOLD)
+    }
+
+    fn annotate(&self, span: Span, marker: char, label: &str, style: &str) -> String {
+        let loc = span.location();
+        let LineColumn { line, column } = loc.lc_range.start;
+        let end = loc.lc_range.end;
+        let mut out = self.location(&loc);
+        let file = match &loc.file {
+            Some(file) if end.line <= file.line_count() => file,
+            _ => return out,
+        };
+
+        let width = number_width(end.line);
+        out += &self.gutter(None, width);
+        out.push('\n');
+        for lineno in line..=end.line {
+            if lineno > line + 1 && lineno < end.line {
+                if lineno == line + 2 {
+                    out += &self.paint(LOCATION, "...");
+                    out.push('\n');
+                }
+                continue;
+            }
+            let text = file.line(lineno).unwrap();
+            let from = if lineno == line { column } else { 1 };
+            let to = if lineno == end.line {
+                end.column.max(from + 1)
+            } else {
+                text.chars().count() + 1
+            };
+            out += &self.gutter(Some(lineno), width);
+            out.push(' ');
+            out += text;
+            out.push('\n');
+            out += &self.gutter(None, width);
+            out.push(' ');
+            let mut marks = underline(text, from..to, marker);
+            if lineno == end.line && !label.is_empty() {
+                marks.push(' ');
+                marks += label;
+            }
+            out += &self.paint(style, &marks);
+            out.push('\n');
+        }
+        out
+    }
+
+    // the line as it reads after applying the fix-it
+    fn fixit(&self, fixit: &FixIt) -> String {
+        let span = fixit.span;
+        let loc = span.location();
+        let LineColumn { line, column } = loc.lc_range.start;
+        let end = loc.lc_range.end;
+        let text = match &loc.file {
+            Some(file) if line == end.line && line <= file.line_count() => file.line(line).unwrap(),
+            _ => return self.annotate(span, '~', "", ADDITION),
+        };
+        if fixit.replacement.is_empty() {
+            return self.annotate(span, '-', "", Severity::Error.style());
+        }
+
+        let chars: Vec<char> = text.chars().collect();
+        let split = |col: usize| (col - 1).min(chars.len());
+        let mut fixed: String = chars[..split(column)].iter().collect();
+        fixed += &fixit.replacement;
+        fixed.extend(&chars[split(end.column)..]);
+
+        let marker = if column == end.column { '+' } else { '~' };
+        let to = column + fixit.replacement.chars().count();
+        let width = number_width(line);
+        format!(
+            "{}{}\n{} {}\n{} {}\n",
+            self.location(&loc),
+            self.gutter(None, width),
+            self.gutter(Some(line), width),
+            fixed,
+            self.gutter(None, width),
+            self.paint(ADDITION, &underline(&fixed, column..to, marker)),
+        )
+    }
+
+    fn location(&self, loc: &Location) -> String {
+        let start = loc.lc_range.start;
+        let arrow = self.paint(LOCATION, "-->");
+        format!(
+            " {} {}:{}:{}\n",
+            arrow, loc.file_path, start.line, start.column
+        )
+    }
+
+    fn gutter(&self, lineno: Option<usize>, width: usize) -> String {
+        let text = match lineno {
+            Some(n) => format!("{:>w$} |", n, w = width),
+            None => format!("{:w$} |", "", w = width),
+        };
+        self.paint(LOCATION, &text)
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.into()
+        }
+    }
+}
+
+// marks the 1-based columns in `columns`, keeping tabs so the marks line up
+fn underline(text: &str, columns: std::ops::Range<usize>, marker: char) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    for col in 1..columns.end {
+        let ch = chars.next();
+        if col >= columns.start {
+            out.push(marker);
+        } else if ch == Some('\t') {
+            out.push('\t');
+        } else {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_file::SourceFile;
+    use crate::source_map;
+
+    #[test]
+    fn codes() {
+        assert_eq!(Code::UnclosedComment.to_string(), "E0001");
+        assert_eq!(Code::Redefinition.to_string(), "E0302");
+    }
+
+    #[test]
+    fn render() {
+        let renderer = Renderer::new();
+        let src = SourceFile::new("int x;\n\tint x = 1\n", "a.c");
+        let span = |range| src.span(range);
+
+        let diagnostic = Diagnostic::error(span(12..13), "redefinition of 'x'")
+            .with_code(Code::Redefinition)
+            .with_label("redefined here")
+            .with_secondary(span(4..5), "previous definition is here")
+            .with_note("'x' has external linkage")
+            .with_fixit(span(17..17), ";", "add a semicolon");
+        let expected = "\
+error[E0302]: redefinition of 'x'
+ --> a.c:2:6
+  |
+2 | \tint x = 1
+  | \t    ^ redefined here
+ --> a.c:1:5
+  |
+1 | int x;
+  |     - previous definition is here
+ = note: 'x' has external linkage
+help: add a semicolon
+ --> a.c:2:11
+  |
+2 | \tint x = 1;
+  | \t         +
+";
+        assert_eq!(renderer.render(&diagnostic), expected);
+
+        let warning = Diagnostic::warning(span(0..10), "spans lines");
+        let expected = "\
+warning: spans lines
+ --> a.c:1:1
+  |
+1 | int x;
+  | ^^^^^^
+2 | \tint x = 1
+  | ^^^
+";
+        assert_eq!(renderer.render(&warning), expected);
+
+        // files that were never read only get a location
+        let elsewhere = source_map::with(|sm| {
+            sm.add_external(Location {
+                file_path: "b.c".into(),
+                byte_range: 0..1,
+                lc_range: LineColumn { line: 1, column: 1 }..LineColumn { line: 1, column: 2 },
+                file: None,
+            })
+        });
+        let expected = "error: missing\n --> b.c:1:1\n";
+        assert_eq!(
+            renderer.render(&Diagnostic::error(elsewhere, "missing")),
+            expected
+        );
+    }
+
+    #[test]
+    fn include_chain() {
+        let main = SourceFile::new("#include \"a.h\"\n", "main.c");
+        let header = SourceFile::included("int x\n", "a.h", main.span(0..14));
+        let diagnostic = Diagnostic::error(header.span(5..5), "expected ';'");
+        let expected = "\
+error: expected ';'
+ --> a.h:1:6
+  |
+1 | int x
+  |      ^
+ --> main.c:1:1
+  |
+1 | #include \"a.h\"
+  | -------------- included from here
+";
+        assert_eq!(Renderer::new().render(&diagnostic), expected);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    ast::*,
+    checker::{convert_value, ConstValue, TypeTable},
+    diagnostics::Diagnostic,
+    interner::Symbol,
+    tokens::StringLiteral,
+    types::*,
+};
+
+// a scalar, or an aggregate initialized directly from an expression, at its
+// offset in the initialized object; a string literal initializing a character
+// array has the array type
+#[derive(Debug)]
+pub struct InitEntry<'e> {
+    pub offset: u64,
+    pub ty: Type,
+    pub expr: &'e Expr,
+}
+
+impl<'e> InitEntry<'e> {
+    // the string literal initializing a character array, with how many of its
+    // bytes fit: `char s[3] = "abc"` leaves out the terminating NUL
+    pub fn string(&self) -> Option<(&'e StringLiteral, u64)> {
+        match (&self.ty.kind, &self.expr.kind) {
+            (TypeKind::Array(..), ExprKind::StringLiteral(s)) => {
+                let len = s.bytes().len() as u64;
+                Some((s, len.min(self.ty.size().unwrap_or(len))))
+            }
+            _ => None,
+        }
+    }
+}
+
+// the size of an object defined with type `ty`; a tentative definition of an
+// array of unknown size has one element
+pub fn object_size(ty: &Type) -> u64 {
+    match &ty.kind {
+        TypeKind::Array(elem, None) => elem.size(),
+        _ => ty.size(),
+    }
+    .unwrap_or(0)
+}
+
+// whether an automatic object is to be zeroed before its entries are stored,
+// as members without an entry are zero, and a string literal may be shorter
+// than the array it initializes
+pub fn zeroes_first(ty: &Type, init: &Initializer) -> bool {
+    matches!(init, Initializer::List(..)) || ty.is_array()
+}
+
+// the entries of a checked initializer in the order they are to be stored,
+// following the checker's rules for brace elision and designators;
+// members without an entry are zero-initialized
+pub fn flatten<'e>(types: &TypeTable, ty: &Type, init: &'e Initializer) -> Vec<InitEntry<'e>> {
+    let mut flattener = Flattener {
+        types,
+        entries: Vec::new(),
+    };
+    flattener.initialize(0, ty, init);
+    flattener.entries
+}
+
+struct Flattener<'t, 'e> {
+    types: &'t TypeTable,
+    entries: Vec<InitEntry<'e>>,
+}
+
+// the members of a record with their offsets
+fn fields(ty: &Type) -> Vec<(u64, Type)> {
+    match &ty.kind {
+        TypeKind::Record(record) => {
+            let record = record.borrow();
+            let offsets = record.layout().map(|layout| layout.offsets);
+            record
+                .fields
+                .iter()
+                .flatten()
+                .zip(offsets.into_iter().flatten())
+                .map(|(field, offset)| (offset, field.ty.clone()))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl<'t, 'e> Flattener<'t, 'e> {
+    fn initialize(&mut self, offset: u64, ty: &Type, init: &'e Initializer) {
+        match init {
+            Initializer::Expr(expr) => self.entries.push(InitEntry {
+                offset,
+                ty: ty.clone(),
+                expr,
+            }),
+            Initializer::List(items, _) if ty.is_aggregate() => {
+                self.aggregate(offset, ty, items, &mut 0, true)
+            }
+            Initializer::List(items, _) => {
+                if let Some(item) = items.first() {
+                    self.initialize(offset, ty, &item.init);
+                }
+            }
+        }
+    }
+
+    fn aggregate(
+        &mut self,
+        offset: u64,
+        ty: &Type,
+        items: &'e [InitItem],
+        pos: &mut usize,
+        braced: bool,
+    ) {
+        let fields = fields(ty);
+        let positional = match &ty.kind {
+            TypeKind::Record(record) if record.borrow().kind == StructKind::Union => 1,
+            _ => fields.len(),
+        };
+
+        let mut idx = 0;
+        while let Some(item) = items.get(*pos) {
+            if let Some(designator) = item.designators.first() {
+                if !braced {
+                    break;
+                }
+                *pos += 1;
+                let (i, off, target) = self.designated(ty, designator, &fields);
+                idx = i;
+                self.designated_rest(offset + off, &target, &item.designators[1..], &item.init);
+            } else {
+                let (off, target) = match &ty.kind {
+                    TypeKind::Array(elem, size) if size.is_none_or(|size| idx < size) => {
+                        (idx * elem.size().unwrap_or(0), (**elem).clone())
+                    }
+                    TypeKind::Record(_) if (idx as usize) < positional.min(fields.len()) => {
+                        fields[idx as usize].clone()
+                    }
+                    // excess elements, which the checker has warned about
+                    _ if braced => {
+                        *pos = items.len();
+                        break;
+                    }
+                    _ => break,
+                };
+                self.element(offset + off, &target, items, pos);
+            }
+            idx += 1;
+        }
+    }
+
+    fn element(&mut self, offset: u64, ty: &Type, items: &'e [InitItem], pos: &mut usize) {
+        let item = &items[*pos];
+        let e = match &item.init {
+            Initializer::Expr(e) if ty.is_aggregate() => e,
+            init => {
+                *pos += 1;
+                return self.initialize(offset, ty, init);
+            }
+        };
+
+        let is_direct = match &e.kind {
+            ExprKind::StringLiteral(_) => ty.is_array(),
+            _ => ty.is_record() && self.types.expr(e).is_compatible_unqualified(ty),
+        };
+        if is_direct {
+            *pos += 1;
+            self.initialize(offset, ty, &item.init);
+        } else {
+            self.aggregate(offset, ty, items, pos, false);
+        }
+    }
+
+    // the element index, offset and type a designator refers to
+    fn designated(
+        &self,
+        ty: &Type,
+        designator: &Designator,
+        fields: &[(u64, Type)],
+    ) -> (u64, u64, Type) {
+        match (&ty.kind, designator) {
+            (TypeKind::Array(elem, _), Designator::Index(e)) => {
+                let idx = match self.types.value(e) {
+                    Some(ConstValue::Int(idx)) => idx as u64,
+                    _ => 0,
+                };
+                (idx, idx * elem.size().unwrap_or(0), (**elem).clone())
+            }
+            (TypeKind::Record(record), Designator::Member(name)) => {
+                let idx = record
+                    .borrow()
+                    .fields
+                    .iter()
+                    .flatten()
+                    .position(|f| f.name == Some(name.value))
+                    .unwrap_or(0);
+                let (offset, ty) = fields[idx].clone();
+                (idx as u64, offset, ty)
+            }
+            _ => unreachable!("designators are checked"),
+        }
+    }
+
+    fn designated_rest(
+        &mut self,
+        offset: u64,
+        ty: &Type,
+        designators: &[Designator],
+        init: &'e Initializer,
+    ) {
+        match designators.split_first() {
+            None => self.initialize(offset, ty, init),
+            Some((designator, rest)) => {
+                let (_, off, target) = self.designated(ty, designator, &fields(ty));
+                self.designated_rest(offset + off, &target, rest, init);
+            }
+        }
+    }
+}
+
+// what an address constant points to; identifiers are resolved by the caller
+#[derive(Debug, Clone, Copy)]
+pub enum Target<'e> {
+    Symbol(Symbol),
+    String(&'e StringLiteral),
+}
+
+#[derive(Debug)]
+pub struct Relocation<'e> {
+    pub offset: u64,
+    pub target: Target<'e>,
+    pub addend: i64,
+    pub expr: &'e Expr,
+}
+
+// the bytes of an object with static storage duration, with the 8-byte
+// address constants to be filled in at link time
+#[derive(Debug)]
+pub struct StaticImage<'e> {
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<Relocation<'e>>,
+}
+
+pub fn static_image<'e>(
+    types: &TypeTable,
+    ty: &Type,
+    size: u64,
+    init: &'e Initializer,
+) -> Result<StaticImage<'e>, Box<Diagnostic>> {
+    let mut image = StaticImage {
+        bytes: vec![0; size as usize],
+        relocations: Vec::new(),
+    };
+    for entry in flatten(types, ty, init) {
+        let offset = entry.offset as usize;
+        let e = entry.expr;
+        if let Some((s, len)) = entry.string() {
+            let len = len as usize;
+            image.bytes[offset..offset + len].copy_from_slice(&s.bytes()[..len]);
+            continue;
+        }
+
+        let source = types.expr(e).decay();
+        let size = entry.ty.size().unwrap_or(0) as usize;
+        let is_long_double = matches!(entry.ty.kind, TypeKind::Float(FloatKind::LongDouble));
+        match types
+            .value(e)
+            .and_then(|v| convert_value(v, &source, &entry.ty))
+        {
+            Some(value) if !is_long_double => {
+                let encoded = match (value, &entry.ty.kind) {
+                    (ConstValue::Float(v), TypeKind::Float(FloatKind::Float)) => {
+                        (v as f32).to_le_bytes().to_vec()
+                    }
+                    (ConstValue::Float(v), _) => v.to_le_bytes().to_vec(),
+                    (ConstValue::Int(v), _) => v.to_le_bytes().to_vec(),
+                };
+                image.bytes[offset..offset + size].copy_from_slice(&encoded[..size]);
+            }
+            _ => match static_address(types, e) {
+                Some((target, addend)) if size == 8 => image.relocations.push(Relocation {
+                    offset: entry.offset,
+                    target,
+                    addend,
+                    expr: e,
+                }),
+                _ => {
+                    return Err(Box::new(Diagnostic::error(
+                        e.span,
+                        "initializer element is not a compile-time constant",
+                    )))
+                }
+            },
+        }
+    }
+    image
+        .relocations
+        .sort_by_key(|relocation| relocation.offset);
+    Ok(image)
+}
+
+// the address constants of a static initializer with the expressions they
+// come from, or the first element that is neither them nor an arithmetic
+// constant; identifiers are for the caller to check, as with relocations
+pub fn constants<'e>(
+    types: &TypeTable,
+    ty: &Type,
+    init: &'e Initializer,
+) -> Result<Vec<(Target<'e>, &'e Expr)>, &'e Expr> {
+    let mut targets = Vec::new();
+    for entry in flatten(types, ty, init) {
+        let e = entry.expr;
+        if entry.string().is_some() || types.value(e).is_some() {
+            continue;
+        }
+        match static_address(types, e) {
+            Some((target, _)) if entry.ty.size() == Some(8) => targets.push((target, e)),
+            _ => return Err(e),
+        }
+    }
+    Ok(targets)
+}
+
+fn constant_index(types: &TypeTable, e: &Expr) -> Option<i64> {
+    match types.value(e)? {
+        ConstValue::Int(v) => Some(v),
+        ConstValue::Float(_) => None,
+    }
+}
+
+// the target and addend of an address constant, C11 6.6p9
+pub fn static_address<'e>(types: &TypeTable, expr: &'e Expr) -> Option<(Target<'e>, i64)> {
+    let ty = types.expr(expr);
+    match &expr.kind {
+        ExprKind::StringLiteral(_) | ExprKind::Identifier(_)
+            if ty.is_array() || ty.is_function() =>
+        {
+            static_lvalue(types, expr)
+        }
+        ExprKind::Unary(UnaryOp::Address, operand) => static_lvalue(types, operand),
+        ExprKind::Cast(_, operand) if ty.size() == Some(8) => static_address(types, operand),
+        ExprKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+            let (pointer, index) = if types.expr(lhs).decay().is_pointer() {
+                (lhs, rhs)
+            } else {
+                (rhs, lhs)
+            };
+            let (target, addend) = static_address(types, pointer)?;
+            let step = types.expr(pointer).decay().pointee()?.size().unwrap_or(1) as i64;
+            let delta = constant_index(types, index)?.wrapping_mul(step);
+            match op {
+                BinaryOp::Add => Some((target, addend + delta)),
+                _ => Some((target, addend - delta)),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn static_lvalue<'e>(types: &TypeTable, expr: &'e Expr) -> Option<(Target<'e>, i64)> {
+    match &expr.kind {
+        ExprKind::Identifier(ident) => Some((Target::Symbol(ident.value), 0)),
+        ExprKind::StringLiteral(s) => Some((Target::String(s), 0)),
+        ExprKind::Member(base, member) => {
+            let (target, addend) = static_lvalue(types, base)?;
+            let offset = match &types.expr(base).kind {
+                TypeKind::Record(record) => record.borrow().member(member.value)?.0,
+                _ => return None,
+            };
+            Some((target, addend + offset as i64))
+        }
+        ExprKind::Index(lhs, rhs) => {
+            let (array, index) = if types.expr(lhs).decay().is_pointer() {
+                (lhs, rhs)
+            } else {
+                (rhs, lhs)
+            };
+            let (target, addend) = static_address(types, array)?;
+            let step = types.expr(expr).size().unwrap_or(1) as i64;
+            Some((
+                target,
+                addend + constant_index(types, index)?.wrapping_mul(step),
+            ))
+        }
+        ExprKind::Unary(UnaryOp::Deref, operand) => static_address(types, operand),
+        _ => None,
+    }
+}
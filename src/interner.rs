@@ -0,0 +1,164 @@
+use crate::session;
+use crate::tokens::KeywordKind;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// an interned string, equal to another symbol exactly when their strings
+// are; like a span, it is resolved in the session of the thread that made it
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+// the strings are shared between the map and the list, and freed with the
+// session
+pub struct Interner {
+    symbols: RefCell<HashMap<Rc<str>, Symbol>>,
+    // indexed by symbol
+    strings: RefCell<Vec<Rc<str>>>,
+}
+
+// the interner of this thread's session
+pub fn with<R>(f: impl FnOnce(&Interner) -> R) -> R {
+    session::with(|session| f(&session.interner))
+}
+
+impl Default for Interner {
+    // the keywords go first, so that the symbol of a keyword is its kind
+    fn default() -> Self {
+        let interner = Interner {
+            symbols: RefCell::default(),
+            strings: RefCell::default(),
+        };
+        for kind in KeywordKind::ALL {
+            interner.intern(kind.as_str());
+        }
+        interner
+    }
+}
+
+impl Interner {
+    pub fn intern(&self, s: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.borrow().get(s) {
+            return symbol;
+        }
+        let s: Rc<str> = s.into();
+        let mut strings = self.strings.borrow_mut();
+        let symbol = Symbol::new(u32::try_from(strings.len()).expect("more than 4G symbols"));
+        strings.push(s.clone());
+        self.symbols.borrow_mut().insert(s, symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> Rc<str> {
+        self.strings.borrow()[symbol.0 as usize].clone()
+    }
+
+    // `f` of the string, without sharing it; the strings stay borrowed
+    // while `f` runs, so it must not intern
+    pub fn with_str<R>(&self, symbol: Symbol, f: impl FnOnce(&str) -> R) -> R {
+        f(&self.strings.borrow()[symbol.0 as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Symbol {
+    fn new(idx: u32) -> Self {
+        Symbol(idx, PhantomData)
+    }
+
+    pub fn intern(s: &str) -> Self {
+        with(|interner| interner.intern(s))
+    }
+
+    // the string, shared with the interner
+    pub fn as_str(self) -> Rc<str> {
+        with(|interner| interner.resolve(self))
+    }
+
+    // `f` must not intern, see `Interner::with_str`; use `as_str` if it may
+    pub fn with_str<R>(self, f: impl FnOnce(&str) -> R) -> R {
+        with(|interner| interner.with_str(self, f))
+    }
+
+    pub fn len(self) -> usize {
+        self.with_str(str::len)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    pub fn keyword(self) -> Option<KeywordKind> {
+        KeywordKind::ALL.get(self.0 as usize).copied()
+    }
+}
+
+impl KeywordKind {
+    pub fn symbol(self) -> Symbol {
+        Symbol::new(self as u32)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.with_str(|s| s == other)
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.with_str(|s| s == *other)
+    }
+}
+
+// as the string, which is what tokens and diagnostics show
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_str(|s| fmt::Debug::fmt(s, f))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_str(|s| f.write_str(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let a = Symbol::intern("main");
+        let b = Symbol::intern(&String::from("main"));
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("mai"));
+        assert_eq!(&*a.as_str(), "main");
+        assert_eq!(a, "main");
+        assert_eq!(format!("{} {:?}", a, a), "main \"main\"");
+        assert_eq!(a.len(), 4);
+
+        for kind in KeywordKind::ALL {
+            assert_eq!(Symbol::intern(kind.as_str()), kind.symbol());
+            assert_eq!(kind.symbol().keyword(), Some(kind));
+        }
+        assert_eq!(a.keyword(), None);
+        assert!(with(|interner| interner.len()) > KeywordKind::ALL.len());
+    }
+}
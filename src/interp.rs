@@ -0,0 +1,1303 @@
+mod printf;
+
+use crate::{
+    ast::*,
+    checker::{convert_value, fold_binary, ConstValue, TypeTable},
+    diagnostics::Diagnostic,
+    initializer,
+    interner::Symbol,
+    span::Span,
+    tokens::StringLiteral,
+    types::*,
+};
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Write;
+use std::mem;
+
+// every interpreted call takes several host frames, see `main`
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    // integers and pointers
+    Int(i64),
+    Float(f64),
+    // structures and unions, by their object representation
+    Bytes(Vec<u8>),
+    Void,
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+    Goto(Symbol, Span),
+}
+
+struct Allocation {
+    bytes: Vec<u8>,
+    is_string_literal: bool,
+}
+
+// an address is `(allocation << 32) | offset`, allocation 0 being the null pointer;
+// freed allocations are never reused so that dangling pointers can be detected
+struct Memory {
+    allocations: Vec<Option<Allocation>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Builtin {
+    Printf,
+    Puts,
+    Putchar,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function<'a> {
+    Defined(&'a FunctionDef),
+    Builtin(Builtin),
+    Undefined,
+}
+
+#[derive(Default)]
+struct Scope {
+    objects: HashMap<Symbol, u64>,
+    allocations: Vec<u64>,
+}
+
+pub struct Interpreter<'a, W> {
+    types: &'a TypeTable,
+    out: W,
+    memory: Memory,
+    globals: HashMap<Symbol, u64>,
+    // by the address of their zero-sized allocation
+    functions: HashMap<u64, (Symbol, Function<'a>)>,
+    statics: HashMap<*const Declarator, u64>,
+    strings: HashMap<*const StringLiteral, u64>,
+    // of the function being executed
+    scopes: Vec<Scope>,
+    ret: Type,
+    depth: usize,
+}
+
+pub fn find_main(unit: &TranslationUnit) -> Option<&FunctionDef> {
+    unit.items.iter().find_map(|item| match item {
+        ExternalDecl::Function(f) if declarator_name(&f.declarator) == "main" => Some(f),
+        _ => None,
+    })
+}
+
+// runs a checked translation unit and returns the exit status of `main`
+pub fn run<'a, W: Write>(
+    unit: &'a TranslationUnit,
+    types: &'a TypeTable,
+    main: &'a FunctionDef,
+    out: W,
+) -> Result<i32> {
+    let mut interp = Interpreter {
+        types,
+        out,
+        memory: Memory {
+            allocations: vec![None],
+        },
+        globals: HashMap::new(),
+        functions: HashMap::new(),
+        statics: HashMap::new(),
+        strings: HashMap::new(),
+        scopes: Vec::new(),
+        ret: Type::new(TypeKind::Void),
+        depth: 0,
+    };
+    interp.load(unit)?;
+
+    // `argc` is allowed to be 0, with `argv[0]` the terminating null pointer
+    let argv = interp.memory.allocate(vec![0; 8], false);
+    let status = match interp.call(
+        main,
+        vec![Value::Int(0), Value::Int(argv as i64)],
+        &main.span,
+    )? {
+        Value::Int(status) => status as i32,
+        _ => 0,
+    };
+    interp
+        .out
+        .flush()
+        .map_err(|e| Diagnostic::error(main.span, format!("cannot write output: {}", e)))?;
+    Ok(status)
+}
+
+fn error(span: &Span, msg: impl Into<String>) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(*span, msg))
+}
+
+fn declarator_name(declarator: &Declarator) -> Symbol {
+    declarator
+        .name
+        .as_ref()
+        .map_or_else(|| Symbol::intern(""), |name| name.value)
+}
+
+fn offset(addr: u64, delta: i64) -> u64 {
+    let offset = ((addr & 0xffff_ffff) as i64).wrapping_add(delta) as u64;
+    (addr & !0xffff_ffff) | (offset & 0xffff_ffff)
+}
+
+fn pointee_size(ty: &Type) -> i64 {
+    // arithmetic on `void *` steps by bytes, as a GNU extension
+    ty.pointee().and_then(Type::size).unwrap_or(1) as i64
+}
+
+fn member_offset(ty: &Type, name: Symbol) -> i64 {
+    match &ty.kind {
+        TypeKind::Record(record) => record
+            .borrow()
+            .member(name)
+            .map_or(0, |(off, _)| off as i64),
+        _ => 0,
+    }
+}
+
+impl Value {
+    fn as_int(&self) -> i64 {
+        match self {
+            Value::Int(v) => *v,
+            Value::Float(v) => *v as i64,
+            Value::Bytes(_) | Value::Void => 0,
+        }
+    }
+
+    fn scalar(&self) -> Option<ConstValue> {
+        match self {
+            Value::Int(v) => Some(ConstValue::Int(*v)),
+            Value::Float(v) => Some(ConstValue::Float(*v)),
+            Value::Bytes(_) | Value::Void => None,
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Int(v) => *v != 0,
+            Value::Float(v) => *v != 0.0,
+            Value::Bytes(_) | Value::Void => false,
+        }
+    }
+}
+
+impl From<ConstValue> for Value {
+    fn from(value: ConstValue) -> Self {
+        match value {
+            ConstValue::Int(v) => Value::Int(v),
+            ConstValue::Float(v) => Value::Float(v),
+        }
+    }
+}
+
+fn convert(value: Value, from: &Type, to: &Type) -> Value {
+    if to.is_void() {
+        return Value::Void;
+    }
+    match value.scalar().and_then(|v| convert_value(v, from, to)) {
+        Some(v) => v.into(),
+        None => value,
+    }
+}
+
+fn decode(bytes: &[u8], ty: &Type) -> Value {
+    match &ty.kind {
+        TypeKind::Float(FloatKind::Float) => {
+            Value::Float(f32::from_le_bytes(bytes.try_into().unwrap()) as f64)
+        }
+        // `long double` is kept with the precision of `double`
+        TypeKind::Float(_) => Value::Float(f64::from_le_bytes(bytes[..8].try_into().unwrap())),
+        TypeKind::Record(_) => Value::Bytes(bytes.to_vec()),
+        _ => {
+            let mut buf = [0; 8];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            let v = i64::from_le_bytes(buf);
+            Value::Int(ty.int_kind().map_or(v, |kind| kind.wrap(v)))
+        }
+    }
+}
+
+fn encode(value: &Value, ty: &Type) -> Vec<u8> {
+    let size = ty.size().unwrap_or(0) as usize;
+    let mut bytes = match (value, &ty.kind) {
+        (Value::Float(v), TypeKind::Float(FloatKind::Float)) => (*v as f32).to_le_bytes().to_vec(),
+        (Value::Float(v), _) => v.to_le_bytes().to_vec(),
+        (Value::Int(v), _) => v.to_le_bytes().to_vec(),
+        (Value::Bytes(bytes), _) => bytes.clone(),
+        (Value::Void, _) => Vec::new(),
+    };
+    bytes.resize(size, 0);
+    bytes
+}
+
+impl Memory {
+    fn allocate(&mut self, bytes: Vec<u8>, is_string_literal: bool) -> u64 {
+        self.allocations.push(Some(Allocation {
+            bytes,
+            is_string_literal,
+        }));
+        ((self.allocations.len() - 1) as u64) << 32
+    }
+
+    fn free(&mut self, addr: u64) {
+        self.allocations[(addr >> 32) as usize] = None;
+    }
+
+    fn resize(&mut self, addr: u64, size: u64) {
+        if let Some(Some(allocation)) = self.allocations.get_mut((addr >> 32) as usize) {
+            if allocation.bytes.len() < size as usize {
+                allocation.bytes.resize(size as usize, 0);
+            }
+        }
+    }
+
+    // the allocation and offset of `len` bytes at `addr`
+    fn locate(&self, addr: u64, len: u64) -> std::result::Result<(usize, usize), String> {
+        let id = (addr >> 32) as usize;
+        let off = addr & 0xffff_ffff;
+        if id == 0 {
+            return Err("null pointer dereference".into());
+        }
+        match self.allocations.get(id) {
+            Some(Some(allocation)) if off + len <= allocation.bytes.len() as u64 => {
+                Ok((id, off as usize))
+            }
+            Some(Some(allocation)) => Err(format!(
+                "out-of-bounds access of {} bytes at offset {} of an object of size {}",
+                len,
+                off as i32,
+                allocation.bytes.len()
+            )),
+            Some(None) => Err("use of an object after its lifetime has ended".into()),
+            None => Err("dereference of an invalid pointer".into()),
+        }
+    }
+
+    fn read(&self, addr: u64, len: u64) -> std::result::Result<&[u8], String> {
+        let (id, off) = self.locate(addr, len)?;
+        let allocation = self.allocations[id].as_ref().unwrap();
+        Ok(&allocation.bytes[off..off + len as usize])
+    }
+
+    fn write(&mut self, addr: u64, bytes: &[u8]) -> std::result::Result<(), String> {
+        let (id, off) = self.locate(addr, bytes.len() as u64)?;
+        let allocation = self.allocations[id].as_mut().unwrap();
+        if allocation.is_string_literal {
+            return Err("modification of a string literal".into());
+        }
+        allocation.bytes[off..off + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // the bytes of the NUL-terminated string at `addr`
+    fn string(&self, addr: u64) -> std::result::Result<Vec<u8>, String> {
+        let (id, off) = self.locate(addr, 0)?;
+        let bytes = &self.allocations[id].as_ref().unwrap().bytes[off..];
+        match bytes.iter().position(|&b| b == 0) {
+            Some(len) => Ok(bytes[..len].to_vec()),
+            None => Err("string is not null-terminated".into()),
+        }
+    }
+}
+
+// declarations and storage
+impl<'a, W: Write> Interpreter<'a, W> {
+    fn load(&mut self, unit: &'a TranslationUnit) -> Result<()> {
+        for item in &unit.items {
+            match item {
+                ExternalDecl::Function(f) => {
+                    let addr = self.function(declarator_name(&f.declarator));
+                    self.functions.get_mut(&addr).unwrap().1 = Function::Defined(f);
+                }
+                ExternalDecl::Declaration(decl) => self.declare_global(decl)?,
+            }
+        }
+        Ok(())
+    }
+
+    // the address of the function `name`, known from a declaration so far
+    fn function(&mut self, name: Symbol) -> u64 {
+        if let Some(&addr) = self.globals.get(&name) {
+            return addr;
+        }
+        let function = name.with_str(|name| match name {
+            "printf" => Function::Builtin(Builtin::Printf),
+            "puts" => Function::Builtin(Builtin::Puts),
+            "putchar" => Function::Builtin(Builtin::Putchar),
+            _ => Function::Undefined,
+        });
+        let addr = self.memory.allocate(Vec::new(), false);
+        self.functions.insert(addr, (name, function));
+        self.globals.insert(name, addr);
+        addr
+    }
+
+    fn declare_global(&mut self, decl: &'a Declaration) -> Result<()> {
+        let types = self.types;
+        for init in &decl.declarators {
+            let name = match &init.declarator.name {
+                Some(name) => name.value,
+                None => continue,
+            };
+            let ty = types.declarator(&init.declarator);
+            if decl.specifiers.is_typedef() {
+                continue;
+            }
+            if ty.is_function() {
+                self.function(name);
+                continue;
+            }
+
+            let size = initializer::object_size(ty);
+            let addr = match self.globals.get(&name) {
+                Some(&addr) => {
+                    self.memory.resize(addr, size);
+                    addr
+                }
+                None => {
+                    let addr = self.memory.allocate(vec![0; size as usize], false);
+                    self.globals.insert(name, addr);
+                    addr
+                }
+            };
+            if let Some(initializer) = &init.initializer {
+                self.initialize(addr, ty, initializer)?;
+            }
+        }
+        Ok(())
+    }
+
+    // `initialize` is false for declarations a `switch` jumped over
+    fn declare(&mut self, decl: &Declaration, initialize: bool) -> Result<()> {
+        let types = self.types;
+        let is_static = decl.specifiers.storage.contains(&StorageClass::Static);
+        let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+        for init in &decl.declarators {
+            let name = match &init.declarator.name {
+                Some(name) => name,
+                None => continue,
+            };
+            let ty = types.declarator(&init.declarator);
+            if decl.specifiers.is_typedef() || ty.is_function() {
+                continue;
+            }
+
+            if is_extern {
+                let addr = *self.globals.get(&name.value).ok_or_else(|| {
+                    error(
+                        &name.span,
+                        format!("undefined reference to '{}'", name.value),
+                    )
+                })?;
+                self.bind(name.value, addr);
+            } else if is_static {
+                let key = &init.declarator as *const Declarator;
+                match self.statics.get(&key) {
+                    Some(&addr) => self.bind(name.value, addr),
+                    None => {
+                        let size = ty.size().unwrap_or(0);
+                        let addr = self.memory.allocate(vec![0; size as usize], false);
+                        self.statics.insert(key, addr);
+                        self.bind(name.value, addr);
+                        if let Some(initializer) = &init.initializer {
+                            self.initialize(addr, ty, initializer)?;
+                        }
+                    }
+                }
+            } else {
+                let addr = self.allocate(ty);
+                self.bind(name.value, addr);
+                if let (true, Some(initializer)) = (initialize, &init.initializer) {
+                    self.initialize(addr, ty, initializer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, name: Symbol, addr: u64) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.objects.insert(name, addr);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<u64> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.objects.get(&name))
+            .or_else(|| self.globals.get(&name))
+            .copied()
+    }
+
+    // zero-initialized storage that lives until the current scope ends
+    fn allocate(&mut self, ty: &Type) -> u64 {
+        let size = ty.size().unwrap_or(0);
+        let addr = self.memory.allocate(vec![0; size as usize], false);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.allocations.push(addr);
+        }
+        addr
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for addr in scope.allocations {
+            self.memory.free(addr);
+        }
+    }
+
+    fn string(&mut self, s: &StringLiteral) -> u64 {
+        let key = s as *const StringLiteral;
+        if let Some(&addr) = self.strings.get(&key) {
+            return addr;
+        }
+        let bytes = s.bytes();
+        let addr = self.memory.allocate(bytes, true);
+        self.strings.insert(key, addr);
+        addr
+    }
+
+    fn read(&self, addr: u64, ty: &Type, span: &Span) -> Result<Value> {
+        let size = ty.size().unwrap_or(0);
+        let bytes = self
+            .memory
+            .read(addr, size)
+            .map_err(|msg| error(span, msg))?;
+        Ok(decode(bytes, ty))
+    }
+
+    fn write(&mut self, addr: u64, ty: &Type, value: &Value, span: &Span) -> Result<()> {
+        let bytes = encode(value, ty);
+        self.memory
+            .write(addr, &bytes)
+            .map_err(|msg| error(span, msg))
+    }
+}
+
+impl<'a, W: Write> Interpreter<'a, W> {
+    fn initialize(&mut self, addr: u64, ty: &Type, init: &Initializer) -> Result<()> {
+        let types = self.types;
+        for entry in initializer::flatten(types, ty, init) {
+            let addr = offset(addr, entry.offset as i64);
+            let e = entry.expr;
+            if let Some((s, len)) = entry.string() {
+                self.memory
+                    .write(addr, &s.bytes()[..len as usize])
+                    .map_err(|msg| error(&e.span, msg))?;
+                continue;
+            }
+            let value = self.eval(e)?;
+            let value = convert(value, &types.expr(e).decay(), &entry.ty);
+            self.write(addr, &entry.ty, &value, &e.span)?;
+        }
+        Ok(())
+    }
+}
+
+// statements
+impl<'a, W: Write> Interpreter<'a, W> {
+    fn call(&mut self, f: &'a FunctionDef, args: Vec<Value>, span: &Span) -> Result<Value> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(error(
+                span,
+                format!("call depth exceeds the limit of {}", MAX_CALL_DEPTH),
+            ));
+        }
+        let ret = match &self.types.declarator(&f.declarator).kind {
+            TypeKind::Function(func) => (*func.ret).clone(),
+            _ => unreachable!("function definitions have function type"),
+        };
+        let scopes = mem::replace(&mut self.scopes, vec![Scope::default()]);
+        let ret = mem::replace(&mut self.ret, ret);
+        self.depth += 1;
+
+        let result = self.call_body(f, args);
+
+        self.depth -= 1;
+        while !self.scopes.is_empty() {
+            self.pop_scope();
+        }
+        self.scopes = scopes;
+        self.ret = ret;
+        result
+    }
+
+    fn call_body(&mut self, f: &FunctionDef, args: Vec<Value>) -> Result<Value> {
+        let types = self.types;
+        if let Some(DerivedDeclarator::Function(func)) = f.declarator.derived.first() {
+            for (param, value) in func.params.iter().zip(args) {
+                let ty = types.declarator(&param.declarator);
+                let addr = self.allocate(ty);
+                self.write(addr, ty, &value, &param.span)?;
+                if let Some(name) = &param.declarator.name {
+                    self.bind(name.value, addr);
+                }
+            }
+        }
+
+        let mut name = declarator_name(&f.declarator).with_str(|name| name.as_bytes().to_vec());
+        name.push(0);
+        let addr = self.memory.allocate(name, false);
+        self.scopes[0].allocations.push(addr);
+        self.bind(Symbol::intern("__func__"), addr);
+
+        match self.exec_items(&f.body.items, 0)? {
+            Flow::Return(value) => Ok(value),
+            Flow::Goto(label, span) => Err(error(
+                &span,
+                format!("cannot jump into the block containing label '{}'", label),
+            )),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    // runs a block from its `start`th item, as a `switch` does
+    fn exec_block(&mut self, items: &[BlockItem], start: usize) -> Result<Flow> {
+        self.push_scope();
+        let mut flow = Ok(Flow::Normal);
+        // declarations jumped over still have storage, but no initial value
+        for item in &items[..start] {
+            if let BlockItem::Declaration(decl) = item {
+                flow = self.declare(decl, false).map(|_| Flow::Normal);
+                if flow.is_err() {
+                    break;
+                }
+            }
+        }
+        if flow.is_ok() {
+            flow = self.exec_items(items, start);
+        }
+        self.pop_scope();
+        flow
+    }
+
+    fn exec_items(&mut self, items: &[BlockItem], start: usize) -> Result<Flow> {
+        let mut i = start;
+        while let Some(item) = items.get(i) {
+            let flow = match item {
+                BlockItem::Declaration(decl) => {
+                    self.declare(decl, true)?;
+                    Flow::Normal
+                }
+                BlockItem::Stmt(stmt) => self.exec(stmt)?,
+            };
+            match flow {
+                Flow::Normal => i += 1,
+                Flow::Goto(label, span) => match label_index(items, label) {
+                    Some(target) => i = target,
+                    None => return Ok(Flow::Goto(label, span)),
+                },
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Flow> {
+        let types = self.types;
+        let flow = match &stmt.kind {
+            StmtKind::Compound(block) => self.exec_block(&block.items, 0)?,
+            StmtKind::Expr(e) => {
+                if let Some(e) = e {
+                    self.eval(e)?;
+                }
+                Flow::Normal
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                if self.eval(cond)?.is_true() {
+                    self.exec(then)?
+                } else if let Some(otherwise) = otherwise {
+                    self.exec(otherwise)?
+                } else {
+                    Flow::Normal
+                }
+            }
+            StmtKind::While(cond, body) => {
+                while self.eval(cond)?.is_true() {
+                    match self.exec(body)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Flow::Normal
+            }
+            StmtKind::DoWhile(body, cond) => {
+                loop {
+                    match self.exec(body)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                        flow => return Ok(flow),
+                    }
+                    if !self.eval(cond)?.is_true() {
+                        break;
+                    }
+                }
+                Flow::Normal
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.push_scope();
+                let flow = self.exec_for(init, cond.as_ref(), step.as_ref(), body);
+                self.pop_scope();
+                flow?
+            }
+            StmtKind::Switch(cond, body) => {
+                let ty = types.expr(cond).decay();
+                let value = self.eval(cond)?;
+                let value = convert(value, &ty, &promote(&ty)).as_int();
+                let flow = match &body.kind {
+                    StmtKind::Compound(block) => match self.case_index(&block.items, value) {
+                        Some(start) => self.exec_block(&block.items, start)?,
+                        None => Flow::Normal,
+                    },
+                    _ if self.case_matches(body, value) => self.exec(body)?,
+                    _ => Flow::Normal,
+                };
+                match flow {
+                    Flow::Break => Flow::Normal,
+                    flow => flow,
+                }
+            }
+            StmtKind::Case(_, body) | StmtKind::Default(body) | StmtKind::Labeled(_, body) => {
+                self.exec(body)?
+            }
+            StmtKind::Goto(label) => Flow::Goto(label.value, stmt.span),
+            StmtKind::Continue => Flow::Continue,
+            StmtKind::Break => Flow::Break,
+            StmtKind::Return(e) => match e {
+                Some(e) => {
+                    let value = self.eval(e)?;
+                    Flow::Return(convert(value, &types.expr(e).decay(), &self.ret))
+                }
+                None => Flow::Return(Value::Void),
+            },
+        };
+        Ok(flow)
+    }
+
+    fn exec_for(
+        &mut self,
+        init: &ForInit,
+        cond: Option<&Expr>,
+        step: Option<&Expr>,
+        body: &Stmt,
+    ) -> Result<Flow> {
+        match init {
+            ForInit::Empty => {}
+            ForInit::Expr(e) => {
+                self.eval(e)?;
+            }
+            ForInit::Declaration(decl) => self.declare(decl, true)?,
+        }
+        loop {
+            if let Some(cond) = cond {
+                if !self.eval(cond)?.is_true() {
+                    break;
+                }
+            }
+            match self.exec(body)? {
+                Flow::Break => break,
+                Flow::Normal | Flow::Continue => {}
+                flow => return Ok(flow),
+            }
+            if let Some(step) = step {
+                self.eval(step)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // only labels directly in the switch body are found, not those of nested statements
+    fn case_index(&self, items: &[BlockItem], value: i64) -> Option<usize> {
+        let mut default = None;
+        for (i, item) in items.iter().enumerate() {
+            let mut stmt = match item {
+                BlockItem::Stmt(stmt) => stmt,
+                BlockItem::Declaration(_) => continue,
+            };
+            loop {
+                match &stmt.kind {
+                    StmtKind::Case(..) if self.case_matches(stmt, value) => return Some(i),
+                    StmtKind::Default(_) => default = default.or(Some(i)),
+                    StmtKind::Case(..) | StmtKind::Labeled(..) => {}
+                    _ => break,
+                }
+                stmt = match &stmt.kind {
+                    StmtKind::Case(_, body)
+                    | StmtKind::Default(body)
+                    | StmtKind::Labeled(_, body) => body,
+                    _ => unreachable!(),
+                };
+            }
+        }
+        default
+    }
+
+    fn case_matches(&self, stmt: &Stmt, value: i64) -> bool {
+        match &stmt.kind {
+            StmtKind::Case(e, _) => self.types.value(e) == Some(ConstValue::Int(value)),
+            _ => false,
+        }
+    }
+}
+
+fn label_index(items: &[BlockItem], label: Symbol) -> Option<usize> {
+    items.iter().position(|item| {
+        let mut stmt = match item {
+            BlockItem::Stmt(stmt) => stmt,
+            BlockItem::Declaration(_) => return false,
+        };
+        loop {
+            stmt = match &stmt.kind {
+                StmtKind::Labeled(name, _) if name.value == label => return true,
+                StmtKind::Case(_, body) | StmtKind::Default(body) | StmtKind::Labeled(_, body) => {
+                    body
+                }
+                _ => return false,
+            };
+        }
+    })
+}
+
+// expressions
+impl<'a, W: Write> Interpreter<'a, W> {
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        let types = self.types;
+        if let Some(value) = types.value(expr) {
+            return Ok(value.into());
+        }
+        let ty = types.expr(expr);
+        if ty.is_array() || ty.is_function() {
+            return Ok(Value::Int(self.place(expr)? as i64));
+        }
+
+        let span = &expr.span;
+        match &expr.kind {
+            ExprKind::Identifier(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Unary(UnaryOp::Deref, _)
+            | ExprKind::Index(..)
+            | ExprKind::Member(..)
+            | ExprKind::Arrow(..) => {
+                let addr = self.place(expr)?;
+                self.read(addr, ty, span)
+            }
+            ExprKind::Constant(_) | ExprKind::SizeofExpr(_) | ExprKind::SizeofType(_) => {
+                unreachable!("constants are folded by the checker")
+            }
+            ExprKind::Unary(op, operand) => self.eval_unary(*op, operand, ty),
+            ExprKind::Binary(BinaryOp::And, lhs, rhs) => {
+                let value = self.eval(lhs)?.is_true() && self.eval(rhs)?.is_true();
+                Ok(Value::Int(value as i64))
+            }
+            ExprKind::Binary(BinaryOp::Or, lhs, rhs) => {
+                let value = self.eval(lhs)?.is_true() || self.eval(rhs)?.is_true();
+                Ok(Value::Int(value as i64))
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let l = self.eval(lhs)?;
+                let r = self.eval(rhs)?;
+                let lt = types.expr(lhs).decay();
+                let rt = types.expr(rhs).decay();
+                self.binary(*op, l, &lt, r, &rt, span)
+            }
+            ExprKind::Assign(op, lhs, rhs) => {
+                let target = types.expr(lhs);
+                let addr = self.place(lhs)?;
+                let value = self.eval(rhs)?;
+                let rt = types.expr(rhs).decay();
+                let value = match op.binary_op() {
+                    None => convert(value, &rt, target),
+                    Some(op) => {
+                        let lt = target.unqualified();
+                        let old = self.read(addr, &lt, &lhs.span)?;
+                        let result = self.binary(op, old, &lt, value, &rt, span)?;
+                        let result_ty = if lt.is_pointer() {
+                            lt.clone()
+                        } else {
+                            operand_type(op, &lt, &rt)
+                        };
+                        convert(result, &result_ty, &lt)
+                    }
+                };
+                self.write(addr, target, &value, &lhs.span)?;
+                Ok(value)
+            }
+            ExprKind::Conditional(cond, then, otherwise) => {
+                let branch = if self.eval(cond)?.is_true() {
+                    then
+                } else {
+                    otherwise
+                };
+                let value = self.eval(branch)?;
+                Ok(convert(value, &types.expr(branch).decay(), ty))
+            }
+            ExprKind::Comma(exprs) => {
+                let mut value = Value::Void;
+                for e in exprs {
+                    value = self.eval(e)?;
+                }
+                Ok(value)
+            }
+            ExprKind::Cast(_, operand) => {
+                let value = self.eval(operand)?;
+                Ok(convert(value, &types.expr(operand).decay(), ty))
+            }
+            ExprKind::Call(callee, args) => self.eval_call(callee, args, span),
+        }
+    }
+
+    // the address of an lvalue, or of a temporary holding an rvalue
+    fn place(&mut self, expr: &Expr) -> Result<u64> {
+        let types = self.types;
+        match &expr.kind {
+            ExprKind::Identifier(ident) => self.lookup(ident.value).ok_or_else(|| {
+                error(
+                    &expr.span,
+                    format!(
+                        "'{}' is used before its declaration is reached",
+                        ident.value
+                    ),
+                )
+            }),
+            ExprKind::StringLiteral(s) => Ok(self.string(s)),
+            ExprKind::Unary(UnaryOp::Deref, operand) => Ok(self.eval(operand)?.as_int() as u64),
+            ExprKind::Index(lhs, rhs) => self.index(lhs, rhs, &expr.span, false),
+            ExprKind::Member(base, member) => {
+                let addr = self.place(base)?;
+                Ok(offset(addr, member_offset(types.expr(base), member.value)))
+            }
+            ExprKind::Arrow(base, member) => {
+                let addr = self.eval(base)?.as_int() as u64;
+                let pointer = types.expr(base).decay();
+                let record = pointer.pointee().unwrap();
+                Ok(offset(addr, member_offset(record, member.value)))
+            }
+            _ => {
+                let ty = types.expr(expr);
+                let value = self.eval(expr)?;
+                let addr = self.allocate(ty);
+                self.write(addr, ty, &value, &expr.span)?;
+                Ok(addr)
+            }
+        }
+    }
+
+    // `&a[n]` may point one past the end of `a`, but not be read from
+    fn index(&mut self, lhs: &Expr, rhs: &Expr, span: &Span, allow_end: bool) -> Result<u64> {
+        let types = self.types;
+        let (array, index) = if types.expr(lhs).decay().is_pointer() {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        let base = self.eval(array)?.as_int() as u64;
+        let idx = self.eval(index)?.as_int();
+
+        let ty = types.expr(array);
+        if let TypeKind::Array(_, Some(len)) = &ty.kind {
+            let len = *len as i64;
+            if idx < 0 || idx > len || idx == len && !allow_end {
+                return Err(error(
+                    span,
+                    format!("array index {} is out of bounds for type '{}'", idx, ty),
+                ));
+            }
+        }
+        Ok(offset(base, idx.wrapping_mul(pointee_size(&ty.decay()))))
+    }
+
+    fn eval_unary(&mut self, op: UnaryOp, operand: &Expr, ty: &Type) -> Result<Value> {
+        let types = self.types;
+        match op {
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                let target = types.expr(operand);
+                let addr = self.place(operand)?;
+                let old = self.read(addr, target, &operand.span)?;
+                let delta = match op {
+                    UnaryOp::PreInc | UnaryOp::PostInc => 1,
+                    _ => -1,
+                };
+                let new = match old {
+                    Value::Int(p) if target.is_pointer() => {
+                        Value::Int(offset(p as u64, delta * pointee_size(target)) as i64)
+                    }
+                    Value::Int(v) => convert(Value::Int(v.wrapping_add(delta)), target, target),
+                    Value::Float(v) => convert(Value::Float(v + delta as f64), target, target),
+                    ref value => value.clone(),
+                };
+                self.write(addr, target, &new, &operand.span)?;
+                match op {
+                    UnaryOp::PreInc | UnaryOp::PreDec => Ok(new),
+                    _ => Ok(old),
+                }
+            }
+            UnaryOp::Address => {
+                let addr = match &operand.kind {
+                    ExprKind::Index(lhs, rhs) => self.index(lhs, rhs, &operand.span, true)?,
+                    _ => self.place(operand)?,
+                };
+                Ok(Value::Int(addr as i64))
+            }
+            UnaryOp::Deref => unreachable!("dereferences are places"),
+            UnaryOp::Not => Ok(Value::Int(!self.eval(operand)?.is_true() as i64)),
+            UnaryOp::Plus | UnaryOp::Minus | UnaryOp::BitNot => {
+                let value = self.eval(operand)?;
+                let value = convert(value, &types.expr(operand).decay(), ty);
+                let kind = ty.int_kind();
+                Ok(match (op, value) {
+                    (UnaryOp::Minus, Value::Int(v)) => {
+                        Value::Int(kind.unwrap().wrap(v.wrapping_neg()))
+                    }
+                    (UnaryOp::Minus, Value::Float(v)) => Value::Float(-v),
+                    (UnaryOp::BitNot, Value::Int(v)) => Value::Int(kind.unwrap().wrap(!v)),
+                    (_, value) => value,
+                })
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        op: BinaryOp,
+        l: Value,
+        lt: &Type,
+        r: Value,
+        rt: &Type,
+        span: &Span,
+    ) -> Result<Value> {
+        if lt.is_pointer() || rt.is_pointer() {
+            let (a, b) = (l.as_int(), r.as_int());
+            let value = match op {
+                BinaryOp::Add if lt.is_pointer() => {
+                    offset(a as u64, b.wrapping_mul(pointee_size(lt))) as i64
+                }
+                BinaryOp::Add => offset(b as u64, a.wrapping_mul(pointee_size(rt))) as i64,
+                BinaryOp::Sub if rt.is_pointer() => {
+                    ((a & 0xffff_ffff) - (b & 0xffff_ffff)) / pointee_size(lt)
+                }
+                BinaryOp::Sub => {
+                    offset(a as u64, b.wrapping_mul(pointee_size(lt)).wrapping_neg()) as i64
+                }
+                BinaryOp::Lt => ((a as u64) < (b as u64)) as i64,
+                BinaryOp::Gt => ((a as u64) > (b as u64)) as i64,
+                BinaryOp::Le => ((a as u64) <= (b as u64)) as i64,
+                BinaryOp::Ge => ((a as u64) >= (b as u64)) as i64,
+                BinaryOp::Eq => (a == b) as i64,
+                BinaryOp::Ne => (a != b) as i64,
+                _ => unreachable!("pointer operands are checked"),
+            };
+            return Ok(Value::Int(value));
+        }
+
+        let ty = operand_type(op, lt, rt);
+        let a = convert(l, lt, &ty).scalar().unwrap();
+        match op {
+            BinaryOp::Shl | BinaryOp::Shr => {
+                let (a, b) = (Value::from(a).as_int(), r.as_int());
+                let kind = ty.int_kind().unwrap();
+                let bits = kind.size() as i64 * 8;
+                let is_negative = rt.int_kind().is_some_and(|k| k.is_signed()) && b < 0;
+                if is_negative || b as u64 >= bits as u64 {
+                    return Err(error(
+                        span,
+                        format!("shift count {} is out of range for type '{}'", b, ty),
+                    ));
+                }
+                let value = match op {
+                    BinaryOp::Shl => a << b,
+                    _ if kind.is_signed() => a >> b,
+                    _ => ((a as u64) >> b) as i64,
+                };
+                return Ok(Value::Int(kind.wrap(value)));
+            }
+            BinaryOp::Div | BinaryOp::Mod if ty.is_integer() && r.as_int() == 0 => {
+                return Err(error(span, "division by zero"));
+            }
+            _ => {}
+        }
+        let b = convert(r, rt, &ty).scalar().unwrap();
+        Ok(fold_binary(op, a, b, &ty)
+            .expect("operand types are checked")
+            .into())
+    }
+
+    fn eval_call(&mut self, callee: &Expr, args: &[Expr], span: &Span) -> Result<Value> {
+        let types = self.types;
+        let addr = self.eval(callee)?.as_int() as u64;
+        let func = match types.expr(callee).decay().pointee().map(|ty| &ty.kind) {
+            Some(TypeKind::Function(func)) => func.clone(),
+            _ => unreachable!("callees are checked"),
+        };
+
+        let mut values = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            let ty = types.expr(arg).decay();
+            let value = self.eval(arg)?;
+            let target = match func.params.get(i) {
+                Some(param) => param.clone(),
+                // the default argument promotions
+                None if matches!(ty.kind, TypeKind::Float(FloatKind::Float)) => {
+                    Type::new(TypeKind::Float(FloatKind::Double))
+                }
+                None => promote(&ty),
+            };
+            values.push(convert(value, &ty, &target));
+        }
+
+        match self.functions.get(&addr) {
+            Some((_, Function::Defined(f))) => {
+                let f = *f;
+                self.call(f, values, span)
+            }
+            Some((_, Function::Builtin(builtin))) => self.builtin(*builtin, &values, span),
+            Some((name, Function::Undefined)) => {
+                Err(error(span, format!("undefined reference to '{}'", name)))
+            }
+            None => Err(error(
+                &callee.span,
+                "call through a pointer that does not point to a function",
+            )),
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Value], span: &Span) -> Result<Value> {
+        let bytes = match builtin {
+            Builtin::Printf => {
+                let fmt = self.c_string(&args[0], span)?;
+                let args = args[1..]
+                    .iter()
+                    .map(Value::scalar)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| error(span, "cannot pass a structure or union to 'printf'"))?;
+                let memory = &self.memory;
+                printf::format(&fmt, &args, &mut |addr| memory.string(addr))
+                    .map_err(|msg| error(span, msg))?
+            }
+            Builtin::Puts => {
+                let mut s = self.c_string(&args[0], span)?;
+                s.push(b'\n');
+                s
+            }
+            Builtin::Putchar => vec![args[0].as_int() as u8],
+        };
+        self.out
+            .write_all(&bytes)
+            .map_err(|e| error(span, format!("cannot write output: {}", e)))?;
+        Ok(match builtin {
+            Builtin::Putchar => Value::Int(bytes[0] as i64),
+            _ => Value::Int(bytes.len() as i64),
+        })
+    }
+
+    fn c_string(&self, value: &Value, span: &Span) -> Result<Vec<u8>> {
+        self.memory
+            .string(value.as_int() as u64)
+            .map_err(|msg| error(span, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source_file::SourceFile;
+
+    fn run_src(src: &str) -> (std::result::Result<i32, String>, String) {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let checked = Checker::new().check(&unit);
+        assert!(checked.errors.is_empty(), "{:?}", checked.errors);
+
+        let mut out = Vec::new();
+        let main = find_main(&unit).expect("no main");
+        let status = run(&unit, &checked.types, main, &mut out).map_err(|e| e.msg);
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    fn output(src: &str) -> String {
+        let (status, out) = run_src(src);
+        assert_eq!(status, Ok(0), "{}", out);
+        out
+    }
+
+    fn runtime_error(src: &str) -> String {
+        run_src(src).0.unwrap_err()
+    }
+
+    const PRELUDE: &str =
+        "int printf(const char *, ...); int puts(const char *); int putchar(int);\n";
+
+    #[test]
+    fn exit_status() {
+        assert_eq!(run_src("int main(void) { return 3 * 4; }").0, Ok(12));
+        assert_eq!(run_src("int main(void) { }").0, Ok(0));
+        assert_eq!(
+            run_src("int main(int argc, char **argv) { return argc + (argv[0] == 0); }").0,
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            int main(void) {
+                int a = 7, b = -2;
+                unsigned u = 1;
+                double d = 1.5;
+                char c = 200;
+                printf("%d %d %d %d\n", a / b, a % b, a << 3, -a >> 1);
+                printf("%d %u %d\n", u > -1, u - 2, c);
+                printf("%.2f %d %f\n", d * a, (int)(d * 3), 1 / 3.0f);
+                a += 5; a *= 2; b -= a; a >>= 1; u <<= 4;
+                printf("%d %d %u %d\n", a, b, u, a++ + ++a);
+                int big = 2147483647;
+                printf("%d %lu\n", big + 1, sizeof big);
+                return 0;
+            }
+            "#
+        );
+        assert_eq!(
+            output(&src),
+            "-3 1 56 -4\n0 4294967295 -56\n10.50 4 0.333333\n12 -26 16 26\n-2147483648 4\n"
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            int fib(int n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+            int main(void) {
+                int i, sum = 0;
+                for (i = 0; i < 10; i++) {
+                    if (i == 2) continue;
+                    if (i == 8) break;
+                    sum += i;
+                }
+                while (sum > 20) sum -= 7;
+                do { sum++; } while (sum < 18);
+                printf("%d %d\n", sum, fib(15));
+                for (i = 0; i < 4; i++) {
+                    switch (i) {
+                    case 0: putchar('a');
+                    case 1: putchar('b'); break;
+                    default: putchar('d');
+                    }
+                }
+                i = 0;
+            again:
+                if (++i < 3) goto again;
+                printf("\n%d\n", i);
+                return 0;
+            }
+            "#
+        );
+        assert_eq!(output(&src), "20 610\nabbdd\n3\n");
+    }
+
+    #[test]
+    fn memory() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            struct point { int x, y; };
+            struct point make(int x, int y) { struct point p = { x, y }; return p; }
+            void swap(int *a, int *b) { int t = *a; *a = *b; *b = t; }
+            int counter(void) { static int n = 10; return n++; }
+            int squares[5] = { [2] = 4, 9 };
+            char greeting[] = "hi";
+            int main(void) {
+                int a[4] = { 1, 2 }, *p = a + 1;
+                struct point pts[2] = { 1, 2, { .y = 5 } }, *q = &pts[1];
+                swap(&a[0], p);
+                printf("%d %d %d %d %ld\n", a[0], a[1], a[2], p[-1], &a[4] - p);
+                printf("%d %d %d %d\n", pts[0].y, q->x, q->y, make(3, 4).y);
+                counter();
+                printf("%d %d %d\n", counter(), squares[2], squares[3]);
+                const char *s = "hello";
+                puts(s + 1);
+                printf("%s %c %zu %s\n", greeting, s[4], sizeof greeting, __func__);
+                return 0;
+            }
+            "#
+        );
+        assert_eq!(
+            output(&src),
+            "2 1 0 2 3\n2 0 5 4\n11 4 9\nello\nhi o 3 main\n"
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        let src = format!(
+            "{}{}",
+            PRELUDE,
+            r#"
+            int wide[] = L"h" "é";
+            unsigned short utf16[4] = u"😀";
+            int main(void) {
+                const char *s = "con" "cat" u8"é";
+                printf("%s %zu %zu\n", s, sizeof "a" "b", sizeof(U"a" "b"));
+                printf("%d %d %zu %d\n", wide[1], wide[2], sizeof wide, L'é');
+                printf("%x %x %d\n", utf16[0], utf16[1], 'ab');
+                return 0;
+            }
+            "#
+        );
+        assert_eq!(
+            output(&src),
+            "concaté 3 12\n233 0 12 233\nd83d de00 24930\n"
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(
+            runtime_error("int main(void) { int a = 0; return 1 / a; }"),
+            "division by zero"
+        );
+        assert_eq!(
+            runtime_error("int main(void) { int a[3]; int i = 3; return a[i]; }"),
+            "array index 3 is out of bounds for type 'int [3]'"
+        );
+        assert_eq!(
+            runtime_error("int main(void) { int *p = 0; return *p; }"),
+            "null pointer dereference"
+        );
+        assert_eq!(
+            runtime_error("int *f(void) { int x = 1; return &x; } int main(void) { return *f(); }"),
+            "use of an object after its lifetime has ended"
+        );
+        assert_eq!(
+            runtime_error("int main(void) { char *s = \"ab\"; s[0] = 'x'; return 0; }"),
+            "modification of a string literal"
+        );
+        assert_eq!(
+            runtime_error("int f(void); int main(void) { return f(); }"),
+            "undefined reference to 'f'"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::checker::ConstValue;
+
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    // of the integer argument; `None` means `int`
+    bits: Option<u32>,
+}
+
+// formats `fmt` like C `printf`; `read_str` reads the NUL-terminated string
+// a `%s` argument points to
+pub fn format(
+    fmt: &[u8],
+    args: &[ConstValue],
+    read_str: &mut dyn FnMut(u64) -> Result<Vec<u8>, String>,
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    let mut next_arg = move || {
+        args.next()
+            .copied()
+            .ok_or_else(|| "too few arguments for format string".to_owned())
+    };
+
+    let mut i = 0;
+    while i < fmt.len() {
+        let b = fmt[i];
+        i += 1;
+        if b != b'%' {
+            out.push(b);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&flag) = fmt.get(i) {
+            match flag {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                b'0' => spec.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        if fmt.get(i) == Some(&b'*') {
+            i += 1;
+            let width = int(next_arg()?) as i32;
+            spec.left |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+        } else {
+            spec.width = digits(fmt, &mut i);
+        }
+
+        if fmt.get(i) == Some(&b'.') {
+            i += 1;
+            spec.precision = if fmt.get(i) == Some(&b'*') {
+                i += 1;
+                let precision = int(next_arg()?) as i32;
+                // a negative precision is taken as if it were omitted
+                (precision >= 0).then_some(precision as usize)
+            } else {
+                Some(digits(fmt, &mut i))
+            };
+        }
+
+        for (modifier, bits) in [
+            (&b"hh"[..], 8),
+            (b"h", 16),
+            (b"ll", 64),
+            (b"l", 64),
+            (b"j", 64),
+            (b"z", 64),
+            (b"t", 64),
+            (b"L", 64),
+        ] {
+            if fmt[i..].starts_with(modifier) {
+                spec.bits = Some(bits);
+                i += modifier.len();
+                break;
+            }
+        }
+
+        let conv = *fmt
+            .get(i)
+            .ok_or_else(|| "incomplete format specifier".to_owned())?;
+        i += 1;
+        match conv {
+            b'%' => out.push(b'%'),
+            b'd' | b'i' => {
+                let v = sign_extend(int(next_arg()?), spec.bits.unwrap_or(32));
+                let digits = integer_digits(&spec, v.unsigned_abs(), 10, false);
+                let sign = sign(&spec, v < 0);
+                pad(&mut out, &spec, sign, &digits, spec.precision.is_none());
+            }
+            b'u' | b'o' | b'x' | b'X' => {
+                let v = truncate(int(next_arg()?), spec.bits.unwrap_or(32));
+                let radix = match conv {
+                    b'u' => 10,
+                    b'o' => 8,
+                    _ => 16,
+                };
+                let mut digits = integer_digits(&spec, v, radix, conv == b'X');
+                let mut prefix = "";
+                if spec.alt && conv == b'o' && !digits.starts_with('0') {
+                    digits.insert(0, '0');
+                } else if spec.alt && v != 0 && conv == b'x' {
+                    prefix = "0x";
+                } else if spec.alt && v != 0 && conv == b'X' {
+                    prefix = "0X";
+                }
+                pad(&mut out, &spec, prefix, &digits, spec.precision.is_none());
+            }
+            b'c' => {
+                let c = int(next_arg()?) as u8;
+                pad_bytes(&mut out, &spec, &[c]);
+            }
+            b's' => {
+                let mut s = read_str(int(next_arg()?) as u64)?;
+                if let Some(precision) = spec.precision {
+                    s.truncate(precision);
+                }
+                pad_bytes(&mut out, &spec, &s);
+            }
+            b'p' => {
+                let p = int(next_arg()?) as u64;
+                let digits = if p == 0 {
+                    "(nil)".to_owned()
+                } else {
+                    format!("0x{:x}", p)
+                };
+                pad(&mut out, &spec, "", &digits, false);
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let v = match next_arg()? {
+                    ConstValue::Float(v) => v,
+                    ConstValue::Int(v) => v as f64,
+                };
+                let sign = sign(&spec, v.is_sign_negative() && !v.is_nan());
+                let upper = conv.is_ascii_uppercase();
+                let digits = if v.is_finite() {
+                    let precision = spec.precision.unwrap_or(6);
+                    match conv.to_ascii_lowercase() {
+                        b'f' => fixed(v.abs(), precision, spec.alt),
+                        b'e' => exponential(v.abs(), precision, spec.alt, upper),
+                        _ => general(v.abs(), precision, spec.alt, upper),
+                    }
+                } else {
+                    let s = if v.is_nan() { "nan" } else { "inf" };
+                    if upper {
+                        s.to_ascii_uppercase()
+                    } else {
+                        s.to_owned()
+                    }
+                };
+                pad(&mut out, &spec, sign, &digits, v.is_finite());
+            }
+            _ => return Err(format!("invalid conversion specifier '{}'", conv as char)),
+        }
+    }
+    Ok(out)
+}
+
+fn int(value: ConstValue) -> i64 {
+    match value {
+        ConstValue::Int(v) => v,
+        ConstValue::Float(v) => v as i64,
+    }
+}
+
+fn digits(fmt: &[u8], i: &mut usize) -> usize {
+    let mut n = 0;
+    while let Some(d) = fmt.get(*i).filter(|b| b.is_ascii_digit()) {
+        n = n * 10 + (d - b'0') as usize;
+        *i += 1;
+    }
+    n
+}
+
+fn sign_extend(v: i64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    (v << shift) >> shift
+}
+
+fn truncate(v: i64, bits: u32) -> u64 {
+    let shift = 64 - bits;
+    ((v as u64) << shift) >> shift
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+fn integer_digits(spec: &Spec, v: u64, radix: u32, upper: bool) -> String {
+    let mut digits = match radix {
+        8 => format!("{:o}", v),
+        16 if upper => format!("{:X}", v),
+        16 => format!("{:x}", v),
+        _ => v.to_string(),
+    };
+    match spec.precision {
+        Some(0) if v == 0 => digits.clear(),
+        Some(precision) if digits.len() < precision => {
+            digits.insert_str(0, &"0".repeat(precision - digits.len()));
+        }
+        _ => {}
+    }
+    digits
+}
+
+// `prefix` is the sign or `0x`, zero padding goes between it and the digits
+fn pad(out: &mut Vec<u8>, spec: &Spec, prefix: &str, digits: &str, allow_zero: bool) {
+    let len = prefix.len() + digits.len();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(digits.as_bytes());
+        out.resize(out.len() + fill, b' ');
+    } else if spec.zero && allow_zero {
+        out.extend_from_slice(prefix.as_bytes());
+        out.resize(out.len() + fill, b'0');
+        out.extend_from_slice(digits.as_bytes());
+    } else {
+        out.resize(out.len() + fill, b' ');
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(digits.as_bytes());
+    }
+}
+
+fn pad_bytes(out: &mut Vec<u8>, spec: &Spec, bytes: &[u8]) {
+    let fill = spec.width.saturating_sub(bytes.len());
+    if !spec.left {
+        out.resize(out.len() + fill, b' ');
+    }
+    out.extend_from_slice(bytes);
+    if spec.left {
+        out.resize(out.len() + fill, b' ');
+    }
+}
+
+fn fixed(v: f64, precision: usize, alt: bool) -> String {
+    let mut s = format!("{:.*}", precision, v);
+    if alt && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+// `d.ddde+XX`, with at least two exponent digits
+fn exponential(v: f64, precision: usize, alt: bool, upper: bool) -> String {
+    let s = format!("{:.*e}", precision, v);
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let mut mantissa = mantissa.to_owned();
+    if alt && precision == 0 {
+        mantissa.push('.');
+    }
+    let e = if upper { 'E' } else { 'e' };
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}{}{}{:02}", mantissa, e, sign, exp.abs())
+}
+
+// C11 7.21.6.1p8: `%e` style if the exponent is less than -4 or not less than the precision
+fn general(v: f64, precision: usize, alt: bool, upper: bool) -> String {
+    let precision = precision.max(1);
+    let exp = if v == 0.0 {
+        0
+    } else {
+        let s = format!("{:.*e}", precision - 1, v);
+        s[s.find('e').unwrap() + 1..].parse::<i64>().unwrap()
+    };
+
+    let s = if exp < -4 || exp >= precision as i64 {
+        exponential(v, precision - 1, alt, upper)
+    } else {
+        fixed(v, (precision as i64 - 1 - exp) as usize, alt)
+    };
+    if alt {
+        return s;
+    }
+
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(idx) => s.split_at(idx),
+        None => (s.as_str(), ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printf(fmt: &str, args: &[ConstValue]) -> String {
+        let mut read_str = |_| Ok(b"hello".to_vec());
+        String::from_utf8(format(fmt.as_bytes(), args, &mut read_str).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn integers() {
+        use ConstValue::Int;
+        assert_eq!(
+            printf("%d|%5d|%-5d|%05d", &[Int(-42); 4]),
+            "-42|  -42|-42  |-0042"
+        );
+        assert_eq!(
+            printf("%+d % d %.3d %.0d", &[Int(7), Int(7), Int(7), Int(0)]),
+            "+7  7 007 "
+        );
+        assert_eq!(
+            printf(
+                "%u %x %X %#x %#o",
+                &[Int(-1), Int(255), Int(255), Int(255), Int(8)]
+            ),
+            "4294967295 ff FF 0xff 010"
+        );
+        assert_eq!(
+            printf("%ld %lu %hhd %hu", &[Int(-1), Int(-1), Int(255), Int(-1)]),
+            "-1 18446744073709551615 -1 65535"
+        );
+        assert_eq!(
+            printf("%*d|%-*d|%c%%", &[Int(4), Int(1), Int(3), Int(2), Int(65)]),
+            "   1|2  |A%"
+        );
+        assert_eq!(
+            printf("[%s] [%7.3s] [%-6s]", &[Int(1); 3]),
+            "[hello] [    hel] [hello ]"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        use ConstValue::Float;
+        assert_eq!(
+            printf(
+                "%f %.2f %8.3f %-8.1f|",
+                &[Float(1.5), Float(1.23456), Float(-2.5), Float(0.25)]
+            ),
+            "1.500000 1.23   -2.500 0.2     |"
+        );
+        assert_eq!(
+            printf("%e %.2E %e", &[Float(123.456), Float(0.000123), Float(0.0)]),
+            "1.234560e+02 1.23E-04 0.000000e+00"
+        );
+        assert_eq!(
+            printf(
+                "%g %g %g %g %g",
+                &[
+                    Float(100000.0),
+                    Float(1000000.0),
+                    Float(0.0001),
+                    Float(0.00001),
+                    Float(123.456)
+                ]
+            ),
+            "100000 1e+06 0.0001 1e-05 123.456"
+        );
+        assert_eq!(
+            printf("%.3g %#g %G", &[Float(1.23456), Float(1.0), Float(1e-10)]),
+            "1.23 1.00000 1E-10"
+        );
+        assert_eq!(
+            printf(
+                "%f %F %+.1f %05.1f",
+                &[
+                    Float(f64::INFINITY),
+                    Float(f64::NAN),
+                    Float(2.0),
+                    Float(-1.0)
+                ]
+            ),
+            "inf NAN +2.0 -01.0"
+        );
+    }
+}
@@ -0,0 +1,935 @@
+mod lower;
+mod parser;
+mod ssa;
+
+pub use lower::lower;
+pub use parser::parse;
+pub use ssa::{construct_ssa, dominance_frontiers, dominators};
+
+use crate::interner::Symbol;
+use crate::span::Span;
+
+use std::fmt;
+
+// A typed three-address code. Functions are lists of basic blocks; every
+// block ends in exactly one terminator, and instructions compute at most one
+// virtual register. Lowering puts all locals in `alloca`s, and `construct_ssa`
+// then promotes the scalar ones to registers, inserting `phi` nodes.
+// Structures and unions stay in memory: a value of `agg` type is the address
+// of the object.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrType {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Ptr,
+    Agg { size: u64, align: u64 },
+    Void,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Value(Value),
+    Int(i64),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    SDiv,
+    UDiv,
+    SRem,
+    URem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    LShr,
+    AShr,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    FNeg,
+}
+
+// integer comparisons, and the ordered (or, for `Une`, unordered)
+// floating-point comparisons C needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Slt,
+    Sle,
+    Sgt,
+    Sge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+    Oeq,
+    Une,
+    Olt,
+    Ole,
+    Ogt,
+    Oge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastOp {
+    Trunc,
+    ZExt,
+    SExt,
+    FpTrunc,
+    FpExt,
+    FpToSi,
+    FpToUi,
+    SiToFp,
+    UiToFp,
+    PtrToInt,
+    IntToPtr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Direct(Symbol),
+    Indirect(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    // a stack slot of `size` bytes
+    Alloca {
+        size: u64,
+        align: u64,
+    },
+    Load(Operand),
+    // stores a value of the instruction's type
+    Store {
+        value: Operand,
+        ptr: Operand,
+    },
+    Binary(BinOp, Operand, Operand),
+    Unary(UnOp, Operand),
+    // compares operands of type `IrType`, the result is an `i32` 0 or 1
+    Cmp(Cond, IrType, Operand, Operand),
+    // converts from `IrType` to the instruction's type
+    Cast(CastOp, IrType, Operand),
+    // the address of a function or global
+    Global(Symbol),
+    // a pointer plus an `i64` byte offset
+    PtrAdd(Operand, Operand),
+    // arguments from `fixed` on are variadic
+    Call {
+        callee: Callee,
+        args: Vec<(IrType, Operand)>,
+        fixed: Option<usize>,
+    },
+    Phi(Vec<(BlockId, Operand)>),
+    MemCopy {
+        dst: Operand,
+        src: Operand,
+        size: u64,
+    },
+    MemZero {
+        dst: Operand,
+        size: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Inst {
+    pub dest: Option<Value>,
+    pub ty: IrType,
+    pub kind: InstKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
+    Jump(BlockId),
+    // to the first block if the integer operand is nonzero
+    Branch(Operand, BlockId, BlockId),
+    Switch {
+        ty: IrType,
+        value: Operand,
+        default: BlockId,
+        cases: Vec<(i64, BlockId)>,
+    },
+    Return(Option<(IrType, Operand)>),
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Terminator {
+    pub kind: TermKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub id: BlockId,
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Symbol,
+    pub is_static: bool,
+    pub ret: IrType,
+    pub params: Vec<(Value, IrType)>,
+    pub is_variadic: bool,
+    // the entry block comes first
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobalItem {
+    Bytes(Vec<u8>),
+    Zero(u64),
+    // an 8-byte address
+    Addr(Symbol, i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: Symbol,
+    pub is_static: bool,
+    pub size: u64,
+    pub align: u64,
+    // `None` for zero-initialized objects
+    pub init: Option<Vec<GlobalItem>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+impl IrType {
+    pub fn is_float(self) -> bool {
+        matches!(self, IrType::F32 | IrType::F64)
+    }
+
+    pub fn size(self) -> u64 {
+        match self {
+            IrType::I8 => 1,
+            IrType::I16 => 2,
+            IrType::I32 | IrType::F32 => 4,
+            IrType::I64 | IrType::F64 | IrType::Ptr => 8,
+            IrType::Agg { size, .. } => size,
+            IrType::Void => 0,
+        }
+    }
+}
+
+impl Operand {
+    pub fn as_value(self) -> Option<Value> {
+        match self {
+            Operand::Value(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl InstKind {
+    // the operands read by the instruction
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            InstKind::Alloca { .. } | InstKind::Global(_) => Vec::new(),
+            InstKind::Load(a) | InstKind::Unary(_, a) | InstKind::Cast(_, _, a) => vec![*a],
+            InstKind::MemZero { dst, .. } => vec![*dst],
+            InstKind::Store { value, ptr } => vec![*value, *ptr],
+            InstKind::Binary(_, a, b) | InstKind::Cmp(_, _, a, b) | InstKind::PtrAdd(a, b) => {
+                vec![*a, *b]
+            }
+            InstKind::MemCopy { dst, src, .. } => vec![*dst, *src],
+            InstKind::Call { callee, args, .. } => {
+                let mut operands = match callee {
+                    Callee::Direct(_) => Vec::new(),
+                    Callee::Indirect(f) => vec![*f],
+                };
+                operands.extend(args.iter().map(|(_, a)| *a));
+                operands
+            }
+            InstKind::Phi(incoming) => incoming.iter().map(|(_, a)| *a).collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            InstKind::Alloca { .. } | InstKind::Global(_) => Vec::new(),
+            InstKind::Load(a) | InstKind::Unary(_, a) | InstKind::Cast(_, _, a) => vec![a],
+            InstKind::MemZero { dst, .. } => vec![dst],
+            InstKind::Store { value, ptr } => vec![value, ptr],
+            InstKind::Binary(_, a, b) | InstKind::Cmp(_, _, a, b) | InstKind::PtrAdd(a, b) => {
+                vec![a, b]
+            }
+            InstKind::MemCopy { dst, src, .. } => vec![dst, src],
+            InstKind::Call { callee, args, .. } => {
+                let mut operands = match callee {
+                    Callee::Direct(_) => Vec::new(),
+                    Callee::Indirect(f) => vec![f],
+                };
+                operands.extend(args.iter_mut().map(|(_, a)| a));
+                operands
+            }
+            InstKind::Phi(incoming) => incoming.iter_mut().map(|(_, a)| a).collect(),
+        }
+    }
+}
+
+impl TermKind {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            TermKind::Jump(target) => vec![*target],
+            TermKind::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            TermKind::Switch { default, cases, .. } => {
+                let mut targets = vec![*default];
+                targets.extend(cases.iter().map(|(_, target)| *target));
+                targets
+            }
+            TermKind::Return(_) | TermKind::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            TermKind::Branch(cond, ..) => vec![cond],
+            TermKind::Switch { value, .. } => vec![value],
+            TermKind::Return(Some((_, value))) => vec![value],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn block(&self, id: BlockId) -> &Block {
+        self.blocks.iter().find(|b| b.id == id).unwrap()
+    }
+
+    // a register number not used in the function yet
+    pub fn next_value(&self) -> u32 {
+        let params = self.params.iter().map(|(v, _)| v.0);
+        let dests = self
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter_map(|inst| inst.dest.map(|v| v.0));
+        params.chain(dests).max().map_or(0, |max| max + 1)
+    }
+}
+
+// the textual form, which `parse` reads back
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrType::I8 => f.write_str("i8"),
+            IrType::I16 => f.write_str("i16"),
+            IrType::I32 => f.write_str("i32"),
+            IrType::I64 => f.write_str("i64"),
+            IrType::F32 => f.write_str("f32"),
+            IrType::F64 => f.write_str("f64"),
+            IrType::Ptr => f.write_str("ptr"),
+            IrType::Agg { size, align } => write!(f, "agg({}, {})", size, align),
+            IrType::Void => f.write_str("void"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Value(v) => write!(f, "{}", v),
+            Operand::Int(v) => write!(f, "{}", v),
+            // `Debug` always prints a `.`, an exponent, `NaN` or `inf`,
+            // and round-trips
+            Operand::Float(v) => write!(f, "{:?}", v),
+        }
+    }
+}
+
+pub(crate) const BIN_OPS: [(BinOp, &str); 17] = [
+    (BinOp::Add, "add"),
+    (BinOp::Sub, "sub"),
+    (BinOp::Mul, "mul"),
+    (BinOp::SDiv, "sdiv"),
+    (BinOp::UDiv, "udiv"),
+    (BinOp::SRem, "srem"),
+    (BinOp::URem, "urem"),
+    (BinOp::And, "and"),
+    (BinOp::Or, "or"),
+    (BinOp::Xor, "xor"),
+    (BinOp::Shl, "shl"),
+    (BinOp::LShr, "lshr"),
+    (BinOp::AShr, "ashr"),
+    (BinOp::FAdd, "fadd"),
+    (BinOp::FSub, "fsub"),
+    (BinOp::FMul, "fmul"),
+    (BinOp::FDiv, "fdiv"),
+];
+
+pub(crate) const UN_OPS: [(UnOp, &str); 3] =
+    [(UnOp::Neg, "neg"), (UnOp::Not, "not"), (UnOp::FNeg, "fneg")];
+
+pub(crate) const CONDS: [(Cond, &str); 16] = [
+    (Cond::Eq, "eq"),
+    (Cond::Ne, "ne"),
+    (Cond::Slt, "slt"),
+    (Cond::Sle, "sle"),
+    (Cond::Sgt, "sgt"),
+    (Cond::Sge, "sge"),
+    (Cond::Ult, "ult"),
+    (Cond::Ule, "ule"),
+    (Cond::Ugt, "ugt"),
+    (Cond::Uge, "uge"),
+    (Cond::Oeq, "oeq"),
+    (Cond::Une, "une"),
+    (Cond::Olt, "olt"),
+    (Cond::Ole, "ole"),
+    (Cond::Ogt, "ogt"),
+    (Cond::Oge, "oge"),
+];
+
+pub(crate) const CAST_OPS: [(CastOp, &str); 11] = [
+    (CastOp::Trunc, "trunc"),
+    (CastOp::ZExt, "zext"),
+    (CastOp::SExt, "sext"),
+    (CastOp::FpTrunc, "fptrunc"),
+    (CastOp::FpExt, "fpext"),
+    (CastOp::FpToSi, "fptosi"),
+    (CastOp::FpToUi, "fptoui"),
+    (CastOp::SiToFp, "sitofp"),
+    (CastOp::UiToFp, "uitofp"),
+    (CastOp::PtrToInt, "ptrtoint"),
+    (CastOp::IntToPtr, "inttoptr"),
+];
+
+fn name_of<T: PartialEq>(table: &[(T, &'static str)], key: &T) -> &'static str {
+    table.iter().find(|(k, _)| k == key).unwrap().1
+}
+
+fn fmt_args(
+    f: &mut fmt::Formatter<'_>,
+    args: &[(IrType, Operand)],
+    fixed: Option<usize>,
+) -> fmt::Result {
+    f.write_str("(")?;
+    for (i, (ty, arg)) in args.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        if fixed == Some(i) {
+            f.write_str("..., ")?;
+        }
+        write!(f, "{} {}", ty, arg)?;
+    }
+    if fixed == Some(args.len()) {
+        if !args.is_empty() {
+            f.write_str(", ")?;
+        }
+        f.write_str("...")?;
+    }
+    f.write_str(")")
+}
+
+// `!loc("file", 40..44, 3:5..3:9)`; macro expansions are not kept
+pub(crate) struct Loc<'s>(pub &'s Span);
+
+impl fmt::Display for Loc<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loc = self.0.location();
+        write!(
+            f,
+            "!loc({:?}, {}..{}, {}:{}..{}:{})",
+            &*loc.file_path,
+            loc.byte_range.start,
+            loc.byte_range.end,
+            loc.lc_range.start.line,
+            loc.lc_range.start.column,
+            loc.lc_range.end.line,
+            loc.lc_range.end.column
+        )
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(dest) = self.dest {
+            write!(f, "{} = ", dest)?;
+        }
+        let ty = self.ty;
+        match &self.kind {
+            InstKind::Alloca { size, align } => write!(f, "alloca {}, {}", size, align)?,
+            InstKind::Load(ptr) => write!(f, "load {} {}", ty, ptr)?,
+            InstKind::Store { value, ptr } => write!(f, "store {} {}, {}", ty, value, ptr)?,
+            InstKind::Binary(op, a, b) => {
+                write!(f, "{} {} {}, {}", name_of(&BIN_OPS, op), ty, a, b)?
+            }
+            InstKind::Unary(op, a) => write!(f, "{} {} {}", name_of(&UN_OPS, op), ty, a)?,
+            InstKind::Cmp(cond, operand_ty, a, b) => write!(
+                f,
+                "cmp {} {} {}, {}",
+                name_of(&CONDS, cond),
+                operand_ty,
+                a,
+                b
+            )?,
+            InstKind::Cast(op, from, a) => {
+                write!(f, "{} {} {} to {}", name_of(&CAST_OPS, op), from, a, ty)?
+            }
+            InstKind::Global(name) => write!(f, "global @{}", name)?,
+            InstKind::PtrAdd(ptr, offset) => write!(f, "ptradd {}, {}", ptr, offset)?,
+            InstKind::Call {
+                callee,
+                args,
+                fixed,
+            } => {
+                write!(f, "call {} ", ty)?;
+                match callee {
+                    Callee::Direct(name) => write!(f, "@{}", name)?,
+                    Callee::Indirect(ptr) => write!(f, "{}", ptr)?,
+                }
+                fmt_args(f, args, *fixed)?;
+            }
+            InstKind::Phi(incoming) => {
+                write!(f, "phi {}", ty)?;
+                for (i, (block, value)) in incoming.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}[{}, {}]", sep, block, value)?;
+                }
+            }
+            InstKind::MemCopy { dst, src, size } => {
+                write!(f, "memcopy {}, {}, {}", dst, src, size)?
+            }
+            InstKind::MemZero { dst, size } => write!(f, "memzero {}, {}", dst, size)?,
+        }
+        write!(f, "  {}", Loc(&self.span))
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TermKind::Jump(target) => write!(f, "jmp {}", target)?,
+            TermKind::Branch(cond, then, otherwise) => {
+                write!(f, "br {}, {}, {}", cond, then, otherwise)?
+            }
+            TermKind::Switch {
+                ty,
+                value,
+                default,
+                cases,
+            } => {
+                write!(f, "switch {} {}, {} [", ty, value, default)?;
+                for (i, (case, target)) in cases.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}: {}", sep, case, target)?;
+                }
+                f.write_str("]")?;
+            }
+            TermKind::Return(None) => f.write_str("ret void")?,
+            TermKind::Return(Some((ty, value))) => write!(f, "ret {} {}", ty, value)?,
+            TermKind::Unreachable => f.write_str("unreachable")?,
+        }
+        write!(f, "  {}", Loc(&self.span))
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_static {
+            f.write_str("static ")?;
+        }
+        write!(f, "define {} @{}", self.ret, self.name)?;
+        let params = self
+            .params
+            .iter()
+            .map(|(v, ty)| (*ty, Operand::Value(*v)))
+            .collect::<Vec<_>>();
+        let fixed = if self.is_variadic {
+            Some(params.len())
+        } else {
+            None
+        };
+        fmt_args(f, &params, fixed)?;
+        f.write_str(" {\n")?;
+        for block in &self.blocks {
+            writeln!(f, "{}:", block.id)?;
+            for inst in &block.insts {
+                writeln!(f, "  {}", inst)?;
+            }
+            writeln!(f, "  {}", block.term)?;
+        }
+        f.write_str("}\n")
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_static {
+            f.write_str("static ")?;
+        }
+        write!(f, "global @{}, {}, {}", self.name, self.size, self.align)?;
+        if let Some(items) = &self.init {
+            f.write_str(" = {")?;
+            for (i, item) in items.iter().enumerate() {
+                f.write_str(if i == 0 { " " } else { ", " })?;
+                match item {
+                    GlobalItem::Bytes(bytes) => {
+                        f.write_str("bytes [")?;
+                        for (i, byte) in bytes.iter().enumerate() {
+                            let sep = if i == 0 { "" } else { " " };
+                            write!(f, "{}{}", sep, byte)?;
+                        }
+                        f.write_str("]")?;
+                    }
+                    GlobalItem::Zero(n) => write!(f, "zero {}", n)?,
+                    GlobalItem::Addr(name, addend) => write!(f, "addr @{}{:+}", name, addend)?,
+                }
+            }
+            f.write_str(" }")?;
+        }
+        writeln!(f)
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            write!(f, "{}", global)?;
+        }
+        for function in &self.functions {
+            writeln!(f)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source_file::SourceFile;
+
+    use std::collections::{HashMap, HashSet};
+    use std::fs;
+    use std::path::Path;
+
+    fn compile(src: &str, file_path: &str) -> Module {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, file_path)).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let checked = Checker::new().check(&unit);
+        assert!(checked.errors.is_empty(), "{:?}", checked.errors);
+        let mut module = lower(&unit, &checked.types).unwrap();
+        for function in &mut module.functions {
+            construct_ssa(function);
+            verify(function);
+        }
+        module
+    }
+
+    // the error of lowering `src`, which the checker also rejects
+    fn lower_error(src: &str) -> String {
+        let (tokens, _) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let checked = Checker::new().check(&unit);
+        lower(&unit, &checked.types).unwrap_err().msg
+    }
+
+    // every register is defined once, before its uses on every path, and
+    // phis have one operand per predecessor
+    fn verify(f: &Function) {
+        let mut defs = HashMap::new();
+        for (v, _) in &f.params {
+            assert!(defs.insert(*v, None).is_none());
+        }
+        for block in &f.blocks {
+            for (i, inst) in block.insts.iter().enumerate() {
+                if let Some(dest) = inst.dest {
+                    assert!(defs.insert(dest, Some((block.id, i))).is_none(), "{}", dest);
+                }
+            }
+        }
+        let idom = dominators(f);
+        let dominates = |a: BlockId, mut b: BlockId| loop {
+            if a == b {
+                return true;
+            }
+            match idom.get(&b) {
+                Some(up) => b = *up,
+                None => return false,
+            }
+        };
+        let mut preds: HashMap<BlockId, HashSet<BlockId>> = HashMap::new();
+        for block in &f.blocks {
+            for succ in block.term.kind.successors() {
+                preds.entry(succ).or_default().insert(block.id);
+            }
+        }
+        let check = |v: Value, block: BlockId, at: usize| match defs[&v] {
+            None => {}
+            Some((def_block, def_at)) if def_block == block => assert!(def_at < at, "{}", v),
+            Some((def_block, _)) => assert!(dominates(def_block, block), "{}", v),
+        };
+        for block in &f.blocks {
+            for (i, inst) in block.insts.iter().enumerate() {
+                if let InstKind::Phi(incoming) = &inst.kind {
+                    let from = incoming.iter().map(|(b, _)| *b).collect::<HashSet<_>>();
+                    assert_eq!(from, preds[&block.id]);
+                    for (pred, value) in incoming {
+                        if let Some(v) = value.as_value() {
+                            check(v, *pred, usize::MAX);
+                        }
+                    }
+                } else {
+                    for v in inst
+                        .kind
+                        .operands()
+                        .into_iter()
+                        .filter_map(Operand::as_value)
+                    {
+                        check(v, block.id, i);
+                    }
+                }
+            }
+            let mut term = block.term.kind.clone();
+            for v in term
+                .operands_mut()
+                .into_iter()
+                .filter_map(|op| op.as_value())
+            {
+                check(v, block.id, usize::MAX);
+            }
+        }
+    }
+
+    fn parse_str(text: &str) -> Result<Module, Box<crate::diagnostics::Diagnostic>> {
+        parse(&SourceFile::new(text, "<dummy file>"))
+    }
+
+    #[test]
+    fn golden() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("cases/ir");
+        let mut paths = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            let src = fs::read_to_string(&path).unwrap();
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            let actual = compile(&src, file_name).to_string();
+            let expected_path = path.with_extension("ir");
+            // `BLESS=1 cargo test` rewrites the expected output
+            if std::env::var_os("BLESS").is_some() {
+                fs::write(&expected_path, &actual).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&expected_path).unwrap();
+            assert_eq!(actual, expected, "{}", expected_path.display());
+
+            // the printed form reads back to the same module
+            let reparsed = parse_str(&actual).unwrap();
+            assert_eq!(reparsed.to_string(), actual);
+        }
+    }
+
+    #[test]
+    fn misplaced_jumps() {
+        assert_eq!(
+            lower_error("int main(void) { break; return 0; }"),
+            "'break' statement not in loop or switch statement"
+        );
+        assert_eq!(
+            lower_error("int main(void) { switch (1) { continue; } return 0; }"),
+            "'continue' statement not in loop statement"
+        );
+        assert_eq!(
+            lower_error("int main(void) { while (1) { case 1: return 1; } }"),
+            "'case' statement not in switch statement"
+        );
+        assert_eq!(
+            lower_error("int main(void) { default: return 0; }"),
+            "'default' statement not in switch statement"
+        );
+    }
+
+    #[test]
+    fn promotion() {
+        let src =
+            "int f(int c) { int x = 1, y = 2; int *p = &y; if (c) x = 3; *p = x; return x + y; }";
+        let module = compile(src, "<dummy file>");
+        let text = module.to_string();
+        // `y` has its address taken, `x` and `p` become registers
+        assert_eq!(text.matches("alloca").count(), 1, "{}", text);
+        assert!(text.contains("phi i32 [bb0, 1], [bb1, 3]"), "{}", text);
+    }
+
+    #[test]
+    fn dominance() {
+        let text = "
+            define void @f(i32 %0) {
+            bb0:
+              br %0, bb1, bb2
+            bb1:
+              jmp bb3
+            bb2:
+              jmp bb3
+            bb3:
+              br %0, bb4, bb5
+            bb4:
+              jmp bb3
+            bb5:
+              ret void
+            bb6:
+              jmp bb5
+            }
+        ";
+        let mut module = parse_str(text).unwrap();
+        let f = &mut module.functions[0];
+        let idom = dominators(f);
+        let expected = [(1, 0), (2, 0), (3, 0), (4, 3), (5, 3)];
+        assert_eq!(idom.len(), expected.len());
+        for (block, dom) in expected {
+            assert_eq!(idom[&BlockId(block)], BlockId(dom));
+        }
+        let frontiers = dominance_frontiers(f, &idom);
+        let frontier = |b| {
+            frontiers[&BlockId(b)]
+                .iter()
+                .map(|b| b.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(frontier(1), [3]);
+        assert_eq!(frontier(4), [3]);
+        assert_eq!(frontier(3), [3]);
+        assert!(frontier(0).is_empty());
+
+        construct_ssa(f);
+        assert_eq!(f.blocks.len(), 6);
+    }
+
+    #[test]
+    fn text_form() {
+        let text = r#"
+            ; comments are ignored
+            static global @s, 16, 8 = { bytes [1 2], zero 6, addr @f-8 }
+            global @b, 4, 4
+
+            define f64 @f(agg(12, 4) %0, ...) {
+            bb0:
+              %1 = load f32 %0  !loc("a.c", 3..7, 1:4..1:8)
+              %2 = fpext f32 %1 to f64
+              %3 = fadd f64 %2, -1.5e-7
+              %4 = call i32 @g(ptr %0, ..., f64 inf, f64 NaN)
+              %5 = cmp une f64 %3, -0.0
+              switch i32 %5, bb1 [0: bb1, -1: bb2]
+            bb1:
+              %6 = phi f64 [bb0, %3], [bb2, 1.0]
+              ret f64 %6
+            bb2:
+              memzero %0, 12
+              jmp bb1
+            }
+        "#;
+        let module = parse_str(text).unwrap();
+        let printed = module.to_string();
+        assert_eq!(parse_str(&printed).unwrap().to_string(), printed);
+        assert_eq!(
+            module.globals[0].init.as_ref().unwrap()[2],
+            GlobalItem::Addr(Symbol::intern("f"), -8)
+        );
+        let f = &module.functions[0];
+        assert!(f.is_variadic);
+        assert_eq!(f.params, [(Value(0), IrType::Agg { size: 12, align: 4 })]);
+        let span = &f.blocks[0].insts[0].span;
+        assert_eq!((&*span.file_path(), span.byte_range()), ("a.c", 3..7));
+        // without `!loc`, the span of the instruction's own text
+        let span = &f.blocks[0].insts[1].span;
+        assert_eq!(&*span.file_path(), "<dummy file>");
+        assert_eq!(span.lc_range().start.line, 9);
+        assert!(printed.contains("call i32 @g(ptr %0, ..., f64 inf, f64 NaN)"));
+        assert!(printed.contains("fadd f64 %2, -1.5e-7"));
+
+        let error = |text: &str| parse_str(text).unwrap_err().msg;
+        assert_eq!(
+            error("define i32 @f() {\nbb0:\n  %1 = add i33 1, 2\n}"),
+            "expected a type, found 'i33'"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n  add i32 1, 2\n  ret void\n}"),
+            "the result of 'add' must be assigned to a register"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n  %0 = frob\n}"),
+            "unknown instruction 'frob'"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n}"),
+            "expected a terminator, found '}'"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n  jmp bb5\n}"),
+            "use of undefined block 'bb5'"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n  %1 = add i32 %99, 1\n  ret void\n}"),
+            "use of undefined register '%99'"
+        );
+        assert_eq!(
+            error("define void @f() {\nbb0:\n  jmp bb0\nbb0:\n  ret void\n}"),
+            "redefinition of 'bb0'"
+        );
+        assert_eq!(
+            error("define void @f(i32 %0) {\nbb0:\n  %0 = add i32 1, 2\n  ret void\n}"),
+            "redefinition of '%0'"
+        );
+        assert_eq!(
+            error("define void @f(i32 %0, i32 %0) {\nbb0:\n  ret void\n}"),
+            "redefinition of '%0'"
+        );
+    }
+}
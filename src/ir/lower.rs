@@ -0,0 +1,1251 @@
+use super::*;
+use crate::{
+    ast::*,
+    checker::{convert_value, ConstValue, TypeTable},
+    diagnostics::Diagnostic,
+    initializer::{self, StaticImage, Target},
+    interner::Symbol,
+    tokens::StringLiteral,
+    types::*,
+};
+
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+#[derive(Debug, Clone)]
+enum Local {
+    // a stack slot, or the copy of a structure passed by value
+    Addr(Operand),
+    Static(Symbol),
+}
+
+struct PartialBlock {
+    id: BlockId,
+    insts: Vec<Inst>,
+    term: Option<Terminator>,
+}
+
+#[derive(Default)]
+struct FunctionState {
+    name: Option<Symbol>,
+    ret: Option<Type>,
+    blocks: Vec<PartialBlock>,
+    // hoisted to the entry block
+    allocas: Vec<Inst>,
+    next_value: u32,
+    next_block: u32,
+    breaks: Vec<BlockId>,
+    continues: Vec<BlockId>,
+    cases: HashMap<*const Stmt, BlockId>,
+    labels: HashMap<Symbol, BlockId>,
+    func_name: Option<Symbol>,
+}
+
+struct Lowerer<'a> {
+    types: &'a TypeTable,
+    module: Module,
+    counter: usize,
+    strings: HashMap<*const StringLiteral, Symbol>,
+    scopes: Vec<HashMap<Symbol, Local>>,
+    state: FunctionState,
+}
+
+// lowers a checked translation unit; locals live in `alloca`s until
+// `construct_ssa` promotes them
+pub fn lower(unit: &TranslationUnit, types: &TypeTable) -> Result<Module> {
+    let mut lowerer = Lowerer {
+        types,
+        module: Module::default(),
+        counter: 0,
+        strings: HashMap::new(),
+        scopes: vec![HashMap::new()],
+        state: FunctionState::default(),
+    };
+    lowerer.globals(unit)?;
+    for item in &unit.items {
+        if let ExternalDecl::Function(f) = item {
+            lowerer.function(f)?;
+        }
+    }
+    Ok(lowerer.module)
+}
+
+fn declarator_name(declarator: &Declarator) -> Symbol {
+    declarator
+        .name
+        .as_ref()
+        .map_or_else(|| Symbol::intern(""), |name| name.value)
+}
+
+fn int_type(size: u64) -> IrType {
+    match size {
+        1 => IrType::I8,
+        2 => IrType::I16,
+        4 => IrType::I32,
+        _ => IrType::I64,
+    }
+}
+
+fn is_signed(ty: &Type) -> bool {
+    ty.int_kind().is_some_and(|kind| kind.is_signed())
+}
+
+fn zero(ty: IrType) -> Operand {
+    if ty.is_float() {
+        Operand::Float(0.0)
+    } else {
+        Operand::Int(0)
+    }
+}
+
+fn const_operand(value: ConstValue) -> Operand {
+    match value {
+        ConstValue::Int(v) => Operand::Int(v),
+        ConstValue::Float(v) => Operand::Float(v),
+    }
+}
+
+// splits an image into items, with runs of zeros kept short
+fn global_items(bytes: &[u8], out: &mut Vec<GlobalItem>) {
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let zeros = rest.iter().take_while(|&&b| b == 0).count();
+        if zeros >= 8 || zeros == rest.len() {
+            out.push(GlobalItem::Zero(zeros as u64));
+            rest = &rest[zeros..];
+        } else {
+            let mut end = 0;
+            while end < rest.len() && !rest[end..].starts_with(&[0; 8]) {
+                end += 1;
+            }
+            out.push(GlobalItem::Bytes(rest[..end].to_vec()));
+            rest = &rest[end..];
+        }
+    }
+}
+
+impl<'a> Lowerer<'a> {
+    fn ir_type(&self, ty: &Type, span: &Span) -> Result<IrType> {
+        Ok(match &ty.kind {
+            TypeKind::Void => IrType::Void,
+            TypeKind::Float(FloatKind::Float) => IrType::F32,
+            TypeKind::Float(FloatKind::Double) => IrType::F64,
+            TypeKind::Float(FloatKind::LongDouble) => {
+                return Err(Box::new(Diagnostic::error(
+                    *span,
+                    "'long double' is not supported by the IR",
+                )))
+            }
+            TypeKind::Pointer(_) | TypeKind::Array(..) | TypeKind::Function(_) => IrType::Ptr,
+            TypeKind::Record(_) => IrType::Agg {
+                size: ty.size().unwrap_or(0),
+                align: ty.align().unwrap_or(1),
+            },
+            _ => int_type(ty.size().unwrap_or(4)),
+        })
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Local> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))
+            .cloned()
+    }
+
+    fn bind(&mut self, name: Symbol, local: Local) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.insert(name, local);
+    }
+
+    fn unique(&mut self, name: &str) -> Symbol {
+        self.counter += 1;
+        Symbol::intern(&format!("{}.{}", name, self.counter - 1))
+    }
+
+    fn string_global(&mut self, s: &StringLiteral) -> Symbol {
+        let key = s as *const StringLiteral;
+        if let Some(&name) = self.strings.get(&key) {
+            return name;
+        }
+        let name = self.unique(".str");
+        self.bytes_global(name, s.bytes());
+        self.strings.insert(key, name);
+        name
+    }
+
+    fn bytes_global(&mut self, name: Symbol, bytes: Vec<u8>) {
+        self.module.globals.push(Global {
+            name,
+            is_static: true,
+            size: bytes.len() as u64,
+            align: 1,
+            init: Some(vec![GlobalItem::Bytes(bytes)]),
+        });
+    }
+
+    // file-scope objects, merging tentative definitions
+    fn globals(&mut self, unit: &TranslationUnit) -> Result<()> {
+        let mut globals: Vec<(Symbol, Type, Option<&Initializer>, bool)> = Vec::new();
+        for item in &unit.items {
+            let decl = match item {
+                ExternalDecl::Declaration(decl) if !decl.specifiers.is_typedef() => decl,
+                _ => continue,
+            };
+            let is_static = decl.specifiers.storage.contains(&StorageClass::Static);
+            let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+            for init in &decl.declarators {
+                let ty = self.types.declarator(&init.declarator);
+                let name = declarator_name(&init.declarator);
+                if ty.is_function() || is_extern && init.initializer.is_none() {
+                    continue;
+                }
+                let idx = match globals.iter().position(|g| g.0 == name) {
+                    Some(idx) => idx,
+                    None => {
+                        globals.push((name, ty.clone(), None, is_static));
+                        globals.len() - 1
+                    }
+                };
+                let global = &mut globals[idx];
+                if init.initializer.is_some() || ty.is_complete() {
+                    global.1 = ty.clone();
+                }
+                if init.initializer.is_some() {
+                    global.2 = init.initializer.as_ref();
+                }
+                global.3 |= is_static;
+            }
+        }
+        for (name, ty, init, is_static) in globals {
+            self.static_object(name, &ty, init, is_static)?;
+        }
+        Ok(())
+    }
+
+    fn static_object(
+        &mut self,
+        name: Symbol,
+        ty: &Type,
+        init: Option<&Initializer>,
+        is_static: bool,
+    ) -> Result<()> {
+        let size = initializer::object_size(ty);
+        let init = match init {
+            Some(init) => {
+                let image = initializer::static_image(self.types, ty, size, init)?;
+                Some(self.static_items(image)?)
+            }
+            None => None,
+        };
+        self.module.globals.push(Global {
+            name,
+            is_static,
+            size,
+            align: ty.align().unwrap_or(1),
+            init,
+        });
+        Ok(())
+    }
+
+    fn static_items(&mut self, image: StaticImage) -> Result<Vec<GlobalItem>> {
+        let mut items = Vec::new();
+        let mut pos = 0;
+        for relocation in image.relocations {
+            let symbol = match relocation.target {
+                Target::String(s) => self.string_global(s),
+                Target::Symbol(name) => match self.lookup(name) {
+                    Some(Local::Static(symbol)) => symbol,
+                    Some(Local::Addr(_)) => {
+                        return Err(Box::new(Diagnostic::error(
+                            relocation.expr.span,
+                            "initializer element is not a compile-time constant",
+                        )))
+                    }
+                    None => name,
+                },
+            };
+            let offset = relocation.offset as usize;
+            global_items(&image.bytes[pos..offset], &mut items);
+            items.push(GlobalItem::Addr(symbol, relocation.addend));
+            pos = offset + 8;
+        }
+        global_items(&image.bytes[pos..], &mut items);
+        Ok(items)
+    }
+}
+
+// building blocks
+impl<'a> Lowerer<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.state.next_block += 1;
+        BlockId(self.state.next_block - 1)
+    }
+
+    fn new_value(&mut self) -> Value {
+        self.state.next_value += 1;
+        Value(self.state.next_value - 1)
+    }
+
+    // makes `id` the block instructions are appended to
+    fn start(&mut self, id: BlockId) {
+        self.state.blocks.push(PartialBlock {
+            id,
+            insts: Vec::new(),
+            term: None,
+        });
+    }
+
+    fn current(&mut self) -> &mut PartialBlock {
+        // code after a jump is unreachable but still needs a block
+        if self.state.blocks.last().unwrap().term.is_some() {
+            let id = self.new_block();
+            self.start(id);
+        }
+        self.state.blocks.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, ty: IrType, kind: InstKind, span: &Span) -> Operand {
+        let has_result = !matches!(
+            kind,
+            InstKind::Store { .. } | InstKind::MemCopy { .. } | InstKind::MemZero { .. }
+        ) && ty != IrType::Void;
+        let dest = if has_result {
+            Some(self.new_value())
+        } else {
+            None
+        };
+        let inst = Inst {
+            dest,
+            ty,
+            kind,
+            span: *span,
+        };
+        self.current().insts.push(inst);
+        dest.map_or(Operand::Int(0), Operand::Value)
+    }
+
+    fn terminate(&mut self, kind: TermKind, span: &Span) {
+        self.current().term = Some(Terminator { kind, span: *span });
+    }
+
+    fn jump_to(&mut self, target: BlockId, span: &Span) {
+        self.terminate(TermKind::Jump(target), span);
+        self.start(target);
+    }
+
+    fn alloca(&mut self, ty: &Type, span: &Span) -> Operand {
+        let value = self.new_value();
+        self.state.allocas.push(Inst {
+            dest: Some(value),
+            ty: IrType::Ptr,
+            kind: InstKind::Alloca {
+                size: ty.size().unwrap_or(0),
+                align: ty.align().unwrap_or(1),
+            },
+            span: *span,
+        });
+        Operand::Value(value)
+    }
+
+    fn offset(&mut self, ptr: Operand, offset: i64, span: &Span) -> Operand {
+        if offset == 0 {
+            return ptr;
+        }
+        self.emit(
+            IrType::Ptr,
+            InstKind::PtrAdd(ptr, Operand::Int(offset)),
+            span,
+        )
+    }
+
+    fn load(&mut self, addr: Operand, ty: &Type, span: &Span) -> Result<Operand> {
+        if ty.is_array() || ty.is_function() || ty.is_record() {
+            return Ok(addr);
+        }
+        let ir_ty = self.ir_type(ty, span)?;
+        Ok(self.emit(ir_ty, InstKind::Load(addr), span))
+    }
+
+    fn store(&mut self, value: Operand, addr: Operand, ty: &Type, span: &Span) -> Result<()> {
+        let ir_ty = self.ir_type(ty, span)?;
+        let kind = match ir_ty {
+            IrType::Agg { size, .. } => InstKind::MemCopy {
+                dst: addr,
+                src: value,
+                size,
+            },
+            _ => InstKind::Store { value, ptr: addr },
+        };
+        self.emit(ir_ty, kind, span);
+        Ok(())
+    }
+
+    // a nonzero scalar becomes 1, zero becomes 0, as an `i32`
+    fn truth(&mut self, value: Operand, ty: &Type, span: &Span) -> Result<Operand> {
+        let ir_ty = self.ir_type(ty, span)?;
+        let cond = if ir_ty.is_float() {
+            Cond::Une
+        } else {
+            Cond::Ne
+        };
+        let kind = InstKind::Cmp(cond, ir_ty, value, zero(ir_ty));
+        Ok(self.emit(IrType::I32, kind, span))
+    }
+
+    fn convert(&mut self, value: Operand, from: &Type, to: &Type, span: &Span) -> Result<Operand> {
+        if to.is_void() || !to.is_scalar() || !from.is_scalar() {
+            return Ok(value);
+        }
+        // constants are converted at compile time
+        let constant = match value {
+            Operand::Int(v) => Some(ConstValue::Int(v)),
+            Operand::Float(v) => Some(ConstValue::Float(v)),
+            Operand::Value(_) => None,
+        };
+        if let Some(converted) = constant.and_then(|c| convert_value(c, from, to)) {
+            return Ok(const_operand(converted));
+        }
+
+        if to.int_kind() == Some(IntKind::Bool) {
+            let truth = self.truth(value, from, span)?;
+            return Ok(self.emit(
+                IrType::I8,
+                InstKind::Cast(CastOp::Trunc, IrType::I32, truth),
+                span,
+            ));
+        }
+        let (src, dst) = (self.ir_type(from, span)?, self.ir_type(to, span)?);
+        let op = match (src, dst) {
+            _ if src == dst => return Ok(value),
+            (IrType::F32, IrType::F64) => CastOp::FpExt,
+            (IrType::F64, IrType::F32) => CastOp::FpTrunc,
+            (_, IrType::F32 | IrType::F64) if is_signed(from) => CastOp::SiToFp,
+            (_, IrType::F32 | IrType::F64) => CastOp::UiToFp,
+            (IrType::F32 | IrType::F64, _) if is_signed(to) => CastOp::FpToSi,
+            (IrType::F32 | IrType::F64, _) => CastOp::FpToUi,
+            (IrType::Ptr, _) => CastOp::PtrToInt,
+            (_, IrType::Ptr) if src == IrType::I64 => CastOp::IntToPtr,
+            (_, IrType::Ptr) => {
+                let long = Type::int(IntKind::Long);
+                let value = self.convert(value, from, &long, span)?;
+                return self.convert(value, &long, to, span);
+            }
+            _ if dst.size() < src.size() => CastOp::Trunc,
+            _ if is_signed(from) => CastOp::SExt,
+            _ => CastOp::ZExt,
+        };
+        if let (CastOp::PtrToInt, true) = (op, dst != IrType::I64) {
+            let long = Type::int(IntKind::ULong);
+            let value = self.convert(value, from, &long, span)?;
+            return self.convert(value, &long, to, span);
+        }
+        Ok(self.emit(dst, InstKind::Cast(op, src, value), span))
+    }
+}
+
+// functions and statements
+impl<'a> Lowerer<'a> {
+    fn function(&mut self, f: &FunctionDef) -> Result<()> {
+        let types = self.types;
+        let name = declarator_name(&f.declarator);
+        let func = match &types.declarator(&f.declarator).kind {
+            TypeKind::Function(func) => func.clone(),
+            _ => unreachable!("function definitions have function type"),
+        };
+        self.state = FunctionState {
+            name: Some(name),
+            ret: Some((*func.ret).clone()),
+            ..FunctionState::default()
+        };
+        self.scopes.push(HashMap::new());
+        let entry = self.new_block();
+        self.start(entry);
+
+        let mut params = Vec::new();
+        let decls = match f.declarator.derived.first() {
+            Some(DerivedDeclarator::Function(func)) => func.params.as_slice(),
+            _ => &[],
+        };
+        for param in decls {
+            let ty = types.declarator(&param.declarator);
+            let ir_ty = self.ir_type(ty, &param.span)?;
+            let value = self.new_value();
+            params.push((value, ir_ty));
+            let addr = if ty.is_record() {
+                Operand::Value(value)
+            } else {
+                let addr = self.alloca(ty, &param.span);
+                self.store(Operand::Value(value), addr, ty, &param.span)?;
+                addr
+            };
+            if let Some(name) = &param.declarator.name {
+                self.bind(name.value, Local::Addr(addr));
+            }
+        }
+
+        for item in &f.body.items {
+            self.block_item(item)?;
+        }
+        let ret = self.ir_type(&func.ret, &f.span)?;
+        let falls_off = self.state.blocks.last().unwrap().term.is_none();
+        // reaching the end of `main` returns 0
+        let value = match ret {
+            _ if !falls_off => None,
+            IrType::Void => None,
+            IrType::Agg { size, .. } => {
+                let addr = self.alloca(&func.ret, &f.body.span);
+                self.emit(ret, InstKind::MemZero { dst: addr, size }, &f.body.span);
+                Some((ret, addr))
+            }
+            _ => Some((ret, zero(ret))),
+        };
+        if falls_off {
+            self.terminate(TermKind::Return(value), &f.body.span);
+        }
+        self.scopes.pop();
+
+        let state = std::mem::take(&mut self.state);
+        let mut blocks = state
+            .blocks
+            .into_iter()
+            .map(|block| Block {
+                id: block.id,
+                insts: block.insts,
+                term: block.term.unwrap(),
+            })
+            .collect::<Vec<_>>();
+        let mut allocas = state.allocas;
+        allocas.append(&mut blocks[0].insts);
+        blocks[0].insts = allocas;
+
+        let mut function = Function {
+            name,
+            is_static: f.specifiers.storage.contains(&StorageClass::Static),
+            ret,
+            params,
+            is_variadic: func.variadic,
+            blocks,
+        };
+        ssa::remove_unreachable(&mut function);
+        self.module.functions.push(function);
+        Ok(())
+    }
+
+    fn block_item(&mut self, item: &BlockItem) -> Result<()> {
+        match item {
+            BlockItem::Declaration(decl) => self.declare(decl),
+            BlockItem::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn declare(&mut self, decl: &Declaration) -> Result<()> {
+        let types = self.types;
+        let is_static = decl.specifiers.storage.contains(&StorageClass::Static);
+        let is_extern = decl.specifiers.storage.contains(&StorageClass::Extern);
+        for init in &decl.declarators {
+            let name = match &init.declarator.name {
+                Some(name) => name.value,
+                None => continue,
+            };
+            let ty = types.declarator(&init.declarator);
+            if decl.specifiers.is_typedef() || ty.is_function() {
+                continue;
+            }
+
+            if is_extern {
+                self.bind(name, Local::Static(name));
+            } else if is_static {
+                let symbol = self.unique(&name.as_str());
+                self.bind(name, Local::Static(symbol));
+                self.static_object(symbol, ty, init.initializer.as_ref(), true)?;
+            } else {
+                let addr = self.alloca(ty, &init.declarator.span);
+                self.bind(name, Local::Addr(addr));
+                if let Some(initializer) = &init.initializer {
+                    self.initialize(addr, ty, initializer, &init.span)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn initialize(
+        &mut self,
+        addr: Operand,
+        ty: &Type,
+        init: &Initializer,
+        span: &Span,
+    ) -> Result<()> {
+        let types = self.types;
+        if initializer::zeroes_first(ty, init) {
+            let size = ty.size().unwrap_or(0);
+            self.emit(IrType::Void, InstKind::MemZero { dst: addr, size }, span);
+        }
+        for entry in initializer::flatten(types, ty, init) {
+            let e = entry.expr;
+            let dst = self.offset(addr, entry.offset as i64, &e.span);
+            if let Some((s, size)) = entry.string() {
+                let symbol = self.string_global(s);
+                let src = self.emit(IrType::Ptr, InstKind::Global(symbol), &e.span);
+                self.emit(IrType::Void, InstKind::MemCopy { dst, src, size }, &e.span);
+                continue;
+            }
+            let value = self.rvalue(e)?;
+            let value = self.convert(value, &types.expr(e).decay(), &entry.ty, &e.span)?;
+            self.store(value, dst, &entry.ty, &e.span)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        let span = &stmt.span;
+        match &stmt.kind {
+            StmtKind::Compound(block) => {
+                self.scopes.push(HashMap::new());
+                for item in &block.items {
+                    self.block_item(item)?;
+                }
+                self.scopes.pop();
+            }
+            StmtKind::Expr(e) => {
+                if let Some(e) = e {
+                    self.rvalue(e)?;
+                }
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let then_block = self.new_block();
+                let end = self.new_block();
+                let else_block = match otherwise {
+                    Some(_) => self.new_block(),
+                    None => end,
+                };
+                self.branch(cond, then_block, else_block)?;
+                self.start(then_block);
+                self.stmt(then)?;
+                self.terminate(TermKind::Jump(end), span);
+                if let Some(otherwise) = otherwise {
+                    self.start(else_block);
+                    self.stmt(otherwise)?;
+                    self.terminate(TermKind::Jump(end), span);
+                }
+                self.start(end);
+            }
+            StmtKind::While(cond, body) => {
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let end = self.new_block();
+                self.jump_to(head, span);
+                self.branch(cond, body_block, end)?;
+                self.start(body_block);
+                self.looped(body, head, end)?;
+                self.terminate(TermKind::Jump(head), span);
+                self.start(end);
+            }
+            StmtKind::DoWhile(body, cond) => {
+                let body_block = self.new_block();
+                let next = self.new_block();
+                let end = self.new_block();
+                self.jump_to(body_block, span);
+                self.looped(body, next, end)?;
+                self.jump_to(next, span);
+                self.branch(cond, body_block, end)?;
+                self.start(end);
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.scopes.push(HashMap::new());
+                match init {
+                    ForInit::Empty => {}
+                    ForInit::Expr(e) => {
+                        self.rvalue(e)?;
+                    }
+                    ForInit::Declaration(decl) => self.declare(decl)?,
+                }
+                let head = self.new_block();
+                let body_block = self.new_block();
+                let next = self.new_block();
+                let end = self.new_block();
+                self.jump_to(head, span);
+                match cond {
+                    Some(cond) => self.branch(cond, body_block, end)?,
+                    None => self.terminate(TermKind::Jump(body_block), span),
+                }
+                self.start(body_block);
+                self.looped(body, next, end)?;
+                self.jump_to(next, span);
+                if let Some(step) = step {
+                    self.rvalue(step)?;
+                }
+                self.terminate(TermKind::Jump(head), span);
+                self.start(end);
+                self.scopes.pop();
+            }
+            StmtKind::Switch(cond, body) => self.switch(cond, body, span)?,
+            StmtKind::Case(_, body) | StmtKind::Default(body) => {
+                let block = match self.state.cases.get(&(stmt as *const Stmt)) {
+                    Some(&block) => block,
+                    None => return Err(misplaced(stmt)),
+                };
+                self.jump_to(block, span);
+                self.stmt(body)?;
+            }
+            StmtKind::Labeled(name, body) => {
+                let block = self.label_block(name.value);
+                self.jump_to(block, span);
+                self.stmt(body)?;
+            }
+            StmtKind::Goto(name) => {
+                let block = self.label_block(name.value);
+                self.terminate(TermKind::Jump(block), span);
+            }
+            StmtKind::Continue => {
+                let target = *self.state.continues.last().ok_or_else(|| misplaced(stmt))?;
+                self.terminate(TermKind::Jump(target), span);
+            }
+            StmtKind::Break => {
+                let target = *self.state.breaks.last().ok_or_else(|| misplaced(stmt))?;
+                self.terminate(TermKind::Jump(target), span);
+            }
+            StmtKind::Return(e) => {
+                let value = match e {
+                    Some(e) => {
+                        let ret = self.state.ret.clone().unwrap();
+                        let value = self.rvalue(e)?;
+                        let value = self.convert(value, &self.types.expr(e).decay(), &ret, span)?;
+                        match self.ir_type(&ret, span)? {
+                            IrType::Void => None,
+                            ty => Some((ty, value)),
+                        }
+                    }
+                    None => None,
+                };
+                self.terminate(TermKind::Return(value), span);
+            }
+        }
+        Ok(())
+    }
+
+    fn label_block(&mut self, name: Symbol) -> BlockId {
+        if let Some(block) = self.state.labels.get(&name) {
+            return *block;
+        }
+        let block = self.new_block();
+        self.state.labels.insert(name, block);
+        block
+    }
+
+    fn looped(&mut self, body: &Stmt, next: BlockId, end: BlockId) -> Result<()> {
+        self.state.continues.push(next);
+        self.state.breaks.push(end);
+        let result = self.stmt(body);
+        self.state.continues.pop();
+        self.state.breaks.pop();
+        result
+    }
+
+    fn branch(&mut self, cond: &Expr, then: BlockId, otherwise: BlockId) -> Result<()> {
+        let value = self.condition(cond)?;
+        self.terminate(TermKind::Branch(value, then, otherwise), &cond.span);
+        Ok(())
+    }
+
+    fn condition(&mut self, cond: &Expr) -> Result<Operand> {
+        let value = self.rvalue(cond)?;
+        self.truth(value, &self.types.expr(cond).decay(), &cond.span)
+    }
+
+    fn switch(&mut self, cond: &Expr, body: &Stmt, span: &Span) -> Result<()> {
+        let types = self.types;
+        let ty = types.expr(cond).decay();
+        let promoted = promote(&ty);
+        let value = self.rvalue(cond)?;
+        let value = self.convert(value, &ty, &promoted, &cond.span)?;
+        let end = self.new_block();
+        let mut default = end;
+        let mut cases = Vec::new();
+        for stmt in body.switch_cases() {
+            let block = self.new_block();
+            match &stmt.kind {
+                StmtKind::Case(e, _) => {
+                    let value = types
+                        .value(e)
+                        .and_then(|v| convert_value(v, types.expr(e), &promoted));
+                    if let Some(ConstValue::Int(value)) = value {
+                        cases.push((value, block));
+                    }
+                }
+                _ => default = block,
+            }
+            self.state.cases.insert(stmt as *const Stmt, block);
+        }
+        let kind = TermKind::Switch {
+            ty: self.ir_type(&promoted, span)?,
+            value,
+            default,
+            cases,
+        };
+        self.terminate(kind, span);
+
+        self.state.breaks.push(end);
+        let result = self.stmt(body);
+        self.state.breaks.pop();
+        self.jump_to(end, span);
+        result
+    }
+}
+
+// expressions
+impl<'a> Lowerer<'a> {
+    // the value of an expression after lvalue conversion; structures and
+    // unions evaluate to their address
+    fn rvalue(&mut self, e: &Expr) -> Result<Operand> {
+        let types = self.types;
+        let ty = types.expr(e);
+        let span = &e.span;
+        if let Some(value) = types.value(e) {
+            self.ir_type(ty, span)?;
+            return Ok(const_operand(value));
+        }
+
+        match &e.kind {
+            ExprKind::Identifier(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Unary(UnaryOp::Deref, _)
+            | ExprKind::Index(..)
+            | ExprKind::Member(..)
+            | ExprKind::Arrow(..) => {
+                let addr = self.lvalue(e)?;
+                self.load(addr, ty, span)
+            }
+            ExprKind::Constant(_) | ExprKind::SizeofExpr(_) | ExprKind::SizeofType(_) => {
+                unreachable!("constants are folded by the checker")
+            }
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, ty, span),
+            ExprKind::Binary(op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => {
+                let result = self.alloca(&Type::int(IntKind::Int), span);
+                let rhs_block = self.new_block();
+                let short = self.new_block();
+                let end = self.new_block();
+                let lhs = self.condition(lhs)?;
+                let kind = match op {
+                    BinaryOp::And => TermKind::Branch(lhs, rhs_block, short),
+                    _ => TermKind::Branch(lhs, short, rhs_block),
+                };
+                self.terminate(kind, span);
+                self.start(rhs_block);
+                let rhs = self.condition(rhs)?;
+                let int = Type::int(IntKind::Int);
+                self.store(rhs, result, &int, span)?;
+                self.terminate(TermKind::Jump(end), span);
+                self.start(short);
+                self.store(
+                    Operand::Int((*op == BinaryOp::Or) as i64),
+                    result,
+                    &int,
+                    span,
+                )?;
+                self.jump_to(end, span);
+                self.load(result, &int, span)
+            }
+            ExprKind::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs, span),
+            ExprKind::Assign(op, lhs, rhs) => self.assign(*op, lhs, rhs, span),
+            ExprKind::Conditional(cond, then, otherwise) => {
+                // the result of a structure type is its address
+                let slot_ty = if ty.is_record() {
+                    Type::pointer_to(ty.clone())
+                } else {
+                    ty.clone()
+                };
+                let result = if ty.is_void() {
+                    None
+                } else {
+                    Some(self.alloca(&slot_ty, span))
+                };
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let end = self.new_block();
+                self.branch(cond, then_block, else_block)?;
+                for (block, arm) in [(then_block, then), (else_block, otherwise)] {
+                    self.start(block);
+                    let value = self.rvalue(arm)?;
+                    let value = self.convert(value, &types.expr(arm).decay(), ty, span)?;
+                    if let Some(result) = result {
+                        self.store(value, result, &slot_ty, span)?;
+                    }
+                    self.terminate(TermKind::Jump(end), span);
+                }
+                self.start(end);
+                match result {
+                    Some(result) => self.load(result, &slot_ty, span),
+                    None => Ok(Operand::Int(0)),
+                }
+            }
+            ExprKind::Comma(exprs) => {
+                let mut value = Operand::Int(0);
+                for e in exprs {
+                    value = self.rvalue(e)?;
+                }
+                Ok(value)
+            }
+            ExprKind::Cast(_, operand) => {
+                let value = self.rvalue(operand)?;
+                self.convert(value, &types.expr(operand).decay(), ty, span)
+            }
+            ExprKind::Call(callee, args) => self.call(callee, args, ty, span),
+        }
+    }
+
+    fn lvalue(&mut self, e: &Expr) -> Result<Operand> {
+        let types = self.types;
+        let span = &e.span;
+        match &e.kind {
+            ExprKind::Identifier(ident) => match self.lookup(ident.value) {
+                Some(Local::Addr(addr)) => Ok(addr),
+                Some(Local::Static(symbol)) => {
+                    Ok(self.emit(IrType::Ptr, InstKind::Global(symbol), span))
+                }
+                None if ident.value == "__func__" => {
+                    let symbol = match self.state.func_name {
+                        Some(symbol) => symbol,
+                        None => {
+                            let symbol = self.unique("__func__");
+                            let name = self.state.name.unwrap();
+                            let mut bytes = name.with_str(|name| name.as_bytes().to_vec());
+                            bytes.push(0);
+                            self.bytes_global(symbol, bytes);
+                            self.state.func_name = Some(symbol);
+                            symbol
+                        }
+                    };
+                    Ok(self.emit(IrType::Ptr, InstKind::Global(symbol), span))
+                }
+                None => Ok(self.emit(IrType::Ptr, InstKind::Global(ident.value), span)),
+            },
+            ExprKind::StringLiteral(s) => {
+                let symbol = self.string_global(s);
+                Ok(self.emit(IrType::Ptr, InstKind::Global(symbol), span))
+            }
+            ExprKind::Unary(UnaryOp::Deref, operand) => self.rvalue(operand),
+            ExprKind::Index(lhs, rhs) => {
+                let (array, index) = if types.expr(lhs).decay().is_pointer() {
+                    (lhs, rhs)
+                } else {
+                    (rhs, lhs)
+                };
+                let base = self.rvalue(array)?;
+                let step = types.expr(e).size().unwrap_or(1) as i64;
+                self.ptr_add(base, index, step, false, span)
+            }
+            ExprKind::Member(base, member) | ExprKind::Arrow(base, member) => {
+                let record = match &e.kind {
+                    ExprKind::Arrow(..) => types.expr(base).decay().pointee().unwrap().clone(),
+                    _ => types.expr(base).clone(),
+                };
+                let addr = match &e.kind {
+                    ExprKind::Arrow(..) => self.rvalue(base)?,
+                    _ => self.lvalue(base)?,
+                };
+                let offset = match &record.kind {
+                    TypeKind::Record(record) => record
+                        .borrow()
+                        .member(member.value)
+                        .map_or(0, |(offset, _)| offset),
+                    _ => 0,
+                };
+                Ok(self.offset(addr, offset as i64, span))
+            }
+            // structures returned by calls, assignments and conditionals
+            _ => self.rvalue(e),
+        }
+    }
+
+    // `base + index * step`, or minus
+    fn ptr_add(
+        &mut self,
+        base: Operand,
+        index: &Expr,
+        step: i64,
+        negate: bool,
+        span: &Span,
+    ) -> Result<Operand> {
+        let long = Type::int(IntKind::Long);
+        let value = self.rvalue(index)?;
+        let value = self.convert(value, &self.types.expr(index).decay(), &long, span)?;
+        self.scaled_add(base, value, step, negate, span)
+    }
+
+    fn scaled_add(
+        &mut self,
+        base: Operand,
+        index: Operand,
+        step: i64,
+        negate: bool,
+        span: &Span,
+    ) -> Result<Operand> {
+        let step = if negate { -step } else { step };
+        let offset = match index {
+            Operand::Int(v) => Operand::Int(v.wrapping_mul(step)),
+            _ if step == 1 => index,
+            _ => self.emit(
+                IrType::I64,
+                InstKind::Binary(BinOp::Mul, index, Operand::Int(step)),
+                span,
+            ),
+        };
+        if offset == Operand::Int(0) {
+            return Ok(base);
+        }
+        Ok(self.emit(IrType::Ptr, InstKind::PtrAdd(base, offset), span))
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &Expr, ty: &Type, span: &Span) -> Result<Operand> {
+        let types = self.types;
+        let source = types.expr(operand).decay();
+        match op {
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                let target = types.expr(operand).unqualified();
+                let is_inc = matches!(op, UnaryOp::PreInc | UnaryOp::PostInc);
+                let addr = self.lvalue(operand)?;
+                let old = self.load(addr, &target, span)?;
+                let new = if let Some(pointee) = target.pointee() {
+                    let step = pointee.size().unwrap_or(1) as i64;
+                    self.scaled_add(old, Operand::Int(1), step, !is_inc, span)?
+                } else {
+                    // computed in the promoted type, then converted back
+                    let promoted = match target.int_kind() {
+                        Some(_) => promote(&target),
+                        None => target.clone(),
+                    };
+                    let value = self.convert(old, &target, &promoted, span)?;
+                    let ir_ty = self.ir_type(&promoted, span)?;
+                    let (op, one) = match (ir_ty.is_float(), is_inc) {
+                        (true, true) => (BinOp::FAdd, Operand::Float(1.0)),
+                        (true, false) => (BinOp::FSub, Operand::Float(1.0)),
+                        (false, true) => (BinOp::Add, Operand::Int(1)),
+                        (false, false) => (BinOp::Sub, Operand::Int(1)),
+                    };
+                    let value = self.emit(ir_ty, InstKind::Binary(op, value, one), span);
+                    self.convert(value, &promoted, &target, span)?
+                };
+                self.store(new, addr, &target, span)?;
+                Ok(match op {
+                    UnaryOp::PostInc | UnaryOp::PostDec => old,
+                    _ => new,
+                })
+            }
+            UnaryOp::Address => self.lvalue(operand),
+            UnaryOp::Deref => unreachable!("dereferences are lvalues"),
+            UnaryOp::Not => {
+                let value = self.rvalue(operand)?;
+                let ir_ty = self.ir_type(&source, span)?;
+                let cond = if ir_ty.is_float() {
+                    Cond::Oeq
+                } else {
+                    Cond::Eq
+                };
+                let kind = InstKind::Cmp(cond, ir_ty, value, zero(ir_ty));
+                Ok(self.emit(IrType::I32, kind, span))
+            }
+            UnaryOp::Plus | UnaryOp::Minus | UnaryOp::BitNot => {
+                let value = self.rvalue(operand)?;
+                let value = self.convert(value, &source, ty, span)?;
+                let ir_ty = self.ir_type(ty, span)?;
+                let op = match op {
+                    UnaryOp::Plus => return Ok(value),
+                    UnaryOp::Minus if ir_ty.is_float() => UnOp::FNeg,
+                    UnaryOp::Minus => UnOp::Neg,
+                    _ => UnOp::Not,
+                };
+                Ok(self.emit(ir_ty, InstKind::Unary(op, value), span))
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, span: &Span) -> Result<Operand> {
+        let types = self.types;
+        let lt = types.expr(lhs).decay();
+        let rt = types.expr(rhs).decay();
+
+        if lt.is_pointer() || rt.is_pointer() {
+            let step = |ty: &Type| ty.pointee().and_then(Type::size).unwrap_or(1) as i64;
+            match op {
+                BinaryOp::Add | BinaryOp::Sub if !(lt.is_pointer() && rt.is_pointer()) => {
+                    if lt.is_pointer() {
+                        let base = self.rvalue(lhs)?;
+                        self.ptr_add(base, rhs, step(&lt), op == BinaryOp::Sub, span)
+                    } else {
+                        let index = self.rvalue(lhs)?;
+                        let long = Type::int(IntKind::Long);
+                        let index = self.convert(index, &lt, &long, span)?;
+                        let base = self.rvalue(rhs)?;
+                        self.scaled_add(base, index, step(&rt), false, span)
+                    }
+                }
+                BinaryOp::Sub => {
+                    let long = Type::int(IntKind::Long);
+                    let a = self.rvalue(lhs)?;
+                    let a = self.convert(a, &lt, &long, span)?;
+                    let b = self.rvalue(rhs)?;
+                    let b = self.convert(b, &rt, &long, span)?;
+                    let diff = self.emit(IrType::I64, InstKind::Binary(BinOp::Sub, a, b), span);
+                    let kind = InstKind::Binary(BinOp::SDiv, diff, Operand::Int(step(&lt)));
+                    Ok(self.emit(IrType::I64, kind, span))
+                }
+                _ => {
+                    // comparisons, possibly with a null pointer constant
+                    let pointer = if lt.is_pointer() { &lt } else { &rt };
+                    let a = self.rvalue(lhs)?;
+                    let a = self.convert(a, &lt, pointer, span)?;
+                    let b = self.rvalue(rhs)?;
+                    let b = self.convert(b, &rt, pointer, span)?;
+                    let cond = compare(op, false, false);
+                    Ok(self.emit(IrType::I32, InstKind::Cmp(cond, IrType::Ptr, a, b), span))
+                }
+            }
+        } else {
+            let ty = operand_type(op, &lt, &rt);
+            let a = self.rvalue(lhs)?;
+            let a = self.convert(a, &lt, &ty, span)?;
+            let b = self.rvalue(rhs)?;
+            let b = self.convert(b, &rt, &ty, span)?;
+            self.arithmetic(op, &ty, a, b, span)
+        }
+    }
+
+    // an operation on operands converted to `ty`
+    fn arithmetic(
+        &mut self,
+        op: BinaryOp,
+        ty: &Type,
+        a: Operand,
+        b: Operand,
+        span: &Span,
+    ) -> Result<Operand> {
+        let ir_ty = self.ir_type(ty, span)?;
+        let is_float = ir_ty.is_float();
+        let signed = is_signed(ty);
+        let bin_op = match op {
+            BinaryOp::Add if is_float => BinOp::FAdd,
+            BinaryOp::Sub if is_float => BinOp::FSub,
+            BinaryOp::Mul if is_float => BinOp::FMul,
+            BinaryOp::Div if is_float => BinOp::FDiv,
+            BinaryOp::Add => BinOp::Add,
+            BinaryOp::Sub => BinOp::Sub,
+            BinaryOp::Mul => BinOp::Mul,
+            BinaryOp::Div if signed => BinOp::SDiv,
+            BinaryOp::Div => BinOp::UDiv,
+            BinaryOp::Mod if signed => BinOp::SRem,
+            BinaryOp::Mod => BinOp::URem,
+            BinaryOp::Shl => BinOp::Shl,
+            BinaryOp::Shr if signed => BinOp::AShr,
+            BinaryOp::Shr => BinOp::LShr,
+            BinaryOp::BitAnd => BinOp::And,
+            BinaryOp::BitXor => BinOp::Xor,
+            BinaryOp::BitOr => BinOp::Or,
+            _ => {
+                let cond = compare(op, signed, is_float);
+                return Ok(self.emit(IrType::I32, InstKind::Cmp(cond, ir_ty, a, b), span));
+            }
+        };
+        Ok(self.emit(ir_ty, InstKind::Binary(bin_op, a, b), span))
+    }
+
+    fn assign(&mut self, op: AssignOp, lhs: &Expr, rhs: &Expr, span: &Span) -> Result<Operand> {
+        let types = self.types;
+        let target = types.expr(lhs).unqualified();
+        let rt = types.expr(rhs).decay();
+        let addr = self.lvalue(lhs)?;
+        let value = match op.binary_op() {
+            None => {
+                let value = self.rvalue(rhs)?;
+                self.convert(value, &rt, &target, span)?
+            }
+            Some(op) if target.is_pointer() => {
+                let old = self.load(addr, &target, span)?;
+                let step = target.pointee().and_then(Type::size).unwrap_or(1) as i64;
+                self.ptr_add(old, rhs, step, op == BinaryOp::Sub, span)?
+            }
+            Some(op) => {
+                let ty = operand_type(op, &target, &rt);
+                let old = self.load(addr, &target, span)?;
+                let a = self.convert(old, &target, &ty, span)?;
+                let b = self.rvalue(rhs)?;
+                let b = self.convert(b, &rt, &ty, span)?;
+                let value = self.arithmetic(op, &ty, a, b, span)?;
+                self.convert(value, &ty, &target, span)?
+            }
+        };
+        self.store(value, addr, &target, span)?;
+        Ok(if target.is_record() { addr } else { value })
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], ret: &Type, span: &Span) -> Result<Operand> {
+        let types = self.types;
+        let func = match types.expr(callee).decay().pointee().map(|ty| &ty.kind) {
+            Some(TypeKind::Function(func)) => func.clone(),
+            _ => unreachable!("callees are checked"),
+        };
+
+        let callee = match &callee.kind {
+            ExprKind::Identifier(ident) if types.expr(callee).is_function() => {
+                Callee::Direct(ident.value)
+            }
+            _ => Callee::Indirect(self.rvalue(callee)?),
+        };
+        let mut operands = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            // the parameter type, or the default argument promotions
+            let ty = match func.params.get(i) {
+                Some(param) => param.unqualified(),
+                None => match types.expr(arg).decay() {
+                    ty if matches!(ty.kind, TypeKind::Float(FloatKind::Float)) => {
+                        Type::new(TypeKind::Float(FloatKind::Double))
+                    }
+                    ty => promote(&ty),
+                },
+            };
+            let value = self.rvalue(arg)?;
+            let value = self.convert(value, &types.expr(arg).decay(), &ty, &arg.span)?;
+            operands.push((self.ir_type(&ty, &arg.span)?, value));
+        }
+        let fixed = if func.variadic {
+            Some(func.params.len())
+        } else {
+            None
+        };
+        let kind = InstKind::Call {
+            callee,
+            args: operands,
+            fixed,
+        };
+        let ret = self.ir_type(ret, span)?;
+        Ok(self.emit(ret, kind, span))
+    }
+}
+
+fn misplaced(stmt: &Stmt) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(stmt.span, stmt.misplaced_msg()))
+}
+
+fn compare(op: BinaryOp, is_signed: bool, is_float: bool) -> Cond {
+    match (op, is_signed, is_float) {
+        (BinaryOp::Eq, _, true) => Cond::Oeq,
+        (BinaryOp::Ne, _, true) => Cond::Une,
+        (BinaryOp::Lt, _, true) => Cond::Olt,
+        (BinaryOp::Le, _, true) => Cond::Ole,
+        (BinaryOp::Gt, _, true) => Cond::Ogt,
+        (BinaryOp::Ge, _, true) => Cond::Oge,
+        (BinaryOp::Eq, ..) => Cond::Eq,
+        (BinaryOp::Ne, ..) => Cond::Ne,
+        (BinaryOp::Lt, true, _) => Cond::Slt,
+        (BinaryOp::Le, true, _) => Cond::Sle,
+        (BinaryOp::Gt, true, _) => Cond::Sgt,
+        (BinaryOp::Ge, true, _) => Cond::Sge,
+        (BinaryOp::Lt, false, _) => Cond::Ult,
+        (BinaryOp::Le, false, _) => Cond::Ule,
+        (BinaryOp::Gt, false, _) => Cond::Ugt,
+        (BinaryOp::Ge, false, _) => Cond::Uge,
+        _ => unreachable!("operand types are checked"),
+    }
+}
@@ -0,0 +1,805 @@
+use super::*;
+use crate::{
+    diagnostics::Diagnostic,
+    source_file::SourceFile,
+    source_map::{self, Location},
+    span::{LineColumn, Span},
+};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+// call arguments and the index of the first variadic one.
+type CallArgs = (Vec<(IrType, Operand)>, Option<usize>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Global(String),
+    Reg(u32),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+    Eof,
+}
+
+const PUNCTS: [&str; 12] = [
+    "...", "..", "=", ",", ":", "(", ")", "[", "]", "{", "}", "!",
+];
+
+struct Parser {
+    toks: Vec<(Tok, Span)>,
+    pos: usize,
+    // the blocks and registers the current function refers to, checked
+    // against its definitions once it has been read
+    block_uses: Vec<(BlockId, Span)>,
+    value_uses: Vec<(Value, Span)>,
+}
+
+// reads the textual form printed by `Module`'s `Display`; instructions
+// without a `!loc` get the span of their own text. Every block and register
+// a function refers to must be defined in it, and only once
+pub fn parse(src: &SourceFile) -> Result<Module> {
+    let toks = tokenize(src)?;
+    let mut parser = Parser {
+        toks,
+        pos: 0,
+        block_uses: Vec::new(),
+        value_uses: Vec::new(),
+    };
+    parser.module()
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn tokenize(src: &SourceFile) -> Result<Vec<(Tok, Span)>> {
+    let text = &*src.content;
+    let at = |i: usize| text[i..].chars().next();
+    let span = |start: usize, end: usize| src.span(start..end);
+    let mut toks = Vec::new();
+    let mut i = 0;
+    while let Some(c) = at(i) {
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        let start = i;
+        let take_while = |i: &mut usize, f: &dyn Fn(char) -> bool| {
+            let begin = *i;
+            while let Some(c) = at(*i).filter(|&c| f(c)) {
+                *i += c.len_utf8();
+            }
+            text[begin..*i].to_owned()
+        };
+
+        let tok = if c == ';' {
+            // comments run to the end of the line
+            take_while(&mut i, &|c| c != '\n');
+            continue;
+        } else if c == '@' {
+            i += 1;
+            Tok::Global(take_while(&mut i, &is_name_char))
+        } else if c == '%' {
+            i += 1;
+            let digits = take_while(&mut i, &|c| c.is_ascii_digit());
+            match digits.parse() {
+                Ok(n) => Tok::Reg(n),
+                Err(_) => {
+                    return Err(Box::new(Diagnostic::error(
+                        span(start, i),
+                        "expected a register number",
+                    )))
+                }
+            }
+        } else if c == '"' {
+            i += 1;
+            let mut s = String::new();
+            loop {
+                match at(i) {
+                    None | Some('\n') => {
+                        return Err(Box::new(Diagnostic::error(
+                            span(start, i),
+                            "unterminated string",
+                        )))
+                    }
+                    Some('"') => break,
+                    Some('\\') if i + 1 < text.len() => {
+                        i += 1;
+                        let c = at(i).unwrap();
+                        s.push(c);
+                        i += c.len_utf8();
+                    }
+                    Some(c) => {
+                        s.push(c);
+                        i += c.len_utf8();
+                    }
+                }
+            }
+            i += 1;
+            Tok::Str(s)
+        } else if c.is_ascii_digit()
+            || (c == '-' || c == '+') && at(i + 1).is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            let mut number = String::new();
+            if !c.is_ascii_digit() {
+                number.push(c);
+                i += 1;
+            }
+            let is = |i: usize, f: &dyn Fn(char) -> bool| at(i).is_some_and(f);
+            if is(i, &|c| c.is_ascii_alphabetic()) {
+                // `inf` and `NaN`
+                number += &take_while(&mut i, &|c| c.is_ascii_alphabetic());
+            } else {
+                number += &take_while(&mut i, &|c| c.is_ascii_digit());
+                if is(i, &|c| c == '.') && is(i + 1, &|c| c.is_ascii_digit()) {
+                    i += 1;
+                    number.push('.');
+                    number += &take_while(&mut i, &|c| c.is_ascii_digit());
+                }
+                if is(i, &|c| c == 'e' || c == 'E') {
+                    i += 1;
+                    number.push('e');
+                    if let Some(sign) = at(i).filter(|&c| c == '-' || c == '+') {
+                        number.push(sign);
+                        i += 1;
+                    }
+                    number += &take_while(&mut i, &|c| c.is_ascii_digit());
+                }
+            }
+            let is_float = number.contains(['.', 'e', 'i', 'N']);
+            let tok = if is_float {
+                number.parse().map(Tok::Float).ok()
+            } else {
+                number.parse().map(Tok::Int).ok()
+            };
+            match tok {
+                Some(tok) => tok,
+                None => {
+                    let msg = format!("invalid number: {:?}", number);
+                    return Err(Box::new(Diagnostic::error(span(start, i), msg)));
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            Tok::Word(take_while(&mut i, &|c| {
+                c.is_ascii_alphanumeric() || c == '_'
+            }))
+        } else {
+            match PUNCTS.iter().find(|p| text[i..].starts_with(**p)) {
+                Some(p) => {
+                    i += p.len();
+                    Tok::Punct(p)
+                }
+                None => {
+                    i += c.len_utf8();
+                    let msg = format!("unexpected character: {:?}", c);
+                    return Err(Box::new(Diagnostic::error(span(start, i), msg)));
+                }
+            }
+        };
+        toks.push((tok, span(start, i)));
+    }
+    let eof = span(i, i);
+    toks.push((Tok::Eof, eof));
+    Ok(toks)
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Word(w) => format!("'{}'", w),
+        Tok::Global(name) => format!("'@{}'", name),
+        Tok::Reg(n) => format!("'%{}'", n),
+        Tok::Int(v) => format!("'{}'", v),
+        Tok::Float(v) => format!("'{:?}'", v),
+        Tok::Str(s) => format!("{:?}", s),
+        Tok::Punct(p) => format!("'{}'", p),
+        Tok::Eof => "end of file".to_owned(),
+    }
+}
+
+fn define<T: fmt::Display + Eq + Hash>(
+    defined: &mut HashMap<T, Span>,
+    name: T,
+    span: Span,
+) -> Result<()> {
+    let msg = format!("redefinition of '{}'", name);
+    match defined.insert(name, span) {
+        Some(prev) => Err(Box::new(
+            Diagnostic::error(span, msg).with_secondary(prev, "previous definition is here"),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_uses<T: fmt::Display + Eq + Hash>(
+    defined: &HashMap<T, Span>,
+    uses: &[(T, Span)],
+    what: &str,
+) -> Result<()> {
+    match uses.iter().find(|(name, _)| !defined.contains_key(name)) {
+        Some((name, span)) => {
+            let msg = format!("use of undefined {} '{}'", what, name);
+            Err(Box::new(Diagnostic::error(*span, msg)))
+        }
+        None => Ok(()),
+    }
+}
+
+fn lookup<T: Copy>(table: &[(T, &'static str)], word: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, name)| *name == word)
+        .map(|(k, _)| *k)
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].0
+    }
+
+    fn span(&self) -> Span {
+        self.toks[self.pos].1
+    }
+
+    fn bump(&mut self) -> Tok {
+        let tok = self.toks[self.pos].0.clone();
+        if tok != Tok::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T> {
+        let msg = format!("expected {}, found {}", expected, describe(self.peek()));
+        Err(Box::new(Diagnostic::error(self.span(), msg)))
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        if *self.peek() == Tok::Punct(PUNCTS.iter().find(|p| **p == punct).unwrap()) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<()> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", punct))
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Tok::Word(w) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            self.error(&format!("'{}'", word))
+        }
+    }
+
+    fn word(&mut self) -> Result<String> {
+        match self.peek() {
+            Tok::Word(w) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("a keyword"),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        match self.peek() {
+            Tok::Int(v) => {
+                let v = *v;
+                self.pos += 1;
+                Ok(v)
+            }
+            _ => self.error("an integer"),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64> {
+        let span = self.span();
+        let v = self.int()?;
+        if v < 0 {
+            return Err(Box::new(Diagnostic::error(
+                span,
+                "expected a non-negative integer",
+            )));
+        }
+        Ok(v as u64)
+    }
+
+    fn global(&mut self) -> Result<Symbol> {
+        match self.peek() {
+            Tok::Global(name) => {
+                let name = Symbol::intern(name);
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => self.error("a global name"),
+        }
+    }
+
+    fn reg(&mut self) -> Result<Value> {
+        match self.peek() {
+            Tok::Reg(n) => {
+                let v = Value(*n);
+                self.pos += 1;
+                Ok(v)
+            }
+            _ => self.error("a register"),
+        }
+    }
+
+    fn block_id(&mut self) -> Result<BlockId> {
+        match self.peek() {
+            Tok::Word(w) if w.starts_with("bb") => match w[2..].parse() {
+                Ok(n) => {
+                    self.pos += 1;
+                    Ok(BlockId(n))
+                }
+                Err(_) => self.error("a block label"),
+            },
+            _ => self.error("a block label"),
+        }
+    }
+
+    // a block label used as a jump target
+    fn target(&mut self) -> Result<BlockId> {
+        let span = self.span();
+        let id = self.block_id()?;
+        self.block_uses.push((id, span));
+        Ok(id)
+    }
+
+    fn ty(&mut self) -> Result<IrType> {
+        let span = self.span();
+        Ok(match self.word()?.as_str() {
+            "i8" => IrType::I8,
+            "i16" => IrType::I16,
+            "i32" => IrType::I32,
+            "i64" => IrType::I64,
+            "f32" => IrType::F32,
+            "f64" => IrType::F64,
+            "ptr" => IrType::Ptr,
+            "void" => IrType::Void,
+            "agg" => {
+                self.expect_punct("(")?;
+                let size = self.uint()?;
+                self.expect_punct(",")?;
+                let align = self.uint()?;
+                self.expect_punct(")")?;
+                IrType::Agg { size, align }
+            }
+            word => {
+                let msg = format!("expected a type, found '{}'", word);
+                return Err(Box::new(Diagnostic::error(span, msg)));
+            }
+        })
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        match self.peek() {
+            Tok::Reg(_) => {
+                let span = self.span();
+                let v = self.reg()?;
+                self.value_uses.push((v, span));
+                Ok(Operand::Value(v))
+            }
+            Tok::Int(v) => {
+                let v = *v;
+                self.pos += 1;
+                Ok(Operand::Int(v))
+            }
+            Tok::Float(v) => {
+                let v = *v;
+                self.pos += 1;
+                Ok(Operand::Float(v))
+            }
+            Tok::Word(w) if w == "inf" || w == "NaN" => {
+                let v = w.parse().unwrap();
+                self.pos += 1;
+                Ok(Operand::Float(v))
+            }
+            _ => self.error("an operand"),
+        }
+    }
+
+    fn module(&mut self) -> Result<Module> {
+        let mut module = Module::default();
+        while *self.peek() != Tok::Eof {
+            let is_static = self.eat_word("static");
+            if self.eat_word("global") {
+                module.globals.push(self.global_def(is_static)?);
+            } else if self.eat_word("define") {
+                module.functions.push(self.function(is_static)?);
+            } else {
+                return self.error("'global' or 'define'");
+            }
+        }
+        Ok(module)
+    }
+
+    fn global_def(&mut self, is_static: bool) -> Result<Global> {
+        let name = self.global()?;
+        self.expect_punct(",")?;
+        let size = self.uint()?;
+        self.expect_punct(",")?;
+        let align = self.uint()?;
+        let mut init = None;
+        if self.eat_punct("=") {
+            self.expect_punct("{")?;
+            let mut items = Vec::new();
+            loop {
+                let item = match self.word()?.as_str() {
+                    "bytes" => {
+                        self.expect_punct("[")?;
+                        let mut bytes = Vec::new();
+                        while !self.eat_punct("]") {
+                            let span = self.span();
+                            let byte = self.int()?;
+                            if !(0..=255).contains(&byte) {
+                                return Err(Box::new(Diagnostic::error(span, "expected a byte")));
+                            }
+                            bytes.push(byte as u8);
+                        }
+                        GlobalItem::Bytes(bytes)
+                    }
+                    "zero" => GlobalItem::Zero(self.uint()?),
+                    "addr" => {
+                        let symbol = self.global()?;
+                        GlobalItem::Addr(symbol, self.int()?)
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        return self.error("'bytes', 'zero' or 'addr'");
+                    }
+                };
+                items.push(item);
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+            self.expect_punct("}")?;
+            init = Some(items);
+        }
+        Ok(Global {
+            name,
+            is_static,
+            size,
+            align,
+            init,
+        })
+    }
+
+    // `(type operand, ..., ...)`, returning where the variadic part starts
+    fn args(&mut self) -> Result<CallArgs> {
+        self.expect_punct("(")?;
+        let mut args = Vec::new();
+        let mut fixed = None;
+        if !self.eat_punct(")") {
+            loop {
+                if fixed.is_none() && self.eat_punct("...") {
+                    fixed = Some(args.len());
+                } else {
+                    let ty = self.ty()?;
+                    args.push((ty, self.operand()?));
+                }
+                if !self.eat_punct(",") {
+                    break;
+                }
+            }
+            self.expect_punct(")")?;
+        }
+        Ok((args, fixed))
+    }
+
+    fn function(&mut self, is_static: bool) -> Result<Function> {
+        let ret = self.ty()?;
+        let name = self.global()?;
+        self.value_uses.clear();
+        self.block_uses.clear();
+        let (args, fixed) = self.args()?;
+        let mut params = Vec::new();
+        for (ty, arg) in args {
+            match arg {
+                Operand::Value(v) => params.push((v, ty)),
+                _ => return self.error("a parameter register"),
+            }
+        }
+        // the parameters were read as operands
+        let mut values = HashMap::new();
+        for (v, span) in self.value_uses.drain(..) {
+            define(&mut values, v, span)?;
+        }
+        let mut labels = HashMap::new();
+        self.expect_punct("{")?;
+        let mut blocks = Vec::new();
+        while !self.eat_punct("}") {
+            let span = self.span();
+            let id = self.block_id()?;
+            define(&mut labels, id, span)?;
+            self.expect_punct(":")?;
+            let mut insts = Vec::new();
+            let term = loop {
+                let start = self.span();
+                match self.peek() {
+                    Tok::Word(w)
+                        if ["jmp", "br", "switch", "ret", "unreachable"].contains(&w.as_str()) =>
+                    {
+                        break self.terminator(start)?;
+                    }
+                    Tok::Eof | Tok::Punct("}") => return self.error("a terminator"),
+                    _ => {
+                        let inst = self.inst(start)?;
+                        if let Some(dest) = inst.dest {
+                            define(&mut values, dest, start)?;
+                        }
+                        insts.push(inst);
+                    }
+                }
+            };
+            blocks.push(Block { id, insts, term });
+        }
+        if blocks.is_empty() {
+            return self.error("a block");
+        }
+        check_uses(&labels, &self.block_uses, "block")?;
+        check_uses(&values, &self.value_uses, "register")?;
+        Ok(Function {
+            name,
+            is_static,
+            ret,
+            params,
+            is_variadic: fixed.is_some(),
+            blocks,
+        })
+    }
+
+    // `!loc(...)`, or the span of the text from `start`
+    fn loc(&mut self, start: Span) -> Result<Span> {
+        if !self.eat_punct("!") {
+            let end = &self.toks[self.pos - 1].1;
+            return Ok(start.to(*end));
+        }
+        self.expect_word("loc")?;
+        self.expect_punct("(")?;
+        let file_path: Rc<str> = match self.bump() {
+            Tok::Str(s) => s.into(),
+            _ => {
+                self.pos -= 1;
+                return self.error("a file path");
+            }
+        };
+        self.expect_punct(",")?;
+        let byte_start = self.uint()? as usize;
+        self.expect_punct("..")?;
+        let byte_end = self.uint()? as usize;
+        self.expect_punct(",")?;
+        let mut line_column = || -> Result<LineColumn> {
+            let line = self.uint()? as usize;
+            self.expect_punct(":")?;
+            let column = self.uint()? as usize;
+            Ok(LineColumn { line, column })
+        };
+        let lc_start = line_column()?;
+        self.expect_punct("..")?;
+        let lc_end = self.uint().and_then(|line| {
+            self.expect_punct(":")?;
+            Ok(LineColumn {
+                line: line as usize,
+                column: self.uint()? as usize,
+            })
+        })?;
+        self.expect_punct(")")?;
+        let location = Location {
+            file_path,
+            byte_range: byte_start..byte_end,
+            lc_range: lc_start..lc_end,
+            file: None,
+        };
+        Ok(source_map::with(|sm| sm.add_external(location)))
+    }
+
+    fn inst(&mut self, start: Span) -> Result<Inst> {
+        let dest = match self.peek() {
+            Tok::Reg(_) => {
+                let dest = self.reg()?;
+                self.expect_punct("=")?;
+                Some(dest)
+            }
+            _ => None,
+        };
+        let op_span = self.span();
+        let op = self.word()?;
+        let (ty, kind) = match op.as_str() {
+            "alloca" => {
+                let size = self.uint()?;
+                self.expect_punct(",")?;
+                let align = self.uint()?;
+                (IrType::Ptr, InstKind::Alloca { size, align })
+            }
+            "load" => {
+                let ty = self.ty()?;
+                (ty, InstKind::Load(self.operand()?))
+            }
+            "store" => {
+                let ty = self.ty()?;
+                let value = self.operand()?;
+                self.expect_punct(",")?;
+                let ptr = self.operand()?;
+                (ty, InstKind::Store { value, ptr })
+            }
+            "cmp" => {
+                let cond_span = self.span();
+                let cond = match lookup(&CONDS, &self.word()?) {
+                    Some(cond) => cond,
+                    None => {
+                        return Err(Box::new(Diagnostic::error(
+                            cond_span,
+                            "expected a condition",
+                        )))
+                    }
+                };
+                let ty = self.ty()?;
+                let a = self.operand()?;
+                self.expect_punct(",")?;
+                (IrType::I32, InstKind::Cmp(cond, ty, a, self.operand()?))
+            }
+            "global" => (IrType::Ptr, InstKind::Global(self.global()?)),
+            "ptradd" => {
+                let ptr = self.operand()?;
+                self.expect_punct(",")?;
+                (IrType::Ptr, InstKind::PtrAdd(ptr, self.operand()?))
+            }
+            "call" => {
+                let ty = self.ty()?;
+                let callee = match self.peek() {
+                    Tok::Global(_) => Callee::Direct(self.global()?),
+                    _ => Callee::Indirect(self.operand()?),
+                };
+                let (args, fixed) = self.args()?;
+                (
+                    ty,
+                    InstKind::Call {
+                        callee,
+                        args,
+                        fixed,
+                    },
+                )
+            }
+            "phi" => {
+                let ty = self.ty()?;
+                let mut incoming = Vec::new();
+                loop {
+                    self.expect_punct("[")?;
+                    let block = self.target()?;
+                    self.expect_punct(",")?;
+                    incoming.push((block, self.operand()?));
+                    self.expect_punct("]")?;
+                    if !self.eat_punct(",") {
+                        break;
+                    }
+                }
+                (ty, InstKind::Phi(incoming))
+            }
+            "memcopy" => {
+                let dst = self.operand()?;
+                self.expect_punct(",")?;
+                let src = self.operand()?;
+                self.expect_punct(",")?;
+                let size = self.uint()?;
+                (IrType::Void, InstKind::MemCopy { dst, src, size })
+            }
+            "memzero" => {
+                let dst = self.operand()?;
+                self.expect_punct(",")?;
+                let size = self.uint()?;
+                (IrType::Void, InstKind::MemZero { dst, size })
+            }
+            word => {
+                if let Some(op) = lookup(&BIN_OPS, word) {
+                    let ty = self.ty()?;
+                    let a = self.operand()?;
+                    self.expect_punct(",")?;
+                    (ty, InstKind::Binary(op, a, self.operand()?))
+                } else if let Some(op) = lookup(&UN_OPS, word) {
+                    let ty = self.ty()?;
+                    (ty, InstKind::Unary(op, self.operand()?))
+                } else if let Some(op) = lookup(&CAST_OPS, word) {
+                    let from = self.ty()?;
+                    let value = self.operand()?;
+                    self.expect_word("to")?;
+                    (self.ty()?, InstKind::Cast(op, from, value))
+                } else {
+                    let msg = format!("unknown instruction '{}'", word);
+                    return Err(Box::new(Diagnostic::error(op_span, msg)));
+                }
+            }
+        };
+        let has_result = !matches!(
+            kind,
+            InstKind::Store { .. } | InstKind::MemCopy { .. } | InstKind::MemZero { .. }
+        ) && ty != IrType::Void;
+        if has_result != dest.is_some() {
+            let msg = if has_result {
+                format!("the result of '{}' must be assigned to a register", op)
+            } else {
+                format!("'{}' has no result", op)
+            };
+            return Err(Box::new(Diagnostic::error(op_span, msg)));
+        }
+        let span = self.loc(start)?;
+        Ok(Inst {
+            dest,
+            ty,
+            kind,
+            span,
+        })
+    }
+
+    fn terminator(&mut self, start: Span) -> Result<Terminator> {
+        let kind = match self.word()?.as_str() {
+            "jmp" => TermKind::Jump(self.target()?),
+            "br" => {
+                let cond = self.operand()?;
+                self.expect_punct(",")?;
+                let then = self.target()?;
+                self.expect_punct(",")?;
+                TermKind::Branch(cond, then, self.target()?)
+            }
+            "switch" => {
+                let ty = self.ty()?;
+                let value = self.operand()?;
+                self.expect_punct(",")?;
+                let default = self.target()?;
+                self.expect_punct("[")?;
+                let mut cases = Vec::new();
+                while !self.eat_punct("]") {
+                    if !cases.is_empty() {
+                        self.expect_punct(",")?;
+                    }
+                    let case = self.int()?;
+                    self.expect_punct(":")?;
+                    cases.push((case, self.target()?));
+                }
+                TermKind::Switch {
+                    ty,
+                    value,
+                    default,
+                    cases,
+                }
+            }
+            "ret" => {
+                if self.eat_word("void") {
+                    TermKind::Return(None)
+                } else {
+                    let ty = self.ty()?;
+                    TermKind::Return(Some((ty, self.operand()?)))
+                }
+            }
+            _ => TermKind::Unreachable,
+        };
+        let span = self.loc(start)?;
+        Ok(Terminator { kind, span })
+    }
+}
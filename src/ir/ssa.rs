@@ -0,0 +1,518 @@
+use super::*;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+fn predecessors(f: &Function) -> HashMap<BlockId, Vec<BlockId>> {
+    let mut preds: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    for block in &f.blocks {
+        preds.entry(block.id).or_default();
+        for succ in block.term.kind.successors() {
+            let list = preds.entry(succ).or_default();
+            if !list.contains(&block.id) {
+                list.push(block.id);
+            }
+        }
+    }
+    preds
+}
+
+// the blocks reachable from the entry, in reverse postorder
+fn reverse_postorder(f: &Function) -> Vec<BlockId> {
+    let successors = f
+        .blocks
+        .iter()
+        .map(|b| (b.id, b.term.kind.successors()))
+        .collect::<HashMap<_, _>>();
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    // (block, index of the next successor to visit)
+    let mut stack = vec![(f.blocks[0].id, 0)];
+    visited.insert(f.blocks[0].id);
+    while let Some((block, next)) = stack.pop() {
+        match successors[&block].get(next) {
+            Some(&succ) => {
+                stack.push((block, next + 1));
+                if visited.insert(succ) {
+                    stack.push((succ, 0));
+                }
+            }
+            None => order.push(block),
+        }
+    }
+    order.reverse();
+    order
+}
+
+// drops the blocks the entry cannot reach, and their phi operands
+pub fn remove_unreachable(f: &mut Function) {
+    let reachable = reverse_postorder(f).into_iter().collect::<HashSet<_>>();
+    f.blocks.retain(|b| reachable.contains(&b.id));
+    for block in &mut f.blocks {
+        for inst in &mut block.insts {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                incoming.retain(|(pred, _)| reachable.contains(pred));
+            }
+        }
+    }
+}
+
+// the immediate dominator of every reachable block but the entry,
+// after Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+pub fn dominators(f: &Function) -> HashMap<BlockId, BlockId> {
+    let order = reverse_postorder(f);
+    let index = order
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (*b, i))
+        .collect::<HashMap<_, _>>();
+    let preds = predecessors(f);
+    let entry = order[0];
+    let mut idom: HashMap<BlockId, BlockId> = HashMap::new();
+    idom.insert(entry, entry);
+
+    let intersect = |idom: &HashMap<BlockId, BlockId>, mut a: BlockId, mut b: BlockId| {
+        while a != b {
+            while index[&a] > index[&b] {
+                a = idom[&a];
+            }
+            while index[&b] > index[&a] {
+                b = idom[&b];
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let mut new_idom = None;
+            for &pred in &preds[&block] {
+                if !idom.contains_key(&pred) {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(current) => intersect(&idom, pred, current),
+                });
+            }
+            let new_idom = new_idom.unwrap();
+            if idom.get(&block) != Some(&new_idom) {
+                idom.insert(block, new_idom);
+                changed = true;
+            }
+        }
+    }
+    idom.remove(&entry);
+    idom
+}
+
+// the dominance frontier of every reachable block
+pub fn dominance_frontiers(
+    f: &Function,
+    idom: &HashMap<BlockId, BlockId>,
+) -> HashMap<BlockId, BTreeSet<BlockId>> {
+    let preds = predecessors(f);
+    let mut frontiers: HashMap<BlockId, BTreeSet<BlockId>> = HashMap::new();
+    for block in reverse_postorder(f) {
+        frontiers.entry(block).or_default();
+        let preds = &preds[&block];
+        if preds.len() < 2 {
+            continue;
+        }
+        for &pred in preds {
+            let mut runner = pred;
+            // unreachable predecessors have no dominator
+            if runner != f.blocks[0].id && !idom.contains_key(&runner) {
+                continue;
+            }
+            while Some(&runner) != idom.get(&block) {
+                frontiers.entry(runner).or_default().insert(block);
+                match idom.get(&runner) {
+                    Some(&up) => runner = up,
+                    None => break,
+                }
+            }
+        }
+    }
+    frontiers
+}
+
+// the `alloca`s only ever loaded from and stored to as a whole, with one type
+fn promotable(f: &Function) -> HashMap<Value, IrType> {
+    let mut candidates = HashMap::new();
+    for inst in f.blocks.iter().flat_map(|b| &b.insts) {
+        if let (Some(dest), InstKind::Alloca { size, .. }) = (inst.dest, &inst.kind) {
+            candidates.insert(dest, (*size, None));
+        }
+    }
+    let mut rejected = HashSet::new();
+    for inst in f.blocks.iter().flat_map(|b| &b.insts) {
+        let (access, ptr) = match &inst.kind {
+            InstKind::Load(ptr) => (Some(inst.ty), ptr.as_value()),
+            InstKind::Store { value, ptr } => {
+                if let Some(value) = value.as_value() {
+                    rejected.insert(value);
+                }
+                (Some(inst.ty), ptr.as_value())
+            }
+            kind => {
+                for operand in kind.operands() {
+                    if let Some(value) = operand.as_value() {
+                        rejected.insert(value);
+                    }
+                }
+                (None, None)
+            }
+        };
+        if let (Some(ty), Some(ptr)) = (access, ptr) {
+            if let Some((size, seen)) = candidates.get_mut(&ptr) {
+                let fits = ty.size() == *size && !matches!(ty, IrType::Agg { .. });
+                if !fits || seen.is_some_and(|seen| seen != ty) {
+                    rejected.insert(ptr);
+                }
+                *seen = Some(ty);
+            }
+        }
+    }
+    for term in f.blocks.iter().map(|b| &b.term) {
+        let operands = match &term.kind {
+            TermKind::Branch(cond, ..) => vec![*cond],
+            TermKind::Switch { value, .. } => vec![*value],
+            TermKind::Return(Some((_, value))) => vec![*value],
+            _ => Vec::new(),
+        };
+        rejected.extend(operands.iter().filter_map(|op| op.as_value()));
+    }
+    candidates
+        .into_iter()
+        .filter(|(value, _)| !rejected.contains(value))
+        .filter_map(|(value, (_, ty))| ty.map(|ty| (value, ty)))
+        .collect()
+}
+
+// promotes scalar `alloca`s to registers, placing phi nodes at the iterated
+// dominance frontiers of their stores and renaming along the dominator tree
+pub fn construct_ssa(f: &mut Function) {
+    remove_unreachable(f);
+    let vars = promotable(f);
+    if vars.is_empty() {
+        return renumber(f);
+    }
+    let idom = dominators(f);
+    let frontiers = dominance_frontiers(f, &idom);
+    let mut next_value = f.next_value();
+
+    // the variable of each phi
+    let mut phis: HashMap<Value, Value> = HashMap::new();
+    let mut sorted_vars = vars.keys().copied().collect::<Vec<_>>();
+    sorted_vars.sort();
+    for var in sorted_vars {
+        let span = f
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .find(|inst| inst.dest == Some(var))
+            .unwrap()
+            .span;
+        let mut work = f
+            .blocks
+            .iter()
+            .filter(|b| {
+                b.insts.iter().any(|inst| {
+                    matches!(inst.kind, InstKind::Store { ptr, .. } if ptr == Operand::Value(var))
+                })
+            })
+            .map(|b| b.id)
+            .collect::<Vec<_>>();
+        let mut placed = HashSet::new();
+        while let Some(block) = work.pop() {
+            for &frontier in &frontiers[&block] {
+                if !placed.insert(frontier) {
+                    continue;
+                }
+                let dest = Value(next_value);
+                next_value += 1;
+                let block = f.blocks.iter_mut().find(|b| b.id == frontier).unwrap();
+                block.insts.insert(
+                    0,
+                    Inst {
+                        dest: Some(dest),
+                        ty: vars[&var],
+                        kind: InstKind::Phi(Vec::new()),
+                        span,
+                    },
+                );
+                phis.insert(dest, var);
+                work.push(frontier);
+            }
+        }
+    }
+
+    let mut children: HashMap<BlockId, Vec<BlockId>> = HashMap::new();
+    for block in &f.blocks {
+        if let Some(parent) = idom.get(&block.id) {
+            children.entry(*parent).or_default().push(block.id);
+        }
+    }
+    let mut renamer = Renamer {
+        vars: &vars,
+        phis: &phis,
+        stacks: HashMap::new(),
+        replaced: HashMap::new(),
+        children: &children,
+    };
+    let entry = f.blocks[0].id;
+    renamer.rename(f, entry);
+
+    // loads in unreachable code, and values defined by the removed loads
+    let replaced = renamer.replaced;
+    let resolve = |mut operand: Operand| {
+        while let Some(value) = operand.as_value().and_then(|v| replaced.get(&v)) {
+            operand = *value;
+        }
+        operand
+    };
+    for block in &mut f.blocks {
+        block.insts.retain(|inst| match inst.kind {
+            InstKind::Alloca { .. } => !vars.contains_key(&inst.dest.unwrap()),
+            _ => true,
+        });
+        for inst in &mut block.insts {
+            for operand in inst.kind.operands_mut() {
+                *operand = resolve(*operand);
+            }
+        }
+        for operand in block.term.kind.operands_mut() {
+            *operand = resolve(*operand);
+        }
+    }
+    remove_trivial_phis(f);
+    renumber(f);
+}
+
+// numbers registers and blocks in the order they appear
+pub fn renumber(f: &mut Function) {
+    let blocks = f
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.id, BlockId(i as u32)))
+        .collect::<HashMap<_, _>>();
+    let mut values = HashMap::new();
+    let defs = f.params.iter().map(|(v, _)| *v).chain(
+        f.blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter_map(|inst| inst.dest),
+    );
+    for def in defs {
+        let next = Value(values.len() as u32);
+        values.insert(def, next);
+    }
+    let value = |operand: &mut Operand| {
+        if let Operand::Value(v) = operand {
+            *v = values[v];
+        }
+    };
+
+    for (v, _) in &mut f.params {
+        *v = values[v];
+    }
+    for block in &mut f.blocks {
+        block.id = blocks[&block.id];
+        for inst in &mut block.insts {
+            inst.dest = inst.dest.map(|v| values[&v]);
+            inst.kind.operands_mut().into_iter().for_each(value);
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (block, _) in incoming.iter_mut() {
+                    *block = blocks[block];
+                }
+                incoming.sort_by_key(|(block, _)| *block);
+            }
+        }
+        block.term.kind.operands_mut().into_iter().for_each(value);
+        match &mut block.term.kind {
+            TermKind::Jump(target) => *target = blocks[target],
+            TermKind::Branch(_, then, otherwise) => {
+                *then = blocks[then];
+                *otherwise = blocks[otherwise];
+            }
+            TermKind::Switch { default, cases, .. } => {
+                *default = blocks[default];
+                for (_, target) in cases {
+                    *target = blocks[target];
+                }
+            }
+            TermKind::Return(_) | TermKind::Unreachable => {}
+        }
+    }
+}
+
+struct Renamer<'r> {
+    vars: &'r HashMap<Value, IrType>,
+    phis: &'r HashMap<Value, Value>,
+    stacks: HashMap<Value, Vec<Operand>>,
+    // the loads removed, by the value they load
+    replaced: HashMap<Value, Operand>,
+    children: &'r HashMap<BlockId, Vec<BlockId>>,
+}
+
+impl Renamer<'_> {
+    fn current(&self, var: Value) -> Operand {
+        match self.stacks.get(&var).and_then(|stack| stack.last()) {
+            Some(value) => *value,
+            // read before any store, so the value is indeterminate
+            None if self.vars[&var].is_float() => Operand::Float(0.0),
+            None => Operand::Int(0),
+        }
+    }
+
+    fn resolve(&self, mut operand: Operand) -> Operand {
+        while let Some(value) = operand.as_value().and_then(|v| self.replaced.get(&v)) {
+            operand = *value;
+        }
+        operand
+    }
+
+    fn rename(&mut self, f: &mut Function, block_id: BlockId) {
+        let mut pushed: Vec<Value> = Vec::new();
+        let idx = f.blocks.iter().position(|b| b.id == block_id).unwrap();
+        let insts = std::mem::take(&mut f.blocks[idx].insts);
+        let mut kept = Vec::with_capacity(insts.len());
+        for mut inst in insts {
+            if !matches!(inst.kind, InstKind::Phi(_)) {
+                for operand in inst.kind.operands_mut() {
+                    *operand = self.resolve(*operand);
+                }
+            }
+            match inst.kind {
+                InstKind::Phi(_) if self.phis.contains_key(&inst.dest.unwrap()) => {
+                    let var = self.phis[&inst.dest.unwrap()];
+                    self.stacks
+                        .entry(var)
+                        .or_default()
+                        .push(Operand::Value(inst.dest.unwrap()));
+                    pushed.push(var);
+                }
+                InstKind::Load(Operand::Value(ptr)) if self.vars.contains_key(&ptr) => {
+                    let value = self.current(ptr);
+                    self.replaced.insert(inst.dest.unwrap(), value);
+                    continue;
+                }
+                InstKind::Store {
+                    value,
+                    ptr: Operand::Value(ptr),
+                } if self.vars.contains_key(&ptr) => {
+                    self.stacks.entry(ptr).or_default().push(value);
+                    pushed.push(ptr);
+                    continue;
+                }
+                _ => {}
+            }
+            kept.push(inst);
+        }
+        f.blocks[idx].insts = kept;
+        for operand in f.blocks[idx].term.kind.operands_mut() {
+            *operand = self.resolve(*operand);
+        }
+
+        let mut successors = f.blocks[idx].term.kind.successors();
+        successors.dedup();
+        let mut seen = HashSet::new();
+        for succ in successors {
+            if !seen.insert(succ) {
+                continue;
+            }
+            let succ_idx = f.blocks.iter().position(|b| b.id == succ).unwrap();
+            for i in 0..f.blocks[succ_idx].insts.len() {
+                let dest = f.blocks[succ_idx].insts[i].dest;
+                let var = match dest.and_then(|d| self.phis.get(&d)) {
+                    Some(var) => *var,
+                    None => continue,
+                };
+                let value = self.current(var);
+                if let InstKind::Phi(incoming) = &mut f.blocks[succ_idx].insts[i].kind {
+                    incoming.push((block_id, value));
+                }
+            }
+        }
+
+        let children = self.children.get(&block_id).cloned().unwrap_or_default();
+        for child in children {
+            self.rename(f, child);
+        }
+        for var in pushed {
+            self.stacks.get_mut(&var).unwrap().pop();
+        }
+    }
+}
+
+// replaces phis merging a single value besides themselves by that value,
+// and drops phis nothing uses
+fn remove_trivial_phis(f: &mut Function) {
+    loop {
+        let mut replaced: HashMap<Value, Operand> = HashMap::new();
+        for inst in f.blocks.iter().flat_map(|b| &b.insts) {
+            if let (Some(dest), InstKind::Phi(incoming)) = (inst.dest, &inst.kind) {
+                let mut values = incoming
+                    .iter()
+                    .map(|(_, v)| *v)
+                    .filter(|v| *v != Operand::Value(dest));
+                // one at a time, as phis may only merge each other
+                if let Some(first) = values.next() {
+                    if replaced.is_empty() && values.all(|v| v == first) {
+                        replaced.insert(dest, first);
+                    }
+                }
+            }
+        }
+
+        let mut used = HashSet::new();
+        for block in &f.blocks {
+            for inst in &block.insts {
+                for operand in inst.kind.operands() {
+                    if operand != inst.dest.map_or(Operand::Int(0), Operand::Value) {
+                        used.extend(operand.as_value());
+                    }
+                }
+            }
+            let mut term = block.term.kind.clone();
+            used.extend(
+                term.operands_mut()
+                    .into_iter()
+                    .filter_map(|op| op.as_value()),
+            );
+        }
+        let unused = f
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter(|inst| matches!(inst.kind, InstKind::Phi(_)))
+            .filter_map(|inst| inst.dest)
+            .filter(|dest| !used.contains(dest) && !replaced.contains_key(dest))
+            .collect::<HashSet<_>>();
+        if replaced.is_empty() && unused.is_empty() {
+            return;
+        }
+
+        let resolve = |operand: Operand| match operand.as_value().and_then(|v| replaced.get(&v)) {
+            Some(value) => *value,
+            None => operand,
+        };
+        for block in &mut f.blocks {
+            block.insts.retain(|inst| {
+                let dest = inst.dest.unwrap_or(Value(u32::MAX));
+                !replaced.contains_key(&dest) && !unused.contains(&dest)
+            });
+            for inst in &mut block.insts {
+                for operand in inst.kind.operands_mut() {
+                    *operand = resolve(*operand);
+                }
+            }
+            for operand in block.term.kind.operands_mut() {
+                *operand = resolve(*operand);
+            }
+        }
+    }
+}
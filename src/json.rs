@@ -0,0 +1,282 @@
+use crate::diagnostics::{Diagnostic, FixIt, Label};
+use crate::span::{LineColumn, Span};
+use crate::tokens::{Constant, Token};
+
+use std::fmt::{self, Write};
+
+// the schema is documented in README.md
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            Json::Float(x) if x.is_finite() => write!(f, "{:?}", x),
+            Json::Float(_) => f.write_str("null"),
+            Json::String(s) => write_str(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{0}'..='\u{1f}' | '\u{7f}' => write!(f, "\\u{:04x}", ch as u32)?,
+            _ => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.into())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Int(n as i128)
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        self.as_str().into()
+    }
+}
+
+impl ToJson for LineColumn {
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("line", self.line.into()),
+            ("column", self.column.into()),
+        ])
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        let range = |start: Json, end: Json| Json::Object(vec![("start", start), ("end", end)]);
+        let loc = self.location();
+        Json::Object(vec![
+            ("file_path", (*loc.file_path).into()),
+            (
+                "byte_range",
+                range(loc.byte_range.start.into(), loc.byte_range.end.into()),
+            ),
+            (
+                "lc_range",
+                range(loc.lc_range.start.to_json(), loc.lc_range.end.to_json()),
+            ),
+            ("expansion", self.expansion().to_json()),
+            ("included_from", self.included_from().to_json()),
+        ])
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        let mut fields = vec![("type", "token".into())];
+        let (kind, rest): (&str, Vec<(&'static str, Json)>) = match self {
+            Token::Identifier(ident) => (
+                "identifier",
+                vec![("value", (&*ident.value.as_str()).into())],
+            ),
+            Token::Keyword(kw) => ("keyword", vec![("value", kw.kind.as_str().into())]),
+            Token::Constant(Constant::Int(int)) => (
+                "integer",
+                vec![
+                    ("literal", int.literal.to_json()),
+                    ("value", Json::Int(int.value as i128)),
+                ],
+            ),
+            Token::Constant(Constant::Float(float)) => (
+                "float",
+                vec![
+                    ("literal", float.literal.to_json()),
+                    ("value", Json::Float(float.value)),
+                ],
+            ),
+            Token::Constant(Constant::Char(ch)) => (
+                "char",
+                vec![
+                    ("literal", ch.literal.to_json()),
+                    ("value", Json::Int(ch.value as i128)),
+                ],
+            ),
+            Token::StringLiteral(s) => (
+                "string",
+                vec![
+                    ("prefix", s.encoding.prefix().into()),
+                    ("value", (&*s.text()).into()),
+                ],
+            ),
+            Token::Punctuator(p) => (
+                "punctuator",
+                vec![
+                    ("literal", p.kind.as_str().into()),
+                    ("spelling", p.spelling.into()),
+                ],
+            ),
+            Token::Operator(op) => (
+                "operator",
+                vec![
+                    ("literal", op.kind.as_str().into()),
+                    ("spelling", op.spelling.into()),
+                ],
+            ),
+            Token::Directive(d) => (
+                "directive",
+                vec![("name", d.name.to_json()), ("args", d.args.to_json())],
+            ),
+            Token::Error(e) => ("error", vec![("literal", e.literal.to_json())]),
+        };
+        fields.push(("kind", kind.into()));
+        fields.extend(rest);
+        fields.push(("span", self.span().to_json()));
+        Json::Object(fields)
+    }
+}
+
+impl ToJson for Label {
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("span", self.span.to_json()),
+            ("message", self.msg.to_json()),
+        ])
+    }
+}
+
+impl ToJson for FixIt {
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("span", self.span.to_json()),
+            ("replacement", self.replacement.to_json()),
+            ("message", self.msg.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Diagnostic {
+    fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("type", "diagnostic".into()),
+            ("severity", self.severity.to_string().to_json()),
+            ("code", self.code.map(|c| c.to_string()).to_json()),
+            ("message", self.msg.to_json()),
+            ("span", self.span.to_json()),
+            ("label", self.label.to_json()),
+            ("labels", self.labels.to_json()),
+            ("notes", self.notes.to_json()),
+            ("help", self.help.to_json()),
+            ("fixits", self.fixits.to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Code;
+    use crate::lexer::Lexer;
+    use crate::source_file::SourceFile;
+
+    #[test]
+    fn escapes() {
+        let value = Json::Array(vec![
+            "a\"b\\c\n\u{1}é".into(),
+            Json::Float(f64::NAN),
+            Json::Float(0.5),
+            Json::Int(-3),
+            Json::Bool(true),
+            Json::Null,
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"["a\"b\\c\n\u0001é",null,0.5,-3,true,null]"#
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let src = SourceFile::new("x = 0x1F;", "a.c");
+        let (tokens, _) = Lexer::from_src(src).resolve();
+        let lines: Vec<String> = tokens.iter().map(|t| t.to_json().to_string()).collect();
+        assert_eq!(
+            lines[0],
+            concat!(
+                r#"{"type":"token","kind":"identifier","value":"x","span":{"file_path":"a.c","#,
+                r#""byte_range":{"start":0,"end":1},"lc_range":{"start":{"line":1,"column":1},"#,
+                r#""end":{"line":1,"column":2}},"expansion":null,"included_from":null}}"#
+            )
+        );
+        assert!(lines[2]
+            .starts_with(r#"{"type":"token","kind":"integer","literal":"0x1F","value":31,"#));
+        assert!(lines[3].starts_with(r#"{"type":"token","kind":"punctuator","literal":";","#));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let src = SourceFile::new("/* x", "a.c");
+        let (_, error) = Lexer::from_src(src).resolve();
+        let json = error[0].to_json().to_string();
+        assert!(json.starts_with(r#"{"type":"diagnostic","severity":"error","code":"E0001","#));
+        assert!(json.ends_with(r#""label":null,"labels":[],"notes":[],"help":[],"fixits":[]}"#));
+        assert_eq!(error[0].code, Some(Code::UnclosedComment));
+    }
+}
@@ -1,24 +1,157 @@
 use crate::{
-    char_stream::CharStream, errors::SynError, source_file::SourceFile, span::*, tokens::*,
+    char_stream::CharStream,
+    diagnostics::{Code, Diagnostic},
+    interner::Symbol,
+    source_file::SourceFile,
+    span::*,
+    tokens::*,
 };
 
+use std::collections::VecDeque;
+use std::ops::Range;
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
 pub struct Lexer {
     chars: CharStream,
+    // a `#` here starts a directive
+    line_start: bool,
+    // scanned or pushed back, yielded before anything else
+    lookahead: VecDeque<Result<Token>>,
+    // the error token that follows the error just yielded
+    recovered: Option<Token>,
 }
 
 impl Lexer {
     pub fn from_src(src: SourceFile) -> Self {
         Self {
             chars: CharStream::new(src),
+            line_start: true,
+            lookahead: VecDeque::new(),
+            recovered: None,
+        }
+    }
+
+    // lexes a fragment of a line, found at offset `start` of the source map
+    pub fn from_fragment(text: &str, start: u32) -> Self {
+        Self {
+            chars: CharStream::at(text, start),
+            // directive args never start a new line
+            line_start: false,
+            lookahead: VecDeque::new(),
+            recovered: None,
+        }
+    }
+
+    pub fn from_directive_args(directive: &Directive) -> Self {
+        Self::from_fragment(&directive.args, directive.args_span.lo)
+    }
+
+    // reads trigraphs, as in `-trigraphs` of GCC
+    pub fn with_trigraphs(mut self, enabled: bool) -> Self {
+        self.chars.set_trigraphs(enabled);
+        self
+    }
+
+    // lexes the whole input, keeping tokens and errors apart
+    pub fn resolve(self) -> (Vec<Token>, Vec<Diagnostic>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(*e),
+            }
         }
+        (tokens, errors)
     }
 
-    pub fn resolve(mut self) -> (Vec<Token>, Vec<SynError>) {
-        let mut tokens: Vec<Token> = Vec::new();
-        let mut errors: Vec<SynError> = Vec::new();
-        let mut start_pos;
+    // lexes the whole input, keeping the text between tokens as trivia so
+    // that nothing of the input is lost
+    pub fn resolve_lossless(self) -> (LosslessTokens, Vec<Diagnostic>) {
+        let (content, start) = self.chars.content();
+        let content = content.clone();
+        let trigraphs = self.chars.trigraphs();
+        let gap = |lo: u32, hi: u32| {
+            let text = &content[(lo - start) as usize..(hi - start) as usize];
+            scan_trivia(text, lo, trigraphs)
+        };
 
-        let mut state = 0;
+        let (tokens, errors) = self.resolve();
+        let mut lossless = Vec::with_capacity(tokens.len());
+        let mut pos = start;
+        for token in tokens {
+            let span = token.span();
+            debug_assert!(pos <= span.lo, "tokens out of order at {}", span.lo);
+            let mut leading = gap(pos, span.lo);
+            if let Some(prev) = lossless.last_mut() {
+                take_trailing(prev, &mut leading);
+            }
+            lossless.push(TriviaToken {
+                leading,
+                token,
+                trailing: Vec::new(),
+            });
+            pos = span.hi;
+        }
+        let mut end = gap(pos, start + content.len() as u32);
+        if let Some(prev) = lossless.last_mut() {
+            take_trailing(prev, &mut end);
+        }
+
+        let lossless = LosslessTokens {
+            tokens: lossless,
+            end,
+            content,
+            start,
+        };
+        (lossless, errors)
+    }
+
+    pub fn peek(&mut self) -> Option<&Result<Token>> {
+        self.peek_nth(0)
+    }
+
+    // scans ahead as far as needed, `peek_nth(0)` is the next item
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<Token>> {
+        while self.lookahead.len() <= n {
+            let result = self.scan()?;
+            self.lookahead.push_back(result);
+        }
+        self.lookahead.get(n)
+    }
+
+    // `tokens` are yielded next, in order, before anything not yet yielded
+    pub fn unget(&mut self, tokens: impl IntoIterator<Item = Token>) {
+        let tokens: Vec<Token> = tokens.into_iter().collect();
+        for token in tokens.into_iter().rev() {
+            self.lookahead.push_front(Ok(token));
+        }
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lookahead.pop_front() {
+            Some(result) => Some(result),
+            None => self.scan(),
+        }
+    }
+}
+
+impl Lexer {
+    // runs the DFA until it accepts a token or finds an error; after an
+    // error, the text skipped to resynchronize is yielded as an error token
+    fn scan(&mut self) -> Option<Result<Token>> {
+        if let Some(token) = self.recovered.take() {
+            return Some(Ok(token));
+        }
+
+        let start_pos;
+
+        let mut state = if self.line_start { 0 } else { 1 };
 
         'dfa: loop {
             match state {
@@ -35,35 +168,49 @@ impl Lexer {
                                 state = 0; // -> 0: line start
                                 continue 'dfa;
                             }
-                            '#' => {
+                            '#' | '%' if self.starts_directive() => {
                                 state = 2; // -> 2: directive
                                 continue 'dfa;
                             }
                             '/' => match self.chars.peek2() {
+                                Some('/') | Some('*') => {
+                                    state = 3; // -> 3: comment
+                                    continue 'dfa;
+                                }
+                                None | Some(_) => {
+                                    state = 5; // -> 5: punctuator
+                                    continue 'dfa;
+                                }
+                            },
+                            'A'..='Z' | 'a'..='z' | '_' => match self.literal_prefix() {
+                                Some((_, '"')) => {
+                                    state = 6; // -> 6: string literal
+                                    continue 'dfa;
+                                }
+                                Some(_) => {
+                                    state = 7; // -> 7: constant
+                                    continue 'dfa;
+                                }
                                 None => {
+                                    state = 4; // -> 4: ident
+                                    continue 'dfa;
+                                }
+                            },
+                            '.' => match self.chars.peek2() {
+                                Some('0'..='9') => {
+                                    state = 7; // -> 7: constant
+                                    continue 'dfa;
+                                }
+                                None | Some(_) => {
                                     state = 5; // -> 5: punctuator
                                     continue 'dfa;
                                 }
-                                Some(ch_ahead2) => match ch_ahead2 {
-                                    '/' | '*' => {
-                                        state = 3; // -> 3: comment
-                                        continue 'dfa;
-                                    }
-                                    _ => {
-                                        state = 5; // -> 5: punctuator
-                                        continue 'dfa;
-                                    }
-                                },
                             },
-                            'A'..='Z' | 'a'..='z' | '_' => {
-                                state = 4; // -> 4: ident
-                                continue 'dfa;
-                            }
                             '"' => {
                                 state = 6; // -> 6: string literal
                                 continue 'dfa;
                             }
-                            '0'..='9' => {
+                            '0'..='9' | '\'' => {
                                 state = 7; // -> 7: constant
                                 continue 'dfa;
                             }
@@ -74,7 +221,9 @@ impl Lexer {
                             _ => {
                                 let ch = self.chars.consume1();
                                 start_pos = self.chars.pos();
-                                errors.push(self.error_unexpected_char(ch, start_pos));
+                                self.line_start = true;
+                                let error = self.error_unexpected_char(ch, start_pos);
+                                return Some(self.recover(Err(error), start_pos));
                             }
                         }
                     }
@@ -96,41 +245,39 @@ impl Lexer {
                             state = 1; // -> 1: line body
                             continue 'dfa;
                         }
-                        '/' => match self.chars.peek() {
-                            None => {
+                        '/' => match self.chars.peek2() {
+                            Some('/') | Some('*') => {
+                                state = 3; // -> 3: comment
+                                continue 'dfa;
+                            }
+                            None | Some(_) => {
                                 state = 5; // -> 5: punctuator
                                 continue 'dfa;
                             }
-                            Some(ch_ahead) => match ch_ahead {
-                                '/' | '*' => {
-                                    state = 3; // -> 3: comment
-                                    continue 'dfa;
-                                }
-                                _ => {
-                                    state = 5; // -> 5: punctuator
-                                    continue 'dfa;
-                                }
-                            },
                         },
-                        'A'..='Z' | 'a'..='z' | '_' => {
-                            state = 4; // -> 4: ident
-                            continue 'dfa;
-                        }
-                        '.' => match self.chars.peek() {
+                        'A'..='Z' | 'a'..='z' | '_' => match self.literal_prefix() {
+                            Some((_, '"')) => {
+                                state = 6; // -> 6: string literal
+                                continue 'dfa;
+                            }
+                            Some(_) => {
+                                state = 7; // -> 7: constant
+                                continue 'dfa;
+                            }
                             None => {
-                                state = 5;
+                                state = 4; // -> 4: ident
+                                continue 'dfa;
+                            }
+                        },
+                        '.' => match self.chars.peek2() {
+                            Some('0'..='9') => {
+                                state = 7; // -> 7: constant
+                                continue 'dfa;
+                            }
+                            None | Some(_) => {
+                                state = 5; // -> 5: punctuator
                                 continue 'dfa;
                             }
-                            Some(ch_ahead) => match ch_ahead {
-                                '0'..='9' => {
-                                    state = 7;
-                                    continue 'dfa;
-                                }
-                                _ => {
-                                    state = 5;
-                                    continue 'dfa;
-                                }
-                            },
                         },
                         '"' => {
                             state = 6; // -> 6: string literal
@@ -147,84 +294,73 @@ impl Lexer {
                         _ => {
                             let ch = self.chars.consume1();
                             start_pos = self.chars.pos();
-                            errors.push(self.error_unexpected_char(ch, start_pos));
+                            self.line_start = false;
+                            let error = self.error_unexpected_char(ch, start_pos);
+                            return Some(self.recover(Err(error), start_pos));
                         }
                     },
                 },
                 // 2: directive
                 2 => {
-                    match self.expect_directive() {
-                        Ok(t) => tokens.push(Token::Directive(t)),
-                        Err(e) => errors.push(e),
-                    }
-                    state = 0; // -> 0: line start
-                    continue 'dfa;
+                    start_pos = self.chars.next_pos();
+                    let result = self.expect_directive().map(Token::Directive);
+                    self.line_start = true; // -> 0: line start
+                    return Some(self.recover(result, start_pos));
                 }
                 // 3: comment
-                3 => {
-                    match self.expect_comment() {
-                        Ok(()) => {}
-                        Err(e) => errors.push(e),
+                3 => match self.expect_comment() {
+                    Ok(()) => {
+                        state = 1; // -> 1: line body
+                        continue 'dfa;
                     }
-                    state = 1; // -> 1: line body
-                    continue 'dfa;
-                }
+                    Err(e) => {
+                        self.line_start = false;
+                        return Some(Err(e));
+                    }
+                },
                 // 4: ident
                 4 => {
-                    match self.expect_ident() {
-                        Ok(t) => {
-                            if t.is_keyword() {
-                                tokens.push(Token::Keyword(Keyword {
-                                    value: t.value,
-                                    span: t.span,
-                                }))
-                            } else {
-                                tokens.push(Token::Identifier(t))
-                            }
-                        }
-                        Err(e) => errors.push(e),
-                    }
-                    state = 1; // -> 1: line body
-                    continue 'dfa;
+                    start_pos = self.chars.next_pos();
+                    let result = self.expect_ident().map(|t| match t.value.keyword() {
+                        Some(kind) => Token::Keyword(Keyword { kind, span: t.span }),
+                        None => Token::Identifier(t),
+                    });
+                    self.line_start = false; // -> 1: line body
+                    return Some(self.recover(result, start_pos));
                 }
                 // 5: punctuator
                 5 => {
-                    match self.expect_punctuator() {
-                        Ok(t) => {
-                            if t.is_operator() {
-                                tokens.push(Token::Operator(Operator {
-                                    literal: t.literal,
-                                    span: t.span,
-                                }))
-                            } else {
-                                tokens.push(Token::Punctuator(t))
-                            }
+                    start_pos = self.chars.next_pos();
+                    let result = self.expect_punctuator().map(|t| {
+                        if t.kind.is_operator() {
+                            Token::Operator(Operator {
+                                kind: t.kind,
+                                spelling: t.spelling,
+                                span: t.span,
+                            })
+                        } else {
+                            Token::Punctuator(t)
                         }
-                        Err(e) => errors.push(e),
-                    }
-                    state = 1; // -> 1: line body
-                    continue 'dfa;
+                    });
+                    self.line_start = false; // -> 1: line body
+                    return Some(self.recover(result, start_pos));
                 }
                 // 6: string literal
                 6 => {
-                    match self.expect_string_literal() {
-                        Ok(t) => tokens.push(Token::StringLiteral(t)),
-                        Err(e) => errors.push(e),
-                    }
-                    state = 1; // -> 1: line body
-                    continue 'dfa;
+                    start_pos = self.chars.next_pos();
+                    let result = self.expect_string_literal().map(Token::StringLiteral);
+                    self.line_start = false; // -> 1: line body
+                    return Some(self.recover(result, start_pos));
                 }
                 // 7: constant
                 7 => {
-                    match self.expect_constant() {
-                        Ok(t) => tokens.push(Token::Constant(t)),
-                        Err(e) => errors.push(e),
-                    }
-                    state = 1; // -> 1: line body
-                    continue 'dfa;
+                    start_pos = self.chars.next_pos();
+                    let result = self.expect_constant().map(Token::Constant);
+                    self.line_start = false; // -> 1: line body
+                    return Some(self.recover(result, start_pos));
                 }
                 // 255: end
-                255 => return (tokens, errors),
+                255 => return None,
                 _ => unreachable!(),
             }
         }
@@ -232,63 +368,132 @@ impl Lexer {
 }
 
 impl Lexer {
+    // keeps what was scanned from `start_pos` as an error token, yielded
+    // after the error
+    fn recover(&mut self, result: Result<Token>, start_pos: u32) -> Result<Token> {
+        if result.is_err() {
+            self.recovered = Some(Token::Error(ErrorToken {
+                literal: self.chars.since(start_pos).into(),
+                span: self.emit_span(start_pos),
+            }));
+        }
+        result
+    }
+
     #[must_use]
-    fn emit_span(&self, start_pos: Pos) -> Span {
-        let end_pos = self.chars.pos().add1();
+    fn emit_span(&self, start_pos: u32) -> Span {
+        Span::new(start_pos, self.chars.end_pos().max(start_pos))
+    }
 
-        let start_lc = LineColumn {
-            line: start_pos.lineno,
-            column: start_pos.column,
-        };
+    #[must_use]
+    fn emit_error(&self, code: Code, msg: String, start_pos: u32) -> Diagnostic {
+        Diagnostic::error(self.emit_span(start_pos), msg).with_code(code)
+    }
 
-        let end_lc = LineColumn {
-            line: end_pos.lineno,
-            column: end_pos.column,
-        };
+    fn error_unexpected_char(&self, ch: char, start_pos: u32) -> Box<Diagnostic> {
+        let error = self.emit_error(
+            Code::UnexpectedChar,
+            format!("unexpected char: {:?}", ch),
+            start_pos,
+        );
+        Box::new(match CONFUSABLE_TABLE.iter().find(|&&(c, _)| c == ch) {
+            Some(&(_, ascii)) => error.with_fixit(
+                self.emit_span(start_pos),
+                ascii,
+                format!("did you mean {:?}?", ascii),
+            ),
+            None => error,
+        })
+    }
 
-        Span {
-            byte_range: start_pos.byte_pos..end_pos.byte_pos,
-            lc_range: start_lc..end_lc,
-            file_path: self.chars.file_path().clone(),
+    // whether the line starts with `#` or `%:`, but not the single token `##`
+    // or `%:%:`
+    fn starts_directive(&self) -> bool {
+        let mut ahead = self.chars.ahead();
+        match (ahead.next(), ahead.next()) {
+            (Some('#'), Some('#')) => false,
+            (Some('#'), _) => true,
+            (Some('%'), Some(':')) => !(ahead.next() == Some('%') && ahead.next() == Some(':')),
+            _ => false,
         }
     }
 
-    #[must_use]
-    fn emit_error(&self, msg: String, start_pos: Pos) -> SynError {
-        SynError {
-            span: self.emit_span(start_pos),
-            msg,
+    // skips to the end of the line, leaving the newline
+    fn skip_line(&mut self) {
+        while let Some(ch_ahead) = self.chars.peek() {
+            if ch_ahead == '\n' {
+                break;
+            }
+            self.chars.consume1();
+        }
+    }
+
+    // up to the closing quote or the end of the line, as the error for an
+    // unterminated literal comes when the args are lexed
+    fn skip_quoted(&mut self) {
+        let quote = self.chars.consume1();
+        while let Some(ch_ahead) = self.chars.peek() {
+            match ch_ahead {
+                '\n' => break,
+                '\\' => {
+                    self.chars.consume1();
+                    if !matches!(self.chars.peek(), None | Some('\n')) {
+                        self.chars.consume1();
+                    }
+                }
+                _ => {
+                    self.chars.consume1();
+                    if ch_ahead == quote {
+                        break;
+                    }
+                }
+            }
         }
     }
 
-    fn error_unexpected_char(&self, ch: char, start_pos: Pos) -> SynError {
-        self.emit_error(format!("unexpected char: {:?}", ch), start_pos)
+    // in a directive, where a block comment is a blank even if it runs past
+    // the end of the line
+    fn skip_blanks(&mut self) -> Result<()> {
+        loop {
+            match (self.chars.peek(), self.chars.peek2()) {
+                (Some(' ' | '\r' | '\t' | '\x0C'), _) => {
+                    self.chars.consume1();
+                }
+                (Some('/'), Some('*')) => self.expect_comment()?,
+                _ => return Ok(()),
+            }
+        }
     }
 
-    fn error_expected(&self, which: &str, start_pos: Pos) -> SynError {
-        self.emit_error(format!("expected {}", which), start_pos)
+    fn error_expected(&self, which: &str, start_pos: u32) -> Box<Diagnostic> {
+        Box::new(self.emit_error(
+            Code::UnexpectedChar,
+            format!("expected {}", which),
+            start_pos,
+        ))
     }
 }
 
 impl Lexer {
-    fn expect_ident(&mut self) -> Result<Identifier, SynError> {
-        let mut start_pos = self.chars.pos().add1();
+    fn expect_ident(&mut self) -> Result<Identifier> {
+        let mut start_pos = self.chars.next_pos();
 
-        let mut literal: String = match self.chars.next() {
+        match self.chars.next() {
             None => return Err(self.error_expected("identifier", start_pos)),
             Some(ch) => match ch {
-                'A'..='Z' | 'a'..='z' | '_' => ch.into(),
+                'A'..='Z' | 'a'..='z' | '_' => {}
                 _ => return Err(self.error_unexpected_char(ch, start_pos)),
             },
-        };
+        }
 
         start_pos = self.chars.pos();
+        let mut len = 1;
 
         while let Some(ch_ahead) = self.chars.peek() {
             match ch_ahead {
                 'A'..='Z' | 'a'..='z' | '0'..='9' | '_' => {
-                    let ch = self.chars.consume1();
-                    literal.push(ch);
+                    self.chars.consume1();
+                    len += 1;
                 }
                 _ => {
                     break;
@@ -296,66 +501,96 @@ impl Lexer {
             }
         }
 
+        // interned as written, without allocating, unless spliced
+        let literal = self.chars.since(start_pos);
+        let value = if literal.len() == len {
+            Symbol::intern(literal)
+        } else {
+            let literal: String = literal
+                .chars()
+                .filter(|&ch| ch.is_ascii_alphanumeric() || ch == '_')
+                .collect();
+            Symbol::intern(&literal)
+        };
+
         Ok(Identifier {
-            value: literal,
+            value,
             span: self.emit_span(start_pos),
         })
     }
 
-    fn expect_directive(&mut self) -> Result<Directive, SynError> {
-        let mut start_pos = self.chars.pos().add1();
+    // the name is empty for a null directive, a `#` alone on its line; the
+    // newline is left to the line start
+    fn expect_directive(&mut self) -> Result<Directive> {
+        let start_pos = self.chars.next_pos();
 
         match self.chars.next() {
             None => return Err(self.error_expected("directive", start_pos)),
             Some(ch) => match ch {
                 '#' => {}
+                '%' if self.chars.peek() == Some(':') => {
+                    self.chars.consume1();
+                }
                 _ => return Err(self.error_unexpected_char(ch, start_pos)),
             },
         };
 
-        start_pos = self.chars.pos();
-
-        let ident = self.expect_ident()?;
-
-        match self.chars.next() {
+        self.skip_blanks()?;
+        match self.chars.peek() {
             None | Some('\n') => {
+                let args_span = self.emit_span(self.chars.next_pos());
                 return Ok(Directive {
-                    name: ident.value,
-                    args: "".into(),
+                    name: String::new(),
+                    args: String::new(),
+                    args_span,
                     span: self.emit_span(start_pos),
-                })
+                });
+            }
+            Some('A'..='Z' | 'a'..='z' | '_') => {}
+            Some(_) => {
+                let ch = self.chars.consume1();
+                let error = self.emit_error(
+                    Code::UnexpectedChar,
+                    format!("expected directive name, found {:?}", ch),
+                    self.chars.pos(),
+                );
+                // nothing more of the line is lexed
+                self.skip_line();
+                return Err(Box::new(error));
             }
-            Some(ch) => match ch {
-                ' ' | '\r' | '\t' | '\x0C' => {
-                    // do nothing
-                }
-                _ => return Err(self.error_unexpected_char(ch, start_pos)),
-            },
         }
 
-        let mut args = String::new();
-        while let Some(ch) = self.chars.next() {
-            match ch {
-                '\n' => break,
-                '\r' => {
-                    if self.chars.peek() == Some('\n') {
-                        self.chars.consume1();
-                        break;
-                    }
+        let ident = self.expect_ident()?;
+
+        // the args may follow the name right away, as in `#include<stdio.h>`
+        self.skip_blanks()?;
+        // as written, so that offsets into it are those of the source
+        let args_pos = self.chars.next_pos();
+        while let Some(ch_ahead) = self.chars.peek() {
+            match (ch_ahead, self.chars.peek2()) {
+                ('\n', _) | ('\r', Some('\n')) => break,
+                // the line goes on past the newlines of a block comment
+                ('/', Some('*')) => self.expect_comment()?,
+                // nor does a comment start in a literal
+                ('"' | '\'', _) => self.skip_quoted(),
+                _ => {
+                    self.chars.consume1();
                 }
-                _ => args.push(ch),
-            }
+            };
         }
+        let args = self.chars.since(args_pos).into();
+        let args_span = self.emit_span(args_pos);
 
         Ok(Directive {
-            name: ident.value,
+            name: ident.value.to_string(),
             args,
+            args_span,
             span: self.emit_span(start_pos),
         })
     }
 
-    fn expect_comment(&mut self) -> Result<(), SynError> {
-        let mut start_pos = self.chars.pos().add1();
+    fn expect_comment(&mut self) -> Result<()> {
+        let mut start_pos = self.chars.next_pos();
 
         match self.chars.next() {
             None => return Err(self.error_expected("comment", start_pos)),
@@ -368,7 +603,13 @@ impl Lexer {
         start_pos = self.chars.pos();
 
         let is_line_comment = match self.chars.next() {
-            None => return Err(self.emit_error("expected comment, found '/'".into(), start_pos)),
+            None => {
+                return Err(Box::new(self.emit_error(
+                    Code::UnexpectedChar,
+                    "expected comment, found '/'".into(),
+                    start_pos,
+                )))
+            }
             Some(ch) => match ch {
                 '/' => true,
                 '*' => false,
@@ -377,20 +618,25 @@ impl Lexer {
         };
 
         if is_line_comment {
-            while let Some(ch) = self.chars.next() {
+            for ch in self.chars.by_ref() {
                 if ch == '\n' {
                     break;
                 }
             }
             Ok(())
         } else {
+            let open = self.emit_span(start_pos);
             loop {
                 let ch = self.chars.next();
                 let ch_ahead = self.chars.peek();
 
                 match (ch, ch_ahead) {
+                    // points at the `/*`
                     (None, _) | (_, None) => {
-                        return Err(self.emit_error("unclosed comment".into(), start_pos))
+                        return Err(Box::new(
+                            Diagnostic::error(open, "unclosed comment")
+                                .with_code(Code::UnclosedComment),
+                        ));
                     }
                     (Some(ch), Some(ch_ahead)) => {
                         if let ('*', '/') = (ch, ch_ahead) {
@@ -404,137 +650,151 @@ impl Lexer {
         }
     }
 
-    fn expect_string_literal(&mut self) -> Result<StringLiteral, SynError> {
-        let mut start_pos = self.chars.pos().add1();
+    // the encoding and quote of the prefixed char constant or string
+    // literal the input starts with
+    fn literal_prefix(&self) -> Option<(Encoding, char)> {
+        // as most identifiers are not
+        if !matches!(self.chars.peek(), Some('u' | 'U' | 'L')) {
+            return None;
+        }
+        ENCODING_PREFIX_TABLE
+            .iter()
+            .find_map(|&(prefix, encoding)| {
+                let mut ahead = self.chars.ahead();
+                if !prefix.chars().all(|ch| ahead.next() == Some(ch)) {
+                    return None;
+                }
+                match ahead.next()? {
+                    quote @ ('"' | '\'') => Some((encoding, quote)),
+                    _ => None,
+                }
+            })
+    }
 
+    fn expect_encoding(&mut self) -> Encoding {
+        match self.literal_prefix() {
+            Some((encoding, _)) => {
+                for _ in encoding.prefix().chars() {
+                    self.chars.consume1();
+                }
+                encoding
+            }
+            None => Encoding::None,
+        }
+    }
+
+    fn expect_string_literal(&mut self) -> Result<StringLiteral> {
+        let start_pos = self.chars.next_pos();
+
+        let encoding = self.expect_encoding();
         match self.chars.next() {
             None => return Err(self.error_expected("string literal", start_pos)),
             Some(ch) => match ch {
                 '"' => {}
-                _ => return Err(self.error_unexpected_char(ch, start_pos)),
+                _ => return Err(self.error_unexpected_char(ch, self.chars.pos())),
             },
         }
 
-        start_pos = self.chars.pos();
+        let quote_pos = self.chars.pos();
+        let literal_pos = self.chars.next_pos();
+        let literal = match self.expect_quoted('"') {
+            Some(literal) => literal,
+            None => {
+                return Err(self.error_unclosed(Code::UnclosedString, "string literal", quote_pos))
+            }
+        };
 
-        let mut literal = String::new();
-        loop {
-            match self.chars.next() {
-                None => return Err(self.emit_error("unclosed string literal".into(), start_pos)),
-                Some(ch) => match ch {
-                    '"' | '\n' => break,
-                    '\\' => match self.chars.next() {
-                        None => break,
-                        Some(ch) => literal.push(ch),
-                    },
+        let mut value = Vec::new();
+        let span_of = |range| self.chars.span_of(literal_pos, range);
+        decode_literal(&literal, &span_of, encoding, |units, _, _| {
+            value.extend_from_slice(units);
+            Ok(())
+        })?;
 
-                    _ => literal.push(ch),
-                },
-            }
-        }
+        Ok(StringLiteral::from_units(
+            encoding,
+            value,
+            self.emit_span(start_pos),
+        ))
+    }
 
-        let mut value = String::new();
-        let mut literal_chars = literal.chars();
-        while let Some(ch) = literal_chars.next() {
-            match ch {
-                '\n' => return Err(self.emit_error("unclosed string literal".into(), start_pos)),
-                '\\' => match literal_chars.next() {
-                    None => return Err(self.error_expected("escape sequence", start_pos)),
-                    Some(ch) => {
-                        match SIMPLE_ESCAPE_SEQUENCE_TABLE
-                            .iter()
-                            .copied()
-                            .find(|&(c, _)| c == ch)
-                        {
-                            None => {
-                                return Err(self.emit_error(
-                                    format!("invalid escape sequence: {:?}", format!("\\{:?}", ch)),
-                                    start_pos,
-                                ))
-                            }
-                            Some((_, v)) => value.push(v),
-                        }
+    // the text up to the closing `quote`, which is consumed; `None` if the
+    // line ends first, leaving the newline
+    fn expect_quoted(&mut self, quote: char) -> Option<String> {
+        let mut literal = String::new();
+        loop {
+            match self.chars.peek()? {
+                '\n' => return None,
+                '\\' => {
+                    literal.push(self.chars.consume1());
+                    match self.chars.peek() {
+                        None | Some('\n') => {}
+                        Some(_) => literal.push(self.chars.consume1()),
                     }
-                },
-                '\u{0}'..='\u{127}' => value.push(ch),
-                _ => return Err(self.emit_error("non-ascii string literal".into(), start_pos)),
+                }
+                ch if ch == quote => {
+                    self.chars.consume1();
+                    return Some(literal);
+                }
+                _ => literal.push(self.chars.consume1()),
             }
         }
-
-        Ok(StringLiteral {
-            value,
-            span: self.emit_span(start_pos),
-        })
     }
 
-    fn expect_punctuator(&mut self) -> Result<Punctuator, SynError> {
-        let mut start_pos = self.chars.pos().add1();
+    // points at the opening quote
+    fn error_unclosed(&self, code: Code, which: &str, quote_pos: u32) -> Box<Diagnostic> {
+        let span = Span::new(quote_pos, quote_pos + 1);
+        Box::new(Diagnostic::error(span, format!("unclosed {}", which)).with_code(code))
+    }
 
-        let ch = self.chars.next();
-        let ch_ahead = self.chars.peek();
+    fn expect_punctuator(&mut self) -> Result<Punctuator> {
+        let start_pos = self.chars.next_pos();
 
-        let (ch1, ch2) = match (ch, ch_ahead) {
-            (None, _) => return Err(self.error_expected("punctuator", start_pos)),
-            (Some(ch), None) => {
-                start_pos = self.chars.pos();
-                if PUNCTUATOR_LEN1_TABLE.contains(&ch) {
-                    return Ok(Punctuator {
-                        literal: ch.into(),
-                        span: self.emit_span(start_pos),
-                    });
-                } else {
-                    return Err(self.error_unexpected_char(ch, start_pos));
+        // the longest punctuator the input starts with, of 4 chars at most
+        let mut buf = [0; 16];
+        let mut len = 0;
+        for ch in self.chars.ahead().take(4) {
+            len += ch.encode_utf8(&mut buf[len..]).len();
+        }
+        let ahead = std::str::from_utf8(&buf[..len]).unwrap();
+        let punc = (1..=ahead.len())
+            .rev()
+            .filter(|&len| ahead.is_char_boundary(len))
+            .find_map(|len| {
+                let text = &ahead[..len];
+                match DIGRAPH_TABLE.iter().find(|&&(digraph, _)| digraph == text) {
+                    Some(&(digraph, kind)) => Some((kind, digraph)),
+                    None => text
+                        .parse()
+                        .ok()
+                        .map(|kind: PunctuatorKind| (kind, kind.as_str())),
                 }
-            }
-            (Some(ch), Some(ch_ahead)) => (ch, ch_ahead),
-        };
-
-        start_pos = self.chars.pos();
+            });
 
-        if let ('<', '<') | ('>', '>') = (ch1, ch2) {
-            self.chars.consume1();
-            match self.chars.peek() {
-                Some('=') => {
+        match punc {
+            None => match self.chars.next() {
+                None => Err(self.error_expected("punctuator", start_pos)),
+                Some(ch) => Err(self.error_unexpected_char(ch, start_pos)),
+            },
+            Some((kind, spelling)) => {
+                for _ in spelling.chars() {
                     self.chars.consume1();
-                    return Ok(Punctuator {
-                        literal: format!("{}{}=", ch1, ch2),
-                        span: self.emit_span(start_pos),
-                    });
                 }
-                None | Some(_) => {
-                    return Ok(Punctuator {
-                        literal: format!("{}{}", ch1, ch2),
-                        span: self.emit_span(start_pos),
-                    })
-                }
-            }
-        };
-
-        let punc = format!("{}{}", ch1, ch2);
-        for &op in &OPERATOR_TABLE {
-            if op.len() == 2 && op == punc {
-                self.chars.consume1();
-                return Ok(Punctuator {
-                    literal: op.into(),
+                Ok(Punctuator {
+                    kind,
+                    spelling,
                     span: self.emit_span(start_pos),
-                });
+                })
             }
         }
+    }
 
-        if PUNCTUATOR_LEN1_TABLE.contains(&ch1) {
-            return Ok(Punctuator {
-                literal: ch1.into(),
-                span: self.emit_span(start_pos),
-            });
+    fn expect_constant(&mut self) -> Result<Constant> {
+        if self.chars.peek() == Some('\'') || self.literal_prefix().is_some() {
+            return self.expect_char_constant().map(Constant::Char);
         }
 
-        self.chars.consume1();
-        start_pos = self.chars.pos();
-        Err(self.error_unexpected_char(ch2, start_pos))
-    }
-
-    fn expect_constant(&mut self) -> Result<Constant, SynError> {
-        let mut start_pos = self.chars.pos().add1();
+        let mut start_pos = self.chars.next_pos();
 
         let ch_leading = match self.chars.next() {
             None => return Err(self.error_expected("constant", start_pos)),
@@ -544,100 +804,53 @@ impl Lexer {
         start_pos = self.chars.pos();
 
         match ch_leading {
-            '\'' => {
-                let mut literal = String::new();
-                loop {
-                    match self.chars.next() {
-                        None => {
-                            return Err(self.emit_error("unclosed char constant".into(), start_pos))
-                        }
-                        Some(ch) => match ch {
-                            '\'' => break,
-                            '\\' => match self.chars.next() {
-                                None => break,
-                                Some(ch) => literal.push(ch),
-                            },
-                            _ => literal.push(ch),
-                        },
-                    };
-                }
-                let value;
-                let mut literal_chars = literal.chars();
-                match literal_chars.next() {
-                    None => return Err(self.emit_error("empty char constant".into(), start_pos)),
-                    Some(ch) => match ch {
-                        '\\' => match literal_chars.next() {
-                            None => return Err(self.error_expected("escape sequence", start_pos)),
-                            Some(ch) => {
-                                match SIMPLE_ESCAPE_SEQUENCE_TABLE
-                                    .iter()
-                                    .copied()
-                                    .find(|&(c, _)| c == ch)
-                                {
-                                    None => {
-                                        return Err(self.emit_error(
-                                            format!(
-                                                "invalid escape sequence: {:?}",
-                                                format!("\\{:?}", ch)
-                                            ),
-                                            start_pos,
-                                        ))
-                                    }
-                                    Some((_, v)) => value = v,
-                                }
-                            }
-                        },
-                        '\u{0}'..='\u{127}' => value = ch,
-                        _ => {
-                            return Err(self.emit_error("non-ascii char constant".into(), start_pos))
-                        }
-                    },
-                }
-                if literal_chars.next().is_some() {
-                    return Err(
-                        self.emit_error("multiple chars in char constant".into(), start_pos)
-                    );
-                }
-                let token = CharConstant {
-                    value,
-                    span: self.emit_span(start_pos),
-                };
-                Ok(Constant::Char(token))
-            }
-
             '.' | '0'..='9' => {
+                // a preprocessing number, which the DFA of the constant it
+                // looks like then checks
                 let mut literal: String = ch_leading.into();
-
-                let mut has_dot = false;
                 while let Some(ch_ahead) = self.chars.peek() {
                     match ch_ahead {
-                        '0'..='9' | 'A'..='Z' | 'a'..='z' => {
-                            literal.push(self.chars.consume1());
-                        }
-                        '.' => {
-                            has_dot = true;
-                            literal.push(self.chars.consume1());
-                            while let Some(ch_ahead) = self.chars.peek() {
-                                match ch_ahead {
-                                    '0'..='9' | 'A'..='Z' | 'a'..='z' => {
-                                        literal.push(self.chars.consume1());
-                                    }
-                                    _ => break,
-                                }
+                        '0'..='9' | 'A'..='Z' | 'a'..='z' | '_' | '.' => {
+                            let ch = self.chars.consume1();
+                            literal.push(ch);
+                            if let ('e' | 'E' | 'p' | 'P', Some('+' | '-')) =
+                                (ch, self.chars.peek())
+                            {
+                                literal.push(self.chars.consume1());
                             }
-                            break;
                         }
+                        // a digit separator
+                        '\'' => match self.chars.peek2() {
+                            Some('0'..='9' | 'A'..='Z' | 'a'..='z' | '_') => {
+                                literal.push(self.chars.consume1());
+                            }
+                            _ => break,
+                        },
                         _ => break,
                     }
                 }
                 let span = self.emit_span(start_pos);
-                if has_dot {
+                let decimal = octal_as_decimal(&literal).map(String::from);
+                // points into the literal
+                let error = |(range, msg): LiteralError| {
+                    let at = self.chars.span_of(start_pos, range);
+                    let error = Diagnostic::error(at, msg).with_code(Code::InvalidNumber);
+                    Box::new(match decimal {
+                        Some(decimal) => error.with_fixit(
+                            span,
+                            decimal.clone(),
+                            format!("did you mean the decimal constant {}?", decimal),
+                        ),
+                        None => error,
+                    })
+                };
+                if is_float_literal(&literal) {
                     FloatConstant::validate(literal, span)
-                        .map_err(|msg| self.emit_error(msg, start_pos))
+                        .map_err(error)
                         .map(Constant::Float)
                 } else {
                     IntegerConstant::validate(literal, span)
-                        .map_err(|msg| self.emit_error(msg, start_pos))
+                        .map_err(error)
                         .map(Constant::Int)
                 }
             }
@@ -645,3 +858,326 @@ impl Lexer {
         }
     }
 }
+
+impl Lexer {
+    fn expect_char_constant(&mut self) -> Result<CharConstant> {
+        let start_pos = self.chars.next_pos();
+
+        let encoding = self.expect_encoding();
+        match self.chars.next() {
+            None => return Err(self.error_expected("char constant", start_pos)),
+            Some(ch) => match ch {
+                '\'' => {}
+                _ => return Err(self.error_unexpected_char(ch, self.chars.pos())),
+            },
+        }
+
+        let quote_pos = self.chars.pos();
+        let literal_pos = self.chars.next_pos();
+        let literal = match self.expect_quoted('\'') {
+            Some(literal) => literal,
+            None => {
+                return Err(self.error_unclosed(Code::UnclosedChar, "char constant", quote_pos))
+            }
+        };
+        if literal.is_empty() {
+            return Err(Box::new(self.emit_error(
+                Code::EmptyChar,
+                "empty char constant".into(),
+                start_pos,
+            )));
+        }
+
+        // each char must fit in one code unit
+        let mut units = Vec::new();
+        let mut spans = Vec::new();
+        let span_of = |range| self.chars.span_of(literal_pos, range);
+        decode_literal(
+            &literal,
+            &span_of,
+            encoding,
+            |char_units, span, is_escape| {
+                if char_units.len() > 1 {
+                    let code = if is_escape {
+                        Code::InvalidEscape
+                    } else {
+                        Code::NonAscii
+                    };
+                    let msg = "character too large for enclosing character literal type";
+                    return Err(Box::new(Diagnostic::error(span, msg).with_code(code)));
+                }
+                units.extend_from_slice(char_units);
+                spans.push(span);
+                Ok(())
+            },
+        )?;
+
+        let value = match (encoding, units.as_slice()) {
+            (Encoding::None, &[unit]) => unit as u8 as i8 as i64,
+            // implementation-defined: like GCC, the chars are the bytes of
+            // an int, the last one lowest
+            (Encoding::None, units) if units.len() <= 4 => {
+                units.iter().fold(0u32, |value, &unit| value << 8 | unit) as i32 as i64
+            }
+            // points at the chars that do not fit
+            (Encoding::None, _) => {
+                let span = spans[4].to(spans[spans.len() - 1]);
+                let error = Diagnostic::error(span, "character constant too long for its type")
+                    .with_code(Code::MultiCharConstant);
+                if literal.contains('"') {
+                    return Err(Box::new(error));
+                }
+                return Err(Box::new(error.with_fixit(
+                    self.emit_span(start_pos),
+                    format!("\"{}\"", literal),
+                    "did you mean a string literal?",
+                )));
+            }
+            (Encoding::Wide, &[unit]) => unit as i32 as i64,
+            (_, &[unit]) => unit as i64,
+            _ => {
+                let span = spans[1].to(spans[spans.len() - 1]);
+                let msg = format!(
+                    "multiple chars in char constant with prefix '{}'",
+                    encoding.prefix()
+                );
+                return Err(Box::new(
+                    Diagnostic::error(span, msg).with_code(Code::MultiCharConstant),
+                ));
+            }
+        };
+
+        Ok(CharConstant {
+            literal: format!("{}'{}'", encoding.prefix(), literal),
+            encoding,
+            value,
+            span: self.emit_span(start_pos),
+        })
+    }
+}
+
+// a hexadecimal float has a `.` or binary exponent, a decimal one a `.` or
+// exponent
+fn is_float_literal(literal: &str) -> bool {
+    match literal.get(..2) {
+        Some("0x" | "0X") => literal.contains(['.', 'p', 'P']),
+        Some("0b" | "0B") => false,
+        _ => literal.contains(['.', 'e', 'E']),
+    }
+}
+
+// the decimal digits of an octal-looking integer with an 8 or 9, like `0187`
+fn octal_as_decimal(literal: &str) -> Option<&str> {
+    let digits = literal.strip_prefix('0')?;
+    let is_decimal = digits.bytes().all(|b| b.is_ascii_digit() || b == b'\'');
+    if !is_decimal || !digits.contains(['8', '9']) {
+        return None;
+    }
+    Some(digits.trim_start_matches(['0', '\'']))
+}
+
+// decodes the chars and escape sequences between the quotes of a literal,
+// whose byte ranges `span_of` maps to the source, passing the code units,
+// span and whether it is an escape of each one to `f`
+fn decode_literal(
+    text: &str,
+    span_of: &dyn Fn(Range<usize>) -> Span,
+    encoding: Encoding,
+    mut f: impl FnMut(&[u32], Span, bool) -> Result<()>,
+) -> Result<()> {
+    let mut units = Vec::new();
+    let mut idx = 0;
+    while let Some(ch) = text[idx..].chars().next() {
+        units.clear();
+        let len = if ch == '\\' {
+            let span_of = |range: Range<usize>| span_of(idx + range.start..idx + range.end);
+            let (escape, len) = decode_escape(&text[idx..], &span_of, encoding.unit_max())?;
+            match escape {
+                Escape::Unit(unit) => units.push(unit),
+                Escape::Char(ch) => encoding.encode(ch, &mut units),
+            }
+            len
+        } else {
+            encoding.encode(ch, &mut units);
+            ch.len_utf8()
+        };
+        f(&units, span_of(idx..idx + len), ch == '\\')?;
+        idx += len;
+    }
+    Ok(())
+}
+
+// a decoded escape sequence
+enum Escape {
+    // a simple, octal or hex escape: the value of one code unit
+    Unit(u32),
+    // a universal character name
+    Char(char),
+}
+
+// decodes the escape sequence starting with the `\` at the start of `text`,
+// whose byte ranges `span_of` maps to the source, for code units up to
+// `max`; also returns its length in bytes
+fn decode_escape(
+    text: &str,
+    span_of: &dyn Fn(Range<usize>) -> Span,
+    max: u32,
+) -> Result<(Escape, usize)> {
+    let error = |len: usize, msg: String| {
+        Diagnostic::error(span_of(0..len), msg).with_code(Code::InvalidEscape)
+    };
+    let digits = |radix: u32, skip: usize, max_len: usize| {
+        let rest = &text[1 + skip..];
+        let len = rest
+            .bytes()
+            .take(max_len)
+            .take_while(|&b| char::from(b).is_digit(radix))
+            .count();
+        &rest[..len]
+    };
+
+    let ch = match text[1..].chars().next() {
+        Some(ch) => ch,
+        None => return Err(Box::new(error(1, "expected escape sequence".into()))),
+    };
+    match ch {
+        '0'..='7' => {
+            let octal = digits(8, 0, 3);
+            let len = 1 + octal.len();
+            match u32::from_str_radix(octal, 8) {
+                Ok(unit) if unit <= max => Ok((Escape::Unit(unit), len)),
+                _ => Err(Box::new(error(
+                    len,
+                    "octal escape sequence out of range".into(),
+                ))),
+            }
+        }
+        'x' => {
+            let hex = digits(16, 1, usize::MAX);
+            let len = 2 + hex.len();
+            if hex.is_empty() {
+                return Err(Box::new(error(
+                    len,
+                    "\\x used with no following hex digits".into(),
+                )));
+            }
+            match u32::from_str_radix(hex, 16) {
+                Ok(unit) if unit <= max => Ok((Escape::Unit(unit), len)),
+                _ => Err(Box::new(error(
+                    len,
+                    "hex escape sequence out of range".into(),
+                ))),
+            }
+        }
+        'u' | 'U' => {
+            let n_digits = if ch == 'u' { 4 } else { 8 };
+            let hex = digits(16, 1, n_digits);
+            let len = 2 + hex.len();
+            if hex.len() < n_digits {
+                return Err(Box::new(error(
+                    len,
+                    "incomplete universal character name".into(),
+                )));
+            }
+            let code = u32::from_str_radix(hex, 16).unwrap();
+            match char::from_u32(code) {
+                // C11 6.4.3p2
+                Some(ch) if code >= 0xA0 || matches!(ch, '$' | '@' | '`') => {
+                    Ok((Escape::Char(ch), len))
+                }
+                Some(ch) => Err(Box::new(error(
+                    len,
+                    format!(
+                        "character '{}' cannot be specified by a universal character name",
+                        ch.escape_default()
+                    ),
+                ))),
+                None => Err(Box::new(error(
+                    len,
+                    format!("\\{}{} is not a valid universal character", ch, hex),
+                ))),
+            }
+        }
+        _ => match SIMPLE_ESCAPE_SEQUENCE_TABLE.iter().find(|&&(c, _)| c == ch) {
+            Some(&(_, value)) => Ok((Escape::Unit(value as u32), 1 + ch.len_utf8())),
+            None => {
+                let msg = format!("unknown escape sequence '\\{}'", ch.escape_default());
+                Err(Box::new(error(1 + ch.len_utf8(), msg).with_fixit(
+                    span_of(0..1),
+                    "\\\\",
+                    "did you mean a literal backslash?",
+                )))
+            }
+        },
+    }
+}
+
+// the trivia up to the first newline trails `token`
+fn take_trailing(token: &mut TriviaToken, trivia: &mut Vec<Trivia>) {
+    let newline = trivia
+        .iter()
+        .position(|t| t.kind == TriviaKind::Newline)
+        .unwrap_or(trivia.len());
+    token.trailing = trivia.drain(..newline).collect();
+}
+
+// splits `text`, found at offset `start` of the source map and holding no
+// tokens, into trivia
+fn scan_trivia(text: &str, start: u32, trigraphs: bool) -> Vec<Trivia> {
+    let mut chars = CharStream::at(text, start);
+    chars.set_trigraphs(trigraphs);
+    let mut trivia = Vec::new();
+    let mut end = start;
+    loop {
+        // what the stream skipped since the last trivia
+        if chars.next_pos() > end {
+            trivia.push(Trivia {
+                kind: TriviaKind::Splice,
+                span: Span::new(end, chars.next_pos()),
+            });
+        }
+        let pos = chars.next_pos();
+        let kind = match (chars.next(), chars.peek()) {
+            (None, _) => break,
+            (Some('\n'), _) => TriviaKind::Newline,
+            (Some('\r'), Some('\n')) => {
+                chars.next();
+                TriviaKind::Newline
+            }
+            (Some('/'), Some('/')) => {
+                while !matches!(chars.peek(), None | Some('\n')) {
+                    chars.next();
+                }
+                TriviaKind::LineComment
+            }
+            (Some('/'), Some('*')) => {
+                chars.next();
+                while let Some(ch) = chars.next() {
+                    if ch == '*' && chars.peek() == Some('/') {
+                        chars.next();
+                        break;
+                    }
+                }
+                TriviaKind::BlockComment
+            }
+            // whitespace, and anything else the lexer let through
+            _ => {
+                // a splice ends the run, to be trivia of its own
+                while chars.next_pos() == chars.end_pos() {
+                    match (chars.peek(), chars.peek2()) {
+                        (Some('\r'), Some('\n')) => break,
+                        (Some(' ' | '\t' | '\x0C' | '\r'), _) => chars.next(),
+                        _ => break,
+                    };
+                }
+                TriviaKind::Whitespace
+            }
+        };
+        end = chars.end_pos();
+        trivia.push(Trivia {
+            kind,
+            span: Span::new(pos, end),
+        });
+    }
+    trivia
+}
@@ -0,0 +1,40 @@
+pub mod ast;
+pub mod codegen;
+pub mod interp;
+pub mod ir;
+pub mod session;
+pub mod tokens;
+
+mod char_stream;
+mod checker;
+mod diagnostics;
+mod initializer;
+mod interner;
+mod json;
+mod lexer;
+mod parser;
+mod pipeline;
+mod preprocessor;
+mod resolver;
+mod source_file;
+mod source_map;
+mod span;
+mod symbols;
+mod types;
+mod utils;
+
+pub use crate::checker::TypeTable;
+pub use crate::diagnostics::{Code, Diagnostic, Renderer, Severity};
+pub use crate::interner::Symbol;
+pub use crate::json::ToJson;
+pub use crate::lexer::Lexer;
+pub use crate::pipeline::{compile, preprocess, Compilation, Options, Output, Stage};
+pub use crate::preprocessor::Preprocessor;
+pub use crate::source_file::SourceFile;
+pub use crate::span::{LineColumn, Span};
+pub use crate::tokens::Token;
+
+// lexes `src` without preprocessing: directives are kept as tokens
+pub fn tokenize(src: &SourceFile) -> (Vec<Token>, Vec<Diagnostic>) {
+    lexer::Lexer::from_src(src.clone()).resolve()
+}
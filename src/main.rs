@@ -1,244 +1,467 @@
-pub mod char_stream;
-pub mod errors;
-pub mod lexer;
-pub mod source_file;
-pub mod span;
-pub mod tokens;
-pub mod utils;
+use toy_compiler::ast::TranslationUnit;
+use toy_compiler::tokens::{self, Token};
+use toy_compiler::{codegen, interp, ir};
+use toy_compiler::{Compilation, Diagnostic, Lexer, Options, Output, Renderer, SourceFile, Stage};
+use toy_compiler::{ToJson, TypeTable};
 
 use std::env;
-use std::fmt;
+use std::fmt::Write as _;
 use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
+
+const USAGE: &str = "\
+usage: toy-compiler [command] [options] <file>
+
+commands:
+    lex           print the tokens of <file> before preprocessing
+    preprocess    print the tokens after preprocessing (the default)
+    parse         print the syntax tree
+    check         report errors and warnings only
+    ir            print the SSA intermediate representation
+    asm           write x86-64 assembly, to <file>.s unless -o is given
+    run           interpret the program and exit with its status
+
+options:
+    -o <path>                    write output to <path> instead of stdout
+    -I <dir>                     add a directory to the include search path
+    -D <name>[=<value>]          predefine a macro
+    --trigraphs                  replace trigraphs such as `??=` before lexing
+    --format <human|json>        how tokens and diagnostics are printed
+    --no-source-listing          do not print the numbered source before tokens
+    --color <auto|always|never>  color diagnostics, `auto` if stderr is a terminal
+    --max-errors <n>             stop after <n> errors, 0 for no limit
+    -h, --help                   print this message
+
+<file> may be `-` to read standard input.
+
+exit status: 0 on success, 1 on compile errors, 2 on usage errors,
+3 on I/O and internal errors; `run` exits with the status of the program.
+";
+
+const EXIT_COMPILE_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_INTERNAL: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Lex,
+    Preprocess,
+    Parse,
+    Check,
+    Ir,
+    Asm,
+    Run,
+}
+
+const COMMANDS: [(&str, Command); 7] = [
+    ("lex", Command::Lex),
+    ("preprocess", Command::Preprocess),
+    ("parse", Command::Parse),
+    ("check", Command::Check),
+    ("ir", Command::Ir),
+    ("asm", Command::Asm),
+    ("run", Command::Run),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
 
 struct Args {
+    command: Command,
     src_path: String,
+    include_paths: Vec<String>,
+    defines: Vec<String>,
+    output: Option<String>,
+    format: Format,
+    source_listing: bool,
+    trigraphs: bool,
+    color: Color,
+    max_errors: usize,
 }
 
-fn parse_args() -> Result<Args, String> {
-    let mut args_iter = env::args();
-    args_iter.next();
-
-    let src_path = args_iter
-        .next()
-        .ok_or_else(|| "missing argument: source path")?;
+// `None` if only the usage was asked for
+fn parse_args(
+    mut args_iter: impl Iterator<Item = String>,
+) -> std::result::Result<Option<Args>, String> {
+    let mut command = None;
+    let mut src_path = None;
+    let mut include_paths = Vec::new();
+    let mut defines = Vec::new();
+    let mut output = None;
+    let mut format = Format::Human;
+    let mut source_listing = true;
+    let mut trigraphs = false;
+    let mut color = Color::Auto;
+    let mut max_errors = 0;
 
-    if args_iter.next().is_some() {
-        return Err("too many arguments".into());
+    while let Some(arg) = args_iter.next() {
+        // long options take `--name value` or `--name=value`
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| match inline.clone().or_else(|| args_iter.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("missing argument after {:?}", name)),
+        };
+        match name {
+            "-h" | "--help" => return Ok(None),
+            "--no-source-listing" => source_listing = false,
+            "--trigraphs" => trigraphs = true,
+            "-o" => output = Some(value(name)?),
+            "--format" => {
+                format = match value(name)?.as_str() {
+                    "human" => Format::Human,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {:?}", other)),
+                }
+            }
+            "--color" => {
+                color = match value(name)?.as_str() {
+                    "auto" => Color::Auto,
+                    "always" => Color::Always,
+                    "never" => Color::Never,
+                    other => return Err(format!("unknown color mode {:?}", other)),
+                }
+            }
+            "--max-errors" => {
+                let n = value(name)?;
+                max_errors = n
+                    .parse()
+                    .map_err(|_| format!("invalid error limit {:?}", n))?;
+            }
+            _ if arg.starts_with("-I") || arg.starts_with("-D") => {
+                let (list, flag) = match arg.starts_with("-I") {
+                    true => (&mut include_paths, "-I"),
+                    false => (&mut defines, "-D"),
+                };
+                let value = match &arg[2..] {
+                    "" => value(flag)?,
+                    attached => attached.to_owned(),
+                };
+                list.push(value);
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option {:?}", arg))
+            }
+            _ => {
+                let subcommand = COMMANDS.iter().find(|&&(name, _)| name == arg);
+                match subcommand {
+                    Some(&(_, c)) if command.is_none() && src_path.is_none() => command = Some(c),
+                    _ if src_path.is_some() => return Err("too many arguments".into()),
+                    _ => src_path = Some(arg),
+                }
+            }
+        }
     }
 
-    Ok(Args { src_path })
+    let src_path = src_path.ok_or("missing argument: source path")?;
+
+    Ok(Some(Args {
+        command: command.unwrap_or(Command::Preprocess),
+        src_path,
+        include_paths,
+        defines,
+        output,
+        format,
+        source_listing,
+        trigraphs,
+        color,
+        max_errors,
+    }))
 }
 
-fn exit_on_error<T, E: fmt::Display>(result: Result<T, E>) -> T {
-    match result {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("error: {}", e);
-            process::exit(1)
-        }
+// usage errors are reported before anything is compiled
+enum Failure {
+    Compile,
+    Internal(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Internal(e.to_string())
     }
 }
 
-use crate::errors::SynError;
-use crate::lexer::Lexer;
-use crate::source_file::SourceFile;
-use crate::tokens::Token;
-use crate::utils::number_width;
+type Result<T> = std::result::Result<T, Failure>;
 
-fn print_token(token: &Token) {
-    match token {
-        Token::Identifier(ident) => {
-            println!("(Identifier, {:?})", ident.value);
-        }
-        Token::Keyword(keyword) => {
-            println!("(Keyword, {:?})", keyword.value);
-        }
+fn write_token(out: &mut String, token: &Token) {
+    let _ = match token {
+        Token::Identifier(ident) => writeln!(out, "(Identifier, {:?})", ident.value),
+        Token::Keyword(keyword) => writeln!(out, "(Keyword, {:?})", keyword.kind.as_str()),
         Token::Constant(constant) => match constant {
-            tokens::Constant::Int(int) => {
-                println!("(IntegerConstant, {:?})", int.literal);
-            }
+            tokens::Constant::Int(int) => writeln!(out, "(IntegerConstant, {:?})", int.literal),
             tokens::Constant::Float(float) => {
-                println!("(FloatConstant, {:?})", float.literal);
-            }
-            tokens::Constant::Char(ch) => {
-                println!("(CharConstant, {:?})", ch.value as char);
+                writeln!(out, "(FloatConstant, {:?})", float.literal)
             }
+            tokens::Constant::Char(ch) => writeln!(out, "(CharConstant, {:?})", ch.literal),
         },
-        Token::StringLiteral(s) => {
-            println!("(StringLiteral, {:?})", s.value);
-        }
-        Token::Punctuator(punc) => {
-            println!("(Punctuator, {:?})", punc.literal);
+        Token::StringLiteral(s) => writeln!(
+            out,
+            "(StringLiteral, {}{:?})",
+            s.encoding.prefix(),
+            s.text()
+        ),
+        Token::Punctuator(punc) => writeln!(out, "(Punctuator, {:?})", punc.kind.as_str()),
+        Token::Operator(op) => writeln!(out, "(Operator, {:?})", op.kind.as_str()),
+        Token::Directive(directive) => writeln!(
+            out,
+            "(Directive, {:?}, {:?})",
+            directive.name, directive.args
+        ),
+        Token::Error(e) => writeln!(out, "(Error, {:?})", e.literal),
+    };
+}
+
+// prints diagnostics, counting errors against `--max-errors`
+struct Reporter {
+    renderer: Renderer,
+    format: Format,
+    max_errors: usize,
+    errors: usize,
+}
+
+impl Reporter {
+    fn new(args: &Args) -> Self {
+        let color = match args.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        };
+        Self {
+            renderer: Renderer::new().with_color(color),
+            format: args.format,
+            max_errors: args.max_errors,
+            errors: 0,
         }
-        Token::Operator(op) => {
-            println!("(Operator, {:?})", op.literal);
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.max_errors != 0 && self.errors >= self.max_errors
+    }
+
+    fn report(&mut self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics {
+            if self.limit_reached() {
+                if self.format == Format::Human {
+                    eprintln!("error: too many errors emitted, stopping now");
+                }
+                return;
+            }
+            if diagnostic.is_error() {
+                self.errors += 1;
+            }
+            match self.format {
+                Format::Human => eprintln!("{}", self.renderer.render(diagnostic)),
+                Format::Json => eprintln!("{}", diagnostic.to_json()),
+            }
         }
-        Token::Directive(directive) => {
-            println!("(Directive, {:?}, {:?})", directive.name, directive.args);
+    }
+
+    // reports `diagnostics`, failing if there are errors among them
+    fn check(&mut self, diagnostics: &[Diagnostic]) -> Result<()> {
+        self.report(diagnostics);
+        match diagnostics.iter().any(Diagnostic::is_error) {
+            true => Err(Failure::Compile),
+            false => Ok(()),
         }
     }
 }
 
-fn print_token_span(token: &Token, src_lines: &[Vec<char>]) {
-    let span = token.span();
-    let start_lc = span.lc_range.start;
-    let end_lc = span.lc_range.end;
-    if start_lc.line == end_lc.line {
-        let line_str = src_lines[&start_lc.line - 1]
-            .iter()
-            .copied()
-            .collect::<String>();
-
-        let indicator = line_str
-            .chars()
-            .enumerate()
-            .map(|(i, ch)| {
-                let col = i + 1;
-                if col >= start_lc.column && col < end_lc.column {
-                    '^'
-                } else if ch == '\t' {
-                    '\t'
-                } else {
-                    ' '
-                }
-            })
-            .collect::<String>();
-
-        println!(
-            " --> {}:{}:{}",
-            span.file_path, start_lc.line, start_lc.column
-        );
-        println!("{}", line_str);
-        println!("{}", indicator);
-    } else {
-        // todo?
+fn read_source(path: &str) -> Result<SourceFile> {
+    let mut content = String::new();
+    let (read, name) = match path {
+        "-" => (io::stdin().read_to_string(&mut content), "<stdin>"),
+        _ => (
+            fs::File::open(path).and_then(|mut f| f.read_to_string(&mut content)),
+            path,
+        ),
+    };
+    match read {
+        Ok(_) => Ok(SourceFile::new(&content, name)),
+        Err(e) => Err(Failure::Internal(format!("cannot read {:?}: {}", name, e))),
     }
 }
 
-fn eprint_error_span(error: &SynError, src_lines: &[Vec<char>]) {
-    let start_lc = error.span.lc_range.start;
-    let end_lc = error.span.lc_range.end;
-
-    if start_lc.line == end_lc.line {
-        let line_str = src_lines[&start_lc.line - 1]
-            .iter()
-            .copied()
-            .collect::<String>();
-
-        let indicator = line_str
-            .chars()
-            .enumerate()
-            .map(|(i, ch)| {
-                let col = i + 1;
-                if col >= start_lc.column && col < end_lc.column {
-                    '^'
-                } else if ch == '\t' {
-                    '\t'
-                } else {
-                    ' '
-                }
-            })
-            .collect::<String>();
-
-        eprintln!(
-            " --> {}:{}:{}",
-            error.span.file_path, start_lc.line, start_lc.column
-        );
-        eprintln!("{}", line_str);
-        eprintln!("{}", indicator);
-    } else {
-        let start_line_str = src_lines[start_lc.line - 1]
-            .iter()
-            .copied()
-            .collect::<String>();
-        let end_line_str = src_lines[end_lc.line - 1]
-            .iter()
-            .copied()
-            .collect::<String>();
-
-        let start_indicator = start_line_str
-            .chars()
-            .enumerate()
-            .map(|(i, ch)| {
-                let col = i + 1;
-                if col >= start_lc.column {
-                    '^'
-                } else if ch == '\t' {
-                    '\t'
-                } else {
-                    ' '
-                }
-            })
-            .collect::<String>();
-
-        let end_indicator = end_line_str
-            .chars()
-            .enumerate()
-            .map(|(i, ch)| {
-                let col = i + 1;
-                if col < end_lc.column {
-                    '^'
-                } else if ch == '\t' {
-                    '\t'
-                } else {
-                    ' '
-                }
-            })
-            .collect::<String>();
-
-        eprintln!(
-            " --> starts at {}:{}:{}",
-            error.span.file_path, start_lc.line, start_lc.column
-        );
-        eprintln!("{}", start_line_str);
-        eprintln!("{}", start_indicator);
-        eprintln!(
-            " --> ends at {}:{}:{}",
-            error.span.file_path, end_lc.line, end_lc.column
-        );
-        eprintln!("{}", end_line_str);
-        eprintln!("{}", end_indicator);
+fn open_output(path: Option<&str>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        None | Some("-") => Box::new(io::stdout()),
+        Some(path) => Box::new(fs::File::create(path)?),
+    })
+}
+
+fn source_listing(out: &mut String, src: &SourceFile) {
+    let lineno_width = src.line_count().to_string().len().max(2);
+    for (idx, line) in src.lines().enumerate() {
+        let _ = writeln!(out, "{:>width$}| {}", idx + 1, line, width = lineno_width);
     }
+    out.push('\n');
 }
 
-fn main() {
-    let args = exit_on_error(parse_args());
+fn token_dump(args: &Args, src: &SourceFile, tokens: &[Token], renderer: &Renderer) -> String {
+    let mut out = String::new();
+    if args.format == Format::Json {
+        for token in tokens {
+            let _ = writeln!(out, "{}", token.to_json());
+        }
+        return out;
+    }
+    if args.source_listing {
+        source_listing(&mut out, src);
+    }
+    for token in tokens {
+        write_token(&mut out, token);
+        out += &renderer.snippet(token.span(), '^', "");
+        out.push('\n');
+    }
+    out
+}
 
-    let source_file = {
-        let content = exit_on_error(fs::read_to_string(&args.src_path));
-        SourceFile::new(&content, &args.src_path)
-    };
+fn compile(args: &Args) -> Result<i32> {
+    let source_file = read_source(&args.src_path)?;
 
-    let src_lines = source_file.generate_lines();
+    if args.command == Command::Lex {
+        let (tokens, errors) = Lexer::from_src(source_file.clone())
+            .with_trigraphs(args.trigraphs)
+            .resolve();
+        let mut reporter = Reporter::new(args);
+        let dump = token_dump(args, &source_file, &tokens, &Renderer::new());
+        open_output(args.output.as_deref())?.write_all(dump.as_bytes())?;
+        reporter.check(&errors)?;
+        return Ok(0);
+    }
+
+    let options = Options {
+        include_paths: args.include_paths.iter().map(PathBuf::from).collect(),
+        defines: args.defines.clone(),
+        trigraphs: args.trigraphs,
+        stop_after: match args.command {
+            Command::Preprocess => Stage::Preprocess,
+            Command::Parse => Stage::Parse,
+            _ => Stage::Check,
+        },
+    };
+    let Compilation {
+        output,
+        diagnostics,
+    } = toy_compiler::compile(source_file.clone(), &options);
+    let mut reporter = Reporter::new(args);
 
-    {
-        // print lines with lineno
-        let lineno_width = number_width(src_lines.len()).max(2);
-        for (idx, line) in src_lines.iter().enumerate() {
-            let lineno = idx + 1;
-            let line = line.iter().copied().collect::<String>();
-            println!("{:>width$}| {}", lineno, line, width = lineno_width);
+    // the tokens are printed even if there are errors
+    if let (Command::Preprocess, Output::Tokens(tokens)) = (args.command, &output) {
+        let dump = token_dump(args, &source_file, tokens, &Renderer::new());
+        open_output(args.output.as_deref())?.write_all(dump.as_bytes())?;
+    }
+    reporter.check(&diagnostics)?;
+    match output {
+        Output::Tokens(_) => Ok(0),
+        Output::Unit(unit) => {
+            let tree = format!("{:#?}\n", unit);
+            open_output(args.output.as_deref())?.write_all(tree.as_bytes())?;
+            Ok(0)
         }
-        println!();
+        Output::Checked(unit, types) => back_end(args, &unit, &types, &mut reporter),
     }
+}
 
-    let lexer = Lexer::from_src(source_file);
-    let (tokens, errors) = lexer.resolve();
+// lowers, generates code for or runs a checked unit
+fn back_end(
+    args: &Args,
+    unit: &TranslationUnit,
+    types: &TypeTable,
+    reporter: &mut Reporter,
+) -> Result<i32> {
+    let lowered = match args.command {
+        Command::Ir => ir::lower(unit, types).map(|mut module| {
+            for function in &mut module.functions {
+                ir::construct_ssa(function);
+            }
+            module.to_string()
+        }),
+        Command::Asm => codegen::generate(unit, types),
+        Command::Run => {
+            // as a linker would report it, there being no span to point at
+            let main = match interp::find_main(unit) {
+                Some(main) => main,
+                None => {
+                    eprintln!("error: undefined reference to 'main'");
+                    return Err(Failure::Compile);
+                }
+            };
+            let out = open_output(args.output.as_deref())?;
+            return match interp::run(unit, types, main, out) {
+                Ok(status) => Ok(status),
+                Err(error) => reporter.check(&[*error]).map(|()| 0),
+            };
+        }
+        _ => return Ok(0),
+    };
+    let text = match lowered {
+        Ok(text) => text,
+        Err(error) => return reporter.check(&[*error]).map(|()| 0),
+    };
 
-    for token in &tokens {
-        print_token(token);
-        print_token_span(token, &src_lines);
-        println!();
-    }
+    // assembly for a named source goes next to it by default
+    let output = match (&args.output, args.command) {
+        (Some(output), _) => Some(output.clone()),
+        (None, Command::Asm) if args.src_path != "-" => Some(
+            Path::new(&args.src_path)
+                .with_extension("s")
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        (None, _) => None,
+    };
+    open_output(output.as_deref())?.write_all(text.as_bytes())?;
+    Ok(0)
+}
 
-    if !errors.is_empty() {
-        eprintln!();
-        for error in &errors {
-            eprintln!("error: {}", error.msg);
-            eprint_error_span(error, &src_lines);
-            eprintln!();
+fn cli() -> i32 {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return 0;
+        }
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            eprintln!("try 'toy-compiler --help' for more information");
+            return EXIT_USAGE;
+        }
+    };
+    match compile(&args) {
+        Ok(status) => status,
+        Err(Failure::Compile) => EXIT_COMPILE_ERROR,
+        Err(Failure::Internal(msg)) => {
+            eprintln!("error: {}", msg);
+            EXIT_INTERNAL
         }
-        process::exit(1);
     }
 }
+
+// the interpreter recurses on the host stack, see `interp::MAX_CALL_DEPTH`
+const STACK_SIZE: usize = 1 << 30;
+
+fn main() {
+    let status = match thread::Builder::new().stack_size(STACK_SIZE).spawn(cli) {
+        Ok(compiler) => compiler.join().unwrap_or(EXIT_INTERNAL),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            EXIT_INTERNAL
+        }
+    };
+    process::exit(status)
+}
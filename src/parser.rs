@@ -0,0 +1,1202 @@
+use crate::{
+    ast::*,
+    char_stream::CharStream,
+    diagnostics::{Code, Diagnostic},
+    interner::Symbol,
+    span::Span,
+    tokens::*,
+};
+
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+mod expr;
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    idx: usize,
+    scopes: Vec<HashMap<Symbol, bool>>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Parser {
+    pub fn from_tokens(tokens: Vec<Token>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .filter(|t| !matches!(t, Token::Directive(_)))
+            .collect();
+        Self {
+            tokens,
+            idx: 0,
+            scopes: vec![HashMap::new()],
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn parse(mut self) -> (TranslationUnit, Vec<Diagnostic>) {
+        let mut items = Vec::new();
+        while self.peek().is_some() {
+            if self.eat(PunctuatorKind::RBrace).is_some() {
+                let span = self.prev_span();
+                let error = Diagnostic::error(span, "unexpected '}'");
+                self.errors.push(error.with_code(Code::UnbalancedBrace));
+                continue;
+            }
+            match self.parse_external_decl() {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    self.errors.push(*e);
+                    self.synchronize();
+                }
+            }
+        }
+        (TranslationUnit { items }, self.errors)
+    }
+
+    pub fn parse_constant_expr(mut self) -> Result<Expr> {
+        let expr = self.parse_conditional()?;
+        if self.peek().is_some() {
+            return Err(self.error_expected("end of expression"));
+        }
+        Ok(expr)
+    }
+}
+
+// token helpers
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.idx + n)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.idx).cloned();
+        if token.is_some() {
+            self.idx += 1;
+        }
+        token
+    }
+
+    fn prev_span(&self) -> Span {
+        self.tokens[self.idx - 1].span()
+    }
+
+    fn cur_span(&self) -> Span {
+        match self.peek() {
+            Some(t) => t.span(),
+            None => self.tokens.last().unwrap().span(),
+        }
+    }
+
+    fn peek_kind_nth(&self, n: usize) -> Option<TokenKind> {
+        self.peek_nth(n).map(Token::kind)
+    }
+
+    fn is_punct_nth(&self, n: usize, kind: PunctuatorKind) -> bool {
+        self.peek_kind_nth(n) == Some(TokenKind::Punctuator(kind))
+    }
+
+    fn is_punct(&self, kind: PunctuatorKind) -> bool {
+        self.is_punct_nth(0, kind)
+    }
+
+    fn is_keyword_nth(&self, n: usize, kind: KeywordKind) -> bool {
+        self.peek_kind_nth(n) == Some(TokenKind::Keyword(kind))
+    }
+
+    fn is_keyword(&self, kind: KeywordKind) -> bool {
+        self.is_keyword_nth(0, kind)
+    }
+
+    fn eat(&mut self, kind: PunctuatorKind) -> Option<Span> {
+        if self.is_punct(kind) {
+            self.bump().map(|t| t.span())
+        } else {
+            None
+        }
+    }
+
+    fn eat_keyword(&mut self, kind: KeywordKind) -> Option<Span> {
+        if self.is_keyword(kind) {
+            self.bump().map(|t| t.span())
+        } else {
+            None
+        }
+    }
+
+    fn expect(&mut self, kind: PunctuatorKind) -> Result<Span> {
+        match self.eat(kind) {
+            Some(span) => Ok(span),
+            // a missing ';' belongs right after the previous token
+            None if kind == PunctuatorKind::Semi && self.idx > 0 => {
+                let error = *self.error_expected(&format!("{:?}", kind.as_str()));
+                Err(Box::new(error.with_fixit(
+                    self.prev_span().end(),
+                    ";",
+                    "insert ';'",
+                )))
+            }
+            None => Err(self.error_expected(&format!("{:?}", kind.as_str()))),
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<Identifier> {
+        match self.peek() {
+            Some(Token::Identifier(_)) => match self.bump() {
+                Some(Token::Identifier(ident)) => Ok(ident),
+                _ => unreachable!(),
+            },
+            _ => Err(self.error_expected("identifier")),
+        }
+    }
+
+    fn expect_string_literal(&mut self) -> Result<StringLiteral> {
+        match self.peek() {
+            Some(Token::StringLiteral(_)) => match self.bump() {
+                Some(Token::StringLiteral(s)) => Ok(s),
+                _ => unreachable!(),
+            },
+            _ => Err(self.error_expected("string literal")),
+        }
+    }
+
+    fn error_expected(&self, which: &str) -> Box<Diagnostic> {
+        let found = match self.peek() {
+            None => "end of file".into(),
+            Some(token) => describe_token(token),
+        };
+        let msg = format!("expected {}, found {}", which, found);
+        Box::new(Diagnostic::error(self.cur_span(), msg).with_code(Code::ExpectedToken))
+    }
+
+    // skips to the end of the current statement or declaration
+    fn synchronize(&mut self) {
+        let mut depth = 0_usize;
+        while let Some(token) = self.peek() {
+            match token.kind() {
+                TokenKind::Punctuator(PunctuatorKind::Semi) if depth == 0 => {
+                    self.bump();
+                    return;
+                }
+                TokenKind::Punctuator(PunctuatorKind::LBrace) => depth += 1,
+                TokenKind::Punctuator(PunctuatorKind::RBrace) => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        self.bump();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+}
+
+fn punct_kind(token: &Token) -> Option<PunctuatorKind> {
+    match token.kind() {
+        TokenKind::Punctuator(kind) => Some(kind),
+        _ => None,
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Identifier(ident) => format!("identifier {:?}", ident.value),
+        Token::Keyword(kw) => format!("keyword {:?}", kw.kind.as_str()),
+        Token::Constant(_) => "constant".into(),
+        Token::StringLiteral(_) => "string literal".into(),
+        Token::Punctuator(p) => format!("{:?}", written(p.span, p.spelling)),
+        Token::Operator(op) => format!("{:?}", written(op.span, op.spelling)),
+        Token::Directive(d) => format!("directive {:?}", d.name),
+        Token::Error(e) => format!("invalid token {:?}", e.literal),
+    }
+}
+
+// a punctuator as the user wrote it, which differs from its spelling by the
+// trigraphs replaced in translation phase 1
+fn written(span: Span, spelling: &str) -> String {
+    let text = match span.source_text() {
+        Some(text) => text,
+        None => return spelling.into(),
+    };
+    let mut chars = CharStream::at(text.as_str(), 0);
+    chars.set_trigraphs(true);
+    match chars.eq(spelling.chars()) {
+        true => text,
+        // a pasted token, whose span covers the operands and the `##`
+        false => spelling.into(),
+    }
+}
+
+// scopes
+impl Parser {
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: Symbol, is_typedef: bool) {
+        self.scopes.last_mut().unwrap().insert(name, is_typedef);
+    }
+
+    fn is_typedef_name(&self, name: Symbol) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if let Some(&is_typedef) = scope.get(&name) {
+                return is_typedef;
+            }
+        }
+        false
+    }
+
+    fn is_type_name_start_nth(&self, n: usize) -> bool {
+        match self.peek_nth(n) {
+            Some(Token::Keyword(kw)) => {
+                TYPE_SPECIFIER_KEYWORDS.contains(&kw.kind)
+                    || TYPE_QUALIFIER_KEYWORDS.contains(&kw.kind)
+            }
+            Some(Token::Identifier(ident)) => self.is_typedef_name(ident.value),
+            _ => false,
+        }
+    }
+
+    fn is_declaration_start(&self) -> bool {
+        match self.peek() {
+            Some(Token::Keyword(kw)) => {
+                STORAGE_CLASS_KEYWORDS.contains(&kw.kind)
+                    || FUNCTION_SPECIFIER_KEYWORDS.contains(&kw.kind)
+                    || self.is_type_name_start_nth(0)
+            }
+            Some(Token::Identifier(_)) => {
+                self.is_type_name_start_nth(0) && !self.is_punct_nth(1, PunctuatorKind::Colon)
+            }
+            _ => false,
+        }
+    }
+}
+
+static TYPE_SPECIFIER_KEYWORDS: [KeywordKind; 14] = [
+    KeywordKind::Void,
+    KeywordKind::Char,
+    KeywordKind::Short,
+    KeywordKind::Int,
+    KeywordKind::Long,
+    KeywordKind::Float,
+    KeywordKind::Double,
+    KeywordKind::Signed,
+    KeywordKind::Unsigned,
+    KeywordKind::Bool,
+    KeywordKind::Complex,
+    KeywordKind::Struct,
+    KeywordKind::Union,
+    KeywordKind::Enum,
+];
+
+static TYPE_QUALIFIER_KEYWORDS: [KeywordKind; 4] = [
+    KeywordKind::Const,
+    KeywordKind::Restrict,
+    KeywordKind::Volatile,
+    KeywordKind::Atomic,
+];
+
+static STORAGE_CLASS_KEYWORDS: [KeywordKind; 6] = [
+    KeywordKind::Typedef,
+    KeywordKind::Extern,
+    KeywordKind::Static,
+    KeywordKind::ThreadLocal,
+    KeywordKind::Auto,
+    KeywordKind::Register,
+];
+
+static FUNCTION_SPECIFIER_KEYWORDS: [KeywordKind; 2] = [KeywordKind::Inline, KeywordKind::Noreturn];
+
+// declarations
+impl Parser {
+    fn parse_external_decl(&mut self) -> Result<ExternalDecl> {
+        let specifiers = self.parse_decl_specifiers()?;
+
+        if let Some(end) = self.eat(PunctuatorKind::Semi) {
+            return Ok(ExternalDecl::Declaration(Declaration {
+                span: specifiers.span.to(end),
+                specifiers,
+                declarators: Vec::new(),
+            }));
+        }
+
+        let declarator = self.parse_declarator(false)?;
+
+        let is_function = matches!(
+            declarator.derived.first(),
+            Some(DerivedDeclarator::Function(_))
+        );
+        if is_function && self.is_punct(PunctuatorKind::LBrace) {
+            if let Some(name) = &declarator.name {
+                self.declare(name.value, false);
+            }
+            self.push_scope();
+            if let Some(DerivedDeclarator::Function(func)) = declarator.derived.first() {
+                for param in &func.params {
+                    if let Some(name) = &param.declarator.name {
+                        self.declare(name.value, false);
+                    }
+                }
+            }
+            let body = self.parse_compound_stmt_in_scope();
+            self.pop_scope();
+            let body = body?;
+            return Ok(ExternalDecl::Function(FunctionDef {
+                span: specifiers.span.to(body.span),
+                specifiers,
+                declarator,
+                body,
+            }));
+        }
+
+        let decl = self.parse_declaration_rest(specifiers, declarator)?;
+        Ok(ExternalDecl::Declaration(decl))
+    }
+
+    fn parse_declaration(&mut self) -> Result<Declaration> {
+        let specifiers = self.parse_decl_specifiers()?;
+        if let Some(end) = self.eat(PunctuatorKind::Semi) {
+            return Ok(Declaration {
+                span: specifiers.span.to(end),
+                specifiers,
+                declarators: Vec::new(),
+            });
+        }
+        let declarator = self.parse_declarator(false)?;
+        self.parse_declaration_rest(specifiers, declarator)
+    }
+
+    fn parse_declaration_rest(
+        &mut self,
+        specifiers: DeclSpecifiers,
+        first: Declarator,
+    ) -> Result<Declaration> {
+        let is_typedef = specifiers.is_typedef();
+        let mut declarators = Vec::new();
+        let mut declarator = first;
+        loop {
+            if let Some(name) = &declarator.name {
+                self.declare(name.value, is_typedef);
+            }
+            let initializer = if self.eat(PunctuatorKind::Eq).is_some() {
+                Some(self.parse_initializer()?)
+            } else {
+                None
+            };
+            let span = match &initializer {
+                Some(Initializer::Expr(e)) => declarator.span.to(e.span),
+                Some(Initializer::List(_, s)) => declarator.span.to(*s),
+                None => declarator.span,
+            };
+            declarators.push(InitDeclarator {
+                declarator,
+                initializer,
+                span,
+            });
+            if self.eat(PunctuatorKind::Comma).is_none() {
+                break;
+            }
+            declarator = self.parse_declarator(false)?;
+        }
+        let end = self.expect(PunctuatorKind::Semi)?;
+        Ok(Declaration {
+            span: specifiers.span.to(end),
+            specifiers,
+            declarators,
+        })
+    }
+
+    fn parse_decl_specifiers(&mut self) -> Result<DeclSpecifiers> {
+        let start = self.cur_span();
+        let mut specifiers = DeclSpecifiers {
+            storage: Vec::new(),
+            qualifiers: Vec::new(),
+            function: Vec::new(),
+            types: Vec::new(),
+            span: start,
+        };
+        let mut end = start;
+
+        loop {
+            let kw = match self.peek() {
+                Some(Token::Keyword(kw)) => kw.kind,
+                Some(Token::Identifier(ident))
+                    if specifiers.types.is_empty() && self.is_typedef_name(ident.value) =>
+                {
+                    let ident = self.expect_ident()?;
+                    end = ident.span;
+                    specifiers.types.push(TypeSpecifier::TypedefName(ident));
+                    continue;
+                }
+                _ => break,
+            };
+
+            let storage = match kw {
+                KeywordKind::Typedef => Some(StorageClass::Typedef),
+                KeywordKind::Extern => Some(StorageClass::Extern),
+                KeywordKind::Static => Some(StorageClass::Static),
+                KeywordKind::ThreadLocal => Some(StorageClass::ThreadLocal),
+                KeywordKind::Auto => Some(StorageClass::Auto),
+                KeywordKind::Register => Some(StorageClass::Register),
+                _ => None,
+            };
+            if let Some(storage) = storage {
+                end = self.bump().unwrap().span();
+                specifiers.storage.push(storage);
+                continue;
+            }
+
+            if let Some(qualifier) = type_qualifier(kw) {
+                end = self.bump().unwrap().span();
+                specifiers.qualifiers.push(qualifier);
+                continue;
+            }
+
+            let function = match kw {
+                KeywordKind::Inline => Some(FunctionSpecifier::Inline),
+                KeywordKind::Noreturn => Some(FunctionSpecifier::Noreturn),
+                _ => None,
+            };
+            if let Some(function) = function {
+                end = self.bump().unwrap().span();
+                specifiers.function.push(function);
+                continue;
+            }
+
+            let ty = match kw {
+                KeywordKind::Void => TypeSpecifier::Void,
+                KeywordKind::Char => TypeSpecifier::Char,
+                KeywordKind::Short => TypeSpecifier::Short,
+                KeywordKind::Int => TypeSpecifier::Int,
+                KeywordKind::Long => TypeSpecifier::Long,
+                KeywordKind::Float => TypeSpecifier::Float,
+                KeywordKind::Double => TypeSpecifier::Double,
+                KeywordKind::Signed => TypeSpecifier::Signed,
+                KeywordKind::Unsigned => TypeSpecifier::Unsigned,
+                KeywordKind::Bool => TypeSpecifier::Bool,
+                KeywordKind::Complex => TypeSpecifier::Complex,
+                KeywordKind::Struct | KeywordKind::Union => {
+                    let s = self.parse_struct_specifier()?;
+                    end = s.span;
+                    specifiers.types.push(TypeSpecifier::Struct(s));
+                    continue;
+                }
+                KeywordKind::Enum => {
+                    let e = self.parse_enum_specifier()?;
+                    end = e.span;
+                    specifiers.types.push(TypeSpecifier::Enum(e));
+                    continue;
+                }
+                _ => break,
+            };
+            end = self.bump().unwrap().span();
+            specifiers.types.push(ty);
+        }
+
+        if specifiers.storage.is_empty()
+            && specifiers.qualifiers.is_empty()
+            && specifiers.function.is_empty()
+            && specifiers.types.is_empty()
+        {
+            return Err(self.error_expected("declaration specifiers"));
+        }
+
+        specifiers.span = start.to(end);
+        Ok(specifiers)
+    }
+
+    fn parse_struct_specifier(&mut self) -> Result<StructSpecifier> {
+        let start = self.cur_span();
+        let kind = if self.eat_keyword(KeywordKind::Struct).is_some() {
+            StructKind::Struct
+        } else {
+            self.eat_keyword(KeywordKind::Union);
+            StructKind::Union
+        };
+
+        let tag = match self.peek() {
+            Some(Token::Identifier(_)) => Some(self.expect_ident()?),
+            _ => None,
+        };
+
+        if self.eat(PunctuatorKind::LBrace).is_none() {
+            return match tag {
+                Some(tag) => Ok(StructSpecifier {
+                    kind,
+                    span: start.to(tag.span),
+                    tag: Some(tag),
+                    fields: None,
+                }),
+                None => Err(self.error_expected("'{'")),
+            };
+        }
+
+        let mut fields = Vec::new();
+        while self.eat(PunctuatorKind::RBrace).is_none() {
+            let specifiers = self.parse_decl_specifiers()?;
+            let mut declarators = Vec::new();
+            if !self.is_punct(PunctuatorKind::Semi) {
+                loop {
+                    let declarator = if self.is_punct(PunctuatorKind::Colon) {
+                        None
+                    } else {
+                        Some(self.parse_declarator(false)?)
+                    };
+                    let bit_width = if self.eat(PunctuatorKind::Colon).is_some() {
+                        Some(self.parse_conditional()?)
+                    } else {
+                        None
+                    };
+                    let span = match (&declarator, &bit_width) {
+                        (Some(d), Some(w)) => d.span.to(w.span),
+                        (Some(d), None) => d.span,
+                        (None, Some(w)) => w.span,
+                        (None, None) => unreachable!(),
+                    };
+                    declarators.push(StructDeclarator {
+                        declarator,
+                        bit_width,
+                        span,
+                    });
+                    if self.eat(PunctuatorKind::Comma).is_none() {
+                        break;
+                    }
+                }
+            }
+            let end = self.expect(PunctuatorKind::Semi)?;
+            fields.push(StructField {
+                span: specifiers.span.to(end),
+                specifiers,
+                declarators,
+            });
+        }
+
+        Ok(StructSpecifier {
+            kind,
+            tag,
+            fields: Some(fields),
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    fn parse_enum_specifier(&mut self) -> Result<EnumSpecifier> {
+        let start = self.cur_span();
+        self.eat_keyword(KeywordKind::Enum);
+
+        let tag = match self.peek() {
+            Some(Token::Identifier(_)) => Some(self.expect_ident()?),
+            _ => None,
+        };
+
+        if self.eat(PunctuatorKind::LBrace).is_none() {
+            return match tag {
+                Some(tag) => Ok(EnumSpecifier {
+                    span: start.to(tag.span),
+                    tag: Some(tag),
+                    enumerators: None,
+                }),
+                None => Err(self.error_expected("'{'")),
+            };
+        }
+
+        let mut enumerators = Vec::new();
+        while self.eat(PunctuatorKind::RBrace).is_none() {
+            let name = self.expect_ident()?;
+            self.declare(name.value, false);
+            let value = if self.eat(PunctuatorKind::Eq).is_some() {
+                Some(self.parse_conditional()?)
+            } else {
+                None
+            };
+            let span = match &value {
+                Some(v) => name.span.to(v.span),
+                None => name.span,
+            };
+            enumerators.push(Enumerator { name, value, span });
+            if self.eat(PunctuatorKind::Comma).is_none() {
+                self.expect(PunctuatorKind::RBrace)?;
+                break;
+            }
+        }
+
+        Ok(EnumSpecifier {
+            tag,
+            enumerators: Some(enumerators),
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    fn parse_pointers(&mut self) -> Vec<(DerivedDeclarator, Span)> {
+        let mut pointers = Vec::new();
+        while let Some(span) = self.eat(PunctuatorKind::Star) {
+            let mut qualifiers = Vec::new();
+            while let Some(Token::Keyword(kw)) = self.peek() {
+                match type_qualifier(kw.kind) {
+                    Some(q) => {
+                        qualifiers.push(q);
+                        self.bump();
+                    }
+                    None => break,
+                }
+            }
+            pointers.push((DerivedDeclarator::Pointer(qualifiers), span));
+        }
+        pointers
+    }
+
+    // parses both concrete and abstract declarators
+    fn parse_declarator(&mut self, is_abstract: bool) -> Result<Declarator> {
+        let start = self.cur_span();
+        let pointers = self.parse_pointers();
+
+        let mut name = None;
+        let mut derived = Vec::new();
+        let mut has_direct = false;
+
+        match self.peek() {
+            Some(Token::Identifier(_)) => {
+                name = Some(self.expect_ident()?);
+                has_direct = true;
+            }
+            Some(_)
+                if self.is_punct(PunctuatorKind::LParen)
+                    && self.is_nested_declarator(is_abstract) =>
+            {
+                self.bump();
+                let inner = self.parse_declarator(is_abstract)?;
+                self.expect(PunctuatorKind::RParen)?;
+                name = inner.name;
+                derived = inner.derived;
+                has_direct = true;
+            }
+            _ => {
+                if !is_abstract {
+                    return Err(self.error_expected("identifier"));
+                }
+            }
+        }
+
+        loop {
+            if self.eat(PunctuatorKind::LBracket).is_some() {
+                let size = if self.is_punct(PunctuatorKind::RBracket) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_assign()?))
+                };
+                self.expect(PunctuatorKind::RBracket)?;
+                derived.push(DerivedDeclarator::Array(size));
+            } else if self.is_punct(PunctuatorKind::LParen) {
+                self.bump();
+                let func = self.parse_param_list()?;
+                derived.push(DerivedDeclarator::Function(func));
+            } else {
+                break;
+            }
+            has_direct = true;
+        }
+
+        let has_pointers = !pointers.is_empty();
+        derived.extend(pointers.into_iter().rev().map(|(p, _)| p));
+
+        let span = if has_direct || has_pointers {
+            start.to(self.prev_span())
+        } else {
+            start
+        };
+
+        Ok(Declarator {
+            name,
+            derived,
+            span,
+        })
+    }
+
+    fn is_nested_declarator(&self, is_abstract: bool) -> bool {
+        if !is_abstract {
+            return true;
+        }
+        // in an abstract declarator, `(` starts a nested declarator only
+        // if it is not the start of a parameter list
+        self.is_punct_nth(1, PunctuatorKind::Star)
+            || self.is_punct_nth(1, PunctuatorKind::LParen)
+            || self.is_punct_nth(1, PunctuatorKind::LBracket)
+    }
+
+    // the opening parenthesis has been consumed
+    fn parse_param_list(&mut self) -> Result<FunctionDeclarator> {
+        let mut params = Vec::new();
+        let mut variadic = false;
+
+        if self.eat(PunctuatorKind::RParen).is_some() {
+            return Ok(FunctionDeclarator { params, variadic });
+        }
+
+        if self.is_keyword(KeywordKind::Void) && self.is_punct_nth(1, PunctuatorKind::RParen) {
+            self.bump();
+            self.bump();
+            return Ok(FunctionDeclarator { params, variadic });
+        }
+
+        loop {
+            if self.eat(PunctuatorKind::Ellipsis).is_some() {
+                variadic = true;
+                break;
+            }
+            let specifiers = self.parse_decl_specifiers()?;
+            let declarator = self.parse_declarator(true)?;
+            params.push(ParamDecl {
+                span: specifiers.span.to(declarator.span),
+                specifiers,
+                declarator,
+            });
+            if self.eat(PunctuatorKind::Comma).is_none() {
+                break;
+            }
+        }
+        self.expect(PunctuatorKind::RParen)?;
+
+        Ok(FunctionDeclarator { params, variadic })
+    }
+
+    fn parse_type_name(&mut self) -> Result<TypeName> {
+        let specifiers = self.parse_decl_specifiers()?;
+        if !specifiers.storage.is_empty() || !specifiers.function.is_empty() {
+            return Err(Box::new(
+                Diagnostic::error(specifiers.span, "unexpected storage class in type name")
+                    .with_code(Code::InvalidTypeName),
+            ));
+        }
+        let declarator = self.parse_declarator(true)?;
+        if let Some(name) = &declarator.name {
+            return Err(Box::new(
+                Diagnostic::error(name.span, "unexpected identifier in type name")
+                    .with_code(Code::InvalidTypeName),
+            ));
+        }
+        Ok(TypeName {
+            span: specifiers.span.to(declarator.span),
+            specifiers,
+            declarator,
+        })
+    }
+
+    fn parse_initializer(&mut self) -> Result<Initializer> {
+        let start = match self.eat(PunctuatorKind::LBrace) {
+            None => return Ok(Initializer::Expr(self.parse_assign()?)),
+            Some(span) => span,
+        };
+
+        let mut items = Vec::new();
+        while self.eat(PunctuatorKind::RBrace).is_none() {
+            let mut designators = Vec::new();
+            loop {
+                if self.eat(PunctuatorKind::LBracket).is_some() {
+                    designators.push(Designator::Index(self.parse_conditional()?));
+                    self.expect(PunctuatorKind::RBracket)?;
+                } else if self.eat(PunctuatorKind::Dot).is_some() {
+                    designators.push(Designator::Member(self.expect_ident()?));
+                } else {
+                    break;
+                }
+            }
+            if !designators.is_empty() {
+                self.expect(PunctuatorKind::Eq)?;
+            }
+            let init = self.parse_initializer()?;
+            items.push(InitItem { designators, init });
+            if self.eat(PunctuatorKind::Comma).is_none() {
+                self.expect(PunctuatorKind::RBrace)?;
+                break;
+            }
+        }
+
+        Ok(Initializer::List(items, start.to(self.prev_span())))
+    }
+}
+
+fn type_qualifier(kw: KeywordKind) -> Option<TypeQualifier> {
+    match kw {
+        KeywordKind::Const => Some(TypeQualifier::Const),
+        KeywordKind::Restrict => Some(TypeQualifier::Restrict),
+        KeywordKind::Volatile => Some(TypeQualifier::Volatile),
+        KeywordKind::Atomic => Some(TypeQualifier::Atomic),
+        _ => None,
+    }
+}
+
+// statements
+impl Parser {
+    fn parse_compound_stmt(&mut self) -> Result<CompoundStmt> {
+        self.push_scope();
+        let ret = self.parse_compound_stmt_in_scope();
+        self.pop_scope();
+        ret
+    }
+
+    fn parse_compound_stmt_in_scope(&mut self) -> Result<CompoundStmt> {
+        let start = self.expect(PunctuatorKind::LBrace)?;
+        let mut items = Vec::new();
+        loop {
+            if let Some(end) = self.eat(PunctuatorKind::RBrace) {
+                return Ok(CompoundStmt {
+                    items,
+                    span: start.to(end),
+                });
+            }
+            if self.peek().is_none() {
+                let error = Diagnostic::error(start, "unclosed block");
+                return Err(Box::new(error.with_code(Code::UnbalancedBrace)));
+            }
+            let item = if self.is_declaration_start() {
+                self.parse_declaration().map(BlockItem::Declaration)
+            } else {
+                self.parse_stmt().map(BlockItem::Stmt)
+            };
+            match item {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    self.errors.push(*e);
+                    self.synchronize();
+                }
+            }
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt> {
+        let start = self.cur_span();
+
+        if self.is_punct(PunctuatorKind::LBrace) {
+            let block = self.parse_compound_stmt()?;
+            return Ok(Stmt {
+                span: block.span,
+                kind: StmtKind::Compound(block),
+            });
+        }
+
+        if let Some(Token::Identifier(_)) = self.peek() {
+            if self.is_punct_nth(1, PunctuatorKind::Colon) {
+                let label = self.expect_ident()?;
+                self.bump();
+                let body = self.parse_stmt()?;
+                return Ok(Stmt {
+                    span: start.to(body.span),
+                    kind: StmtKind::Labeled(label, Box::new(body)),
+                });
+            }
+        }
+
+        let kw = match self.peek() {
+            Some(Token::Keyword(kw)) => Some(kw.kind),
+            _ => None,
+        };
+
+        let kind = match kw {
+            Some(KeywordKind::If) => {
+                self.bump();
+                self.expect(PunctuatorKind::LParen)?;
+                let cond = self.parse_expr()?;
+                self.expect(PunctuatorKind::RParen)?;
+                let then = self.parse_stmt()?;
+                let otherwise = if self.eat_keyword(KeywordKind::Else).is_some() {
+                    Some(Box::new(self.parse_stmt()?))
+                } else {
+                    None
+                };
+                StmtKind::If(cond, Box::new(then), otherwise)
+            }
+            Some(KeywordKind::While) => {
+                self.bump();
+                self.expect(PunctuatorKind::LParen)?;
+                let cond = self.parse_expr()?;
+                self.expect(PunctuatorKind::RParen)?;
+                let body = self.parse_stmt()?;
+                StmtKind::While(cond, Box::new(body))
+            }
+            Some(KeywordKind::Do) => {
+                self.bump();
+                let body = self.parse_stmt()?;
+                if self.eat_keyword(KeywordKind::While).is_none() {
+                    return Err(self.error_expected("'while'"));
+                }
+                self.expect(PunctuatorKind::LParen)?;
+                let cond = self.parse_expr()?;
+                self.expect(PunctuatorKind::RParen)?;
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::DoWhile(Box::new(body), cond)
+            }
+            Some(KeywordKind::For) => {
+                self.bump();
+                self.expect(PunctuatorKind::LParen)?;
+                self.push_scope();
+                let ret = self.parse_for_rest();
+                self.pop_scope();
+                let (init, cond, step, body) = ret?;
+                StmtKind::For(init, cond, step, Box::new(body))
+            }
+            Some(KeywordKind::Switch) => {
+                self.bump();
+                self.expect(PunctuatorKind::LParen)?;
+                let cond = self.parse_expr()?;
+                self.expect(PunctuatorKind::RParen)?;
+                let body = self.parse_stmt()?;
+                StmtKind::Switch(cond, Box::new(body))
+            }
+            Some(KeywordKind::Case) => {
+                self.bump();
+                let value = self.parse_conditional()?;
+                self.expect(PunctuatorKind::Colon)?;
+                let body = self.parse_stmt()?;
+                StmtKind::Case(value, Box::new(body))
+            }
+            Some(KeywordKind::Default) => {
+                self.bump();
+                self.expect(PunctuatorKind::Colon)?;
+                let body = self.parse_stmt()?;
+                StmtKind::Default(Box::new(body))
+            }
+            Some(KeywordKind::Goto) => {
+                self.bump();
+                let label = self.expect_ident()?;
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::Goto(label)
+            }
+            Some(KeywordKind::Continue) => {
+                self.bump();
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::Continue
+            }
+            Some(KeywordKind::Break) => {
+                self.bump();
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::Break
+            }
+            Some(KeywordKind::Return) => {
+                self.bump();
+                let value = if self.is_punct(PunctuatorKind::Semi) {
+                    None
+                } else {
+                    Some(self.parse_expr()?)
+                };
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::Return(value)
+            }
+            _ => {
+                let expr = if self.is_punct(PunctuatorKind::Semi) {
+                    None
+                } else {
+                    Some(self.parse_expr()?)
+                };
+                self.expect(PunctuatorKind::Semi)?;
+                StmtKind::Expr(expr)
+            }
+        };
+
+        Ok(Stmt {
+            kind,
+            span: start.to(self.prev_span()),
+        })
+    }
+
+    fn parse_for_rest(&mut self) -> Result<(ForInit, Option<Expr>, Option<Expr>, Stmt)> {
+        let init = if self.eat(PunctuatorKind::Semi).is_some() {
+            ForInit::Empty
+        } else if self.is_declaration_start() {
+            ForInit::Declaration(self.parse_declaration()?)
+        } else {
+            let expr = self.parse_expr()?;
+            self.expect(PunctuatorKind::Semi)?;
+            ForInit::Expr(expr)
+        };
+        let cond = if self.is_punct(PunctuatorKind::Semi) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        self.expect(PunctuatorKind::Semi)?;
+        let step = if self.is_punct(PunctuatorKind::RParen) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        self.expect(PunctuatorKind::RParen)?;
+        let body = self.parse_stmt()?;
+        Ok((init, cond, step, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::source_file::SourceFile;
+
+    fn parse(src: &str) -> (TranslationUnit, Vec<Diagnostic>) {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        Parser::from_tokens(tokens).parse()
+    }
+
+    fn parse_ok(src: &str) -> TranslationUnit {
+        let (unit, errors) = parse(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        unit
+    }
+
+    fn first_function(unit: &TranslationUnit) -> &FunctionDef {
+        match &unit.items[0] {
+            ExternalDecl::Function(f) => f,
+            _ => panic!("expected function"),
+        }
+    }
+
+    #[test]
+    fn function_definition() {
+        let unit = parse_ok(
+            "int main(int argc, char const *argv[])\n{\n\tchar *str = \"s\";\n\treturn 0;\n}",
+        );
+        assert_eq!(unit.items.len(), 1);
+        let f = first_function(&unit);
+        assert_eq!(f.declarator.name.as_ref().unwrap().value, "main");
+        match &f.declarator.derived[0] {
+            DerivedDeclarator::Function(func) => {
+                assert_eq!(func.params.len(), 2);
+                assert!(matches!(
+                    func.params[1].declarator.derived.as_slice(),
+                    [
+                        DerivedDeclarator::Array(None),
+                        DerivedDeclarator::Pointer(_)
+                    ]
+                ));
+            }
+            _ => panic!("expected function declarator"),
+        }
+        assert_eq!(f.body.items.len(), 2);
+        assert_eq!(f.span.lc_range().start.line, 1);
+        assert_eq!(f.span.lc_range().end.line, 5);
+    }
+
+    #[test]
+    fn declarators() {
+        let unit = parse_ok("int (*fp)(int), *a[3];");
+        let decl = match &unit.items[0] {
+            ExternalDecl::Declaration(d) => d,
+            _ => panic!("expected declaration"),
+        };
+        assert!(matches!(
+            decl.declarators[0].declarator.derived.as_slice(),
+            [
+                DerivedDeclarator::Pointer(_),
+                DerivedDeclarator::Function(_)
+            ]
+        ));
+        assert!(matches!(
+            decl.declarators[1].declarator.derived.as_slice(),
+            [
+                DerivedDeclarator::Array(Some(_)),
+                DerivedDeclarator::Pointer(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn typedef_names() {
+        let unit = parse_ok("typedef int T; int f(void) { T x; (T)x; return x * 2; }");
+        let f = match &unit.items[1] {
+            ExternalDecl::Function(f) => f,
+            _ => panic!("expected function"),
+        };
+        assert!(matches!(f.body.items[0], BlockItem::Declaration(_)));
+        match &f.body.items[1] {
+            BlockItem::Stmt(Stmt {
+                kind: StmtKind::Expr(Some(e)),
+                ..
+            }) => assert!(matches!(e.kind, ExprKind::Cast(..))),
+            _ => panic!("expected cast"),
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let unit = parse_ok(
+            "void f() { for(int i=1;i<6;i++){ if (i) continue; else break; } \
+             while (1) ; do { } while (0); switch (1) { case 1: default: ; } \
+             l: goto l; }",
+        );
+        assert_eq!(first_function(&unit).body.items.len(), 5);
+    }
+
+    #[test]
+    fn expression_precedence() {
+        let unit = parse_ok("int x = 1 + 2 * 3 < 4 && 5;");
+        let decl = match &unit.items[0] {
+            ExternalDecl::Declaration(d) => d,
+            _ => panic!("expected declaration"),
+        };
+        let init = match &decl.declarators[0].initializer {
+            Some(Initializer::Expr(e)) => e,
+            _ => panic!("expected initializer"),
+        };
+        match &init.kind {
+            ExprKind::Binary(BinaryOp::And, lhs, _) => {
+                assert!(matches!(lhs.kind, ExprKind::Binary(BinaryOp::Lt, _, _)))
+            }
+            _ => panic!("expected &&"),
+        }
+        assert_eq!(init.span.byte_range(), 8..26);
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (unit, errors) = parse("int f() { int x = ; return 0; } int g;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "expected expression, found \";\"");
+        assert_eq!(unit.items.len(), 2);
+
+        // as written, not as replaced in translation phase 1
+        let src = SourceFile::new("int a = 1 ??) ;", "<dummy file>");
+        let (tokens, _) = Lexer::from_src(src).with_trigraphs(true).resolve();
+        let (_, errors) = Parser::from_tokens(tokens).parse();
+        assert_eq!(errors[0].msg, "expected \";\", found \"??)\"");
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let (_, errors) = parse("int x = 1\nint y;");
+        assert_eq!(errors[0].code, Some(Code::ExpectedToken));
+        let fixit = &errors[0].fixits[0];
+        assert_eq!(fixit.replacement, ";");
+        assert_eq!(fixit.span.byte_range(), 9..9);
+    }
+
+    #[test]
+    fn type_names() {
+        let (_, errors) = parse("int x = sizeof(int static);\nint y = (int z)1;");
+        let found: Vec<_> = errors.iter().map(|e| (e.code, e.msg.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    Some(Code::InvalidTypeName),
+                    "unexpected storage class in type name"
+                ),
+                (
+                    Some(Code::InvalidTypeName),
+                    "unexpected identifier in type name"
+                )
+            ]
+        );
+    }
+}
@@ -0,0 +1,613 @@
+use super::{punct_kind, Parser};
+use crate::{
+    ast::*,
+    diagnostics::{Code, Diagnostic},
+    tokens::{KeywordKind, PunctuatorKind, Token},
+};
+
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Prec {
+    Comma,
+    Assign,
+    Conditional,
+    LogicalOr,
+    LogicalAnd,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equality,
+    Relational,
+    Shift,
+    Additive,
+    Multiplicative,
+    Prefix,
+    Postfix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Infix {
+    Binary(BinaryOp),
+    Assign(AssignOp),
+    Conditional,
+    Comma,
+}
+
+impl Prec {
+    fn next(self) -> Self {
+        match self {
+            Prec::Comma => Prec::Assign,
+            Prec::Assign => Prec::Conditional,
+            Prec::Conditional => Prec::LogicalOr,
+            Prec::LogicalOr => Prec::LogicalAnd,
+            Prec::LogicalAnd => Prec::BitOr,
+            Prec::BitOr => Prec::BitXor,
+            Prec::BitXor => Prec::BitAnd,
+            Prec::BitAnd => Prec::Equality,
+            Prec::Equality => Prec::Relational,
+            Prec::Relational => Prec::Shift,
+            Prec::Shift => Prec::Additive,
+            Prec::Additive => Prec::Multiplicative,
+            Prec::Multiplicative => Prec::Prefix,
+            Prec::Prefix | Prec::Postfix => Prec::Postfix,
+        }
+    }
+}
+
+// `?` is the infix part of the ternary conditional `?:`
+pub static INFIX_TABLE: [(PunctuatorKind, Infix, Prec, Assoc); 31] = [
+    (
+        PunctuatorKind::Comma,
+        Infix::Comma,
+        Prec::Comma,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Eq,
+        Infix::Assign(AssignOp::Assign),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::StarEq,
+        Infix::Assign(AssignOp::Mul),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::SlashEq,
+        Infix::Assign(AssignOp::Div),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::PercentEq,
+        Infix::Assign(AssignOp::Mod),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::PlusEq,
+        Infix::Assign(AssignOp::Add),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::MinusEq,
+        Infix::Assign(AssignOp::Sub),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::ShlEq,
+        Infix::Assign(AssignOp::Shl),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::ShrEq,
+        Infix::Assign(AssignOp::Shr),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::AmpEq,
+        Infix::Assign(AssignOp::BitAnd),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::CaretEq,
+        Infix::Assign(AssignOp::BitXor),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::PipeEq,
+        Infix::Assign(AssignOp::BitOr),
+        Prec::Assign,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::Question,
+        Infix::Conditional,
+        Prec::Conditional,
+        Assoc::Right,
+    ),
+    (
+        PunctuatorKind::PipePipe,
+        Infix::Binary(BinaryOp::Or),
+        Prec::LogicalOr,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::AmpAmp,
+        Infix::Binary(BinaryOp::And),
+        Prec::LogicalAnd,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Pipe,
+        Infix::Binary(BinaryOp::BitOr),
+        Prec::BitOr,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Caret,
+        Infix::Binary(BinaryOp::BitXor),
+        Prec::BitXor,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Amp,
+        Infix::Binary(BinaryOp::BitAnd),
+        Prec::BitAnd,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::EqEq,
+        Infix::Binary(BinaryOp::Eq),
+        Prec::Equality,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Ne,
+        Infix::Binary(BinaryOp::Ne),
+        Prec::Equality,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Lt,
+        Infix::Binary(BinaryOp::Lt),
+        Prec::Relational,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Gt,
+        Infix::Binary(BinaryOp::Gt),
+        Prec::Relational,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Le,
+        Infix::Binary(BinaryOp::Le),
+        Prec::Relational,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Ge,
+        Infix::Binary(BinaryOp::Ge),
+        Prec::Relational,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Shl,
+        Infix::Binary(BinaryOp::Shl),
+        Prec::Shift,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Shr,
+        Infix::Binary(BinaryOp::Shr),
+        Prec::Shift,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Plus,
+        Infix::Binary(BinaryOp::Add),
+        Prec::Additive,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Minus,
+        Infix::Binary(BinaryOp::Sub),
+        Prec::Additive,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Star,
+        Infix::Binary(BinaryOp::Mul),
+        Prec::Multiplicative,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Slash,
+        Infix::Binary(BinaryOp::Div),
+        Prec::Multiplicative,
+        Assoc::Left,
+    ),
+    (
+        PunctuatorKind::Percent,
+        Infix::Binary(BinaryOp::Mod),
+        Prec::Multiplicative,
+        Assoc::Left,
+    ),
+];
+
+pub static PREFIX_TABLE: [(PunctuatorKind, UnaryOp); 8] = [
+    (PunctuatorKind::PlusPlus, UnaryOp::PreInc),
+    (PunctuatorKind::MinusMinus, UnaryOp::PreDec),
+    (PunctuatorKind::Amp, UnaryOp::Address),
+    (PunctuatorKind::Star, UnaryOp::Deref),
+    (PunctuatorKind::Plus, UnaryOp::Plus),
+    (PunctuatorKind::Minus, UnaryOp::Minus),
+    (PunctuatorKind::Tilde, UnaryOp::BitNot),
+    (PunctuatorKind::Bang, UnaryOp::Not),
+];
+
+pub static POSTFIX_TABLE: [PunctuatorKind; 6] = [
+    PunctuatorKind::LBracket,
+    PunctuatorKind::LParen,
+    PunctuatorKind::Dot,
+    PunctuatorKind::Arrow,
+    PunctuatorKind::PlusPlus,
+    PunctuatorKind::MinusMinus,
+];
+
+fn infix_info(kind: PunctuatorKind) -> Option<(Infix, Prec, Assoc)> {
+    INFIX_TABLE
+        .iter()
+        .find(|&&(k, ..)| k == kind)
+        .map(|&(_, infix, prec, assoc)| (infix, prec, assoc))
+}
+
+impl Parser {
+    pub(super) fn parse_expr(&mut self) -> Result<Expr> {
+        self.parse_expr_bp(Prec::Comma)
+    }
+
+    pub(super) fn parse_assign(&mut self) -> Result<Expr> {
+        self.parse_expr_bp(Prec::Assign)
+    }
+
+    pub(super) fn parse_conditional(&mut self) -> Result<Expr> {
+        self.parse_expr_bp(Prec::Conditional)
+    }
+
+    // parses an expression whose infix operators bind at least as tight as `min_prec`
+    fn parse_expr_bp(&mut self, min_prec: Prec) -> Result<Expr> {
+        let mut lhs = self.parse_cast()?;
+        loop {
+            let info = self.peek().and_then(punct_kind).and_then(infix_info);
+            let (infix, prec, assoc) = match info {
+                Some(info) if info.1 >= min_prec => info,
+                _ => return Ok(lhs),
+            };
+            self.bump();
+
+            let rhs_prec = match assoc {
+                Assoc::Left => prec.next(),
+                Assoc::Right => prec,
+            };
+
+            lhs = match infix {
+                Infix::Conditional => {
+                    let then = self.parse_expr_bp(Prec::Comma)?;
+                    self.expect(PunctuatorKind::Colon)?;
+                    let otherwise = self.parse_expr_bp(rhs_prec)?;
+                    let span = lhs.span.to(otherwise.span);
+                    let kind =
+                        ExprKind::Conditional(Box::new(lhs), Box::new(then), Box::new(otherwise));
+                    Expr::new(kind, span)
+                }
+                Infix::Assign(op) => {
+                    let rhs = self.parse_expr_bp(rhs_prec)?;
+                    let span = lhs.span.to(rhs.span);
+                    Expr::new(ExprKind::Assign(op, Box::new(lhs), Box::new(rhs)), span)
+                }
+                Infix::Binary(op) => {
+                    let rhs = self.parse_expr_bp(rhs_prec)?;
+                    let span = lhs.span.to(rhs.span);
+                    Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span)
+                }
+                Infix::Comma => {
+                    let rhs = self.parse_expr_bp(rhs_prec)?;
+                    let span = lhs.span.to(rhs.span);
+                    let exprs = match lhs.kind {
+                        ExprKind::Comma(mut exprs) => {
+                            exprs.push(rhs);
+                            exprs
+                        }
+                        kind => vec![Expr::new(kind, lhs.span), rhs],
+                    };
+                    Expr::new(ExprKind::Comma(exprs), span)
+                }
+            };
+        }
+    }
+
+    fn parse_cast(&mut self) -> Result<Expr> {
+        if self.is_punct(PunctuatorKind::LParen) && self.is_type_name_start_nth(1) {
+            let start = self.bump().unwrap().span();
+            let type_name = self.parse_type_name()?;
+            self.expect(PunctuatorKind::RParen)?;
+            let expr = self.parse_cast()?;
+            let span = start.to(expr.span);
+            return Ok(Expr::new(
+                ExprKind::Cast(Box::new(type_name), Box::new(expr)),
+                span,
+            ));
+        }
+        self.parse_unary()
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let start = self.cur_span();
+
+        if self.eat_keyword(KeywordKind::Sizeof).is_some() {
+            if self.is_punct(PunctuatorKind::LParen) && self.is_type_name_start_nth(1) {
+                self.bump();
+                let type_name = self.parse_type_name()?;
+                let end = self.expect(PunctuatorKind::RParen)?;
+                return Ok(Expr::new(
+                    ExprKind::SizeofType(Box::new(type_name)),
+                    start.to(end),
+                ));
+            }
+            let expr = self.parse_unary()?;
+            let span = start.to(expr.span);
+            return Ok(Expr::new(ExprKind::SizeofExpr(Box::new(expr)), span));
+        }
+
+        let op = self.peek().and_then(punct_kind).and_then(|kind| {
+            PREFIX_TABLE
+                .iter()
+                .find(|&&(k, _)| k == kind)
+                .map(|&(_, op)| op)
+        });
+
+        match op {
+            Some(op) => {
+                self.bump();
+                let operand = match op {
+                    UnaryOp::PreInc | UnaryOp::PreDec => self.parse_unary()?,
+                    _ => self.parse_cast()?,
+                };
+                let span = start.to(operand.span);
+                Ok(Expr::new(ExprKind::Unary(op, Box::new(operand)), span))
+            }
+            None => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            let kind = match self.peek().and_then(punct_kind) {
+                Some(kind) if POSTFIX_TABLE.contains(&kind) => kind,
+                _ => return Ok(expr),
+            };
+            expr = match kind {
+                PunctuatorKind::LBracket => {
+                    self.bump();
+                    let index = self.parse_expr()?;
+                    let end = self.expect(PunctuatorKind::RBracket)?;
+                    let span = expr.span.to(end);
+                    Expr::new(ExprKind::Index(Box::new(expr), Box::new(index)), span)
+                }
+                PunctuatorKind::LParen => {
+                    self.bump();
+                    let mut args = Vec::new();
+                    if !self.is_punct(PunctuatorKind::RParen) {
+                        loop {
+                            args.push(self.parse_assign()?);
+                            if self.eat(PunctuatorKind::Comma).is_none() {
+                                break;
+                            }
+                        }
+                    }
+                    let end = self.expect(PunctuatorKind::RParen)?;
+                    let span = expr.span.to(end);
+                    Expr::new(ExprKind::Call(Box::new(expr), args), span)
+                }
+                PunctuatorKind::Dot | PunctuatorKind::Arrow => {
+                    self.bump();
+                    let member = self.expect_ident()?;
+                    let span = expr.span.to(member.span);
+                    let kind = if kind == PunctuatorKind::Dot {
+                        ExprKind::Member(Box::new(expr), member)
+                    } else {
+                        ExprKind::Arrow(Box::new(expr), member)
+                    };
+                    Expr::new(kind, span)
+                }
+                PunctuatorKind::PlusPlus | PunctuatorKind::MinusMinus => {
+                    let end = self.bump().unwrap().span();
+                    let op = if kind == PunctuatorKind::PlusPlus {
+                        UnaryOp::PostInc
+                    } else {
+                        UnaryOp::PostDec
+                    };
+                    let span = expr.span.to(end);
+                    Expr::new(ExprKind::Unary(op, Box::new(expr)), span)
+                }
+                _ => return Ok(expr),
+            };
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Identifier(_)) => {
+                let ident = self.expect_ident()?;
+                let span = ident.span;
+                Ok(Expr::new(ExprKind::Identifier(ident), span))
+            }
+            Some(Token::Constant(_)) => match self.bump() {
+                Some(Token::Constant(c)) => {
+                    let span = c.span();
+                    Ok(Expr::new(ExprKind::Constant(c), span))
+                }
+                _ => unreachable!(),
+            },
+            Some(Token::StringLiteral(_)) => {
+                let mut s = self.expect_string_literal()?;
+                // translation phase 6: adjacent literals are one
+                while let Some(Token::StringLiteral(_)) = self.peek() {
+                    let next = self.expect_string_literal()?;
+                    s.concat(&next).map_err(|msg| {
+                        Diagnostic::error(next.span, msg).with_code(Code::UnsupportedConcatenation)
+                    })?;
+                }
+                let span = s.span;
+                Ok(Expr::new(ExprKind::StringLiteral(s), span))
+            }
+            _ if self.is_punct(PunctuatorKind::LParen) => {
+                let start = self.bump().unwrap().span();
+                let mut expr = self.parse_expr()?;
+                let end = self.expect(PunctuatorKind::RParen)?;
+                expr.span = start.to(end);
+                Ok(expr)
+            }
+            _ => Err(self.error_expected("expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::source_file::SourceFile;
+
+    fn parse_expr(src: &str) -> Expr {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mut parser = Parser::from_tokens(tokens);
+        let expr = parser.parse_expr().unwrap();
+        assert!(parser.peek().is_none());
+        expr
+    }
+
+    // renders the tree with explicit parentheses
+    fn show(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Identifier(ident) => ident.value.to_string(),
+            ExprKind::Constant(c) => Token::Constant(c.clone()).spelling(),
+            ExprKind::StringLiteral(s) => Token::StringLiteral(s.clone()).spelling(),
+            ExprKind::Unary(op, e) => format!("({:?} {})", op, show(e)),
+            ExprKind::Binary(op, l, r) => format!("({} {:?} {})", show(l), op, show(r)),
+            ExprKind::Assign(op, l, r) => format!("({} {:?}= {})", show(l), op, show(r)),
+            ExprKind::Conditional(c, t, e) => format!("({} ? {} : {})", show(c), show(t), show(e)),
+            ExprKind::Comma(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(show).collect();
+                format!("({})", exprs.join(", "))
+            }
+            ExprKind::Cast(_, e) => format!("(cast {})", show(e)),
+            ExprKind::SizeofExpr(e) => format!("(sizeof {})", show(e)),
+            ExprKind::SizeofType(_) => "(sizeof type)".into(),
+            ExprKind::Call(f, args) => {
+                let args: Vec<String> = args.iter().map(show).collect();
+                format!("{}({})", show(f), args.join(", "))
+            }
+            ExprKind::Index(a, i) => format!("{}[{}]", show(a), show(i)),
+            ExprKind::Member(e, m) => format!("{}.{}", show(e), m.value),
+            ExprKind::Arrow(e, m) => format!("{}->{}", show(e), m.value),
+        }
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(show(&parse_expr("a - b - c")), "((a Sub b) Sub c)");
+        assert_eq!(show(&parse_expr("a + b * c")), "(a Add (b Mul c))");
+        assert_eq!(
+            show(&parse_expr("a << b < c == d")),
+            "(((a Shl b) Lt c) Eq d)"
+        );
+        assert_eq!(
+            show(&parse_expr("a | b ^ c & d || e && f")),
+            "((a BitOr (b BitXor (c BitAnd d))) Or (e And f))"
+        );
+        assert_eq!(show(&parse_expr("a = b += c")), "(a Assign= (b Add= c))");
+        assert_eq!(
+            show(&parse_expr("a ? b : c ? d : e")),
+            "(a ? b : (c ? d : e))"
+        );
+        assert_eq!(show(&parse_expr("a ? b, c : d")), "(a ? (b, c) : d)");
+        assert_eq!(
+            show(&parse_expr("x = a ? b : c")),
+            "(x Assign= (a ? b : c))"
+        );
+        assert_eq!(
+            show(&parse_expr("a = 1, b = 2, c")),
+            "((a Assign= 1), (b Assign= 2), c)"
+        );
+    }
+
+    #[test]
+    fn prefix_and_postfix() {
+        assert_eq!(show(&parse_expr("-a * b")), "((Minus a) Mul b)");
+        assert_eq!(show(&parse_expr("*p++")), "(Deref (PostInc p))");
+        assert_eq!(show(&parse_expr("!a[i].x->y")), "(Not a[i].x->y)");
+        assert_eq!(show(&parse_expr("(int)a + b")), "((cast a) Add b)");
+        assert_eq!(show(&parse_expr("sizeof a * b")), "((sizeof a) Mul b)");
+        assert_eq!(
+            show(&parse_expr("sizeof(int) + f(a, b = 1)")),
+            "((sizeof type) Add f(a, (b Assign= 1)))"
+        );
+        assert_eq!(show(&parse_expr("(a + b) * c")), "((a Add b) Mul c)");
+    }
+
+    #[test]
+    fn string_concatenation() {
+        let expr = parse_expr(r#"f("a" "b", "c" L"d" "\xff")"#);
+        assert_eq!(show(&expr), r#"f("ab", L"cd\u00ff")"#);
+        match &expr.kind {
+            ExprKind::Call(_, args) => assert_eq!(args[1].span.byte_range(), 11..26),
+            _ => panic!("expected call"),
+        }
+
+        let src = SourceFile::new(r#"u"a" L"b""#, "<dummy file>");
+        let (tokens, _) = Lexer::from_src(src).resolve();
+        let error = Parser::from_tokens(tokens).parse_expr().unwrap_err();
+        assert_eq!(error.span.byte_range(), 5..9);
+        assert_eq!(error.code, Some(Code::UnsupportedConcatenation));
+    }
+
+    #[test]
+    fn spans_cover_subexpressions() {
+        let expr = parse_expr("6.4 <= 5.8");
+        assert_eq!(expr.span.byte_range(), 0..10);
+        assert_eq!(expr.span.lc_range().start.column, 1);
+        assert_eq!(expr.span.lc_range().end.column, 11);
+
+        let expr = parse_expr("x = (a + b) * -c");
+        match &expr.kind {
+            ExprKind::Assign(_, _, rhs) => {
+                assert_eq!(rhs.span.byte_range(), 4..16);
+                match &rhs.kind {
+                    ExprKind::Binary(_, lhs, _) => assert_eq!(lhs.span.byte_range(), 4..11),
+                    _ => panic!("expected binary expression"),
+                }
+            }
+            _ => panic!("expected assignment"),
+        }
+    }
+}
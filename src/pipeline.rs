@@ -0,0 +1,181 @@
+use crate::{
+    ast::TranslationUnit,
+    checker::{Checker, TypeTable},
+    diagnostics::Diagnostic,
+    parser::Parser,
+    preprocessor::Preprocessor,
+    resolver::Resolver,
+    source_file::SourceFile,
+    tokens::Token,
+};
+
+use std::path::PathBuf;
+
+// the passes of `compile`, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Preprocess,
+    Parse,
+    // name resolution, then type checking
+    Check,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    // searched before the built-in headers
+    pub include_paths: Vec<PathBuf>,
+    // as given to `-D`: `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    pub trigraphs: bool,
+    // the last pass to run
+    pub stop_after: Stage,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            include_paths: Vec::new(),
+            defines: Vec::new(),
+            trigraphs: false,
+            stop_after: Stage::Check,
+        }
+    }
+}
+
+// the headers of `include/`, part of the library so that it does not
+// depend on where it was built
+const BUILTIN_HEADERS: [(&str, &str); 1] = [("stdio.h", include_str!("../include/stdio.h"))];
+
+// what the last pass that ran produced
+#[derive(Debug)]
+pub enum Output {
+    Tokens(Vec<Token>),
+    Unit(TranslationUnit),
+    Checked(TranslationUnit, TypeTable),
+}
+
+#[derive(Debug)]
+pub struct Compilation {
+    pub output: Output,
+    // of every pass that ran, warnings included, in the order found
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Compilation {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+// expands `src` into the tokens the parser reads
+pub fn preprocess(src: SourceFile, options: &Options) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.set_trigraphs(options.trigraphs);
+    for path in &options.include_paths {
+        preprocessor.add_include_path(path);
+    }
+    for (name, text) in BUILTIN_HEADERS {
+        preprocessor.add_builtin_header(name, text);
+    }
+    for definition in &options.defines {
+        preprocessor.define(definition);
+    }
+    preprocessor.resolve(src)
+}
+
+// runs the passes up to `options.stop_after`, each only if the ones before
+// it found no errors
+pub fn compile(src: SourceFile, options: &Options) -> Compilation {
+    let (tokens, mut diagnostics) = preprocess(src, options);
+    let failed = |diagnostics: &[Diagnostic]| diagnostics.iter().any(Diagnostic::is_error);
+    if options.stop_after == Stage::Preprocess || failed(&diagnostics) {
+        return Compilation {
+            output: Output::Tokens(tokens),
+            diagnostics,
+        };
+    }
+
+    let (unit, errors) = Parser::from_tokens(tokens).parse();
+    diagnostics.extend(errors);
+    if options.stop_after == Stage::Parse || failed(&diagnostics) {
+        return Compilation {
+            output: Output::Unit(unit),
+            diagnostics,
+        };
+    }
+
+    diagnostics.extend(Resolver::new().resolve(&unit));
+    if failed(&diagnostics) {
+        return Compilation {
+            output: Output::Unit(unit),
+            diagnostics,
+        };
+    }
+    let checked = Checker::new().check(&unit);
+    diagnostics.extend(checked.warnings);
+    diagnostics.extend(checked.errors);
+    Compilation {
+        output: Output::Checked(unit, checked.types),
+        diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Constant;
+
+    fn compile_str(src: &str, options: &Options) -> Compilation {
+        compile(SourceFile::new(src, "<dummy file>"), options)
+    }
+
+    #[test]
+    fn stages() {
+        let src = "#define N 2\nint main(void) { return N; }\n";
+        let options = |stop_after| Options {
+            stop_after,
+            ..Options::default()
+        };
+
+        let result = compile_str(src, &options(Stage::Preprocess));
+        assert!(!result.has_errors());
+        match result.output {
+            Output::Tokens(tokens) => assert!(tokens
+                .iter()
+                .any(|t| matches!(t, Token::Constant(Constant::Int(int)) if int.value == 2))),
+            output => panic!("{:?}", output),
+        }
+        let result = compile_str(src, &options(Stage::Parse));
+        assert!(matches!(result.output, Output::Unit(_)));
+        let result = compile_str(src, &Options::default());
+        assert!(matches!(result.output, Output::Checked(..)));
+        assert!(result.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn builtin_headers() {
+        let src = "#include <stdio.h>\nint main(void) { return puts(NULL); }\n";
+        let result = compile_str(src, &Options::default());
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert!(matches!(result.output, Output::Checked(..)));
+    }
+
+    #[test]
+    fn stops_at_errors() {
+        let options = Options {
+            defines: vec!["T=int".into()],
+            ..Options::default()
+        };
+        let result = compile_str("T main(void) { return x; }", &options);
+        assert!(result.has_errors());
+        assert!(matches!(result.output, Output::Unit(_)));
+
+        let result = compile_str("T main(void) { return ; ", &options);
+        assert!(result.has_errors());
+        assert!(matches!(result.output, Output::Unit(_)));
+
+        let result = compile_str("#error stop\nint x;", &options);
+        assert!(result.has_errors());
+        assert!(matches!(result.output, Output::Tokens(_)));
+    }
+}
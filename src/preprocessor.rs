@@ -0,0 +1,1497 @@
+use crate::{
+    ast::*,
+    diagnostics::{Code, Diagnostic},
+    interner::Symbol,
+    lexer::Lexer,
+    parser::Parser,
+    source_file::SourceFile,
+    source_map,
+    span::*,
+    tokens::*,
+};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+#[derive(Debug, Clone)]
+struct Macro {
+    // `None` for object-like macros
+    params: Option<Vec<Symbol>>,
+    variadic: bool,
+    body: Vec<Token>,
+}
+
+// a token with the set of macro names it must not be expanded by
+#[derive(Debug, Clone)]
+struct PPToken {
+    token: Token,
+    hideset: HideSet,
+}
+
+// sorted, and shared by the tokens of an expansion: a chain of macros makes
+// a set per level, not one per token and level
+#[derive(Debug, Clone, Default)]
+struct HideSet(Rc<[Symbol]>);
+
+struct Conditional {
+    parent_active: bool,
+    active: bool,
+    taken: bool,
+    seen_else: bool,
+    span: Span,
+}
+
+struct FileState {
+    path: PathBuf,
+    // changed by `#line`, only used by `__FILE__` and `__LINE__`
+    presumed_path: Rc<str>,
+    line_delta: isize,
+    conds: Vec<Conditional>,
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    // headers compiled into the program, searched after `include_paths`
+    builtin_headers: HashMap<String, &'static str>,
+    macros: HashMap<Symbol, Macro>,
+    pragma_once: HashSet<PathBuf>,
+    file_stack: Vec<FileState>,
+    output: Vec<Token>,
+    errors: Vec<Diagnostic>,
+    trigraphs: bool,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        let mut pp = Self {
+            include_paths: Vec::new(),
+            builtin_headers: HashMap::new(),
+            macros: HashMap::new(),
+            pragma_once: HashSet::new(),
+            file_stack: Vec::new(),
+            output: Vec::new(),
+            errors: Vec::new(),
+            trigraphs: false,
+        };
+        pp.define("__STDC__=1");
+        pp.define("__STDC_HOSTED__=1");
+        pp.define("__STDC_VERSION__=201112L");
+        pp
+    }
+
+    pub fn set_trigraphs(&mut self, enabled: bool) {
+        self.trigraphs = enabled;
+    }
+
+    pub fn add_include_path(&mut self, path: impl Into<PathBuf>) {
+        self.include_paths.push(path.into());
+    }
+
+    // makes `#include <name>` read `text` if no include path has `name`
+    pub fn add_builtin_header(&mut self, name: &str, text: &'static str) {
+        self.builtin_headers.insert(name.to_owned(), text);
+    }
+
+    // `NAME`, `NAME=VALUE` or `NAME(PARAMS)=VALUE`, as in `-D`
+    pub fn define(&mut self, definition: &str) {
+        let text = match definition.find('=') {
+            Some(idx) => format!("{} {}", &definition[..idx], &definition[idx + 1..]),
+            None => format!("{} 1", definition),
+        };
+        let src = SourceFile::new(&text, "<command line>");
+        let (tokens, errors) = Lexer::from_fragment(&src.content, src.start).resolve();
+        self.errors.extend(errors);
+        if tokens.is_empty() {
+            return;
+        }
+        if let Err(e) = self.define_macro(tokens) {
+            self.errors.push(*e);
+        }
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.macros.remove(&Symbol::intern(name));
+    }
+
+    pub fn resolve(&mut self, src: SourceFile) -> (Vec<Token>, Vec<Diagnostic>) {
+        let path = PathBuf::from(&*src.file_path);
+        self.process_file(src, path);
+        let tokens = std::mem::take(&mut self.output);
+        let errors = std::mem::take(&mut self.errors);
+        (tokens, errors)
+    }
+}
+
+impl Preprocessor {
+    fn process_file(&mut self, src: SourceFile, path: PathBuf) {
+        let lexer = Lexer::from_src(src.clone()).with_trigraphs(self.trigraphs);
+
+        self.file_stack.push(FileState {
+            path,
+            presumed_path: src.file_path.clone(),
+            line_delta: 0,
+            conds: Vec::new(),
+        });
+
+        let mut text: Vec<Token> = Vec::new();
+
+        for result in lexer {
+            match result {
+                Ok(Token::Directive(directive)) => {
+                    self.flush(&mut text);
+                    self.handle_directive(&directive);
+                }
+                Ok(token) => {
+                    if self.is_active() {
+                        text.push(token);
+                    }
+                }
+                // lexical errors inside skipped groups are not errors; the
+                // error token that follows stays in the text, which may be
+                // in the middle of a macro invocation
+                Err(e) => {
+                    if self.is_active() {
+                        self.errors.push(*e);
+                    }
+                }
+            }
+        }
+        self.flush(&mut text);
+
+        let state = self.file_stack.pop().unwrap();
+        for cond in state.conds {
+            let error = Diagnostic::error(cond.span, "unterminated conditional directive")
+                .with_code(Code::UnbalancedConditional);
+            self.errors.push(error);
+        }
+    }
+
+    fn flush(&mut self, text: &mut Vec<Token>) {
+        if text.is_empty() {
+            return;
+        }
+        let tokens = text.drain(..).map(PPToken::new).collect();
+        let expanded = self.expand(tokens);
+        self.output.extend(expanded.into_iter().map(|t| t.token));
+    }
+
+    fn is_active(&self) -> bool {
+        match self.file_stack.last().and_then(|f| f.conds.last()) {
+            Some(cond) => cond.active,
+            None => true,
+        }
+    }
+
+    fn lex_args(&mut self, directive: &Directive) -> Vec<Token> {
+        let (tokens, errors) = Lexer::from_directive_args(directive)
+            .with_trigraphs(self.trigraphs)
+            .resolve();
+        self.errors.extend(errors);
+        tokens
+    }
+}
+
+// directives
+impl Preprocessor {
+    fn handle_directive(&mut self, directive: &Directive) {
+        let result = match directive.name.as_str() {
+            // a null directive
+            "" => Ok(()),
+            "if" | "ifdef" | "ifndef" | "elif" | "else" | "endif" => {
+                self.handle_conditional(directive)
+            }
+            _ if !self.is_active() => Ok(()),
+            "include" => self.handle_include(directive),
+            "define" => {
+                let tokens = self.lex_args(directive);
+                if tokens.is_empty() {
+                    Err(error_at(
+                        Code::InvalidMacroDefinition,
+                        directive.span,
+                        "macro name missing",
+                    ))
+                } else {
+                    self.define_macro(tokens)
+                }
+            }
+            "undef" => {
+                let tokens = self.lex_args(directive);
+                match tokens.first().and_then(macro_name) {
+                    Some(name) => {
+                        self.macros.remove(&name);
+                        Ok(())
+                    }
+                    None => Err(error_at(
+                        Code::InvalidMacroDefinition,
+                        directive.span,
+                        "macro name missing",
+                    )),
+                }
+            }
+            "error" => Err(error_at(
+                Code::ErrorDirective,
+                directive.span,
+                &format!("#error {}", directive.args.trim()),
+            )),
+            "line" => self.handle_line(directive),
+            "pragma" => {
+                if directive.args.trim() == "once" {
+                    let path = &self.file_stack.last().unwrap().path;
+                    let path = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+                    self.pragma_once.insert(path);
+                }
+                Ok(())
+            }
+            _ => Err(error_at(
+                Code::InvalidDirective,
+                directive.span,
+                &format!("invalid preprocessing directive #{}", directive.name),
+            )),
+        };
+        if let Err(e) = result {
+            self.errors.push(*e);
+        }
+    }
+
+    fn handle_conditional(&mut self, directive: &Directive) -> Result<(), Box<Diagnostic>> {
+        let parent_active = self.is_active();
+        match directive.name.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                // a condition in error skips the group, which is still
+                // pushed for its `#endif` to close
+                let condition = match parent_active {
+                    true => self.eval_condition(directive),
+                    false => Ok(false),
+                };
+                let active = *condition.as_ref().unwrap_or(&false);
+                let conds = &mut self.file_stack.last_mut().unwrap().conds;
+                conds.push(Conditional {
+                    parent_active,
+                    active,
+                    taken: active,
+                    seen_else: false,
+                    span: directive.span,
+                });
+                condition.map(|_| ())
+            }
+            "elif" => {
+                let (parent_active, taken) = match self.file_stack.last().unwrap().conds.last() {
+                    None => {
+                        return Err(error_at(
+                            Code::UnbalancedConditional,
+                            directive.span,
+                            "#elif without #if",
+                        ))
+                    }
+                    Some(cond) if cond.seen_else => {
+                        return Err(error_at(
+                            Code::UnbalancedConditional,
+                            directive.span,
+                            "#elif after #else",
+                        ))
+                    }
+                    Some(cond) => (cond.parent_active, cond.taken),
+                };
+                let active = parent_active && !taken && self.eval_condition(directive)?;
+                let cond = self
+                    .file_stack
+                    .last_mut()
+                    .unwrap()
+                    .conds
+                    .last_mut()
+                    .unwrap();
+                cond.active = active;
+                cond.taken |= active;
+                Ok(())
+            }
+            "else" => {
+                let conds = &mut self.file_stack.last_mut().unwrap().conds;
+                match conds.last_mut() {
+                    None => Err(error_at(
+                        Code::UnbalancedConditional,
+                        directive.span,
+                        "#else without #if",
+                    )),
+                    Some(cond) if cond.seen_else => Err(error_at(
+                        Code::UnbalancedConditional,
+                        directive.span,
+                        "#else after #else",
+                    )),
+                    Some(cond) => {
+                        cond.active = cond.parent_active && !cond.taken;
+                        cond.taken = true;
+                        cond.seen_else = true;
+                        Ok(())
+                    }
+                }
+            }
+            "endif" => {
+                let conds = &mut self.file_stack.last_mut().unwrap().conds;
+                match conds.pop() {
+                    None => Err(error_at(
+                        Code::UnbalancedConditional,
+                        directive.span,
+                        "#endif without #if",
+                    )),
+                    Some(_) => Ok(()),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn eval_condition(&mut self, directive: &Directive) -> Result<bool, Box<Diagnostic>> {
+        let tokens = self.lex_args(directive);
+
+        if directive.name != "if" && directive.name != "elif" {
+            return match tokens.first().and_then(macro_name) {
+                Some(name) => {
+                    let defined = self.is_defined(name);
+                    Ok(defined == (directive.name == "ifdef"))
+                }
+                None => Err(error_at(
+                    Code::InvalidMacroDefinition,
+                    directive.span,
+                    "macro name missing",
+                )),
+            };
+        }
+
+        if tokens.is_empty() {
+            return Err(error_at(
+                Code::InvalidPreprocessorExpr,
+                directive.span,
+                &format!("#{} with no expression", directive.name),
+            ));
+        }
+
+        // `defined` must be resolved before macro expansion
+        let mut resolved = Vec::new();
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(token) = iter.next() {
+            if macro_name(&token).is_none_or(|name| name != "defined") {
+                resolved.push(PPToken::new(token));
+                continue;
+            }
+            let has_paren = iter
+                .peek()
+                .is_some_and(|t| is_punct(t, PunctuatorKind::LParen));
+            if has_paren {
+                iter.next();
+            }
+            let name = match iter.next() {
+                Some(t) if macro_name(&t).is_some() => t,
+                _ => {
+                    return Err(error_at(
+                        Code::InvalidMacroDefinition,
+                        token.span(),
+                        "macro name missing",
+                    ))
+                }
+            };
+            let mut span = token.span().to(name.span());
+            if has_paren {
+                match iter.next() {
+                    Some(t) if is_punct(&t, PunctuatorKind::RParen) => span = span.to(t.span()),
+                    _ => {
+                        return Err(error_at(
+                            Code::InvalidPreprocessorExpr,
+                            name.span(),
+                            "missing ')' after \"defined\"",
+                        ))
+                    }
+                }
+            }
+            let value = if self.is_defined(macro_name(&name).unwrap()) {
+                "1"
+            } else {
+                "0"
+            };
+            resolved.push(PPToken::new(int_token(value, span)));
+        }
+
+        let tokens = self
+            .expand(resolved)
+            .into_iter()
+            .map(|t| match t.token {
+                Token::Identifier(_) | Token::Keyword(_) => int_token("0", t.token.span()),
+                token => token,
+            })
+            .collect();
+
+        let expr = Parser::from_tokens(tokens).parse_constant_expr()?;
+        Ok(eval(&expr, true)?.is_true())
+    }
+
+    fn handle_include(&mut self, directive: &Directive) -> Result<(), Box<Diagnostic>> {
+        let args = directive.args.trim();
+        let (name, is_angled) = if args.starts_with('"') || args.starts_with('<') {
+            let close = if args.starts_with('"') { '"' } else { '>' };
+            match args[1..].find(close) {
+                Some(end) => (args[1..=end].to_owned(), close == '>'),
+                None => {
+                    return Err(error_at(
+                        Code::InvalidInclude,
+                        directive.args_span,
+                        "expected \"FILENAME\" or <FILENAME>",
+                    ))
+                }
+            }
+        } else {
+            let tokens = self.lex_args(directive);
+            let tokens: Vec<Token> = self
+                .expand(tokens.into_iter().map(PPToken::new).collect())
+                .into_iter()
+                .map(|t| t.token)
+                .collect();
+            match tokens.as_slice() {
+                [Token::StringLiteral(s)] if s.encoding == Encoding::None => {
+                    (s.text().to_string(), false)
+                }
+                [first, rest @ ..]
+                    if is_punct(first, PunctuatorKind::Lt)
+                        && rest.last().is_some_and(|t| is_punct(t, PunctuatorKind::Gt)) =>
+                {
+                    let inner = &rest[..rest.len() - 1];
+                    (inner.iter().map(Token::spelling).collect(), true)
+                }
+                _ => {
+                    return Err(error_at(
+                        Code::InvalidInclude,
+                        directive.args_span,
+                        "expected \"FILENAME\" or <FILENAME>",
+                    ))
+                }
+            }
+        };
+
+        if self.file_stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(error_at(
+                Code::InvalidInclude,
+                directive.span,
+                "#include nested too deeply",
+            ));
+        }
+
+        let (path, builtin) = match self.find_include(&name, is_angled) {
+            Some(found) => found,
+            None => {
+                return Err(error_at(
+                    Code::FileNotFound,
+                    directive.args_span,
+                    &format!("'{}' file not found", name),
+                ))
+            }
+        };
+
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.pragma_once.contains(&canonical) {
+            return Ok(());
+        }
+
+        let content =
+            match builtin.map_or_else(|| fs::read_to_string(&path), |text| Ok(text.into())) {
+                Ok(content) => content,
+                Err(e) => {
+                    return Err(error_at(
+                        Code::FileNotFound,
+                        directive.args_span,
+                        &format!("cannot read '{}': {}", path.display(), e),
+                    ))
+                }
+            };
+
+        // the directive's span takes in its newline
+        let site = directive.span.to(directive.args_span);
+        let src = SourceFile::included(&content, &path.display().to_string(), site);
+        self.process_file(src, path);
+        Ok(())
+    }
+
+    // the path of the header, with its text if it is a built-in one
+    fn find_include(&self, name: &str, is_angled: bool) -> Option<(PathBuf, Option<&'static str>)> {
+        let mut dirs = Vec::new();
+        if !is_angled {
+            let cur = &self.file_stack.last().unwrap().path;
+            dirs.push(cur.parent().unwrap_or_else(|| Path::new("")).to_owned());
+        }
+        dirs.extend(self.include_paths.iter().cloned());
+        let found = dirs
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file());
+        match found {
+            Some(path) => Some((path, None)),
+            None => {
+                let text = *self.builtin_headers.get(name)?;
+                Some((Path::new("<built-in>").join(name), Some(text)))
+            }
+        }
+    }
+
+    fn handle_line(&mut self, directive: &Directive) -> Result<(), Box<Diagnostic>> {
+        let tokens = self.lex_args(directive);
+        let tokens: Vec<Token> = self
+            .expand(tokens.into_iter().map(PPToken::new).collect())
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+
+        let line = match tokens.first() {
+            Some(Token::Constant(Constant::Int(int)))
+                if int.literal.chars().all(|ch| ch.is_ascii_digit()) =>
+            {
+                int.literal.parse::<isize>().ok()
+            }
+            _ => None,
+        };
+        let line = match line {
+            Some(line) => line,
+            None => {
+                return Err(error_at(
+                    Code::InvalidLineDirective,
+                    directive.args_span,
+                    "#line directive requires a simple digit sequence",
+                ))
+            }
+        };
+
+        let presumed_path = match tokens.get(1) {
+            None => None,
+            Some(Token::StringLiteral(s)) if tokens.len() == 2 && s.encoding == Encoding::None => {
+                Some(s.text())
+            }
+            Some(t) => {
+                return Err(error_at(
+                    Code::InvalidLineDirective,
+                    t.span(),
+                    "invalid filename for #line directive",
+                ))
+            }
+        };
+
+        let next_line = directive.span.lc_range().start.line as isize + 1;
+        let state = self.file_stack.last_mut().unwrap();
+        state.line_delta = line - next_line;
+        if let Some(path) = presumed_path {
+            state.presumed_path = path;
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self, tokens: Vec<Token>) -> Result<(), Box<Diagnostic>> {
+        let mut iter = tokens.into_iter().peekable();
+        let name_token = iter.next().unwrap();
+        let name = match macro_name(&name_token) {
+            Some(name) if name == "defined" => {
+                return Err(error_at(
+                    Code::InvalidMacroDefinition,
+                    name_token.span(),
+                    "\"defined\" cannot be used as a macro name",
+                ))
+            }
+            Some(name) => name,
+            None => {
+                return Err(error_at(
+                    Code::InvalidMacroDefinition,
+                    name_token.span(),
+                    "macro name must be an identifier",
+                ))
+            }
+        };
+
+        // function-like only if `(` immediately follows the name
+        let is_function_like = iter.peek().is_some_and(|t| {
+            is_punct(t, PunctuatorKind::LParen) && t.span().lo == name_token.span().hi
+        });
+
+        let mut params = None;
+        let mut variadic = false;
+        if is_function_like {
+            let lparen = iter.next().unwrap();
+            let mut names: Vec<Symbol> = Vec::new();
+            loop {
+                let token = match iter.next() {
+                    Some(t) => t,
+                    None => {
+                        return Err(error_at(
+                            Code::InvalidMacroDefinition,
+                            lparen.span(),
+                            "missing ')' in macro parameter list",
+                        ))
+                    }
+                };
+                if names.is_empty() && is_punct(&token, PunctuatorKind::RParen) {
+                    break;
+                }
+                if is_punct(&token, PunctuatorKind::Ellipsis) {
+                    variadic = true;
+                    names.push(Symbol::intern("__VA_ARGS__"));
+                    match iter.next() {
+                        Some(t) if is_punct(&t, PunctuatorKind::RParen) => break,
+                        _ => {
+                            return Err(error_at(
+                                Code::InvalidMacroDefinition,
+                                token.span(),
+                                "missing ')' in macro parameter list",
+                            ))
+                        }
+                    }
+                }
+                let param = match macro_name(&token) {
+                    Some(param) => param,
+                    None => {
+                        return Err(error_at(
+                            Code::InvalidMacroDefinition,
+                            token.span(),
+                            "invalid token in macro parameter list",
+                        ))
+                    }
+                };
+                if names.contains(&param) {
+                    return Err(error_at(
+                        Code::InvalidMacroDefinition,
+                        token.span(),
+                        &format!("duplicate macro parameter \"{}\"", param),
+                    ));
+                }
+                names.push(param);
+                match iter.next() {
+                    Some(t) if is_punct(&t, PunctuatorKind::Comma) => {}
+                    Some(t) if is_punct(&t, PunctuatorKind::RParen) => break,
+                    _ => {
+                        return Err(error_at(
+                            Code::InvalidMacroDefinition,
+                            token.span(),
+                            "expected ',' or ')' in macro parameter list",
+                        ))
+                    }
+                }
+            }
+            params = Some(names);
+        }
+
+        let body: Vec<Token> = iter.collect();
+
+        let ends_with_paste = body
+            .first()
+            .is_some_and(|t| is_punct(t, PunctuatorKind::HashHash))
+            || body
+                .last()
+                .is_some_and(|t| is_punct(t, PunctuatorKind::HashHash));
+        if ends_with_paste {
+            return Err(error_at(
+                Code::InvalidMacroDefinition,
+                name_token.span(),
+                "'##' cannot appear at either end of a macro expansion",
+            ));
+        }
+
+        if let Some(params) = &params {
+            for (i, token) in body.iter().enumerate() {
+                if !is_punct(token, PunctuatorKind::Hash) {
+                    continue;
+                }
+                let is_param = body
+                    .get(i + 1)
+                    .and_then(macro_name)
+                    .is_some_and(|name| params.contains(&name));
+                if !is_param {
+                    return Err(error_at(
+                        Code::InvalidMacroDefinition,
+                        token.span(),
+                        "'#' is not followed by a macro parameter",
+                    ));
+                }
+            }
+        }
+
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                variadic,
+                body,
+            },
+        );
+        Ok(())
+    }
+}
+
+// macro expansion, with hidesets as in Prosser's algorithm
+impl Preprocessor {
+    fn is_defined(&self, name: Symbol) -> bool {
+        self.macros.contains_key(&name) || name == "__FILE__" || name == "__LINE__"
+    }
+
+    fn expand(&mut self, tokens: Vec<PPToken>) -> Vec<PPToken> {
+        let mut input: VecDeque<PPToken> = tokens.into();
+        let mut output = Vec::new();
+
+        while let Some(t) = input.pop_front() {
+            let name = match macro_name(&t.token) {
+                Some(name) if !t.hideset.contains(name) => name,
+                _ => {
+                    output.push(t);
+                    continue;
+                }
+            };
+
+            if name == "__FILE__" || name == "__LINE__" {
+                output.push(PPToken::new(self.builtin_macro(name, t.token.span())));
+                continue;
+            }
+
+            let mac = match self.macros.get(&name) {
+                Some(mac) => mac.clone(),
+                None => {
+                    output.push(t);
+                    continue;
+                }
+            };
+
+            let site;
+            let hideset;
+            let args;
+            match &mac.params {
+                None => {
+                    site = t.token.span();
+                    hideset = t.hideset.clone();
+                    args = Vec::new();
+                }
+                Some(params) => {
+                    if !input
+                        .front()
+                        .is_some_and(|t| is_punct(&t.token, PunctuatorKind::LParen))
+                    {
+                        output.push(t);
+                        continue;
+                    }
+                    let (collected, rparen) = match self.collect_args(&mut input, &mac, &t) {
+                        Ok(ret) => ret,
+                        Err(e) => {
+                            self.errors.push(*e);
+                            continue;
+                        }
+                    };
+                    site = t.token.span().to(rparen.token.span());
+                    hideset = t.hideset.intersection(&rparen.hideset);
+                    args = match check_args(collected, params.len(), mac.variadic) {
+                        Ok(args) => args,
+                        Err(given) => {
+                            self.errors.push(*error_at(
+                                Code::MacroArguments,
+                                site,
+                                &format!(
+                                    "macro \"{}\" requires {} arguments, but {} given",
+                                    name,
+                                    params.len(),
+                                    given
+                                ),
+                            ));
+                            continue;
+                        }
+                    };
+                }
+            }
+            let hideset = hideset.with(name);
+
+            let replacement = self.substitute(&mac, &args, &hideset, &site);
+            for token in replacement.into_iter().rev() {
+                input.push_front(token);
+            }
+        }
+
+        output
+    }
+
+    // the name token has been popped; `(` is at the front of `input`
+    fn collect_args(
+        &mut self,
+        input: &mut VecDeque<PPToken>,
+        mac: &Macro,
+        name_token: &PPToken,
+    ) -> Result<(Vec<Vec<PPToken>>, PPToken), Box<Diagnostic>> {
+        let n_params = mac.params.as_ref().map_or(0, Vec::len);
+        input.pop_front();
+        let mut args = vec![Vec::new()];
+        let mut depth = 0;
+        loop {
+            let t = match input.pop_front() {
+                Some(t) => t,
+                None => {
+                    return Err(error_at(
+                        Code::MacroArguments,
+                        name_token.token.span(),
+                        &format!(
+                            "unterminated argument list invoking macro \"{}\"",
+                            name_token.token.spelling()
+                        ),
+                    ))
+                }
+            };
+            // the variable arguments keep their commas
+            let in_va_args = mac.variadic && args.len() == n_params;
+            if is_punct(&t.token, PunctuatorKind::LParen) {
+                depth += 1;
+            } else if is_punct(&t.token, PunctuatorKind::RParen) {
+                if depth == 0 {
+                    return Ok((args, t));
+                }
+                depth -= 1;
+            } else if is_punct(&t.token, PunctuatorKind::Comma) && depth == 0 && !in_va_args {
+                args.push(Vec::new());
+                continue;
+            }
+            args.last_mut().unwrap().push(t);
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        mac: &Macro,
+        args: &[Vec<PPToken>],
+        hideset: &HideSet,
+        site: &Span,
+    ) -> Vec<PPToken> {
+        let empty = Vec::new();
+        let params = mac.params.as_ref().unwrap_or(&empty);
+        let param_index =
+            |t: &Token| macro_name(t).and_then(|name| params.iter().position(|&p| p == name));
+
+        let body = &mac.body;
+        // the body is copied as a whole, keeping its tokens' relative offsets
+        let spelling = match (body.first(), body.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => *site,
+        };
+        let copy = source_map::with(|sm| sm.add_expansion(spelling, *site));
+        let relocate = |span: Span| {
+            Span::new(
+                copy.lo + (span.lo - spelling.lo),
+                copy.lo + (span.hi - spelling.lo),
+            )
+        };
+        let mut out: Vec<PPToken> = Vec::new();
+        // the left operand of a pending `##` is an empty argument
+        let mut placemarker = false;
+        let mut i = 0;
+
+        while i < body.len() {
+            let token = &body[i];
+            let next = body.get(i + 1);
+
+            if mac.params.is_some() && is_punct(token, PunctuatorKind::Hash) {
+                let idx = param_index(next.unwrap()).unwrap();
+                let span = relocate(token.span());
+                out.push(PPToken::new(stringize(&args[idx], span)));
+                placemarker = false;
+                i += 2;
+                continue;
+            }
+
+            if is_punct(token, PunctuatorKind::HashHash) {
+                let rhs = next.unwrap();
+                let rhs: Vec<PPToken> = match param_index(rhs) {
+                    Some(idx) => args[idx].clone(),
+                    None => {
+                        let mut rhs = rhs.clone();
+                        *rhs.span_mut() = relocate(rhs.span());
+                        vec![PPToken::new(rhs)]
+                    }
+                };
+                let mut rhs = rhs.into_iter();
+                match rhs.next() {
+                    None => {}
+                    Some(first) => {
+                        let lhs = if placemarker { None } else { out.pop() };
+                        match lhs {
+                            None => out.push(first),
+                            Some(lhs) => {
+                                let pasted = self.paste(lhs, first);
+                                out.extend(pasted);
+                            }
+                        }
+                        placemarker = false;
+                    }
+                }
+                out.extend(rhs);
+                i += 2;
+                continue;
+            }
+
+            if let Some(idx) = param_index(token) {
+                let before_paste = next.is_some_and(|t| is_punct(t, PunctuatorKind::HashHash));
+                if before_paste {
+                    placemarker = args[idx].is_empty();
+                    out.extend(args[idx].iter().cloned());
+                } else {
+                    let expanded = self.expand(args[idx].clone());
+                    out.extend(expanded);
+                }
+                i += 1;
+                continue;
+            }
+
+            let mut token = token.clone();
+            *token.span_mut() = relocate(token.span());
+            out.push(PPToken::new(token));
+            placemarker = false;
+            i += 1;
+        }
+
+        // the tokens of an argument mostly share one set
+        let mut last: Option<(HideSet, HideSet)> = None;
+        for t in &mut out {
+            let union = match &last {
+                Some((from, union)) if Rc::ptr_eq(&from.0, &t.hideset.0) => union.clone(),
+                _ => t.hideset.union(hideset),
+            };
+            last = Some((std::mem::replace(&mut t.hideset, union.clone()), union));
+        }
+        out
+    }
+
+    fn paste(&mut self, lhs: PPToken, rhs: PPToken) -> Vec<PPToken> {
+        let lhs_text = lhs.token.spelling();
+        let rhs_text = rhs.token.spelling();
+        let text = format!("{}{}", lhs_text, rhs_text);
+        let span = lhs.token.span();
+
+        let (mut tokens, errors) = Lexer::from_fragment(&text, span.lo)
+            .with_trigraphs(self.trigraphs)
+            .resolve();
+        if tokens.len() == 1 && errors.is_empty() {
+            let mut token = tokens.pop().unwrap();
+            *token.span_mut() = span;
+            return vec![PPToken {
+                token,
+                hideset: lhs.hideset,
+            }];
+        }
+
+        self.errors.push(*error_at(
+            Code::InvalidPaste,
+            span,
+            &format!(
+                "pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+                lhs_text, rhs_text
+            ),
+        ));
+        vec![lhs, rhs]
+    }
+
+    fn builtin_macro(&self, name: Symbol, span: Span) -> Token {
+        let state = self.file_stack.last().unwrap();
+        let mut site = span;
+        while let Some(outer) = site.expansion() {
+            site = outer;
+        }
+        if name == "__FILE__" {
+            Token::StringLiteral(StringLiteral::new(&state.presumed_path, span))
+        } else {
+            let line = site.lc_range().start.line as isize + state.line_delta;
+            int_token(&line.to_string(), span)
+        }
+    }
+}
+
+impl PPToken {
+    fn new(token: Token) -> Self {
+        Self {
+            token,
+            hideset: HideSet::default(),
+        }
+    }
+}
+
+impl HideSet {
+    fn contains(&self, name: Symbol) -> bool {
+        self.0.binary_search(&name).is_ok()
+    }
+
+    fn with(&self, name: Symbol) -> Self {
+        match self.0.binary_search(&name) {
+            Ok(_) => self.clone(),
+            Err(idx) => {
+                let mut names = self.0.to_vec();
+                names.insert(idx, name);
+                HideSet(names.into())
+            }
+        }
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        if other.0.is_empty() || Rc::ptr_eq(&self.0, &other.0) {
+            return self.clone();
+        }
+        if self.0.is_empty() {
+            return other.clone();
+        }
+        let mut names = self.0.to_vec();
+        names.extend(other.0.iter().filter(|&&name| !self.contains(name)));
+        names.sort();
+        HideSet(names.into())
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        if Rc::ptr_eq(&self.0, &other.0) {
+            return self.clone();
+        }
+        let names: Vec<Symbol> = self
+            .0
+            .iter()
+            .copied()
+            .filter(|&name| other.contains(name))
+            .collect();
+        HideSet(names.into())
+    }
+}
+
+fn check_args(
+    mut args: Vec<Vec<PPToken>>,
+    n_params: usize,
+    variadic: bool,
+) -> Result<Vec<Vec<PPToken>>, usize> {
+    if n_params == 0 && args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    if variadic && args.len() + 1 == n_params {
+        args.push(Vec::new());
+    }
+    if args.len() != n_params {
+        return Err(args.len());
+    }
+    Ok(args)
+}
+
+fn stringize(arg: &[PPToken], span: Span) -> Token {
+    let mut value = String::new();
+    for (i, t) in arg.iter().enumerate() {
+        if i > 0 {
+            let prev = arg[i - 1].token.span();
+            if prev.spelling().hi != t.token.span().spelling().lo {
+                value.push(' ');
+            }
+        }
+        value.push_str(&t.token.spelling());
+    }
+    Token::StringLiteral(StringLiteral::new(&value, span))
+}
+
+fn macro_name(token: &Token) -> Option<Symbol> {
+    match token {
+        Token::Identifier(ident) => Some(ident.value),
+        Token::Keyword(kw) => Some(kw.kind.symbol()),
+        _ => None,
+    }
+}
+
+fn is_punct(token: &Token, kind: PunctuatorKind) -> bool {
+    token.kind() == TokenKind::Punctuator(kind)
+}
+
+fn int_token(literal: &str, span: Span) -> Token {
+    let int = IntegerConstant::validate(literal.into(), span).expect("valid integer literal");
+    Token::Constant(Constant::Int(int))
+}
+
+fn error_at(code: Code, span: Span, msg: &str) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(span, msg).with_code(code))
+}
+
+// a `#if` value, in `intmax_t` or `uintmax_t`
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    bits: u64,
+    unsigned: bool,
+}
+
+impl Value {
+    fn signed(value: i64) -> Self {
+        Value {
+            bits: value as u64,
+            unsigned: false,
+        }
+    }
+
+    fn truth(b: bool) -> Self {
+        Value::signed(b as i64)
+    }
+
+    fn is_true(self) -> bool {
+        self.bits != 0
+    }
+}
+
+// evaluates a `#if` expression in `intmax_t` or `uintmax_t`, as chosen by the
+// usual arithmetic conversions; division by zero is only an error if `live`,
+// that is, outside the unevaluated operands of `&&`, `||` and `?:`
+fn eval(expr: &Expr, live: bool) -> Result<Value, Box<Diagnostic>> {
+    let error_invalid = || {
+        error_at(
+            Code::InvalidPreprocessorExpr,
+            expr.span,
+            "invalid token in preprocessor expression",
+        )
+    };
+    match &expr.kind {
+        ExprKind::Constant(Constant::Int(int)) => Ok(Value {
+            bits: int.value as u64,
+            unsigned: !int.ty.is_signed(),
+        }),
+        ExprKind::Constant(Constant::Char(ch)) => Ok(Value::signed(ch.value)),
+        ExprKind::Constant(Constant::Float(_)) => Err(error_at(
+            Code::InvalidPreprocessorExpr,
+            expr.span,
+            "floating constant in preprocessor expression",
+        )),
+        ExprKind::Unary(op, operand) => {
+            let v = eval(operand, live)?;
+            match op {
+                UnaryOp::Plus => Ok(v),
+                UnaryOp::Minus => Ok(Value {
+                    bits: v.bits.wrapping_neg(),
+                    ..v
+                }),
+                UnaryOp::BitNot => Ok(Value { bits: !v.bits, ..v }),
+                UnaryOp::Not => Ok(Value::truth(!v.is_true())),
+                _ => Err(error_invalid()),
+            }
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let l = eval(lhs, live)?;
+            match op {
+                BinaryOp::And => {
+                    let r = eval(rhs, live && l.is_true())?;
+                    return Ok(Value::truth(l.is_true() && r.is_true()));
+                }
+                BinaryOp::Or => {
+                    let r = eval(rhs, live && !l.is_true())?;
+                    return Ok(Value::truth(l.is_true() || r.is_true()));
+                }
+                _ => {}
+            }
+            let r = eval(rhs, live)?;
+            // the type of a shift is that of its left operand
+            if let BinaryOp::Shl | BinaryOp::Shr = op {
+                let bits = match op {
+                    BinaryOp::Shl => l.bits.wrapping_shl(r.bits as u32),
+                    _ if l.unsigned => l.bits.wrapping_shr(r.bits as u32),
+                    _ => (l.bits as i64).wrapping_shr(r.bits as u32) as u64,
+                };
+                return Ok(Value { bits, ..l });
+            }
+            let unsigned = l.unsigned || r.unsigned;
+            let (sl, sr) = (l.bits as i64, r.bits as i64);
+            let (ul, ur) = (l.bits, r.bits);
+            if let BinaryOp::Div | BinaryOp::Mod = op {
+                if r.bits == 0 {
+                    if live {
+                        return Err(error_at(
+                            Code::InvalidPreprocessorExpr,
+                            rhs.span,
+                            "division by zero in preprocessor expression",
+                        ));
+                    }
+                    return Ok(Value { bits: 0, unsigned });
+                }
+            }
+            let bits = match op {
+                BinaryOp::Mul => ul.wrapping_mul(ur),
+                BinaryOp::Div if unsigned => ul / ur,
+                BinaryOp::Div => sl.wrapping_div(sr) as u64,
+                BinaryOp::Mod if unsigned => ul % ur,
+                BinaryOp::Mod => sl.wrapping_rem(sr) as u64,
+                BinaryOp::Add => ul.wrapping_add(ur),
+                BinaryOp::Sub => ul.wrapping_sub(ur),
+                BinaryOp::BitAnd => ul & ur,
+                BinaryOp::BitXor => ul ^ ur,
+                BinaryOp::BitOr => ul | ur,
+                _ => {
+                    let result = match op {
+                        BinaryOp::Lt if unsigned => ul < ur,
+                        BinaryOp::Lt => sl < sr,
+                        BinaryOp::Gt if unsigned => ul > ur,
+                        BinaryOp::Gt => sl > sr,
+                        BinaryOp::Le if unsigned => ul <= ur,
+                        BinaryOp::Le => sl <= sr,
+                        BinaryOp::Ge if unsigned => ul >= ur,
+                        BinaryOp::Ge => sl >= sr,
+                        BinaryOp::Eq => ul == ur,
+                        _ => ul != ur,
+                    };
+                    return Ok(Value::truth(result));
+                }
+            };
+            Ok(Value { bits, unsigned })
+        }
+        ExprKind::Conditional(cond, then, otherwise) => {
+            let c = eval(cond, live)?.is_true();
+            let then = eval(then, live && c)?;
+            let otherwise = eval(otherwise, live && !c)?;
+            Ok(Value {
+                bits: if c { then.bits } else { otherwise.bits },
+                unsigned: then.unsigned || otherwise.unsigned,
+            })
+        }
+        ExprKind::Comma(exprs) => {
+            let mut v = Value::signed(0);
+            for e in exprs {
+                v = eval(e, live)?;
+            }
+            Ok(v)
+        }
+        _ => Err(error_invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess(src: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        Preprocessor::new().resolve(SourceFile::new(src, "<dummy file>"))
+    }
+
+    fn spellings(src: &str) -> Vec<String> {
+        let (tokens, errors) = preprocess(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        tokens.iter().map(Token::spelling).collect()
+    }
+
+    #[test]
+    fn object_like() {
+        assert_eq!(
+            spellings("#define N 10\n#define M N + N\nint a[M];\n"),
+            ["int", "a", "[", "10", "+", "10", "]", ";"]
+        );
+        assert_eq!(spellings("#define x x + 1\nx\n"), ["x", "+", "1"]);
+        assert_eq!(spellings("#define N 1\n#undef N\nN\n"), ["N"]);
+    }
+
+    #[test]
+    fn function_like() {
+        assert_eq!(
+            spellings("#define MAX(a, b) ((a) > (b) ? (a) : (b))\nMAX(1, f(2, 3))\n"),
+            [
+                "(", "(", "1", ")", ">", "(", "f", "(", "2", ",", "3", ")", ")", "?", "(", "1",
+                ")", ":", "(", "f", "(", "2", ",", "3", ")", ")", ")"
+            ]
+        );
+        assert_eq!(spellings("#define F (x)\nF\n"), ["(", "x", ")"]);
+        assert_eq!(spellings("#define F(x) x\nF + 1\n"), ["F", "+", "1"]);
+    }
+
+    #[test]
+    fn hidesets() {
+        assert_eq!(
+            spellings("#define x 2 + y\n#define y x\nx y\n"),
+            ["2", "+", "x", "2", "+", "y"]
+        );
+        // C11 6.10.3.4p4: the `g` after `2*` is not hidden from the `g` of `f`
+        assert_eq!(
+            spellings("#define f(a) a*g\n#define g(a) f(a)\nf(2)(9)\n"),
+            ["2", "*", "9", "*", "g"]
+        );
+        let mut src = String::from("#define A0 0\n");
+        for i in 1..=100 {
+            src += &format!("#define A{} A{} + A{}\n", i, i - 1, i);
+        }
+        // `0 + A1 + ... + A100`, each name hidden from its own expansion
+        let tokens = spellings(&(src + "A100\n"));
+        assert_eq!((tokens.len(), tokens[2].as_str()), (201, "A1"));
+    }
+
+    #[test]
+    fn stringize_and_paste() {
+        assert_eq!(spellings("#define S(x) #x\nS(a  +b)\n"), ["\"a +b\""]);
+        assert_eq!(spellings("#define S(x) #x\nS(\"q\")\n"), ["\"\\\"q\\\"\""]);
+        assert_eq!(
+            spellings("#define C(a, b) a ## b\nC(foo, 1) C(, x)\n"),
+            ["foo1", "x"]
+        );
+        assert_eq!(spellings("#define C(a, b) a ## b\nC(+, =)\n"), ["+="]);
+        assert_eq!(
+            spellings("#define T(x) in ## x\nT(t) y;\n"),
+            ["int", "y", ";"]
+        );
+    }
+
+    #[test]
+    fn digraphs_and_trigraphs() {
+        // digraphs keep their spelling
+        assert_eq!(spellings("%:define S(x) %:x\nS(<:)\n"), ["\"<:\""]);
+        assert_eq!(spellings("#define C(a, b) a %:%: b\nC(<, :)\n"), ["<:"]);
+
+        // trigraphs are gone before tokens are formed
+        let src = SourceFile::new("??=define S(x) ??=x\nS(??()\n", "<dummy file>");
+        let mut pp = Preprocessor::new();
+        pp.set_trigraphs(true);
+        let (tokens, errors) = pp.resolve(src);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(tokens[0].spelling(), "\"[\"");
+    }
+
+    #[test]
+    fn conditionals() {
+        let src = "#define A 2\n\
+                   #if A > 1 && defined(A) && !defined B\nyes\n#else\nno\n#endif\n\
+                   #ifdef B\nb\n#elif A == 2\nelif\n#endif\n\
+                   #ifndef A\n#error unreachable\n#endif\n\
+                   #if 0\n@ #bogus\n#endif\n";
+        assert_eq!(spellings(src), ["yes", "elif"]);
+
+        // only the missing name or expression is reported, not the `#endif`
+        let (tokens, errors) = preprocess("#ifdef\nx\n#endif\n#if\ny\n#else\nz\n#endif\n");
+        let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+        assert_eq!(spellings, ["z"]);
+        let codes: Vec<_> = errors.iter().map(|e| e.code).collect();
+        assert_eq!(
+            codes,
+            [
+                Some(Code::InvalidMacroDefinition),
+                Some(Code::InvalidPreprocessorExpr)
+            ],
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn comments_in_directives() {
+        assert_eq!(spellings("#define Z /* multi\n line */ 3\nZ\n"), ["3"]);
+        assert_eq!(spellings("# /* c */ define X 1\nX\n"), ["1"]);
+        assert_eq!(spellings("#define S \"/*\" '\"'\nS\n"), ["\"/*\"", "'\"'"]);
+        assert_eq!(
+            spellings("#if 1 /* a\n */ + 1 // b\nyes\n#endif\n"),
+            ["yes"]
+        );
+    }
+
+    #[test]
+    fn intmax_arithmetic() {
+        for cond in &[
+            "-1 > 0u",
+            "0xffffffffffffffff > 0",
+            "(0u - 1) / 2 > 0",
+            "-1 < 0",
+            "-7 / 2 == -3",
+            "(0 ? 0u : -1) > 0",
+            "-1 >> 1 == -1",
+            "0 && 1 / 0 || 1",
+        ] {
+            let src = format!("#if {}\nyes\n#endif\n", cond);
+            assert_eq!(spellings(&src), ["yes"], "{}", cond);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let (_, errors) = preprocess("#error stop here\n");
+        assert_eq!(errors[0].msg, "#error stop here");
+
+        let (_, errors) = preprocess("#if 1\nx\n");
+        assert_eq!(errors[0].msg, "unterminated conditional directive");
+        assert_eq!(errors[0].span.lc_range().start.line, 1);
+
+        let (_, errors) = preprocess("#define F(a, b) a\nF(1)\n");
+        assert_eq!(
+            errors[0].msg,
+            "macro \"F\" requires 2 arguments, but 1 given"
+        );
+
+        let (_, errors) = preprocess("#include <no_such_header.h>\n");
+        assert_eq!(errors[0].msg, "'no_such_header.h' file not found");
+
+        let (tokens, errors) = preprocess("#define F(x) x\nF(1 @ 2)\n");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].code, Some(Code::UnexpectedChar));
+        let spellings: Vec<_> = tokens.iter().map(Token::spelling).collect();
+        assert_eq!(spellings, ["1", "@", "2"]);
+
+        let (_, errors) = preprocess("#if 1 / 0\n#endif\n");
+        assert_eq!(errors[0].msg, "division by zero in preprocessor expression");
+    }
+
+    #[test]
+    fn line_and_file() {
+        assert_eq!(
+            spellings("__LINE__\n#line 100 \"foo.c\"\n__LINE__ __FILE__\n"),
+            ["1", "100", "\"foo.c\""]
+        );
+    }
+
+    #[test]
+    fn expansion_spans() {
+        let (tokens, _) = preprocess("#define ONE 1\nint x = ONE;\n");
+        let span = tokens[3].span();
+        assert_eq!(span.lc_range().start.line, 1);
+        assert_eq!(span.lc_range().start.column, 13);
+        let site = span.expansion().unwrap();
+        assert_eq!(site.lc_range().start.line, 2);
+        assert_eq!(site.lc_range().start.column, 9);
+    }
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join("toy-compiler-pp-include");
+        fs::create_dir_all(dir.join("sys")).unwrap();
+        fs::write(dir.join("a.h"), "#pragma once\nint a;\n").unwrap();
+        fs::write(dir.join("sys").join("b.h"), "#include \"a.h\"\nint b;\n").unwrap();
+
+        let mut pp = Preprocessor::new();
+        pp.add_include_path(dir.join("sys"));
+        pp.add_include_path(&dir);
+        let main_path = dir.join("main.c").display().to_string();
+        let src = "#include \"a.h\"\n#include <b.h>\nint c;\n";
+        let (tokens, errors) = pp.resolve(SourceFile::new(src, &main_path));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+        assert_eq!(
+            spellings,
+            ["int", "a", ";", "int", "b", ";", "int", "c", ";"]
+        );
+        // `b` is in sys/b.h, read by the second line of main.c
+        let site = tokens[4].span().included_from().unwrap();
+        assert_eq!(*site.file_path(), *main_path);
+        assert_eq!(site.lc_range().start.line, 2);
+        assert_eq!(tokens[7].span().included_from(), None);
+
+        // a built-in header is read when no include path has it
+        let mut pp = Preprocessor::new();
+        pp.add_include_path(&dir);
+        pp.add_builtin_header("a.h", "int shadowed;\n");
+        pp.add_builtin_header("c.h", "#pragma once\nint builtin;\n");
+        let src = "#include <c.h>\n#include \"c.h\"\n#include <a.h>\n";
+        let (tokens, errors) = pp.resolve(SourceFile::new(src, &main_path));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+        assert_eq!(spellings, ["int", "builtin", ";", "int", "a", ";"]);
+        assert_eq!(&*tokens[1].span().file_path(), "<built-in>/c.h");
+    }
+
+    #[test]
+    fn command_line_defines() {
+        let mut pp = Preprocessor::new();
+        pp.define("DEBUG");
+        pp.define("SQ(x)=((x)*(x))");
+        let (tokens, errors) = pp.resolve(SourceFile::new("DEBUG SQ(2)", "<dummy file>"));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+        assert_eq!(
+            spellings,
+            ["1", "(", "(", "2", ")", "*", "(", "2", ")", ")"]
+        );
+    }
+}
@@ -0,0 +1,614 @@
+use crate::{
+    ast::*,
+    diagnostics::{Code, Diagnostic},
+    interner,
+    span::Span,
+    symbols::*,
+    tokens::Identifier,
+};
+
+use std::collections::HashMap;
+
+pub struct Resolver {
+    symbols: SymbolTable,
+    labels: HashMap<interner::Symbol, Span>,
+    gotos: Vec<Identifier>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            symbols: SymbolTable::new(),
+            labels: HashMap::new(),
+            gotos: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, unit: &TranslationUnit) -> Vec<Diagnostic> {
+        for item in &unit.items {
+            match item {
+                ExternalDecl::Function(f) => self.resolve_function(f),
+                ExternalDecl::Declaration(decl) => self.resolve_declaration(decl),
+            }
+        }
+        self.errors
+    }
+}
+
+// declarations
+impl Resolver {
+    fn declare(&mut self, ns: Namespace, symbol: Symbol) {
+        if let Some(prev) = self.symbols.lookup_current(ns, symbol.name) {
+            if let Some((code, msg)) = conflict(prev, &symbol) {
+                let error = Diagnostic::error(symbol.span, msg)
+                    .with_code(code)
+                    .with_secondary(prev.span, "previous definition is here");
+                self.errors.push(error);
+                return;
+            }
+            if prev.is_definition && !symbol.is_definition {
+                return;
+            }
+        }
+        self.symbols.insert(ns, symbol.name, symbol);
+    }
+
+    fn resolve_function(&mut self, f: &FunctionDef) {
+        self.resolve_specifiers(&f.specifiers);
+
+        if let Some(name) = &f.declarator.name {
+            self.declare(
+                Namespace::Ordinary,
+                Symbol {
+                    name: name.value,
+                    kind: SymbolKind::Function,
+                    span: name.span,
+                    is_definition: true,
+                    has_linkage: true,
+                },
+            );
+        }
+
+        self.symbols.push_scope(ScopeKind::Function);
+        self.labels.clear();
+        self.gotos.clear();
+
+        let mut derived = f.declarator.derived.iter();
+        if let Some(DerivedDeclarator::Function(func)) = derived.next() {
+            self.declare_params(func);
+        }
+        for d in derived {
+            self.resolve_derived(d);
+        }
+
+        self.declare(
+            Namespace::Ordinary,
+            Symbol {
+                name: interner::Symbol::intern("__func__"),
+                kind: SymbolKind::Variable,
+                span: f.body.span,
+                is_definition: true,
+                has_linkage: false,
+            },
+        );
+
+        for item in &f.body.items {
+            self.resolve_block_item(item);
+        }
+
+        for goto in std::mem::take(&mut self.gotos) {
+            if !self.labels.contains_key(&goto.value) {
+                self.errors.push(
+                    Diagnostic::error(
+                        goto.span,
+                        format!("use of undeclared label '{}'", goto.value),
+                    )
+                    .with_code(Code::UndeclaredLabel),
+                );
+            }
+        }
+
+        self.symbols.pop_scope();
+    }
+
+    fn resolve_declaration(&mut self, decl: &Declaration) {
+        self.resolve_specifiers(&decl.specifiers);
+
+        let is_file_scope = self.symbols.current_scope().kind == ScopeKind::File;
+        let storage = &decl.specifiers.storage;
+        let is_extern = storage.contains(&StorageClass::Extern);
+
+        for init in &decl.declarators {
+            let declarator = &init.declarator;
+            for d in &declarator.derived {
+                self.resolve_derived(d);
+            }
+
+            if let Some(name) = &declarator.name {
+                let is_function = matches!(
+                    declarator.derived.first(),
+                    Some(DerivedDeclarator::Function(_))
+                );
+                let kind = if decl.specifiers.is_typedef() {
+                    SymbolKind::Typedef
+                } else if is_function {
+                    SymbolKind::Function
+                } else {
+                    SymbolKind::Variable
+                };
+                let has_linkage = is_function || is_file_scope || is_extern;
+                let is_definition = match kind {
+                    SymbolKind::Function => false,
+                    SymbolKind::Variable if has_linkage => init.initializer.is_some(),
+                    _ => true,
+                };
+                self.declare(
+                    Namespace::Ordinary,
+                    Symbol {
+                        name: name.value,
+                        kind,
+                        span: name.span,
+                        is_definition,
+                        has_linkage,
+                    },
+                );
+            }
+
+            if let Some(initializer) = &init.initializer {
+                self.resolve_initializer(initializer);
+            }
+        }
+    }
+
+    fn resolve_specifiers(&mut self, specifiers: &DeclSpecifiers) {
+        for ty in &specifiers.types {
+            match ty {
+                TypeSpecifier::Struct(s) => self.resolve_struct(s),
+                TypeSpecifier::Enum(e) => self.resolve_enum(e),
+                _ => {}
+            }
+        }
+    }
+
+    fn resolve_tag(&mut self, tag: &Identifier, kind: SymbolKind, is_definition: bool) {
+        let symbol = Symbol {
+            name: tag.value,
+            kind,
+            span: tag.span,
+            is_definition,
+            has_linkage: false,
+        };
+
+        if is_definition {
+            return self.declare(Namespace::Tag, symbol);
+        }
+
+        // a reference declares an incomplete type only if the tag is not visible
+        match self.symbols.lookup(Namespace::Tag, tag.value) {
+            None => self.declare(Namespace::Tag, symbol),
+            Some(prev) if prev.kind != kind => {
+                let error = Diagnostic::error(
+                    tag.span,
+                    format!(
+                        "use of '{}' with tag type that does not match previous declaration",
+                        tag.value
+                    ),
+                )
+                .with_code(Code::TagMismatch)
+                .with_secondary(prev.span, "previous use is here");
+                self.errors.push(error);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn resolve_struct(&mut self, s: &StructSpecifier) {
+        let kind = match s.kind {
+            StructKind::Struct => SymbolKind::Struct,
+            StructKind::Union => SymbolKind::Union,
+        };
+        if let Some(tag) = &s.tag {
+            self.resolve_tag(tag, kind, s.fields.is_some());
+        }
+
+        let fields = match &s.fields {
+            Some(fields) => fields,
+            None => return,
+        };
+        let mut members: HashMap<interner::Symbol, &Span> = HashMap::new();
+        for field in fields {
+            self.resolve_specifiers(&field.specifiers);
+            for d in &field.declarators {
+                if let Some(declarator) = &d.declarator {
+                    for derived in &declarator.derived {
+                        self.resolve_derived(derived);
+                    }
+                    if let Some(name) = &declarator.name {
+                        if let Some(prev) = members.insert(name.value, &name.span) {
+                            let error = Diagnostic::error(
+                                name.span,
+                                format!("duplicate member '{}'", name.value),
+                            )
+                            .with_code(Code::DuplicateMember)
+                            .with_secondary(*prev, "previous declaration is here");
+                            self.errors.push(error);
+                        }
+                    }
+                }
+                if let Some(width) = &d.bit_width {
+                    self.resolve_expr(width);
+                }
+            }
+        }
+    }
+
+    fn resolve_enum(&mut self, e: &EnumSpecifier) {
+        if let Some(tag) = &e.tag {
+            self.resolve_tag(tag, SymbolKind::Enum, e.enumerators.is_some());
+        }
+        for enumerator in e.enumerators.iter().flatten() {
+            if let Some(value) = &enumerator.value {
+                self.resolve_expr(value);
+            }
+            self.declare(
+                Namespace::Ordinary,
+                Symbol {
+                    name: enumerator.name.value,
+                    kind: SymbolKind::EnumConstant,
+                    span: enumerator.name.span,
+                    is_definition: true,
+                    has_linkage: false,
+                },
+            );
+        }
+    }
+
+    fn resolve_derived(&mut self, derived: &DerivedDeclarator) {
+        match derived {
+            DerivedDeclarator::Pointer(_) | DerivedDeclarator::Array(None) => {}
+            DerivedDeclarator::Array(Some(size)) => self.resolve_expr(size),
+            DerivedDeclarator::Function(func) => {
+                self.symbols.push_scope(ScopeKind::Prototype);
+                self.declare_params(func);
+                self.symbols.pop_scope();
+            }
+        }
+    }
+
+    fn declare_params(&mut self, func: &FunctionDeclarator) {
+        for param in &func.params {
+            self.resolve_specifiers(&param.specifiers);
+            for d in &param.declarator.derived {
+                self.resolve_derived(d);
+            }
+            if let Some(name) = &param.declarator.name {
+                self.declare(
+                    Namespace::Ordinary,
+                    Symbol {
+                        name: name.value,
+                        kind: SymbolKind::Variable,
+                        span: name.span,
+                        is_definition: true,
+                        has_linkage: false,
+                    },
+                );
+            }
+        }
+    }
+
+    fn resolve_type_name(&mut self, type_name: &TypeName) {
+        self.resolve_specifiers(&type_name.specifiers);
+        for d in &type_name.declarator.derived {
+            self.resolve_derived(d);
+        }
+    }
+
+    fn resolve_initializer(&mut self, initializer: &Initializer) {
+        match initializer {
+            Initializer::Expr(e) => self.resolve_expr(e),
+            Initializer::List(items, _) => {
+                for item in items {
+                    for designator in &item.designators {
+                        if let Designator::Index(e) = designator {
+                            self.resolve_expr(e);
+                        }
+                    }
+                    self.resolve_initializer(&item.init);
+                }
+            }
+        }
+    }
+}
+
+fn conflict(prev: &Symbol, new: &Symbol) -> Option<(Code, String)> {
+    let is_tag = matches!(
+        new.kind,
+        SymbolKind::Struct | SymbolKind::Union | SymbolKind::Enum
+    );
+
+    if prev.kind != new.kind {
+        return Some(if is_tag {
+            let msg = format!(
+                "use of '{}' with tag type that does not match previous declaration",
+                new.name
+            );
+            (Code::TagMismatch, msg)
+        } else {
+            let msg = format!("redefinition of '{}' as different kind of symbol", new.name);
+            (Code::Redefinition, msg)
+        });
+    }
+
+    let is_redefinition = match new.kind {
+        SymbolKind::Typedef => false,
+        SymbolKind::Function => prev.is_definition && new.is_definition,
+        SymbolKind::Variable if prev.has_linkage && new.has_linkage => {
+            prev.is_definition && new.is_definition
+        }
+        SymbolKind::Variable | SymbolKind::EnumConstant => true,
+        SymbolKind::Struct | SymbolKind::Union | SymbolKind::Enum => {
+            prev.is_definition && new.is_definition
+        }
+    };
+
+    if !is_redefinition {
+        return None;
+    }
+    let msg = match new.kind {
+        SymbolKind::Struct => format!("redefinition of 'struct {}'", new.name),
+        SymbolKind::Union => format!("redefinition of 'union {}'", new.name),
+        SymbolKind::Enum => format!("redefinition of 'enum {}'", new.name),
+        _ => format!("redefinition of '{}'", new.name),
+    };
+    Some((Code::Redefinition, msg))
+}
+
+// statements and expressions
+impl Resolver {
+    fn resolve_block_item(&mut self, item: &BlockItem) {
+        match item {
+            BlockItem::Declaration(decl) => self.resolve_declaration(decl),
+            BlockItem::Stmt(stmt) => self.resolve_stmt(stmt),
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Compound(block) => {
+                self.symbols.push_scope(ScopeKind::Block);
+                for item in &block.items {
+                    self.resolve_block_item(item);
+                }
+                self.symbols.pop_scope();
+            }
+            StmtKind::Expr(e) => {
+                if let Some(e) = e {
+                    self.resolve_expr(e);
+                }
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(then);
+                if let Some(otherwise) = otherwise {
+                    self.resolve_stmt(otherwise);
+                }
+            }
+            StmtKind::While(cond, body) | StmtKind::Switch(cond, body) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(body);
+            }
+            StmtKind::DoWhile(body, cond) => {
+                self.resolve_stmt(body);
+                self.resolve_expr(cond);
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.symbols.push_scope(ScopeKind::Block);
+                match init {
+                    ForInit::Empty => {}
+                    ForInit::Expr(e) => self.resolve_expr(e),
+                    ForInit::Declaration(decl) => self.resolve_declaration(decl),
+                }
+                if let Some(cond) = cond {
+                    self.resolve_expr(cond);
+                }
+                if let Some(step) = step {
+                    self.resolve_expr(step);
+                }
+                self.resolve_stmt(body);
+                self.symbols.pop_scope();
+            }
+            StmtKind::Case(value, body) => {
+                self.resolve_expr(value);
+                self.resolve_stmt(body);
+            }
+            StmtKind::Default(body) => self.resolve_stmt(body),
+            StmtKind::Labeled(label, body) => {
+                if let Some(prev) = self.labels.get(&label.value) {
+                    let error = Diagnostic::error(
+                        label.span,
+                        format!("redefinition of label '{}'", label.value),
+                    )
+                    .with_code(Code::Redefinition)
+                    .with_secondary(*prev, "previous definition is here");
+                    self.errors.push(error);
+                } else {
+                    self.labels.insert(label.value, label.span);
+                }
+                self.resolve_stmt(body);
+            }
+            StmtKind::Goto(label) => self.gotos.push(label.clone()),
+            StmtKind::Continue | StmtKind::Break => {}
+            StmtKind::Return(e) => {
+                if let Some(e) = e {
+                    self.resolve_expr(e);
+                }
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(ident) => {
+                if self
+                    .symbols
+                    .lookup(Namespace::Ordinary, ident.value)
+                    .is_none()
+                {
+                    self.errors.push(
+                        Diagnostic::error(
+                            ident.span,
+                            format!("use of undeclared identifier '{}'", ident.value),
+                        )
+                        .with_code(Code::UndeclaredIdentifier),
+                    );
+                }
+            }
+            ExprKind::Constant(_) | ExprKind::StringLiteral(_) => {}
+            ExprKind::Unary(_, e) | ExprKind::SizeofExpr(e) => self.resolve_expr(e),
+            ExprKind::Member(e, _) | ExprKind::Arrow(e, _) => self.resolve_expr(e),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(_, lhs, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            ExprKind::Conditional(cond, then, otherwise) => {
+                self.resolve_expr(cond);
+                self.resolve_expr(then);
+                self.resolve_expr(otherwise);
+            }
+            ExprKind::Comma(exprs) => {
+                for e in exprs {
+                    self.resolve_expr(e);
+                }
+            }
+            ExprKind::Cast(type_name, e) => {
+                self.resolve_type_name(type_name);
+                self.resolve_expr(e);
+            }
+            ExprKind::SizeofType(type_name) => self.resolve_type_name(type_name),
+            ExprKind::Call(f, args) => {
+                self.resolve_expr(f);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source_file::SourceFile;
+
+    fn resolve(src: &str) -> Vec<Diagnostic> {
+        let (tokens, errors) = Lexer::from_src(SourceFile::new(src, "<dummy file>")).resolve();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (unit, errors) = Parser::from_tokens(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        Resolver::new().resolve(&unit)
+    }
+
+    fn messages(src: &str) -> Vec<String> {
+        resolve(src).into_iter().map(|e| e.msg).collect()
+    }
+
+    #[test]
+    fn undeclared_identifier() {
+        let src =
+            "int main(void)\n{\n\tint floatnum = 1;\n\tif(floatn>5)\n\t\treturn 1;\n\treturn 0;\n}";
+        let errors = resolve(src);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "use of undeclared identifier 'floatn'");
+        assert_eq!(errors[0].span.lc_range().start.line, 4);
+        assert_eq!(errors[0].span.lc_range().start.column, 5);
+        assert_eq!(errors[0].span.lc_range().end.column, 11);
+    }
+
+    #[test]
+    fn block_scopes() {
+        assert!(messages(
+            "int f(int a) { { int b = a; } for (int i = 0; i < a; i++) ; return a; }"
+        )
+        .is_empty());
+        assert_eq!(
+            messages("int f(void) { { int b; } return b; }"),
+            ["use of undeclared identifier 'b'"]
+        );
+        assert_eq!(
+            messages("int f(void) { for (int i = 0; ;) ; return i; }"),
+            ["use of undeclared identifier 'i'"]
+        );
+        assert!(
+            messages("int x; int f(void) { int x; { int x; } return x + __func__[0]; }").is_empty()
+        );
+    }
+
+    #[test]
+    fn redefinition() {
+        let errors = resolve("int f(void) {\n  int x;\n  int x;\n  return x;\n}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "redefinition of 'x'");
+        assert_eq!(errors[0].span.lc_range().start.line, 3);
+        assert_eq!(errors[0].code, Some(Code::Redefinition));
+        assert_eq!(errors[0].labels[0].span.lc_range().start.line, 2);
+        assert_eq!(errors[0].labels[0].msg, "previous definition is here");
+
+        assert_eq!(
+            messages("int f(int a) { int a; return a; }"),
+            ["redefinition of 'a'"]
+        );
+        assert_eq!(
+            messages("int f(void); int f(void) { return 0; } int f(void) { return 1; }"),
+            ["redefinition of 'f'"]
+        );
+        assert!(messages("int x; int x; extern int x; int x = 1;").is_empty());
+        assert_eq!(messages("int x = 1; int x = 2;"), ["redefinition of 'x'"]);
+        assert_eq!(
+            messages("int x; void x(void);"),
+            ["redefinition of 'x' as different kind of symbol"]
+        );
+        assert_eq!(messages("enum { A, A };"), ["redefinition of 'A'"]);
+    }
+
+    #[test]
+    fn namespaces() {
+        assert!(messages("struct s { int s; } s; int f(void) { s: return s.s; }").is_empty());
+        assert_eq!(
+            messages("struct s { int a; }; struct s { int b; };"),
+            ["redefinition of 'struct s'"]
+        );
+        assert_eq!(
+            messages("struct s; union s *p;"),
+            ["use of 's' with tag type that does not match previous declaration"]
+        );
+        assert_eq!(
+            messages("struct p { int x; char x; };"),
+            ["duplicate member 'x'"]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert!(messages("void f(void) { goto end; { end: ; } }").is_empty());
+        assert_eq!(
+            messages("void f(void) { l: ; l: ; }"),
+            ["redefinition of label 'l'"]
+        );
+        assert_eq!(
+            messages("void f(void) { goto nowhere; }"),
+            ["use of undeclared label 'nowhere'"]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use crate::interner::Interner;
+use crate::source_map::SourceMap;
+
+use std::cell::RefCell;
+
+// what spans and symbols are resolved through: the source map and the
+// interned strings of a compilation. Each thread has a session of its own,
+// so neither is `Send`; `enter` gives a compilation a new one, whose files
+// and strings are freed when it returns
+#[derive(Default)]
+pub struct Session {
+    pub source_map: SourceMap,
+    pub interner: Interner,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Session> = RefCell::default();
+}
+
+// the session of this thread's compilation
+pub fn with<R>(f: impl FnOnce(&Session) -> R) -> R {
+    CURRENT.with(|current| f(&current.borrow()))
+}
+
+// runs `f` in a new session, then frees it and returns to the previous one;
+// spans and symbols made in `f` must not be resolved after it returns
+pub fn enter<R>(f: impl FnOnce() -> R) -> R {
+    // also on unwinding
+    struct Restore(Option<Session>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let prev = self.0.take().unwrap();
+            CURRENT.with(|current| drop(current.replace(prev)));
+        }
+    }
+
+    let prev = CURRENT.with(|current| current.replace(Session::default()));
+    let _restore = Restore(Some(prev));
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_file::SourceFile;
+
+    #[test]
+    fn sessions() {
+        let outer = SourceFile::new("int x;\n", "outer.c");
+        let span = outer.span(4..5);
+        let inner = enter(|| {
+            let file = SourceFile::new("int y;\n", "inner.c");
+            assert_eq!(&*file.span(4..5).file_path(), "inner.c");
+            with(|session| session.source_map.len())
+        });
+        assert_eq!(inner, 1);
+        assert_eq!(&*span.file_path(), "outer.c");
+        assert_eq!(span.lc_range().start.column, 5);
+    }
+}
@@ -1,55 +1,85 @@
+use crate::source_map;
+use crate::span::{LineColumn, Span};
+
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct SourceFile {
-    pub content: Rc<[char]>,
+    pub content: Rc<str>,
     pub file_path: Rc<str>,
+    // the offset of the first byte in the source map
+    pub start: u32,
+    pub included_from: Option<Span>,
+    // byte offset of the start of each line
+    line_starts: Rc<[usize]>,
 }
 
 impl SourceFile {
+    // adds the file to this thread's source map
     pub fn new(content: &str, file_path: &str) -> Self {
-        let content = content.chars().collect::<Vec<char>>().into();
-        let file_path = file_path.into();
-        Self { content, file_path }
+        Self::add(content, file_path, None)
     }
 
-    pub fn generate_lines(&self) -> Vec<Vec<char>> {
-        let mut chars = self.content.iter().copied().peekable();
-        let mut lines: Vec<Vec<char>> = Vec::new();
+    // a file read by the `#include` at `site`
+    pub fn included(content: &str, file_path: &str, site: Span) -> Self {
+        Self::add(content, file_path, Some(site))
+    }
 
-        let mut line: Option<Vec<char>> = None;
+    fn add(content: &str, file_path: &str, included_from: Option<Span>) -> Self {
+        let line_starts: Rc<[usize]> = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        source_map::with(|sm| {
+            sm.add_file(content.len(), |start| Self {
+                content: content.into(),
+                file_path: file_path.into(),
+                start,
+                included_from,
+                line_starts,
+            })
+        })
+    }
+
+    // the span of bytes `range` of the file
+    pub fn span(&self, range: std::ops::Range<usize>) -> Span {
+        Span::new(
+            self.start + range.start as u32,
+            self.start + range.end as u32,
+        )
+    }
 
-        loop {
-            match chars.next() {
-                None => break,
-                Some(ch) => match ch {
-                    '\n' => {
-                        lines.push(vec![]);
-                        line = Some(Vec::new());
-                    }
-                    _ => {
-                        let mut buf = vec![ch];
-                        line = None;
-                        for ch in &mut chars {
-                            match ch {
-                                '\n' => {
-                                    line = Some(Vec::new());
-                                    break;
-                                }
-                                _ => buf.push(ch),
-                            }
-                        }
-                        lines.push(buf);
-                    }
-                },
-            };
+    pub fn line_count(&self) -> usize {
+        if self.content.is_empty() {
+            0
+        } else {
+            self.line_starts.len()
         }
+    }
 
-        if let Some(line) = line {
-            lines.push(line)
+    // the text of the 1-based line `lineno`, without its newline
+    pub fn line(&self, lineno: usize) -> Option<&str> {
+        if lineno == 0 || lineno > self.line_count() {
+            return None;
         }
+        let start = self.line_starts[lineno - 1];
+        let end = match self.line_starts.get(lineno) {
+            Some(&next) => next - 1,
+            None => self.content.len(),
+        };
+        Some(&self.content[start..end])
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        (1..=self.line_count()).map(move |lineno| self.line(lineno).unwrap())
+    }
 
-        lines
+    // the line and column of the char starting at byte `byte_pos`
+    pub fn line_column(&self, byte_pos: usize) -> LineColumn {
+        let byte_pos = byte_pos.min(self.content.len());
+        let line = self.line_starts.partition_point(|&start| start <= byte_pos);
+        let start = self.line_starts[line - 1];
+        let column = self.content[start..byte_pos].chars().count() + 1;
+        LineColumn { line, column }
     }
 }
 
@@ -63,30 +93,33 @@ mod tests {
 
     #[test]
     fn lines() {
-        {
-            let lhs = dummy_source_file("").generate_lines();
-            let rhs: Vec<Vec<char>> = vec![];
-            assert_eq!(lhs, rhs);
-        }
-        {
-            let lhs = dummy_source_file("a").generate_lines();
-            let rhs: Vec<Vec<char>> = vec![vec!['a']];
-            assert_eq!(lhs, rhs);
-        }
-        {
-            let lhs = dummy_source_file("a\nb\n").generate_lines();
-            let rhs: Vec<Vec<char>> = vec![vec!['a'], vec!['b'], vec![]];
-            assert_eq!(lhs, rhs);
-        }
-        {
-            let lhs = dummy_source_file("a\nb\nc").generate_lines();
-            let rhs: Vec<Vec<char>> = vec![vec!['a'], vec!['b'], vec!['c']];
-            assert_eq!(lhs, rhs);
-        }
-        {
-            let lhs = dummy_source_file("aa\nbb\ncc").generate_lines();
-            let rhs: Vec<Vec<char>> = vec![vec!['a', 'a'], vec!['b', 'b'], vec!['c', 'c']];
-            assert_eq!(lhs, rhs);
-        }
+        let lines = |content: &str| -> Vec<String> {
+            let src = dummy_source_file(content);
+            src.lines().map(String::from).collect()
+        };
+        assert_eq!(lines(""), Vec::<String>::new());
+        assert_eq!(lines("a"), ["a"]);
+        assert_eq!(lines("a\nb\n"), ["a", "b", ""]);
+        assert_eq!(lines("a\nb\nc"), ["a", "b", "c"]);
+        assert_eq!(lines("aa\nbb\ncc"), ["aa", "bb", "cc"]);
+        assert_eq!(dummy_source_file("a\nb").line(3), None);
+    }
+
+    #[test]
+    fn line_column() {
+        let src = dummy_source_file("ab\n好，很\n\nx");
+        let lc = |byte_pos| {
+            let LineColumn { line, column } = src.line_column(byte_pos);
+            (line, column)
+        };
+        assert_eq!(lc(0), (1, 1));
+        assert_eq!(lc(2), (1, 3));
+        assert_eq!(lc(3), (2, 1));
+        assert_eq!(lc(6), (2, 2));
+        assert_eq!(lc(12), (2, 4));
+        assert_eq!(lc(13), (3, 1));
+        assert_eq!(lc(14), (4, 1));
+        assert_eq!(lc(15), (4, 2));
+        assert_eq!(lc(99), (4, 2));
     }
 }
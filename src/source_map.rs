@@ -0,0 +1,229 @@
+use crate::session;
+use crate::source_file::SourceFile;
+use crate::span::{LineColumn, Span};
+
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::rc::Rc;
+
+// every file and macro expansion gets its own range of offsets, so a
+// `Span` is only a pair of offsets, resolved to a file and line on demand
+#[derive(Default)]
+pub struct SourceMap {
+    // sorted by their start offset
+    entries: RefCell<Vec<(u32, Entry)>>,
+    next: Cell<u32>,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    File(SourceFile),
+    // a copy of `spelling` made by expanding a macro at `site`
+    Expansion { spelling: Span, site: Span },
+    // a location known only by its description, as read back from IR's `!loc`
+    External(Location),
+}
+
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file_path: Rc<str>,
+    // relative to the start of the file
+    pub byte_range: Range<usize>,
+    pub lc_range: Range<LineColumn>,
+    // `None` if the text is not loaded
+    pub file: Option<SourceFile>,
+}
+
+// the source map of this thread's session
+pub fn with<R>(f: impl FnOnce(&SourceMap) -> R) -> R {
+    session::with(|session| f(&session.source_map))
+}
+
+impl SourceMap {
+    // `make` gets the offset of the file's first byte
+    pub(crate) fn add_file(&self, len: usize, make: impl FnOnce(u32) -> SourceFile) -> SourceFile {
+        let start = self.reserve(len);
+        let file = make(start);
+        self.entries
+            .borrow_mut()
+            .push((start, Entry::File(file.clone())));
+        file
+    }
+
+    // a new span for a copy of `spelling` expanded at `site`
+    pub fn add_expansion(&self, spelling: Span, site: Span) -> Span {
+        let len = spelling.hi.saturating_sub(spelling.lo);
+        let start = self.reserve(len as usize);
+        self.entries
+            .borrow_mut()
+            .push((start, Entry::Expansion { spelling, site }));
+        Span::new(start, start + len)
+    }
+
+    pub fn add_external(&self, location: Location) -> Span {
+        let len = location.byte_range.len();
+        let start = self.reserve(len);
+        self.entries
+            .borrow_mut()
+            .push((start, Entry::External(location)));
+        Span::new(start, start + len as u32)
+    }
+
+    // the number of files and expansions
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // one offset more than asked, so that an empty span at the end still
+    // belongs to the entry
+    fn reserve(&self, len: usize) -> u32 {
+        let start = self.next.get();
+        let next = u32::try_from(len)
+            .ok()
+            .and_then(|len| start.checked_add(len)?.checked_add(1))
+            .expect("more than 4 GiB of source text");
+        self.next.set(next);
+        start
+    }
+
+    fn entry(&self, pos: u32) -> Option<(u32, Entry)> {
+        if pos >= self.next.get() {
+            return None;
+        }
+        let entries = self.entries.borrow();
+        let idx = entries.partition_point(|(start, _)| *start <= pos);
+        let (start, entry) = entries.get(idx.checked_sub(1)?)?;
+        Some((*start, entry.clone()))
+    }
+
+    // the entry `span` lies in, if both ends are in the same one
+    fn entry_of(&self, span: Span) -> Option<(u32, Entry)> {
+        let (start, entry) = self.entry(span.lo)?;
+        match self.entry(span.hi) {
+            Some((end_start, _)) if end_start == start && span.lo <= span.hi => {
+                Some((start, entry))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn location(&self, span: Span) -> Location {
+        self.locate(span).unwrap_or_else(|| Location {
+            file_path: "<unknown>".into(),
+            byte_range: 0..0,
+            lc_range: LineColumn { line: 0, column: 0 }..LineColumn { line: 0, column: 0 },
+            file: None,
+        })
+    }
+
+    fn locate(&self, span: Span) -> Option<Location> {
+        self.entry(span.lo)?;
+        let (start, entry) = match self.entry_of(span) {
+            Some(found) => found,
+            None => return self.locate_across(span),
+        };
+        let (lo, hi) = (span.lo - start, span.hi - start);
+        match entry {
+            Entry::File(file) => Some(Location {
+                file_path: file.file_path.clone(),
+                byte_range: lo as usize..hi as usize,
+                lc_range: file.line_column(lo as usize)..file.line_column(hi as usize),
+                file: Some(file),
+            }),
+            Entry::Expansion { spelling, .. } => {
+                self.locate(Span::new(spelling.lo + lo, spelling.lo + hi))
+            }
+            Entry::External(location) => Some(location),
+        }
+    }
+
+    // the ends come from different files or expansions, as when an
+    // expression starts inside a macro: use where the macros were expanded
+    fn locate_across(&self, span: Span) -> Option<Location> {
+        let outermost = |mut pos: u32, end: bool| {
+            while let Some((_, Entry::Expansion { site, .. })) = self.entry(pos) {
+                pos = if end { site.hi } else { site.lo };
+            }
+            pos
+        };
+        let (lo, hi) = (outermost(span.lo, false), outermost(span.hi, true));
+        match self.entry_of(Span::new(lo, hi)) {
+            Some(_) => self.locate(Span::new(lo, hi)),
+            None => self.locate(Span::new(lo, lo)),
+        }
+    }
+
+    pub fn expansion(&self, span: Span) -> Option<Span> {
+        match self.entry(span.lo)? {
+            (_, Entry::Expansion { site, .. }) => Some(site),
+            _ => None,
+        }
+    }
+
+    pub fn spelling(&self, span: Span) -> Span {
+        match self.entry_of(span) {
+            Some((start, Entry::Expansion { spelling, .. })) => self.spelling(Span::new(
+                spelling.lo + (span.lo - start),
+                spelling.lo + (span.hi - start),
+            )),
+            _ => span,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_and_expansions() {
+        let a = SourceFile::new("int x;\nint y;\n", "a.c");
+        let site = Span::new(a.start + 11, a.start + 12);
+        let b = SourceFile::included("#define Y y + 1\n", "b.h", site);
+        assert!(b.start > a.start + 14);
+
+        let y = Span::new(a.start + 11, a.start + 12);
+        let loc = y.location();
+        assert_eq!((&*loc.file_path, loc.byte_range), ("a.c", 11..12));
+        assert_eq!(loc.lc_range.start, LineColumn { line: 2, column: 5 });
+        assert_eq!(y.included_from(), None);
+
+        // `y + 1` copied out of the macro body
+        let body = Span::new(b.start + 10, b.start + 15);
+        let copy = with(|sm| sm.add_expansion(body, site));
+        let plus = Span::new(copy.lo + 2, copy.lo + 3);
+        assert_eq!(plus.byte_range(), 12..13);
+        assert_eq!(&*plus.file_path(), "b.h");
+        assert_eq!(plus.expansion(), Some(site));
+        assert_eq!(plus.spelling(), Span::new(b.start + 12, b.start + 13));
+        assert_eq!(plus.included_from(), Some(site));
+
+        // from inside the expansion to outside of it
+        let across = plus.to(Span::new(a.start + 13, a.start + 14));
+        assert_eq!(across.byte_range(), 11..14);
+        assert_eq!(&*across.file_path(), "a.c");
+
+        assert_eq!(&*Span::new(u32::MAX, u32::MAX).file_path(), "<unknown>");
+    }
+
+    #[test]
+    fn external() {
+        let lc = |line, column| LineColumn { line, column };
+        let span = with(|sm| {
+            sm.add_external(Location {
+                file_path: "x.c".into(),
+                byte_range: 40..44,
+                lc_range: lc(3, 5)..lc(3, 9),
+                file: None,
+            })
+        });
+        assert_eq!(span.hi - span.lo, 4);
+        assert_eq!(span.byte_range(), 40..44);
+        assert_eq!(span.lc_range(), lc(3, 5)..lc(3, 9));
+    }
+}
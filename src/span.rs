@@ -1,3 +1,8 @@
+use crate::source_file::SourceFile;
+use crate::source_map::{self, Location};
+
+use std::fmt;
+use std::marker::PhantomData;
 use std::ops::Range;
 use std::rc::Rc;
 
@@ -7,34 +12,82 @@ pub struct LineColumn {
     pub column: usize,
 }
 
-#[derive(Debug, Clone)]
+// a range of offsets in the source map, see `source_map::SourceMap`; only
+// the thread that made it has the session it is resolved in
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub struct Span {
-    pub byte_range: Range<usize>,
-    pub lc_range: Range<LineColumn>,
-    pub file_path: Rc<str>,
+    pub lo: u32,
+    pub hi: u32,
+    not_send: PhantomData<*const ()>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Pos {
-    pub byte_pos: usize,
-    pub lineno: usize,
-    pub column: usize,
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Span")
+            .field("lo", &self.lo)
+            .field("hi", &self.hi)
+            .finish()
+    }
 }
 
-impl Pos {
-    pub fn new(byte_pos: usize, lineno: usize, column: usize) -> Self {
-        Pos {
-            byte_pos,
-            lineno,
-            column,
+impl Span {
+    pub fn new(lo: u32, hi: u32) -> Self {
+        Span {
+            lo,
+            hi,
+            not_send: PhantomData,
         }
     }
 
-    pub fn add1(self) -> Self {
-        Self {
-            byte_pos: self.byte_pos + 1,
-            lineno: self.lineno,
-            column: self.column + 1,
-        }
+    pub fn to(self, end: Span) -> Span {
+        Span::new(self.lo, end.hi)
+    }
+
+    // the empty span just past this one
+    pub fn end(self) -> Span {
+        Span::new(self.hi, self.hi)
+    }
+
+    pub fn location(self) -> Location {
+        source_map::with(|sm| sm.location(self))
+    }
+
+    pub fn file_path(self) -> Rc<str> {
+        self.location().file_path
+    }
+
+    // relative to the start of the file
+    pub fn byte_range(self) -> Range<usize> {
+        self.location().byte_range
+    }
+
+    pub fn lc_range(self) -> Range<LineColumn> {
+        self.location().lc_range
+    }
+
+    pub fn source_file(self) -> Option<SourceFile> {
+        self.location().file
+    }
+
+    // the text as written in its file, if the file is loaded
+    pub fn source_text(self) -> Option<String> {
+        let location = self.location();
+        let text = location.file?.content.get(location.byte_range)?.to_owned();
+        Some(text)
+    }
+
+    // where the macro this span was copied out of was expanded
+    pub fn expansion(self) -> Option<Span> {
+        source_map::with(|sm| sm.expansion(self))
+    }
+
+    // the text this span was copied from by macro expansions
+    pub fn spelling(self) -> Span {
+        source_map::with(|sm| sm.spelling(self))
+    }
+
+    // the `#include` that read the file of this span
+    pub fn included_from(self) -> Option<Span> {
+        self.source_file().and_then(|file| file.included_from)
     }
 }
@@ -0,0 +1,167 @@
+use crate::interner;
+use crate::span::Span;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    File,
+    // parameters and the outermost block of a function definition
+    Function,
+    Block,
+    Prototype,
+}
+
+// labels live in their own, function-wide namespace and are kept by the resolver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    Ordinary,
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Typedef,
+    EnumConstant,
+    Struct,
+    Union,
+    Enum,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: interner::Symbol,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub is_definition: bool,
+    pub has_linkage: bool,
+}
+
+#[derive(Debug)]
+pub struct Scope<T> {
+    pub kind: ScopeKind,
+    ordinary: HashMap<interner::Symbol, T>,
+    tags: HashMap<interner::Symbol, T>,
+}
+
+// generic over what is recorded per name, so later passes can attach their own data
+#[derive(Debug)]
+pub struct SymbolTable<T = Symbol> {
+    scopes: Vec<Scope<T>>,
+}
+
+impl<T> Default for SymbolTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Scope<T> {
+    fn new(kind: ScopeKind) -> Self {
+        Self {
+            kind,
+            ordinary: HashMap::new(),
+            tags: HashMap::new(),
+        }
+    }
+
+    fn namespace(&self, ns: Namespace) -> &HashMap<interner::Symbol, T> {
+        match ns {
+            Namespace::Ordinary => &self.ordinary,
+            Namespace::Tag => &self.tags,
+        }
+    }
+
+    fn namespace_mut(&mut self, ns: Namespace) -> &mut HashMap<interner::Symbol, T> {
+        match ns {
+            Namespace::Ordinary => &mut self.ordinary,
+            Namespace::Tag => &mut self.tags,
+        }
+    }
+}
+
+impl<T> SymbolTable<T> {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::new(ScopeKind::File)],
+        }
+    }
+
+    pub fn push_scope(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope::new(kind));
+    }
+
+    pub fn pop_scope(&mut self) {
+        assert!(self.scopes.len() > 1, "cannot pop the file scope");
+        self.scopes.pop();
+    }
+
+    pub fn current_scope(&self) -> &Scope<T> {
+        self.scopes.last().unwrap()
+    }
+
+    pub fn lookup(&self, ns: Namespace, name: interner::Symbol) -> Option<&T> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.namespace(ns).get(&name))
+    }
+
+    pub fn lookup_current(&self, ns: Namespace, name: interner::Symbol) -> Option<&T> {
+        self.current_scope().namespace(ns).get(&name)
+    }
+
+    pub fn insert_global(&mut self, ns: Namespace, name: interner::Symbol, value: T) {
+        let scope = self.scopes.first_mut().unwrap();
+        scope.namespace_mut(ns).insert(name, value);
+    }
+
+    pub fn insert(&mut self, ns: Namespace, name: interner::Symbol, value: T) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.namespace_mut(ns).insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, kind: SymbolKind) -> Symbol {
+        Symbol {
+            name: interner::Symbol::intern(name),
+            kind,
+            span: Span::default(),
+            is_definition: true,
+            has_linkage: false,
+        }
+    }
+
+    #[test]
+    fn scopes_and_namespaces() {
+        let x = interner::Symbol::intern("x");
+        let mut table: SymbolTable = SymbolTable::new();
+        table.insert(Namespace::Ordinary, x, symbol("x", SymbolKind::Variable));
+        table.insert(Namespace::Tag, x, symbol("x", SymbolKind::Struct));
+
+        table.push_scope(ScopeKind::Block);
+        assert!(table.lookup_current(Namespace::Ordinary, x).is_none());
+        table.insert(Namespace::Ordinary, x, symbol("x", SymbolKind::Typedef));
+        assert_eq!(
+            table.lookup(Namespace::Ordinary, x).unwrap().kind,
+            SymbolKind::Typedef
+        );
+        assert_eq!(
+            table.lookup(Namespace::Tag, x).unwrap().kind,
+            SymbolKind::Struct
+        );
+
+        table.pop_scope();
+        assert_eq!(
+            table.lookup(Namespace::Ordinary, x).unwrap().kind,
+            SymbolKind::Variable
+        );
+        assert_eq!(table.current_scope().kind, ScopeKind::File);
+    }
+}
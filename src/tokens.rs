@@ -1,6 +1,12 @@
+use crate::interner::Symbol;
 use crate::span::Span;
+use crate::types::{FloatKind, IntKind};
 
-#[derive(Debug)]
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::FromStr;
+
+#[derive(Debug, Clone)]
 pub enum Token {
     Identifier(Identifier),
     Keyword(Keyword),
@@ -9,151 +15,642 @@ pub enum Token {
     Punctuator(Punctuator),
     Operator(Operator),
     Directive(Directive),
+    // the text of a token the lexer reported an error for
+    Error(ErrorToken),
+}
+
+// what a token is without its value, to match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Identifier,
+    Keyword(KeywordKind),
+    Constant,
+    StringLiteral,
+    // operators included
+    Punctuator(PunctuatorKind),
+    Directive,
+    Error,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Identifier {
-    pub value: String,
+    pub value: Symbol,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Keyword {
-    pub value: String,
+    pub kind: KeywordKind,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Constant {
     Int(IntegerConstant),
     Float(FloatConstant),
     Char(CharConstant),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IntegerConstant {
     pub literal: String,
+    pub value: u128,
+    pub base: IntegerBase,
+    pub suffix: IntegerSuffix,
+    pub ty: IntKind,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerBase {
+    Decimal,
+    Octal,
+    Hexadecimal,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerSuffix {
+    None,
+    U,
+    L,
+    UL,
+    LL,
+    ULL,
+    // bit-precise, `wb`
+    WB,
+    UWB,
+}
+
+// what is wrong with a constant, and where in its literal
+pub type LiteralError = (Range<usize>, String);
+
+// `value` is rounded to `float` precision for an `f` suffix; `long double` is kept as a `double`
+#[derive(Debug, Clone)]
 pub struct FloatConstant {
     pub literal: String,
+    pub value: f64,
+    pub suffix: FloatSuffix,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatSuffix {
+    None,
+    F,
+    L,
+}
+
+#[derive(Debug, Clone)]
 pub struct CharConstant {
-    pub value: char,
+    pub literal: String,
+    pub encoding: Encoding,
+    // of type `encoding.char_kind()`
+    pub value: i64,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StringLiteral {
-    pub value: String,
+    pub encoding: Encoding,
+    // the code units of the array, without the terminating null
+    pub value: Vec<u32>,
+    // `value` as text, see `text`
+    pub symbol: Symbol,
     pub span: Span,
 }
 
-#[derive(Debug)]
+// the prefix of a char constant or string literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    // UTF-8, the execution character set
+    None,
+    Utf8,
+    Utf16,
+    Utf32,
+    // `wchar_t` is a 32-bit `int` holding UTF-32
+    Wide,
+}
+
+// `kind` is what a digraph stands for, `spelling` the digraph itself
+#[derive(Debug, Clone)]
 pub struct Punctuator {
-    pub literal: String,
+    pub kind: PunctuatorKind,
+    pub spelling: &'static str,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Operator {
-    pub literal: String,
+    pub kind: PunctuatorKind,
+    pub spelling: &'static str,
     pub span: Span,
 }
 
-#[derive(Debug)]
+// `args` are as written, trigraphs and backslash-newlines included
+#[derive(Debug, Clone)]
 pub struct Directive {
     pub name: String,
     pub args: String,
+    pub args_span: Span,
+    pub span: Span,
+}
+
+// `literal` is as written
+#[derive(Debug, Clone)]
+pub struct ErrorToken {
+    pub literal: String,
+    pub span: Span,
+}
+
+// the text between tokens, see `Lexer::resolve_lossless`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    // spaces, tabs, form feeds and stray carriage returns
+    Whitespace,
+    // `\n` or `\r\n`
+    Newline,
+    // up to but not including the newline
+    LineComment,
+    // to the end of the input if unclosed
+    BlockComment,
+    // a backslash-newline between tokens
+    Splice,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
     pub span: Span,
 }
 
+// `trailing` is the trivia after the token up to the end of its line,
+// `leading` the rest of the trivia before it
+#[derive(Debug, Clone)]
+pub struct TriviaToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    pub trailing: Vec<Trivia>,
+}
+
+// the tokens of an input together with all the text between them
+#[derive(Debug, Clone)]
+pub struct LosslessTokens {
+    pub tokens: Vec<TriviaToken>,
+    // the trivia after the last token
+    pub end: Vec<Trivia>,
+    pub(crate) content: Rc<str>,
+    // the source map offset of `content`
+    pub(crate) start: u32,
+}
+
 impl Token {
-    pub fn span(&self) -> &Span {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Identifier(_) => TokenKind::Identifier,
+            Token::Keyword(kw) => TokenKind::Keyword(kw.kind),
+            Token::Constant(_) => TokenKind::Constant,
+            Token::StringLiteral(_) => TokenKind::StringLiteral,
+            Token::Punctuator(p) => TokenKind::Punctuator(p.kind),
+            Token::Operator(op) => TokenKind::Punctuator(op.kind),
+            Token::Directive(_) => TokenKind::Directive,
+            Token::Error(_) => TokenKind::Error,
+        }
+    }
+
+    pub fn span(&self) -> Span {
         match self {
-            Token::Identifier(ident) => &ident.span,
-            Token::Keyword(kw) => &kw.span,
-            Token::Constant(constant) => match constant {
-                Constant::Int(int) => &int.span,
-                Constant::Float(float) => &float.span,
-                Constant::Char(ch) => &ch.span,
-            },
-            Token::Operator(op) => &op.span,
-            Token::StringLiteral(s) => &s.span,
-            Token::Punctuator(p) => &p.span,
-            Token::Directive(d) => &d.span,
+            Token::Identifier(ident) => ident.span,
+            Token::Keyword(kw) => kw.span,
+            Token::Constant(constant) => constant.span(),
+            Token::Operator(op) => op.span,
+            Token::StringLiteral(s) => s.span,
+            Token::Punctuator(p) => p.span,
+            Token::Directive(d) => d.span,
+            Token::Error(e) => e.span,
         }
     }
 }
 
-impl Identifier {
-    pub fn is_keyword(&self) -> bool {
-        KEYWORD_TABLE.iter().any(|&s| s == self.value)
-    }
-}
-
-impl Punctuator {
-    pub fn is_operator(&self) -> bool {
-        OPERATOR_TABLE.iter().any(|&s| s == self.literal)
-    }
-}
-
-pub static KEYWORD_TABLE: [&str; 44] = [
-    "alignof",
-    "auto",
-    "break",
-    "case",
-    "char",
-    "const",
-    "continue",
-    "default",
-    "do",
-    "double",
-    "else",
-    "enum",
-    "extern",
-    "float",
-    "for",
-    "goto",
-    "if",
-    "inline",
-    "int",
-    "long",
-    "register",
-    "restrict",
-    "return",
-    "short",
-    "signed",
-    "sizeof",
-    "static",
-    "struct",
-    "switch",
-    "typedef",
-    "union",
-    "unsigned",
-    "void",
-    "volatile",
-    "while",
-    "_Alignas",
-    "_Atomic",
-    "_Bool",
-    "_Complex",
-    "_Generic",
-    "_Imaginary",
-    "_Noreturn",
-    "_Static_assert",
-    "_Thread_local",
-];
+impl Token {
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Token::Identifier(ident) => &mut ident.span,
+            Token::Keyword(kw) => &mut kw.span,
+            Token::Constant(Constant::Int(int)) => &mut int.span,
+            Token::Constant(Constant::Float(float)) => &mut float.span,
+            Token::Constant(Constant::Char(ch)) => &mut ch.span,
+            Token::Operator(op) => &mut op.span,
+            Token::StringLiteral(s) => &mut s.span,
+            Token::Punctuator(p) => &mut p.span,
+            Token::Directive(d) => &mut d.span,
+            Token::Error(e) => &mut e.span,
+        }
+    }
+
+    pub fn spelling(&self) -> String {
+        match self {
+            Token::Identifier(ident) => ident.value.to_string(),
+            Token::Keyword(kw) => kw.kind.as_str().into(),
+            Token::Constant(Constant::Int(int)) => int.literal.clone(),
+            Token::Constant(Constant::Float(float)) => float.literal.clone(),
+            Token::Constant(Constant::Char(ch)) => ch.literal.clone(),
+            Token::StringLiteral(s) => s.spelling(),
+            Token::Punctuator(p) => p.spelling.into(),
+            Token::Operator(op) => op.spelling.into(),
+            Token::Directive(d) => format!("#{} {}", d.name, d.args),
+            Token::Error(e) => e.literal.clone(),
+        }
+    }
+}
+
+fn units_text(encoding: Encoding, value: &[u32]) -> String {
+    match encoding {
+        Encoding::None | Encoding::Utf8 => {
+            let bytes: Vec<u8> = value.iter().map(|&unit| unit as u8).collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        Encoding::Utf16 => {
+            let units = value.iter().map(|&unit| unit as u16);
+            char::decode_utf16(units)
+                .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        Encoding::Utf32 | Encoding::Wide => value
+            .iter()
+            .map(|&unit| char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+    }
+}
+
+fn escape_char(ch: char, quote: char) -> String {
+    if ch == quote || ch == '\\' {
+        return format!("\\{}", ch);
+    }
+    match SIMPLE_ESCAPE_SEQUENCE_TABLE
+        .iter()
+        .find(|&&(c, v)| v == ch && c.is_ascii_alphabetic())
+    {
+        Some(&(c, _)) => format!("\\{}", c),
+        None if ch.is_ascii_graphic() || ch == ' ' => ch.into(),
+        // three digits, so that a following digit is not taken in
+        None => format!("\\{:03o}", ch as u32),
+    }
+}
+
+impl StringLiteral {
+    // an unprefixed literal holding `text`
+    pub fn new(text: &str, span: Span) -> Self {
+        Self {
+            encoding: Encoding::None,
+            value: text.bytes().map(u32::from).collect(),
+            symbol: Symbol::intern(text),
+            span,
+        }
+    }
+
+    pub fn from_units(encoding: Encoding, value: Vec<u32>, span: Span) -> Self {
+        let symbol = Symbol::intern(&units_text(encoding, &value));
+        Self {
+            encoding,
+            value,
+            symbol,
+            span,
+        }
+    }
+
+    // the bytes of the array, terminating null included, in little-endian order
+    pub fn bytes(&self) -> Vec<u8> {
+        let size = self.encoding.unit_kind().size() as usize;
+        let mut bytes = Vec::with_capacity((self.value.len() + 1) * size);
+        for &unit in self.value.iter().chain(Some(&0)) {
+            bytes.extend_from_slice(&unit.to_le_bytes()[..size]);
+        }
+        bytes
+    }
+
+    // the value as text, with units that are not part of a char replaced
+    pub fn text(&self) -> Rc<str> {
+        self.symbol.as_str()
+    }
+
+    fn spelling(&self) -> String {
+        let mut escaped = String::from(self.encoding.prefix());
+        escaped.push('"');
+        match self.encoding {
+            Encoding::None | Encoding::Utf8 => {
+                for &unit in &self.value {
+                    escaped += &escape_char(char::from(unit as u8), '"');
+                }
+            }
+            _ => {
+                for ch in self.text().chars() {
+                    escaped += &match ch {
+                        // C1 controls cannot be universal character names
+                        '\0'..='\u{9f}' => escape_char(ch, '"'),
+                        '\u{a0}'..='\u{ffff}' => format!("\\u{:04x}", ch as u32),
+                        _ => format!("\\U{:08x}", ch as u32),
+                    };
+                }
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    // appends an adjacent literal, as in translation phase 6 (C11 6.4.5p5)
+    pub fn concat(&mut self, other: &StringLiteral) -> Result<(), String> {
+        let encoding = match (self.encoding, other.encoding) {
+            (lhs, rhs) if lhs == rhs => lhs,
+            (Encoding::None, encoding) | (encoding, Encoding::None) => encoding,
+            (lhs, rhs) => {
+                return Err(format!(
+                    "unsupported concatenation of string literals with prefixes '{}' and '{}'",
+                    lhs.prefix(),
+                    rhs.prefix()
+                ))
+            }
+        };
+        self.value = self.units_in(encoding);
+        self.value.extend(other.units_in(encoding));
+        self.encoding = encoding;
+        self.symbol = Symbol::intern(&units_text(encoding, &self.value));
+        self.span = self.span.to(other.span);
+        Ok(())
+    }
+
+    // only an unprefixed literal is ever converted: its UTF-8 is decoded
+    // again, and bytes outside of any char, as from `\xff`, are kept as units
+    fn units_in(&self, encoding: Encoding) -> Vec<u32> {
+        if self.encoding.unit_kind() == encoding.unit_kind() {
+            return self.value.clone();
+        }
+        let bytes: Vec<u8> = self.value.iter().map(|&unit| unit as u8).collect();
+        let mut units = Vec::new();
+        for chunk in bytes.utf8_chunks() {
+            for ch in chunk.valid().chars() {
+                encoding.encode(ch, &mut units);
+            }
+            units.extend(chunk.invalid().iter().map(|&b| u32::from(b)));
+        }
+        units
+    }
+}
+
+impl Encoding {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Encoding::None => "",
+            Encoding::Utf8 => "u8",
+            Encoding::Utf16 => "u",
+            Encoding::Utf32 => "U",
+            Encoding::Wide => "L",
+        }
+    }
+
+    // the element type of a string literal
+    pub fn unit_kind(self) -> IntKind {
+        match self {
+            Encoding::None | Encoding::Utf8 => IntKind::Char,
+            // `char16_t`, `char32_t` and `wchar_t`
+            Encoding::Utf16 => IntKind::UShort,
+            Encoding::Utf32 => IntKind::UInt,
+            Encoding::Wide => IntKind::Int,
+        }
+    }
 
-pub static OPERATOR_TABLE: [&str; 35] = [
-    ".", "->", "++", "--", "&", "*", "+", "-", "~", "!", "/", "%", "<<", ">>", "<", ">", "<=",
-    ">=", "=", "*=", "/=", "%=", "+=", "-=", "<<=", "==", ">>=", "!=", "&=", "^", "|", "^=", "&&",
-    "||", "|=",
+    // the type of a char constant; `u8` ones are C2x's `unsigned char`
+    pub fn char_kind(self) -> IntKind {
+        match self {
+            Encoding::None => IntKind::Int,
+            Encoding::Utf8 => IntKind::UChar,
+            _ => self.unit_kind(),
+        }
+    }
+
+    // the largest value of a code unit, as from an octal or hex escape
+    pub fn unit_max(self) -> u32 {
+        match self.unit_kind().size() {
+            1 => 0xFF,
+            2 => 0xFFFF,
+            _ => u32::MAX,
+        }
+    }
+
+    pub fn encode(self, ch: char, units: &mut Vec<u32>) {
+        match self {
+            Encoding::None | Encoding::Utf8 => {
+                units.extend(ch.encode_utf8(&mut [0; 4]).bytes().map(u32::from));
+            }
+            Encoding::Utf16 => {
+                units.extend(ch.encode_utf16(&mut [0; 2]).iter().map(|&u| u32::from(u)))
+            }
+            Encoding::Utf32 | Encoding::Wide => units.push(ch as u32),
+        }
+    }
+
+    // whether a literal can initialize an array of `kind` (C11 6.7.9p14-15)
+    pub fn initializes(self, kind: IntKind) -> bool {
+        match self {
+            Encoding::None | Encoding::Utf8 => kind.size() == 1,
+            _ => kind == self.unit_kind(),
+        }
+    }
+}
+
+impl Constant {
+    pub fn span(&self) -> Span {
+        match self {
+            Constant::Int(int) => int.span,
+            Constant::Float(float) => float.span,
+            Constant::Char(ch) => ch.span,
+        }
+    }
+}
+
+impl LosslessTokens {
+    // the text of `span` as written, trigraphs and splices included
+    pub fn text_of(&self, span: Span) -> &str {
+        &self.content[(span.lo - self.start) as usize..(span.hi - self.start) as usize]
+    }
+
+    // the input, byte for byte
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.content.len());
+        let trivia = |text: &mut String, trivia: &[Trivia]| {
+            for t in trivia {
+                text.push_str(self.text_of(t.span));
+            }
+        };
+        for t in &self.tokens {
+            trivia(&mut text, &t.leading);
+            text.push_str(self.text_of(t.token.span()));
+            trivia(&mut text, &t.trailing);
+        }
+        trivia(&mut text, &self.end);
+        text
+    }
+}
+
+// defines a `Copy` enum of the token spellings in a table, with `ALL`,
+// `as_str` and `FromStr`
+macro_rules! token_kinds {
+    ($name:ident; $len:literal; $($kind:ident = $s:literal,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($kind,)*
+        }
+
+        impl $name {
+            pub const ALL: [$name; $len] = [$($name::$kind,)*];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$kind => $s,)*
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ();
+
+            fn from_str(s: &str) -> Result<Self, ()> {
+                match s {
+                    $($s => Ok($name::$kind),)*
+                    _ => Err(()),
+                }
+            }
+        }
+    };
+}
+
+token_kinds! { KeywordKind; 44;
+    Alignof = "alignof",
+    Auto = "auto",
+    Break = "break",
+    Case = "case",
+    Char = "char",
+    Const = "const",
+    Continue = "continue",
+    Default = "default",
+    Do = "do",
+    Double = "double",
+    Else = "else",
+    Enum = "enum",
+    Extern = "extern",
+    Float = "float",
+    For = "for",
+    Goto = "goto",
+    If = "if",
+    Inline = "inline",
+    Int = "int",
+    Long = "long",
+    Register = "register",
+    Restrict = "restrict",
+    Return = "return",
+    Short = "short",
+    Signed = "signed",
+    Sizeof = "sizeof",
+    Static = "static",
+    Struct = "struct",
+    Switch = "switch",
+    Typedef = "typedef",
+    Union = "union",
+    Unsigned = "unsigned",
+    Void = "void",
+    Volatile = "volatile",
+    While = "while",
+    Alignas = "_Alignas",
+    Atomic = "_Atomic",
+    Bool = "_Bool",
+    Complex = "_Complex",
+    Generic = "_Generic",
+    Imaginary = "_Imaginary",
+    Noreturn = "_Noreturn",
+    StaticAssert = "_Static_assert",
+    ThreadLocal = "_Thread_local",
+}
+
+// C11 6.4.6p1 without the digraphs, see `DIGRAPH_TABLE`
+token_kinds! { PunctuatorKind; 48;
+    LBracket = "[",
+    RBracket = "]",
+    LParen = "(",
+    RParen = ")",
+    LBrace = "{",
+    RBrace = "}",
+    Dot = ".",
+    Arrow = "->",
+    PlusPlus = "++",
+    MinusMinus = "--",
+    Amp = "&",
+    Star = "*",
+    Plus = "+",
+    Minus = "-",
+    Tilde = "~",
+    Bang = "!",
+    Slash = "/",
+    Percent = "%",
+    Shl = "<<",
+    Shr = ">>",
+    Lt = "<",
+    Gt = ">",
+    Le = "<=",
+    Ge = ">=",
+    EqEq = "==",
+    Ne = "!=",
+    Caret = "^",
+    Pipe = "|",
+    AmpAmp = "&&",
+    PipePipe = "||",
+    Question = "?",
+    Colon = ":",
+    Semi = ";",
+    Ellipsis = "...",
+    Eq = "=",
+    StarEq = "*=",
+    SlashEq = "/=",
+    PercentEq = "%=",
+    PlusEq = "+=",
+    MinusEq = "-=",
+    ShlEq = "<<=",
+    ShrEq = ">>=",
+    AmpEq = "&=",
+    CaretEq = "^=",
+    PipeEq = "|=",
+    Comma = ",",
+    Hash = "#",
+    HashHash = "##",
+}
+
+impl PunctuatorKind {
+    pub fn is_operator(self) -> bool {
+        use PunctuatorKind::*;
+        !matches!(
+            self,
+            LBracket
+                | RBracket
+                | LParen
+                | RParen
+                | LBrace
+                | RBrace
+                | Question
+                | Colon
+                | Semi
+                | Ellipsis
+                | Comma
+                | Hash
+                | HashHash
+        )
+    }
+}
+
+// `u8` goes before `u`
+pub static ENCODING_PREFIX_TABLE: [(&str, Encoding); 4] = [
+    ("u8", Encoding::Utf8),
+    ("u", Encoding::Utf16),
+    ("U", Encoding::Utf32),
+    ("L", Encoding::Wide),
 ];
 
 pub static SIMPLE_ESCAPE_SEQUENCE_TABLE: [(char, char); 12] = [
@@ -171,30 +668,89 @@ pub static SIMPLE_ESCAPE_SEQUENCE_TABLE: [(char, char); 12] = [
     ('0', '\0'),
 ];
 
-pub static INTEGER_SUFFIX_TABLE: [&str; 22] = [
+pub static INTEGER_SUFFIX_TABLE: [&str; 32] = [
     "u", "U", "l", "L", "ll", "LL", "ul", "uL", "ull", "uLL", "Ul", "UL", "Ull", "ULL", "lu", "Lu",
-    "llu", "LLu", "lU", "LU", "llU", "LLU",
+    "llu", "LLu", "lU", "LU", "llU", "LLU", "wb", "WB", "uwb", "uWB", "Uwb", "UWB", "wbu", "WBu",
+    "wbU", "WBU",
 ];
 
-pub static PUNCTUATOR_LEN1_TABLE: [char; 24] = [
+// C11 6.4.6p3, the punctuators spelled differently
+pub static DIGRAPH_TABLE: [(&str, PunctuatorKind); 6] = [
+    ("<:", PunctuatorKind::LBracket),
+    (":>", PunctuatorKind::RBracket),
+    ("<%", PunctuatorKind::LBrace),
+    ("%>", PunctuatorKind::RBrace),
+    ("%:", PunctuatorKind::Hash),
+    ("%:%:", PunctuatorKind::HashHash),
+];
+
+// C11 5.2.1.1, the char after `??` and the one the trigraph stands for
+pub static TRIGRAPH_TABLE: [(char, char); 9] = [
+    ('=', '#'),
+    ('(', '['),
+    ('/', '\\'),
+    (')', ']'),
+    ('\'', '^'),
+    ('<', '{'),
+    ('!', '|'),
+    ('>', '}'),
+    ('-', '~'),
+];
+
+// the punctuators of one char, which also start all the longer ones
+pub static PUNCTUATOR_LEN1_TABLE: [char; 25] = [
     '[', ']', '(', ')', '{', '}', '~', '?', ':', ';', '.', ',', '+', '-', '*', '/', '%', '&', '^',
-    '!', '|', '<', '>', '=',
+    '!', '|', '<', '>', '=', '#',
+];
+
+// chars typed by mistake, as with a Chinese input method, for what they look like
+pub static CONFUSABLE_TABLE: [(char, char); 20] = [
+    ('“', '"'),
+    ('”', '"'),
+    ('‘', '\''),
+    ('’', '\''),
+    ('；', ';'),
+    ('，', ','),
+    ('。', '.'),
+    ('：', ':'),
+    ('（', '('),
+    ('）', ')'),
+    ('【', '['),
+    ('】', ']'),
+    ('｛', '{'),
+    ('｝', '}'),
+    ('＝', '='),
+    ('！', '!'),
+    ('？', '?'),
+    ('＋', '+'),
+    ('－', '-'),
+    ('\u{3000}', ' '),
 ];
 
 impl IntegerConstant {
-    pub fn validate(literal: String, span: Span) -> Result<Self, String> {
-        let mut chars = literal.chars();
+    pub fn validate(literal: String, span: Span) -> Result<Self, LiteralError> {
+        let len = literal.len();
+        let mut chars = literal.char_indices();
 
         let mut state = 0;
 
-        let mut suffix = String::new();
-        let error_invalid_char = |ch: char| format!("invalid char in integer constant: {:?}", ch);
+        let mut suffix_start = len;
+        let error_at = |idx: usize, ch: char, msg: String| (idx..idx + ch.len_utf8(), msg);
+        let error_invalid_char = |idx: usize, ch: char| {
+            error_at(
+                idx,
+                ch,
+                format!("invalid char in integer constant: {:?}", ch),
+            )
+        };
+        let error_separator =
+            |idx: usize| (idx..idx + 1, "expected digit after digit separator".into());
 
         'dfa: loop {
             match state {
                 0 => match chars.next() {
                     None => panic!("empty integer constant"),
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '1'..='9' => {
                             state = 1;
                             continue 'dfa;
@@ -203,7 +759,7 @@ impl IntegerConstant {
                             state = 2;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 1 => match chars.next() {
@@ -211,17 +767,21 @@ impl IntegerConstant {
                         state = 8;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 3;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 11;
+                            continue 'dfa;
+                        }
                         'A'..='Z' | 'a'..='z' => {
-                            suffix = ch.into();
+                            suffix_start = idx;
                             state = 7;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 2 => match chars.next() {
@@ -229,24 +789,36 @@ impl IntegerConstant {
                         state = 8;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         'x' | 'X' => {
                             state = 4;
                             continue 'dfa;
                         }
+                        'b' | 'B' => {
+                            state = 9;
+                            continue 'dfa;
+                        }
                         '0'..='7' => {
                             state = 5;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 12;
+                            continue 'dfa;
+                        }
                         '8'..='9' => {
-                            return Err(format!("invalid digit {:?} in octal constant", ch))
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid digit {:?} in octal constant", ch),
+                            ))
                         }
                         'A'..='Z' | 'a'..='z' => {
-                            suffix = ch.into();
+                            suffix_start = idx;
                             state = 7;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 3 => match chars.next() {
@@ -254,27 +826,43 @@ impl IntegerConstant {
                         state = 8;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 3;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 11;
+                            continue 'dfa;
+                        }
                         'A'..='Z' | 'a'..='z' => {
-                            suffix = ch.into();
+                            suffix_start = idx;
                             state = 7;
                             continue 'dfa;
                         }
-                        _ => return Err(format!("invalid char in decimal constant: {:?}", ch)),
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in decimal constant: {:?}", ch),
+                            ))
+                        }
                     },
                 },
                 4 => match chars.next() {
-                    None => return Err("expected hexadecimal digit".into()),
-                    Some(ch) => match ch {
+                    None => return Err((len..len, "expected hexadecimal digit".into())),
+                    Some((idx, ch)) => match ch {
                         '0'..='9' | 'A'..='F' | 'a'..='f' => {
                             state = 6;
                             continue 'dfa;
                         }
-                        _ => return Err(format!("invalid char in hexadecimal constant: {:?}", ch)),
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in hexadecimal constant: {:?}", ch),
+                            ))
+                        }
                     },
                 },
                 5 => match chars.next() {
@@ -282,20 +870,34 @@ impl IntegerConstant {
                         state = 8;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='7' => {
                             state = 5;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 12;
+                            continue 'dfa;
+                        }
                         '8'..='9' => {
-                            return Err(format!("invalid digit in octal constant: {:?} ", ch))
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid digit {:?} in octal constant", ch),
+                            ))
                         }
                         'A'..='Z' | 'a'..='z' => {
-                            suffix = ch.into();
+                            suffix_start = idx;
                             state = 7;
                             continue 'dfa;
                         }
-                        _ => return Err(format!("invalid char in octal constant: {:?}", ch)),
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in octal constant: {:?}", ch),
+                            ))
+                        }
                     },
                 },
                 6 => match chars.next() {
@@ -303,36 +905,156 @@ impl IntegerConstant {
                         state = 8;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' | 'A'..='F' | 'a'..='f' => {
                             state = 6;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 13;
+                            continue 'dfa;
+                        }
                         'A'..='Z' | 'a'..='z' => {
-                            suffix = ch.into();
+                            suffix_start = idx;
                             state = 7;
                             continue 'dfa;
                         }
-                        _ => return Err(format!("invalid char in hexadecimal constant: {:?}", ch)),
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in hexadecimal constant: {:?}", ch),
+                            ))
+                        }
                     },
                 },
                 7 => match chars.next() {
                     None => {
-                        let is_valid_suffix = INTEGER_SUFFIX_TABLE.iter().any(|&s| s == suffix);
+                        let suffix = &literal[suffix_start..];
+                        let is_valid_suffix = INTEGER_SUFFIX_TABLE.contains(&suffix);
                         if is_valid_suffix {
                             state = 8;
                             continue 'dfa;
                         } else {
-                            return Err(format!("invalid integer suffix: {:?}", suffix));
+                            // the first char no valid suffix has there, or all
+                            // of it if it is only cut short
+                            let range = suffix
+                                .char_indices()
+                                .find(|&(idx, ch)| {
+                                    let prefix = &suffix[..idx + ch.len_utf8()];
+                                    !INTEGER_SUFFIX_TABLE.iter().any(|s| s.starts_with(prefix))
+                                })
+                                .map_or(suffix_start..len, |(idx, ch)| {
+                                    suffix_start + idx..suffix_start + idx + ch.len_utf8()
+                                });
+                            return Err((range, format!("invalid integer suffix: {:?}", suffix)));
                         }
                     }
-                    Some(ch) => {
-                        suffix.push(ch);
+                    Some(_) => {
                         state = 7;
                         continue 'dfa;
                     }
                 },
-                8 => return Ok(IntegerConstant { literal, span }),
+                8 => return Self::evaluate(literal, suffix_start, span),
+                9 => match chars.next() {
+                    None => return Err((len..len, "expected binary digit".into())),
+                    Some((idx, ch)) => match ch {
+                        '0' | '1' => {
+                            state = 10;
+                            continue 'dfa;
+                        }
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in binary constant: {:?}", ch),
+                            ))
+                        }
+                    },
+                },
+                10 => match chars.next() {
+                    None => {
+                        state = 8;
+                        continue 'dfa;
+                    }
+                    Some((idx, ch)) => match ch {
+                        '0' | '1' => {
+                            state = 10;
+                            continue 'dfa;
+                        }
+                        '\'' => {
+                            state = 14;
+                            continue 'dfa;
+                        }
+                        '2'..='9' => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid digit {:?} in binary constant", ch),
+                            ))
+                        }
+                        'A'..='Z' | 'a'..='z' => {
+                            suffix_start = idx;
+                            state = 7;
+                            continue 'dfa;
+                        }
+                        _ => {
+                            return Err(error_at(
+                                idx,
+                                ch,
+                                format!("invalid char in binary constant: {:?}", ch),
+                            ))
+                        }
+                    },
+                },
+                // 11 to 14: a digit separator, which must be followed by a
+                // digit of the same base
+                11 => match chars.next() {
+                    Some((_, '0'..='9')) => {
+                        state = 3;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                12 => match chars.next() {
+                    Some((_, '0'..='7')) => {
+                        state = 5;
+                        continue 'dfa;
+                    }
+                    Some((idx, ch @ '8'..='9')) => {
+                        return Err(error_at(
+                            idx,
+                            ch,
+                            format!("invalid digit {:?} in octal constant", ch),
+                        ))
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                13 => match chars.next() {
+                    Some((_, '0'..='9' | 'A'..='F' | 'a'..='f')) => {
+                        state = 6;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                14 => match chars.next() {
+                    Some((_, '0' | '1')) => {
+                        state = 10;
+                        continue 'dfa;
+                    }
+                    Some((idx, ch @ '2'..='9')) => {
+                        return Err(error_at(
+                            idx,
+                            ch,
+                            format!("invalid digit {:?} in binary constant", ch),
+                        ))
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
                 _ => unreachable!(),
             }
         }
@@ -340,9 +1062,17 @@ impl IntegerConstant {
 }
 
 impl FloatConstant {
-    pub fn validate(literal: String, span: Span) -> Result<Self, String> {
-        let mut chars = literal.chars().peekable();
-        let error_invalid_char = |ch: char| format!("invalid char in float constant: {:?}", ch);
+    pub fn validate(literal: String, span: Span) -> Result<Self, LiteralError> {
+        let len = literal.len();
+        let mut chars = literal.char_indices();
+        let error_invalid_char = |idx: usize, ch: char| {
+            (
+                idx..idx + ch.len_utf8(),
+                format!("invalid char in float constant: {:?}", ch),
+            )
+        };
+        let error_separator =
+            |idx: usize| (idx..idx + 1, "expected digit after digit separator".into());
 
         let mut state = 0;
 
@@ -350,8 +1080,12 @@ impl FloatConstant {
             match state {
                 0 => match chars.next() {
                     None => panic!("empty float literal"),
-                    Some(ch) => match ch {
-                        '0'..='9' => {
+                    Some((idx, ch)) => match ch {
+                        '0' => {
+                            state = 11;
+                            continue 'dfa;
+                        }
+                        '1'..='9' => {
                             state = 1;
                             continue 'dfa;
                         }
@@ -359,16 +1093,20 @@ impl FloatConstant {
                             state = 2;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 1 => match chars.next() {
-                    None => return Err("invalid float constant".into()),
-                    Some(ch) => match ch {
+                    None => return Err((len..len, "invalid float constant".into())),
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 1;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 16;
+                            continue 'dfa;
+                        }
                         '.' => {
                             state = 3;
                             continue 'dfa;
@@ -377,17 +1115,17 @@ impl FloatConstant {
                             state = 6;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 2 => match chars.next() {
-                    None => return Err("expected digit sequence".into()),
-                    Some(ch) => match ch {
+                    None => return Err((len..len, "expected digit sequence".into())),
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 4;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 3 => match chars.next() {
@@ -395,7 +1133,7 @@ impl FloatConstant {
                         state = 10;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 5;
                             continue 'dfa;
@@ -408,7 +1146,7 @@ impl FloatConstant {
                             state = 9;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 4 => match chars.next() {
@@ -416,11 +1154,15 @@ impl FloatConstant {
                         state = 10;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 4;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 17;
+                            continue 'dfa;
+                        }
                         'e' | 'E' => {
                             state = 6;
                             continue 'dfa;
@@ -429,7 +1171,7 @@ impl FloatConstant {
                             state = 9;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 5 => match chars.next() {
@@ -437,11 +1179,15 @@ impl FloatConstant {
                         state = 10;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 5;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 17;
+                            continue 'dfa;
+                        }
                         'e' | 'E' => {
                             state = 6;
                             continue 'dfa;
@@ -450,13 +1196,12 @@ impl FloatConstant {
                             state = 9;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
-
                 6 => match chars.next() {
-                    None => return Err("expected exponent part".into()),
-                    Some(ch) => match ch {
+                    None => return Err((len..len, "expected exponent part".into())),
+                    Some((idx, ch)) => match ch {
                         '+' | '-' => {
                             state = 7;
                             continue 'dfa;
@@ -465,36 +1210,38 @@ impl FloatConstant {
                             state = 8;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
-
                 7 => match chars.next() {
-                    None => return Err("expected exponent part".into()),
-                    Some(ch) => match ch {
+                    None => return Err((len..len, "expected exponent part".into())),
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 8;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
-
                 8 => match chars.next() {
                     None => {
                         state = 10;
                         continue 'dfa;
                     }
-                    Some(ch) => match ch {
+                    Some((idx, ch)) => match ch {
                         '0'..='9' => {
                             state = 8;
                             continue 'dfa;
                         }
+                        '\'' => {
+                            state = 18;
+                            continue 'dfa;
+                        }
                         'f' | 'F' | 'l' | 'L' => {
                             state = 9;
                             continue 'dfa;
                         }
-                        _ => return Err(error_invalid_char(ch)),
+                        _ => return Err(error_invalid_char(idx, ch)),
                     },
                 },
                 9 => match chars.next() {
@@ -502,26 +1249,351 @@ impl FloatConstant {
                         state = 10;
                         continue 'dfa;
                     }
-                    Some(ch) => return Err(error_invalid_char(ch)),
+                    Some((idx, ch)) => return Err(error_invalid_char(idx, ch)),
+                },
+                10 => return Self::evaluate(literal, span),
+                11 => match chars.next() {
+                    None => return Err((len..len, "invalid float constant".into())),
+                    Some((idx, ch)) => match ch {
+                        '0'..='9' => {
+                            state = 1;
+                            continue 'dfa;
+                        }
+                        '\'' => {
+                            state = 16;
+                            continue 'dfa;
+                        }
+                        '.' => {
+                            state = 3;
+                            continue 'dfa;
+                        }
+                        'e' | 'E' => {
+                            state = 6;
+                            continue 'dfa;
+                        }
+                        'x' | 'X' => {
+                            state = 12;
+                            continue 'dfa;
+                        }
+                        _ => return Err(error_invalid_char(idx, ch)),
+                    },
+                },
+                12 => match chars.next() {
+                    None => return Err((len..len, "expected hexadecimal digit".into())),
+                    Some((idx, ch)) => match ch {
+                        '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                            state = 13;
+                            continue 'dfa;
+                        }
+                        '.' => {
+                            state = 14;
+                            continue 'dfa;
+                        }
+                        _ => return Err(error_invalid_char(idx, ch)),
+                    },
+                },
+                13 => match chars.next() {
+                    None => {
+                        return Err((
+                            len..len,
+                            "hexadecimal floating constant requires an exponent".into(),
+                        ))
+                    }
+                    Some((idx, ch)) => match ch {
+                        '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                            state = 13;
+                            continue 'dfa;
+                        }
+                        '\'' => {
+                            state = 19;
+                            continue 'dfa;
+                        }
+                        '.' => {
+                            state = 15;
+                            continue 'dfa;
+                        }
+                        'p' | 'P' => {
+                            state = 6;
+                            continue 'dfa;
+                        }
+                        _ => return Err(error_invalid_char(idx, ch)),
+                    },
+                },
+                14 => match chars.next() {
+                    None => return Err((len..len, "expected hexadecimal digit".into())),
+                    Some((idx, ch)) => match ch {
+                        '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                            state = 15;
+                            continue 'dfa;
+                        }
+                        _ => return Err(error_invalid_char(idx, ch)),
+                    },
+                },
+                15 => match chars.next() {
+                    None => {
+                        return Err((
+                            len..len,
+                            "hexadecimal floating constant requires an exponent".into(),
+                        ))
+                    }
+                    Some((idx, ch)) => match ch {
+                        '0'..='9' | 'A'..='F' | 'a'..='f' => {
+                            state = 15;
+                            continue 'dfa;
+                        }
+                        '\'' => {
+                            state = 20;
+                            continue 'dfa;
+                        }
+                        'p' | 'P' => {
+                            state = 6;
+                            continue 'dfa;
+                        }
+                        _ => return Err(error_invalid_char(idx, ch)),
+                    },
+                },
+                // 16 to 20: a digit separator, which must be followed by a digit of
+                // the same part
+                16 => match chars.next() {
+                    Some((_, '0'..='9')) => {
+                        state = 1;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                17 => match chars.next() {
+                    Some((_, '0'..='9')) => {
+                        state = 5;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                18 => match chars.next() {
+                    Some((_, '0'..='9')) => {
+                        state = 8;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                19 => match chars.next() {
+                    Some((_, '0'..='9' | 'A'..='F' | 'a'..='f')) => {
+                        state = 13;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
+                },
+                20 => match chars.next() {
+                    Some((_, '0'..='9' | 'A'..='F' | 'a'..='f')) => {
+                        state = 15;
+                        continue 'dfa;
+                    }
+                    Some((idx, _)) => return Err(error_separator(idx)),
+                    None => return Err(error_separator(len)),
                 },
-                10 => return Ok(Self { literal, span }),
                 _ => unreachable!(),
             }
         }
     }
 }
 
+impl IntegerConstant {
+    fn evaluate(literal: String, suffix_start: usize, span: Span) -> Result<Self, LiteralError> {
+        let digits = literal[..suffix_start].replace('\'', "");
+        let (digits, base) = match digits.get(..2) {
+            Some("0x" | "0X") => (&digits[2..], IntegerBase::Hexadecimal),
+            Some("0b" | "0B") => (&digits[2..], IntegerBase::Binary),
+            _ if digits.starts_with('0') => (&digits[..], IntegerBase::Octal),
+            _ => (&digits[..], IntegerBase::Decimal),
+        };
+        let radix = match base {
+            IntegerBase::Decimal => 10,
+            IntegerBase::Octal => 8,
+            IntegerBase::Hexadecimal => 16,
+            IntegerBase::Binary => 2,
+        };
+        let error_too_large = || (0..literal.len(), "integer constant is too large".to_owned());
+        let value = u128::from_str_radix(digits, radix).map_err(|_| error_too_large())?;
+
+        let suffix = IntegerSuffix::parse(&literal[suffix_start..]);
+        let ty = suffix
+            .candidates(base)
+            .iter()
+            .copied()
+            .find(|ty| value <= ty.max() as u128)
+            .ok_or_else(error_too_large)?;
+
+        Ok(Self {
+            literal,
+            value,
+            base,
+            suffix,
+            ty,
+            span,
+        })
+    }
+}
+
+impl IntegerSuffix {
+    fn parse(suffix: &str) -> Self {
+        let suffix = suffix.to_ascii_lowercase();
+        let is_unsigned = suffix.contains('u');
+        match (suffix.trim_matches('u'), is_unsigned) {
+            ("", false) => IntegerSuffix::None,
+            ("", true) => IntegerSuffix::U,
+            ("l", false) => IntegerSuffix::L,
+            ("l", true) => IntegerSuffix::UL,
+            ("ll", false) => IntegerSuffix::LL,
+            ("ll", true) => IntegerSuffix::ULL,
+            ("wb", false) => IntegerSuffix::WB,
+            ("wb", true) => IntegerSuffix::UWB,
+            _ => unreachable!("suffix checked against INTEGER_SUFFIX_TABLE"),
+        }
+    }
+
+    // the types an integer constant may have, in order (C23 6.4.4.1p6)
+    pub fn candidates(self, base: IntegerBase) -> &'static [IntKind] {
+        use IntKind::*;
+        let is_decimal = base == IntegerBase::Decimal;
+        match self {
+            IntegerSuffix::None if is_decimal => &[Int, Long, LongLong],
+            IntegerSuffix::None => &[Int, UInt, Long, ULong, LongLong, ULongLong],
+            IntegerSuffix::U => &[UInt, ULong, ULongLong],
+            IntegerSuffix::L if is_decimal => &[Long, LongLong],
+            IntegerSuffix::L => &[Long, ULong, LongLong, ULongLong],
+            IntegerSuffix::UL => &[ULong, ULongLong],
+            IntegerSuffix::LL if is_decimal => &[LongLong],
+            IntegerSuffix::LL => &[LongLong, ULongLong],
+            IntegerSuffix::ULL => &[ULongLong],
+            // `_BitInt(N)` is not supported: the narrowest standard type
+            // that holds the value stands in for it
+            IntegerSuffix::WB => &[Int, Long],
+            IntegerSuffix::UWB => &[UInt, ULong],
+        }
+    }
+}
+
+impl FloatConstant {
+    fn evaluate(literal: String, span: Span) -> Result<Self, LiteralError> {
+        let (digits, suffix) = match literal.chars().last() {
+            Some('f' | 'F') => (&literal[..literal.len() - 1], FloatSuffix::F),
+            Some('l' | 'L') => (&literal[..literal.len() - 1], FloatSuffix::L),
+            _ => (literal.as_str(), FloatSuffix::None),
+        };
+        let digits = digits.replace('\'', "");
+        let is_float = suffix == FloatSuffix::F;
+        let value = match digits.get(..2) {
+            Some("0x" | "0X") => hex_float_value(&digits[2..], is_float),
+            // parse directly at the target precision so the value is rounded only once
+            _ if is_float => digits
+                .parse::<f32>()
+                .map(f64::from)
+                .map_err(|_| (0..literal.len(), "invalid float constant".to_owned()))?,
+            _ => digits
+                .parse::<f64>()
+                .map_err(|_| (0..literal.len(), "invalid float constant".to_owned()))?,
+        };
+
+        Ok(Self {
+            literal,
+            value,
+            suffix,
+            span,
+        })
+    }
+
+    pub fn ty(&self) -> FloatKind {
+        match self.suffix {
+            FloatSuffix::None => FloatKind::Double,
+            FloatSuffix::F => FloatKind::Float,
+            FloatSuffix::L => FloatKind::LongDouble,
+        }
+    }
+}
+
+// the value of the digits and binary exponent of a hexadecimal float, after
+// the `0x`; rounded once to the target precision unless it is subnormal
+fn hex_float_value(digits: &str, is_float: bool) -> f64 {
+    let (mantissa, exponent) = digits.split_once(['p', 'P']).unwrap();
+    let mut exponent = exponent
+        .parse::<i64>()
+        .unwrap_or(if exponent.starts_with('-') {
+            i64::MIN
+        } else {
+            i64::MAX
+        })
+        .clamp(-100_000, 100_000);
+
+    // 60 bits are more than either precision; any digit left out that is
+    // not zero sets the lowest bit, to round as if it were kept
+    let mut bits = 0u64;
+    let mut is_fraction = false;
+    for ch in mantissa.chars() {
+        let digit = match ch.to_digit(16) {
+            Some(digit) => u64::from(digit),
+            None => {
+                is_fraction = true;
+                continue;
+            }
+        };
+        if bits >> 56 == 0 {
+            bits = bits << 4 | digit;
+            if is_fraction {
+                exponent -= 4;
+            }
+        } else {
+            bits |= u64::from(digit != 0);
+            if !is_fraction {
+                exponent += 4;
+            }
+        }
+    }
+
+    let mut value = if is_float {
+        f64::from(bits as f32)
+    } else {
+        bits as f64
+    };
+    // in steps whose factor is a normal number
+    while exponent != 0 && value != 0.0 && value.is_finite() {
+        let step = exponent.clamp(-1000, 1000);
+        value *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    if is_float {
+        f64::from(value as f32)
+    } else {
+        value
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::span::LineColumn;
 
     fn dummy_span() -> Span {
-        Span {
-            byte_range: 0..0,
-            lc_range: LineColumn { line: 0, column: 0 }..LineColumn { line: 0, column: 0 },
-            file_path: "<dummy file>".into(),
+        Span::default()
+    }
+
+    #[test]
+    fn token_kinds() {
+        for kind in KeywordKind::ALL {
+            assert_eq!(kind.as_str().parse(), Ok(kind));
+        }
+        for kind in PunctuatorKind::ALL {
+            assert_eq!(kind.as_str().parse(), Ok(kind));
         }
+        assert_eq!("Int".parse::<KeywordKind>(), Err(()));
+        // digraphs are only spellings
+        assert_eq!("<:".parse::<PunctuatorKind>(), Err(()));
+
+        let operators = PunctuatorKind::ALL.iter().filter(|kind| kind.is_operator());
+        assert_eq!(operators.count(), 35);
+        assert!(PunctuatorKind::Eq.is_operator());
+        assert!(!PunctuatorKind::Comma.is_operator());
     }
 
     #[test]
@@ -530,11 +1602,18 @@ mod test {
         assert!(FloatConstant::validate(".2e-34".into(), dummy_span()).is_ok());
         assert!(FloatConstant::validate(".2".into(), dummy_span()).is_ok());
         assert!(FloatConstant::validate("1.".into(), dummy_span()).is_ok());
+        assert!(FloatConstant::validate("1e5".into(), dummy_span()).is_ok());
+        assert!(FloatConstant::validate("0x1.8p+3f".into(), dummy_span()).is_ok());
+        assert!(FloatConstant::validate("0x.8P-1".into(), dummy_span()).is_ok());
+        assert!(FloatConstant::validate("1'000.000'1e1'0".into(), dummy_span()).is_ok());
 
         assert!(FloatConstant::validate(".".into(), dummy_span()).is_err());
         assert!(FloatConstant::validate("1.e".into(), dummy_span()).is_err());
         assert!(FloatConstant::validate("1.e-".into(), dummy_span()).is_err());
         assert!(FloatConstant::validate("1.e-x".into(), dummy_span()).is_err());
+        assert!(FloatConstant::validate("0x1.8".into(), dummy_span()).is_err());
+        assert!(FloatConstant::validate("0x.p1".into(), dummy_span()).is_err());
+        assert!(FloatConstant::validate("1.'5".into(), dummy_span()).is_err());
     }
 
     #[test]
@@ -545,10 +1624,115 @@ mod test {
         assert!(IntegerConstant::validate("0xff".into(), dummy_span()).is_ok());
         assert!(IntegerConstant::validate("0xffL".into(), dummy_span()).is_ok());
         assert!(IntegerConstant::validate("1U".into(), dummy_span()).is_ok());
+        assert!(IntegerConstant::validate("0b1010".into(), dummy_span()).is_ok());
+        assert!(IntegerConstant::validate("1'000'000".into(), dummy_span()).is_ok());
+        assert!(IntegerConstant::validate("0'17".into(), dummy_span()).is_ok());
+        assert!(IntegerConstant::validate("0xdead'beefuwb".into(), dummy_span()).is_ok());
 
         assert!(IntegerConstant::validate("0178".into(), dummy_span()).is_err());
         assert!(IntegerConstant::validate("0xgg".into(), dummy_span()).is_err());
         assert!(IntegerConstant::validate(".".into(), dummy_span()).is_err());
         assert!(IntegerConstant::validate("1.0".into(), dummy_span()).is_err());
+        assert!(IntegerConstant::validate("0b".into(), dummy_span()).is_err());
+        assert!(IntegerConstant::validate("0x'1".into(), dummy_span()).is_err());
+        assert!(IntegerConstant::validate("1''0".into(), dummy_span()).is_err());
+        assert!(IntegerConstant::validate("1wB".into(), dummy_span()).is_err());
+    }
+
+    #[test]
+    fn integer_values() {
+        let int = |literal: &str| {
+            let int = IntegerConstant::validate(literal.into(), dummy_span()).unwrap();
+            (int.value, int.ty)
+        };
+        assert_eq!(int("0"), (0, IntKind::Int));
+        assert_eq!(int("017"), (15, IntKind::Int));
+        assert_eq!(int("0xffL"), (255, IntKind::Long));
+        assert_eq!(int("10u"), (10, IntKind::UInt));
+        assert_eq!(int("1uLL"), (1, IntKind::ULongLong));
+        assert_eq!(int("2147483647"), (2147483647, IntKind::Int));
+        assert_eq!(int("2147483648"), (2147483648, IntKind::Long));
+        assert_eq!(int("0x80000000"), (0x80000000, IntKind::UInt));
+        assert_eq!(int("0x8000000000000000"), (1 << 63, IntKind::ULong));
+        assert_eq!(
+            int("18446744073709551615u"),
+            (u64::MAX as u128, IntKind::ULong)
+        );
+
+        let suffix = IntegerConstant::validate("7lu".into(), dummy_span())
+            .unwrap()
+            .suffix;
+        assert_eq!(suffix, IntegerSuffix::UL);
+        assert_eq!(int("0b1010"), (10, IntKind::Int));
+        assert_eq!(int("0B1'0000'0000"), (256, IntKind::Int));
+        assert_eq!(int("1'000'000"), (1_000_000, IntKind::Int));
+        assert_eq!(int("0xffWB"), (255, IntKind::Int));
+        assert_eq!(int("1wbU"), (1, IntKind::UInt));
+
+        let too_large = Err("integer constant is too large".to_owned());
+        let validate = |literal: &str| {
+            IntegerConstant::validate(literal.into(), dummy_span())
+                .map(|int| int.value)
+                .map_err(|(_, msg)| msg)
+        };
+        assert_eq!(validate("9223372036854775808"), too_large);
+        assert_eq!(validate("18446744073709551616u"), too_large);
+        assert_eq!(validate("0x1000000000000000000000000000000000"), too_large);
+    }
+
+    #[test]
+    fn float_values() {
+        let float = |literal: &str| {
+            let float = FloatConstant::validate(literal.into(), dummy_span()).unwrap();
+            (float.value, float.ty())
+        };
+        assert_eq!(float("1.5"), (1.5, FloatKind::Double));
+        assert_eq!(float(".25e2"), (25.0, FloatKind::Double));
+        assert_eq!(float("1.e-3L"), (1e-3, FloatKind::LongDouble));
+        assert_eq!(float("0.1f"), (f64::from(0.1f32), FloatKind::Float));
+        assert_ne!(float("0.1f").0, 0.1);
+        assert_eq!(float("1'000.5"), (1000.5, FloatKind::Double));
+        assert_eq!(float("1e5"), (1e5, FloatKind::Double));
+
+        assert_eq!(float("0x1.8p3"), (12.0, FloatKind::Double));
+        assert_eq!(float("0x.8P1f"), (1.0, FloatKind::Float));
+        assert_eq!(float("0XAp-2L"), (2.5, FloatKind::LongDouble));
+        assert_eq!(float("0x1.fffffffffffffp1023").0, f64::MAX);
+        assert_eq!(float("0x1p-1074").0, 5e-324);
+        assert_eq!(float("0x1p1024").0, f64::INFINITY);
+        assert_eq!(float("0x1p99999999999999999999").0, f64::INFINITY);
+        assert_eq!(float("0x1p-99999999999999999999").0, 0.0);
+        // ties round to even, and digits past the precision still count
+        assert_eq!(float("0x1.00000000000008p0").0, 1.0);
+        assert_eq!(float("0x1.00000000000018p0").0, 1.0 + 2.0 * f64::EPSILON);
+        assert_eq!(
+            float("0x1.000000000000080000000000001p0").0,
+            1.0 + f64::EPSILON
+        );
+        assert_eq!(float("0x1.000001p0f").0, 1.0);
+        assert_eq!(
+            float("0x1.000003p0f").0,
+            f64::from(1.0 + 2.0 * f32::EPSILON)
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let int =
+            |literal: &str| IntegerConstant::validate(literal.into(), dummy_span()).unwrap_err();
+        let float =
+            |literal: &str| FloatConstant::validate(literal.into(), dummy_span()).unwrap_err();
+        assert_eq!(int("0189").0, 2..3);
+        assert_eq!(int("0b0121").0, 4..5);
+        assert_eq!(int("12abc").0, 2..3);
+        assert_eq!(int("12ulx").0, 4..5);
+        assert_eq!(int("12uw").0, 2..4);
+        assert_eq!(int("0x").0, 2..2);
+        assert_eq!(int("1'x").0, 2..3);
+        assert_eq!(int("99999999999999999999999").0, 0..23);
+        assert_eq!(float("1.5e+x").0, 5..6);
+        assert_eq!(float("0x1.8").0, 5..5);
+        assert_eq!(float("0x1.8q3").0, 5..6);
+        assert_eq!(float("1.5fl").0, 4..5);
     }
 }
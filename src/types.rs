@@ -0,0 +1,700 @@
+use crate::ast::{BinaryOp, StructKind};
+use crate::interner::Symbol;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// sizes and alignments follow the x86-64 System V ABI (LP64, signed `char`)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntKind {
+    Bool,
+    Char,
+    SChar,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FloatKind {
+    Float,
+    Double,
+    LongDouble,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Qualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+    pub is_atomic: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Type {
+    pub kind: TypeKind,
+    pub qualifiers: Qualifiers,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Void,
+    Int(IntKind),
+    Float(FloatKind),
+    Pointer(Box<Type>),
+    Array(Box<Type>, Option<u64>),
+    Function(FunctionType),
+    // shared so that a forward declaration is completed everywhere it is used
+    Record(Rc<RefCell<Record>>),
+    Enum(Rc<RefCell<EnumType>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionType {
+    pub ret: Box<Type>,
+    pub params: Vec<Type>,
+    pub variadic: bool,
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub kind: StructKind,
+    pub tag: Option<Symbol>,
+    pub fields: Option<Vec<Field>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    // `None` for anonymous struct and union members
+    pub name: Option<Symbol>,
+    pub ty: Type,
+    pub bit_width: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct EnumType {
+    pub tag: Option<Symbol>,
+    pub is_complete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub offsets: Vec<u64>,
+    pub size: u64,
+    pub align: u64,
+}
+
+impl IntKind {
+    pub fn rank(self) -> u8 {
+        match self {
+            IntKind::Bool => 0,
+            IntKind::Char | IntKind::SChar | IntKind::UChar => 1,
+            IntKind::Short | IntKind::UShort => 2,
+            IntKind::Int | IntKind::UInt => 3,
+            IntKind::Long | IntKind::ULong => 4,
+            IntKind::LongLong | IntKind::ULongLong => 5,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            IntKind::Char
+                | IntKind::SChar
+                | IntKind::Short
+                | IntKind::Int
+                | IntKind::Long
+                | IntKind::LongLong
+        )
+    }
+
+    pub fn size(self) -> u64 {
+        match self.rank() {
+            0 | 1 => 1,
+            2 => 2,
+            3 => 4,
+            _ => 8,
+        }
+    }
+
+    pub fn to_unsigned(self) -> IntKind {
+        match self {
+            IntKind::Char | IntKind::SChar => IntKind::UChar,
+            IntKind::Short => IntKind::UShort,
+            IntKind::Int => IntKind::UInt,
+            IntKind::Long => IntKind::ULong,
+            IntKind::LongLong => IntKind::ULongLong,
+            kind => kind,
+        }
+    }
+
+    pub fn min(self) -> i128 {
+        if self.is_signed() {
+            -(1 << (self.size() * 8 - 1))
+        } else {
+            0
+        }
+    }
+
+    pub fn max(self) -> i128 {
+        match self {
+            IntKind::Bool => 1,
+            _ if self.is_signed() => (1 << (self.size() * 8 - 1)) - 1,
+            _ => (1 << (self.size() * 8)) - 1,
+        }
+    }
+
+    // truncates `value` to the width of this type, as a conversion would
+    pub fn wrap(self, value: i64) -> i64 {
+        if self == IntKind::Bool {
+            return (value != 0) as i64;
+        }
+        let bits = self.size() * 8;
+        if bits == 64 {
+            return value;
+        }
+        let truncated = value & ((1 << bits) - 1);
+        if self.is_signed() && truncated >> (bits - 1) != 0 {
+            truncated - (1 << bits)
+        } else {
+            truncated
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IntKind::Bool => "_Bool",
+            IntKind::Char => "char",
+            IntKind::SChar => "signed char",
+            IntKind::UChar => "unsigned char",
+            IntKind::Short => "short",
+            IntKind::UShort => "unsigned short",
+            IntKind::Int => "int",
+            IntKind::UInt => "unsigned int",
+            IntKind::Long => "long",
+            IntKind::ULong => "unsigned long",
+            IntKind::LongLong => "long long",
+            IntKind::ULongLong => "unsigned long long",
+        }
+    }
+}
+
+impl FloatKind {
+    pub fn size(self) -> u64 {
+        match self {
+            FloatKind::Float => 4,
+            FloatKind::Double => 8,
+            FloatKind::LongDouble => 16,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FloatKind::Float => "float",
+            FloatKind::Double => "double",
+            FloatKind::LongDouble => "long double",
+        }
+    }
+}
+
+impl Qualifiers {
+    pub fn contains(self, other: Qualifiers) -> bool {
+        (self.is_const || !other.is_const)
+            && (self.is_volatile || !other.is_volatile)
+            && (self.is_restrict || !other.is_restrict)
+            && (self.is_atomic || !other.is_atomic)
+    }
+
+    pub fn union(self, other: Qualifiers) -> Qualifiers {
+        Qualifiers {
+            is_const: self.is_const || other.is_const,
+            is_volatile: self.is_volatile || other.is_volatile,
+            is_restrict: self.is_restrict || other.is_restrict,
+            is_atomic: self.is_atomic || other.is_atomic,
+        }
+    }
+}
+
+impl Record {
+    // the offset and type of a member, searching anonymous members too
+    pub fn member(&self, name: Symbol) -> Option<(u64, Type)> {
+        let fields = self.fields.as_ref()?;
+        let layout = self.layout()?;
+        for (field, offset) in fields.iter().zip(layout.offsets) {
+            match &field.name {
+                Some(field_name) if *field_name == name => return Some((offset, field.ty.clone())),
+                Some(_) => {}
+                None => {
+                    if let TypeKind::Record(record) = &field.ty.kind {
+                        if let Some((inner, ty)) = record.borrow().member(name) {
+                            return Some((offset + inner, ty));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // bit-fields are laid out as if they were ordinary members of their declared type
+    pub fn layout(&self) -> Option<Layout> {
+        let fields = self.fields.as_ref()?;
+        let mut offsets = Vec::with_capacity(fields.len());
+        let mut size = 0;
+        let mut align = 1;
+        for field in fields {
+            let field_size = field.ty.size()?;
+            let field_align = field.ty.align()?;
+            align = align.max(field_align);
+            match self.kind {
+                StructKind::Struct => {
+                    let offset = align_to(size, field_align);
+                    offsets.push(offset);
+                    size = offset + field_size;
+                }
+                StructKind::Union => {
+                    offsets.push(0);
+                    size = size.max(field_size);
+                }
+            }
+        }
+        Some(Layout {
+            offsets,
+            size: align_to(size, align),
+            align,
+        })
+    }
+}
+
+pub fn align_to(n: u64, align: u64) -> u64 {
+    n.div_ceil(align) * align
+}
+
+impl Type {
+    pub fn new(kind: TypeKind) -> Self {
+        Self {
+            kind,
+            qualifiers: Qualifiers::default(),
+        }
+    }
+
+    pub fn int(kind: IntKind) -> Self {
+        Self::new(TypeKind::Int(kind))
+    }
+
+    pub fn pointer_to(ty: Type) -> Self {
+        Self::new(TypeKind::Pointer(Box::new(ty)))
+    }
+
+    pub fn with_qualifiers(mut self, qualifiers: Qualifiers) -> Self {
+        self.qualifiers = self.qualifiers.union(qualifiers);
+        self
+    }
+
+    pub fn unqualified(&self) -> Self {
+        Self::new(self.kind.clone())
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self.kind, TypeKind::Void)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self.kind, TypeKind::Int(_) | TypeKind::Enum(_))
+    }
+
+    pub fn is_floating(&self) -> bool {
+        matches!(self.kind, TypeKind::Float(_))
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_floating()
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self.kind, TypeKind::Pointer(_))
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || self.is_pointer()
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self.kind, TypeKind::Array(..))
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self.kind, TypeKind::Function(_))
+    }
+
+    pub fn is_record(&self) -> bool {
+        matches!(self.kind, TypeKind::Record(_))
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        self.is_array() || self.is_record()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        match &self.kind {
+            TypeKind::Void | TypeKind::Function(_) => false,
+            TypeKind::Array(elem, size) => size.is_some() && elem.is_complete(),
+            TypeKind::Record(record) => record.borrow().fields.is_some(),
+            TypeKind::Enum(e) => e.borrow().is_complete,
+            _ => true,
+        }
+    }
+
+    // enumerated types are compatible with `int`
+    pub fn int_kind(&self) -> Option<IntKind> {
+        match &self.kind {
+            TypeKind::Int(kind) => Some(*kind),
+            TypeKind::Enum(_) => Some(IntKind::Int),
+            _ => None,
+        }
+    }
+
+    pub fn pointee(&self) -> Option<&Type> {
+        match &self.kind {
+            TypeKind::Pointer(ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    // the type of an lvalue of this type once it is used as a value
+    pub fn decay(&self) -> Type {
+        match &self.kind {
+            TypeKind::Array(elem, _) => Type::pointer_to((**elem).clone()),
+            TypeKind::Function(_) => Type::pointer_to(self.clone()),
+            _ => self.unqualified(),
+        }
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        match &self.kind {
+            TypeKind::Void | TypeKind::Function(_) => None,
+            TypeKind::Int(kind) => Some(kind.size()),
+            TypeKind::Float(kind) => Some(kind.size()),
+            TypeKind::Pointer(_) => Some(8),
+            TypeKind::Array(elem, size) => Some(elem.size()? * (*size)?),
+            TypeKind::Record(record) => Some(record.borrow().layout()?.size),
+            TypeKind::Enum(e) => e.borrow().is_complete.then_some(4),
+        }
+    }
+
+    pub fn align(&self) -> Option<u64> {
+        match &self.kind {
+            TypeKind::Array(elem, _) => elem.align(),
+            TypeKind::Record(record) => Some(record.borrow().layout()?.align),
+            _ => self.size(),
+        }
+    }
+
+    pub fn is_compatible(&self, other: &Type) -> bool {
+        self.qualifiers == other.qualifiers && self.is_compatible_unqualified(other)
+    }
+
+    pub fn is_compatible_unqualified(&self, other: &Type) -> bool {
+        match (&self.kind, &other.kind) {
+            (TypeKind::Void, TypeKind::Void) => true,
+            (TypeKind::Int(a), TypeKind::Int(b)) => a == b,
+            (TypeKind::Float(a), TypeKind::Float(b)) => a == b,
+            (TypeKind::Enum(a), TypeKind::Enum(b)) => Rc::ptr_eq(a, b),
+            (TypeKind::Enum(_), TypeKind::Int(kind)) | (TypeKind::Int(kind), TypeKind::Enum(_)) => {
+                *kind == IntKind::Int
+            }
+            (TypeKind::Record(a), TypeKind::Record(b)) => Rc::ptr_eq(a, b),
+            (TypeKind::Pointer(a), TypeKind::Pointer(b)) => a.is_compatible(b),
+            (TypeKind::Array(a, a_size), TypeKind::Array(b, b_size)) => {
+                a.is_compatible(b) && (a_size.is_none() || b_size.is_none() || a_size == b_size)
+            }
+            (TypeKind::Function(a), TypeKind::Function(b)) => {
+                a.ret.is_compatible(&b.ret)
+                    && a.variadic == b.variadic
+                    && a.params.len() == b.params.len()
+                    && a.params
+                        .iter()
+                        .zip(&b.params)
+                        .all(|(a, b)| a.is_compatible_unqualified(b))
+            }
+            _ => false,
+        }
+    }
+}
+
+// C11 6.3.1.1
+pub fn promote(ty: &Type) -> Type {
+    match ty.int_kind() {
+        Some(kind) if kind.rank() < IntKind::Int.rank() => Type::int(IntKind::Int),
+        Some(kind) => Type::int(kind),
+        None => ty.unqualified(),
+    }
+}
+
+// C11 6.3.1.8, both operands must have arithmetic type
+pub fn usual_arithmetic_conversions(a: &Type, b: &Type) -> Type {
+    match (&a.kind, &b.kind) {
+        (TypeKind::Float(x), TypeKind::Float(y)) => return Type::new(TypeKind::Float(*x.max(y))),
+        (TypeKind::Float(x), _) | (_, TypeKind::Float(x)) => return Type::new(TypeKind::Float(*x)),
+        _ => {}
+    }
+
+    let a = promote(a).int_kind().unwrap();
+    let b = promote(b).int_kind().unwrap();
+    let kind = if a == b {
+        a
+    } else if a.is_signed() == b.is_signed() {
+        if a.rank() > b.rank() {
+            a
+        } else {
+            b
+        }
+    } else {
+        let (signed, unsigned) = if a.is_signed() { (a, b) } else { (b, a) };
+        if unsigned.rank() >= signed.rank() {
+            unsigned
+        } else if signed.size() > unsigned.size() {
+            signed
+        } else {
+            signed.to_unsigned()
+        }
+    };
+    Type::int(kind)
+}
+
+// the type the operands of an arithmetic binary operator are converted to;
+// the right operand of a shift is promoted on its own
+pub fn operand_type(op: BinaryOp, lhs: &Type, rhs: &Type) -> Type {
+    match op {
+        BinaryOp::Shl | BinaryOp::Shr => promote(lhs),
+        _ => usual_arithmetic_conversions(lhs, rhs),
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", declaration(self, String::new()))
+    }
+}
+
+fn qualifier_words(qualifiers: Qualifiers) -> Vec<&'static str> {
+    let mut words = Vec::new();
+    if qualifiers.is_const {
+        words.push("const");
+    }
+    if qualifiers.is_volatile {
+        words.push("volatile");
+    }
+    if qualifiers.is_restrict {
+        words.push("restrict");
+    }
+    if qualifiers.is_atomic {
+        words.push("_Atomic");
+    }
+    words
+}
+
+// spells `ty` as a C declaration of `inner`, e.g. `int (*)[3]`
+fn declaration(ty: &Type, inner: String) -> String {
+    match &ty.kind {
+        TypeKind::Pointer(pointee) => {
+            let qualifiers = qualifier_words(ty.qualifiers).join(" ");
+            let inner = match (qualifiers.is_empty(), inner.is_empty()) {
+                (true, _) => format!("*{}", inner),
+                (false, true) => format!("*{}", qualifiers),
+                (false, false) => format!("*{} {}", qualifiers, inner),
+            };
+            if pointee.is_array() || pointee.is_function() {
+                declaration(pointee, format!("({})", inner))
+            } else {
+                declaration(pointee, inner)
+            }
+        }
+        TypeKind::Array(elem, size) => {
+            let size = size.map(|n| n.to_string()).unwrap_or_default();
+            declaration(elem, format!("{}[{}]", inner, size))
+        }
+        TypeKind::Function(func) => {
+            let mut params = func
+                .params
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>();
+            if func.variadic {
+                params.push("...".into());
+            } else if params.is_empty() {
+                params.push("void".into());
+            }
+            declaration(&func.ret, format!("{}({})", inner, params.join(", ")))
+        }
+        _ => {
+            let base = match &ty.kind {
+                TypeKind::Void => "void".to_owned(),
+                TypeKind::Int(kind) => kind.name().to_owned(),
+                TypeKind::Float(kind) => kind.name().to_owned(),
+                TypeKind::Record(record) => {
+                    let record = record.borrow();
+                    let keyword = match record.kind {
+                        StructKind::Struct => "struct",
+                        StructKind::Union => "union",
+                    };
+                    match &record.tag {
+                        Some(tag) => format!("{} {}", keyword, tag),
+                        None => format!("{} (anonymous)", keyword),
+                    }
+                }
+                TypeKind::Enum(e) => match &e.borrow().tag {
+                    Some(tag) => format!("enum {}", tag),
+                    None => "enum (anonymous)".to_owned(),
+                },
+                _ => unreachable!(),
+            };
+            let mut words = qualifier_words(ty.qualifiers);
+            words.push(&base);
+            let spelled = words.join(" ");
+            if inner.is_empty() {
+                spelled
+            } else {
+                format!("{} {}", spelled, inner)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(elem: Type, size: u64) -> Type {
+        Type::new(TypeKind::Array(Box::new(elem), Some(size)))
+    }
+
+    fn record(kind: StructKind, fields: Vec<Type>) -> Type {
+        let fields = fields
+            .into_iter()
+            .enumerate()
+            .map(|(i, ty)| Field {
+                name: Some(Symbol::intern(&format!("f{}", i))),
+                ty,
+                bit_width: None,
+            })
+            .collect();
+        Type::new(TypeKind::Record(Rc::new(RefCell::new(Record {
+            kind,
+            tag: Some(Symbol::intern("s")),
+            fields: Some(fields),
+        }))))
+    }
+
+    #[test]
+    fn conversions() {
+        let int = |kind| Type::int(kind);
+        let uac =
+            |a: IntKind, b: IntKind| usual_arithmetic_conversions(&int(a), &int(b)).int_kind();
+
+        assert_eq!(promote(&int(IntKind::Char)).int_kind(), Some(IntKind::Int));
+        assert_eq!(
+            promote(&int(IntKind::UShort)).int_kind(),
+            Some(IntKind::Int)
+        );
+        assert_eq!(promote(&int(IntKind::UInt)).int_kind(), Some(IntKind::UInt));
+
+        assert_eq!(uac(IntKind::Char, IntKind::Short), Some(IntKind::Int));
+        assert_eq!(uac(IntKind::Int, IntKind::UInt), Some(IntKind::UInt));
+        assert_eq!(uac(IntKind::Long, IntKind::UInt), Some(IntKind::Long));
+        assert_eq!(
+            uac(IntKind::Long, IntKind::ULongLong),
+            Some(IntKind::ULongLong)
+        );
+        assert_eq!(
+            uac(IntKind::LongLong, IntKind::ULong),
+            Some(IntKind::ULongLong)
+        );
+
+        let float = usual_arithmetic_conversions(
+            &Type::new(TypeKind::Float(FloatKind::Float)),
+            &int(IntKind::Long),
+        );
+        assert_eq!(float.to_string(), "float");
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(IntKind::Char.wrap(300), 44);
+        assert_eq!(IntKind::Char.wrap(200), -56);
+        assert_eq!(IntKind::UChar.wrap(-1), 255);
+        assert_eq!(IntKind::UInt.wrap(-1), 4294967295);
+        assert_eq!(IntKind::Bool.wrap(7), 1);
+        assert_eq!(IntKind::Long.wrap(-1), -1);
+    }
+
+    #[test]
+    fn layout() {
+        let int = Type::int(IntKind::Int);
+        let char = Type::int(IntKind::Char);
+        let s = record(
+            StructKind::Struct,
+            vec![
+                char.clone(),
+                int.clone(),
+                char.clone(),
+                Type::pointer_to(char.clone()),
+            ],
+        );
+        assert_eq!(s.size(), Some(24));
+        assert_eq!(s.align(), Some(8));
+        if let TypeKind::Record(r) = &s.kind {
+            assert_eq!(r.borrow().layout().unwrap().offsets, [0, 4, 8, 16]);
+            assert_eq!(r.borrow().member(Symbol::intern("f2")).unwrap().0, 8);
+        }
+
+        let u = record(StructKind::Union, vec![char.clone(), array(int, 3)]);
+        assert_eq!(u.size(), Some(12));
+        assert_eq!(u.align(), Some(4));
+    }
+
+    #[test]
+    fn display() {
+        let int = Type::int(IntKind::Int);
+        let const_char = Type::int(IntKind::Char).with_qualifiers(Qualifiers {
+            is_const: true,
+            ..Default::default()
+        });
+        let func = Type::new(TypeKind::Function(FunctionType {
+            ret: Box::new(int.clone()),
+            params: vec![Type::pointer_to(const_char.clone())],
+            variadic: true,
+        }));
+
+        assert_eq!(
+            Type::pointer_to(const_char.clone()).to_string(),
+            "const char *"
+        );
+        assert_eq!(
+            array(Type::pointer_to(int.clone()), 3).to_string(),
+            "int *[3]"
+        );
+        assert_eq!(
+            Type::pointer_to(array(int.clone(), 3)).to_string(),
+            "int (*)[3]"
+        );
+        assert_eq!(func.to_string(), "int (const char *, ...)");
+        assert_eq!(
+            Type::pointer_to(func).to_string(),
+            "int (*)(const char *, ...)"
+        );
+        let const_ptr = Type::pointer_to(int).with_qualifiers(Qualifiers {
+            is_const: true,
+            ..Default::default()
+        });
+        assert_eq!(const_ptr.to_string(), "int *const");
+    }
+}
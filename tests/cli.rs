@@ -0,0 +1,90 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// runs the binary with `input` as a source file on stdin
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_toy-compiler"))
+        .args(args)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn exit_statuses() {
+    let output = run(&["run"], "int main(void) { return 7; }");
+    assert_eq!(output.status.code(), Some(7));
+
+    let output = run(&["check"], "int main(void) { return x; }");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("error[E0300]: use of undeclared identifier 'x'"));
+
+    let output = run(&["check", "--frobnicate"], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = run(&["run"], "int f(void) { return 0; }");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "error: undefined reference to 'main'\n");
+
+    let output = run(
+        &["asm", "-o", "/nonexistent/dir/out.s"],
+        "int main(void) { return 0; }",
+    );
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_toy-compiler"))
+        .args(["check", "/nonexistent/dir/in.c"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).starts_with("error: cannot read \"/nonexistent/dir/in.c\""));
+}
+
+#[test]
+fn listing_and_tokens() {
+    let output = run(&["lex"], "int x;");
+    assert!(stdout(&output).starts_with(" 1| int x;\n\n(Keyword, \"int\")\n --> <stdin>:1:1\n"));
+
+    let output = run(&["lex", "--no-source-listing"], "int x;");
+    assert!(stdout(&output).starts_with("(Keyword, \"int\")\n"));
+}
+
+#[test]
+fn json_lines() {
+    let output = run(&["preprocess", "--format", "json"], "#define N 1\nN;");
+    let lines: Vec<String> = stdout(&output).lines().map(String::from).collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with(r#"{"type":"token","kind":"integer","literal":"1","value":1,"#));
+
+    let output = run(&["check", "--format=json"], "int f(void) { return y; }");
+    let errors = stderr(&output);
+    assert_eq!(errors.lines().count(), 1);
+    assert!(errors.starts_with(r#"{"type":"diagnostic","severity":"error","code":"E0300","#));
+}
+
+#[test]
+fn max_errors() {
+    let src = "int main(void) { a; b; c; }";
+    let output = run(&["check", "--max-errors", "2"], src);
+    let errors = stderr(&output);
+    assert_eq!(errors.matches("undeclared identifier").count(), 2);
+    assert!(errors.ends_with("error: too many errors emitted, stopping now\n"));
+}
@@ -0,0 +1,508 @@
+use toy_compiler::tokens::{
+    Constant, Encoding, KeywordKind, PunctuatorKind, TokenKind, Trivia, TriviaKind,
+};
+use toy_compiler::{
+    tokenize, Code, Diagnostic, Lexer, LineColumn, Preprocessor, Renderer, Severity, SourceFile,
+    Symbol, Token,
+};
+
+#[test]
+fn tokens_and_spans() {
+    let src = SourceFile::new("#include <stdio.h>\nint x = 42;\n", "a.c");
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    // directives are not expanded
+    assert!(matches!(&tokens[0], Token::Directive(d) if d.name == "include"));
+    match &tokens[4] {
+        Token::Constant(Constant::Int(int)) => assert_eq!(int.value, 42),
+        token => panic!("unexpected {:?}", token),
+    }
+    let span = tokens[2].span();
+    assert_eq!(&*span.file_path(), "a.c");
+    assert_eq!(span.byte_range(), 23..24);
+    assert_eq!(span.lc_range().start, LineColumn { line: 2, column: 5 });
+    assert_eq!(span.lc_range().end, LineColumn { line: 2, column: 6 });
+}
+
+#[test]
+fn errors() {
+    let src = SourceFile::new("int c = '';\nint t;\nchar *s = \"abc", "b.c");
+    let (_, errors) = tokenize(&src);
+    let codes: Vec<_> = errors.iter().map(|e| e.code).collect();
+    assert_eq!(codes, [Some(Code::EmptyChar), Some(Code::UnclosedString)]);
+    assert!(errors.iter().all(|e| e.severity == Severity::Error));
+    assert_eq!(errors[1].span.lc_range().start.line, 3);
+}
+
+#[test]
+fn streaming() {
+    let src = SourceFile::new("a b @ c", "c.c");
+    let mut lexer = Lexer::from_src(src);
+    let name = |result: Option<&Result<Token, _>>| match result {
+        Some(Ok(token)) => token.spelling(),
+        Some(Err(_)) => "error".into(),
+        None => "end".into(),
+    };
+
+    // peeking scans ahead without consuming
+    assert_eq!(name(lexer.peek_nth(2)), "error");
+    assert_eq!(name(lexer.peek()), "a");
+    let a = lexer.next().unwrap().unwrap();
+    assert_eq!(name(lexer.next().as_ref()), "b");
+
+    // pushed back tokens come first, in order
+    let c = Lexer::from_src(SourceFile::new("x", "d.c"))
+        .next()
+        .unwrap()
+        .unwrap();
+    lexer.unget(vec![a, c]);
+    let rest: Vec<String> = lexer.by_ref().map(|r| name(Some(&r))).collect();
+    // the error is followed by the text it was about
+    assert_eq!(rest, ["a", "x", "error", "@", "c"]);
+    assert_eq!(name(lexer.next().as_ref()), "end");
+}
+
+#[test]
+fn escapes() {
+    let src = SourceFile::new(r#""\101\x42\u00e9\0" '\377' '\x7f' '$'"#, "e.c");
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    match &tokens[0] {
+        Token::StringLiteral(s) => assert_eq!(s.bytes(), b"AB\xc3\xa9\0\0"),
+        token => panic!("unexpected {:?}", token),
+    }
+    let chars: Vec<i64> = tokens[1..]
+        .iter()
+        .map(|token| match token {
+            Token::Constant(Constant::Char(ch)) => ch.value,
+            token => panic!("unexpected {:?}", token),
+        })
+        .collect();
+    assert_eq!(chars, [-1, 0x7f, '$' as i64]);
+    assert_eq!(tokens[0].spelling(), r#""AB\303\251\000""#);
+
+    // errors point at the escape sequence, not the whole literal
+    let error = |text: &str| {
+        let (_, errors) = tokenize(&SourceFile::new(text, "e.c"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].code, Some(Code::InvalidEscape));
+        (errors[0].msg.clone(), errors[0].span.byte_range())
+    };
+    let cases = [
+        (r#""ab\400""#, "octal escape sequence out of range", 3..7),
+        (r"'\x100'", "hex escape sequence out of range", 1..6),
+        (r#""\x""#, r"\x used with no following hex digits", 1..3),
+        (r#""a\q""#, r"unknown escape sequence '\q'", 2..4),
+        (r#""\u12""#, "incomplete universal character name", 1..5),
+        (
+            r#""\u0041""#,
+            "character 'A' cannot be specified by a universal character name",
+            1..7,
+        ),
+        (
+            r#""\ud800""#,
+            r"\ud800 is not a valid universal character",
+            1..7,
+        ),
+        (
+            r"'\U000000e9'",
+            "character too large for enclosing character literal type",
+            1..11,
+        ),
+    ];
+    for (text, msg, range) in cases {
+        assert_eq!(error(text), (msg.to_owned(), range), "{}", text);
+    }
+}
+
+#[test]
+fn encoding_prefixes() {
+    let src = SourceFile::new(
+        r#"L"aé" u"é😀" U"\U0001F600" u8"é" L 'x' u'é' U'😀' 'ab' u8'\377'"#,
+        "p.c",
+    );
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let strings: Vec<_> = tokens[..4]
+        .iter()
+        .map(|token| match token {
+            Token::StringLiteral(s) => (s.encoding, s.value.clone()),
+            token => panic!("unexpected {:?}", token),
+        })
+        .collect();
+    assert_eq!(
+        strings,
+        [
+            (Encoding::Wide, vec![0x61, 0xe9]),
+            (Encoding::Utf16, vec![0xe9, 0xd83d, 0xde00]),
+            (Encoding::Utf32, vec![0x1f600]),
+            (Encoding::Utf8, vec![0xc3, 0xa9]),
+        ]
+    );
+    assert_eq!(tokens[0].span().byte_range(), 0..6);
+    assert_eq!(tokens[1].spelling(), r#"u"\u00e9\U0001f600""#);
+
+    // `L` is only a prefix right before the quote
+    assert!(matches!(&tokens[4], Token::Identifier(ident) if ident.value == "L"));
+    let chars: Vec<_> = tokens[5..]
+        .iter()
+        .map(|token| match token {
+            Token::Constant(Constant::Char(ch)) => (ch.encoding, ch.value),
+            token => panic!("unexpected {:?}", token),
+        })
+        .collect();
+    assert_eq!(
+        chars,
+        [
+            (Encoding::None, 'x' as i64),
+            (Encoding::Utf16, 0xe9),
+            (Encoding::Utf32, 0x1f600),
+            (Encoding::None, 0x6162),
+            (Encoding::Utf8, 0xff),
+        ]
+    );
+    assert_eq!(tokens[6].spelling(), "u'é'");
+
+    let error = |text: &str| {
+        let (_, errors) = tokenize(&SourceFile::new(text, "p.c"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        (errors[0].code.unwrap(), errors[0].span.byte_range())
+    };
+    assert_eq!(error("u'😀'"), (Code::NonAscii, 2..6));
+    assert_eq!(error("'é'"), (Code::NonAscii, 1..3));
+    assert_eq!(error(r"u8'\u00e9'"), (Code::InvalidEscape, 3..9));
+    assert_eq!(error("L'ab'"), (Code::MultiCharConstant, 3..4));
+    assert_eq!(error("'abcde'"), (Code::MultiCharConstant, 5..6));
+    assert_eq!(error(r#"u"\x10000""#), (Code::InvalidEscape, 2..9));
+}
+
+#[test]
+fn numbers() {
+    let src = SourceFile::new(".5 0x1.8p3 1.5e+3 1e5 0b101 1'000'000 3uwb 1,'a'", "n.c");
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let values: Vec<String> = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Constant(Constant::Float(float)) => float.value.to_string(),
+            Token::Constant(Constant::Int(int)) => int.value.to_string(),
+            token => token.spelling(),
+        })
+        .collect();
+    assert_eq!(
+        values,
+        ["0.5", "12", "1500", "100000", "5", "1000000", "3", "1", ",", "'a'"]
+    );
+
+    // errors point at the first offending char
+    let error = |text: &str| {
+        let (_, errors) = tokenize(&SourceFile::new(text, "n.c"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].code, Some(Code::InvalidNumber));
+        errors[0].span.byte_range()
+    };
+    assert_eq!(error("x = 0189;"), 6..7);
+    assert_eq!(error("0x1.8 "), 5..5);
+    assert_eq!(error("0xe+1"), 3..4);
+    assert_eq!(error("1'000'0x0"), 7..8);
+    assert_eq!(error("12lul"), 4..5);
+}
+
+#[test]
+fn recovery() {
+    // an unclosed literal ends with its line, and the next one lexes as usual
+    let src = SourceFile::new("s = \"abc;\nint c = 'x;\nint y;\n", "r.c");
+    let (tokens, errors) = tokenize(&src);
+    let codes: Vec<_> = errors.iter().map(|e| e.code.unwrap()).collect();
+    assert_eq!(codes, [Code::UnclosedString, Code::UnclosedChar]);
+    assert_eq!(errors[0].span.byte_range(), 4..5);
+    let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+    assert_eq!(
+        spellings,
+        ["s", "=", "\"abc;", "int", "c", "=", "'x;", "int", "y", ";"]
+    );
+    assert!(matches!(&tokens[2], Token::Error(e) if e.span.byte_range() == (4..9)));
+
+    // ... so an apostrophe in a skipped group does not swallow the `#endif`
+    let src = SourceFile::new("#if 0\ndon't\n#endif\nint z;\n", "r.c");
+    let (tokens, errors) = Preprocessor::new().resolve(src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(tokens.len(), 3);
+
+    // a bad directive is skipped to the end of its line; `#` alone is a null
+    // directive
+    let src = SourceFile::new("# 1 \"x.c\"\n#\n#  define X\n#include<a.h>\n", "r.c");
+    let (tokens, errors) = tokenize(&src);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].span.byte_range(), 2..3);
+    let names: Vec<_> = tokens
+        .iter()
+        .map(|token| match token {
+            Token::Directive(d) => format!("{}:{}", d.name, d.args),
+            token => token.spelling(),
+        })
+        .collect();
+    assert_eq!(names, ["# 1 \"x.c\"", ":", "define:X", "include:<a.h>"]);
+}
+
+#[test]
+fn hints() {
+    let fixit = |text: &str| {
+        let (_, errors) = tokenize(&SourceFile::new(text, "h.c"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        let fixit = &errors[0].fixits[0];
+        (fixit.span.byte_range(), fixit.replacement.clone())
+    };
+    assert_eq!(fixit("x = 0187;"), (4..8, "187".into()));
+    assert_eq!(fixit("f(x）;"), (3..6, ")".into()));
+    assert_eq!(fixit(r#""C:\dir""#), (3..4, r"\\".into()));
+    assert_eq!(fixit("'hello'"), (0..7, "\"hello\"".into()));
+}
+
+#[test]
+fn punctuators() {
+    let src = SourceFile::new("f(...); a+++++b <<= c->d .. <: :> <% %> %: %:%: %:%", "p.c");
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+    assert_eq!(
+        spellings,
+        [
+            "f", "(", "...", ")", ";", "a", "++", "++", "+", "b", "<<=", "c", "->", "d", ".", ".",
+            "<:", ":>", "<%", "%>", "%:", "%:%:", "%:", "%"
+        ]
+    );
+    // the punctuators digraphs stand for
+    let kinds: Vec<_> = tokens[16..].iter().map(Token::kind).collect();
+    use PunctuatorKind::*;
+    let expected = [
+        LBracket, RBracket, LBrace, RBrace, Hash, HashHash, Hash, Percent,
+    ];
+    assert_eq!(kinds, expected.map(TokenKind::Punctuator));
+    assert!(matches!(&tokens[23], Token::Operator(op) if op.kind.as_str() == "%"));
+    assert!(matches!(&tokens[4], Token::Punctuator(p) if p.kind == Semi));
+
+    // keywords are classified by kind, identifiers that only look alike are not
+    let (tokens, _) = tokenize(&SourceFile::new("_Static_assert int Int if_", "p.c"));
+    let kinds: Vec<_> = tokens.iter().map(Token::kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Keyword(KeywordKind::StaticAssert),
+            TokenKind::Keyword(KeywordKind::Int),
+            TokenKind::Identifier,
+            TokenKind::Identifier,
+        ]
+    );
+
+    // `%:` starts a directive like `#`, but `##` and `%:%:` do not
+    let (tokens, _) = tokenize(&SourceFile::new("%:define X <:\n", "p.c"));
+    assert!(matches!(&tokens[0], Token::Directive(d) if d.name == "define"));
+    let (tokens, errors) = tokenize(&SourceFile::new("## x\n%:%: y\n", "p.c"));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let kinds: Vec<_> = tokens.iter().map(Token::kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenKind::Punctuator(HashHash),
+            TokenKind::Identifier,
+            TokenKind::Punctuator(HashHash),
+            TokenKind::Identifier,
+        ]
+    );
+}
+
+#[test]
+fn trigraphs() {
+    let text = "??=define X ??(??)\nx ??!??! y ??-z;\n";
+    let (tokens, _) = tokenize(&SourceFile::new(text, "t.c"));
+    assert_eq!(tokens[0].spelling(), "?");
+
+    let src = SourceFile::new(text, "t.c");
+    let (tokens, errors) = Lexer::from_src(src).with_trigraphs(true).resolve();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let spellings: Vec<String> = tokens.iter().map(Token::spelling).collect();
+    assert_eq!(
+        spellings,
+        ["#define X ??(??)", "x", "||", "y", "~", "z", ";"]
+    );
+    // spans cover the trigraphs as written
+    assert_eq!(tokens[2].span().byte_range(), 21..27);
+
+    // and so do those lexed from directive args
+    let directive = match &tokens[0] {
+        Token::Directive(d) => d,
+        token => panic!("unexpected {:?}", token),
+    };
+    let (args, _) = Lexer::from_directive_args(directive)
+        .with_trigraphs(true)
+        .resolve();
+    assert_eq!(args[1].spelling(), "[");
+    assert_eq!(args[2].span().byte_range(), 15..18);
+}
+
+#[test]
+fn splices() {
+    let src = SourceFile::new(
+        "#define ONE \\\n  1\nint ma\\\nin = ONE; \\\nchar *s = \"a\\\n\\q\";\n",
+        "s.c",
+    );
+    let (tokens, errors) = tokenize(&src);
+    let spellings: Vec<String> = tokens[1..].iter().map(Token::spelling).collect();
+    assert_eq!(
+        spellings,
+        [
+            "int",
+            "main",
+            "=",
+            "ONE",
+            ";",
+            "char",
+            "*",
+            "s",
+            "=",
+            "\"a\\\n\\q\"",
+            ";"
+        ]
+    );
+
+    // the identifier is read without the splice, but its span has it
+    let main = match &tokens[2] {
+        Token::Identifier(ident) => ident,
+        token => panic!("unexpected {:?}", token),
+    };
+    assert_eq!(main.value, "main");
+    assert_eq!(main.span.byte_range(), 22..28);
+    let lc = main.span.lc_range();
+    assert_eq!((lc.start.line, lc.end.line), (3, 4));
+    let rendered = Renderer::new().render(&Diagnostic::error(main.span, "here"));
+    assert!(
+        rendered.contains("int ma\\") && rendered.contains("in = ONE;"),
+        "{}",
+        rendered
+    );
+
+    // a token right after a splice starts at its own text
+    assert_eq!(tokens[6].span().byte_range(), 38..42);
+
+    // errors inside a spliced literal point at the source
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0].span.byte_range(), 52..54);
+
+    // directive args keep the splice, and lex across it
+    let directive = match &tokens[0] {
+        Token::Directive(d) => d,
+        token => panic!("unexpected {:?}", token),
+    };
+    assert_eq!(directive.args, "ONE \\\n  1");
+    let (args, _) = Lexer::from_directive_args(directive).resolve();
+    assert_eq!(args[1].span().byte_range(), 16..17);
+}
+
+#[test]
+fn lossless() {
+    let text = "#include <a.h>\r\n#\nint /* one */ x = 1; // x\n\
+                \tint ma\\\nin(??) \\\n  { return 'ab\\q'; }\n\
+                %: define Y <: :>\n@ /* open";
+    let src = SourceFile::new(text, "l.c");
+    let (lossless, errors) = Lexer::from_src(src.clone())
+        .with_trigraphs(true)
+        .resolve_lossless();
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert_eq!(lossless.text(), text);
+
+    let (tokens, _) = Lexer::from_src(src).with_trigraphs(true).resolve();
+    assert_eq!(lossless.tokens.len(), tokens.len());
+
+    let kinds = |trivia: &[Trivia]| {
+        trivia
+            .iter()
+            .map(|t| (t.kind, t.span.byte_range()))
+            .collect::<Vec<_>>()
+    };
+    // `int` leads with the newline after the null directive
+    let int = &lossless.tokens[2];
+    assert_eq!(kinds(&int.leading), [(TriviaKind::Newline, 17..18)]);
+    assert_eq!(
+        kinds(&int.trailing),
+        [
+            (TriviaKind::Whitespace, 21..22),
+            (TriviaKind::BlockComment, 22..31),
+            (TriviaKind::Whitespace, 31..32),
+        ]
+    );
+    // the comment at the end of a line trails the `;` before it
+    let semi = &lossless.tokens[6];
+    assert_eq!(lossless.text_of(semi.token.span()), ";");
+    assert_eq!(
+        kinds(&semi.trailing),
+        [
+            (TriviaKind::Whitespace, 38..39),
+            (TriviaKind::LineComment, 39..43)
+        ]
+    );
+    // a splice between tokens is trivia of its own
+    let bracket = &lossless.tokens[10];
+    assert_eq!(lossless.text_of(bracket.token.span()), "??)");
+    assert_eq!(
+        kinds(&bracket.trailing),
+        [
+            (TriviaKind::Whitespace, 59..60),
+            (TriviaKind::Splice, 60..62),
+            (TriviaKind::Whitespace, 62..64),
+        ]
+    );
+    // an unclosed comment runs to the end
+    assert!(lossless.end.is_empty());
+    let last = lossless.tokens.last().unwrap();
+    assert!(matches!(last.token, Token::Error(_)));
+    assert_eq!(
+        kinds(&last.trailing),
+        [
+            (TriviaKind::Whitespace, 102..103),
+            (TriviaKind::BlockComment, 103..110),
+        ]
+    );
+
+    for path in ["cases/test.c", "cases/testHard.c", "cases/ir/loops.c"] {
+        let text = std::fs::read_to_string(path).unwrap();
+        let (lossless, _) = Lexer::from_src(SourceFile::new(&text, path)).resolve_lossless();
+        assert!(lossless.text() == text, "{}", path);
+    }
+
+    // trivia alone
+    let text = "  // only\n/* trivia */\\\n";
+    let (lossless, errors) = Lexer::from_src(SourceFile::new(text, "t.c")).resolve_lossless();
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert!(lossless.tokens.is_empty());
+    assert_eq!(lossless.end.len(), 5);
+    assert_eq!(lossless.text(), text);
+}
+
+#[test]
+fn interning() {
+    let src = SourceFile::new("x = ma\\\nin(x, \"s\") + \"s\" \"t\";\nmain;", "i.c");
+    let (tokens, errors) = tokenize(&src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let ident = |i: usize| match &tokens[i] {
+        Token::Identifier(ident) => ident.value,
+        token => panic!("unexpected {:?}", token),
+    };
+    let string = |i: usize| match &tokens[i] {
+        Token::StringLiteral(s) => s.symbol,
+        token => panic!("unexpected {:?}", token),
+    };
+
+    // the same name is the same symbol, spliced or not
+    assert_eq!(ident(0), ident(4));
+    assert_eq!(ident(2), ident(12));
+    assert_eq!(ident(2), Symbol::intern("main"));
+    assert_eq!(&*ident(2).as_str(), "main");
+    assert_eq!(string(6), string(9));
+    assert_eq!(string(6), "s");
+
+    // keywords share the interner, ahead of every other name
+    assert_eq!(Symbol::intern("while").keyword(), Some(KeywordKind::While));
+    assert_eq!(ident(0).keyword(), None);
+}